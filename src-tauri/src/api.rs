@@ -0,0 +1,420 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+use crate::export::run_export_job;
+use crate::image_io::{render_preview_with_recipe, PREVIEW_MAX_DIM, PREVIEW_MIN_DIM};
+use crate::models::{DestinationMode, LocalApiAsset, LocalApiExport, LocalApiSettings};
+use crate::recipe_io::load_recipe_for_asset;
+use crate::state::{ensure_allowed, path_for, ASSET_REGISTRY};
+
+// How often the accept loop checks whether it should stop.
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+// Longest wait for any one read, and for the whole request to arrive.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_DEADLINE: Duration = Duration::from_secs(30);
+const MAX_HEADERS: usize = 64;
+// Longest request or header line, CRLF included.
+const MAX_LINE_BYTES: usize = 8 * 1024;
+// Connections served at once, and how many more may wait for a worker; any
+// beyond that are turned away with 503.
+const WORKERS: usize = 4;
+const QUEUED_CONNECTIONS: usize = 16;
+const MAX_BODY_BYTES: usize = 1024 * 1024;
+const DEFAULT_PREVIEW_DIM: u32 = 1440;
+
+struct Running {
+    settings: LocalApiSettings,
+    stop: Arc<AtomicBool>,
+    accept_loop: JoinHandle<()>,
+}
+
+static SERVER: Lazy<Mutex<Option<Running>>> = Lazy::new(|| Mutex::new(None));
+
+struct Request {
+    method: String,
+    path: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>, // names lowercased
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_vec(value).unwrap_or_default(),
+        }
+    }
+
+    fn error(status: u16, message: impl Into<String>) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message.into() }))
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Start, restart or stop the local API to match `settings`. The listener only
+/// binds 127.0.0.1 and refuses to start without a token.
+pub fn sync_server(settings: &LocalApiSettings) -> Result<(), String> {
+    let mut server = SERVER.lock().map_err(|e| e.to_string())?;
+    if let Some(running) = server.as_ref() {
+        if settings.enabled && running.settings == *settings {
+            return Ok(());
+        }
+    }
+    if let Some(running) = server.take() {
+        running.stop.store(true, Ordering::Relaxed);
+        // the port is free again once the loop has returned
+        let _ = running.accept_loop.join();
+    }
+    if !settings.enabled {
+        return Ok(());
+    }
+    if settings.token.trim().is_empty() {
+        return Err("Local API token is required".into());
+    }
+
+    let listener = TcpListener::bind(("127.0.0.1", settings.port))
+        .map_err(|e| format!("Start local API failed: {e}"))?;
+    listener
+        .set_nonblocking(true)
+        .map_err(|e| format!("Start local API failed: {e}"))?;
+    let stop = Arc::new(AtomicBool::new(false));
+    let (queue, connections) = mpsc::sync_channel::<TcpStream>(QUEUED_CONNECTIONS);
+    let connections = Arc::new(Mutex::new(connections));
+    for _ in 0..WORKERS {
+        let connections = connections.clone();
+        let token = settings.token.clone();
+        thread::spawn(move || serve_queue(&connections, &token));
+    }
+    let accept_loop = {
+        let stop = stop.clone();
+        // the queue closes when this loop returns, which lets the workers go
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        if let Err(TrySendError::Full(stream)) = queue.try_send(stream) {
+                            respond(stream, Response::error(503, "Too many connections"));
+                        }
+                    }
+                    // WouldBlock while idle; anything else is retried the same way
+                    Err(_) => thread::sleep(ACCEPT_POLL),
+                }
+            }
+        })
+    };
+    *server = Some(Running {
+        settings: settings.clone(),
+        stop,
+        accept_loop,
+    });
+    Ok(())
+}
+
+// A worker: serves queued connections one at a time until the queue closes.
+fn serve_queue(connections: &Mutex<Receiver<TcpStream>>, token: &str) {
+    loop {
+        let next = match connections.lock() {
+            Ok(connections) => connections.recv(),
+            Err(_) => return,
+        };
+        match next {
+            Ok(stream) => serve_connection(stream, token),
+            Err(_) => return,
+        }
+    }
+}
+
+// One request per connection; the response closes it.
+fn serve_connection(stream: TcpStream, token: &str) {
+    if stream.set_nonblocking(false).is_err() {
+        return;
+    }
+    let deadline = Deadline {
+        stream: &stream,
+        until: Instant::now() + REQUEST_DEADLINE,
+    };
+    let response = match read_request(deadline) {
+        Ok(request) => route(&request, token),
+        Err(response) => response,
+    };
+    respond(stream, response);
+}
+
+// Reads from the stream until the request's deadline, so a client trickling
+// bytes in under READ_TIMEOUT still cannot hold a worker for longer.
+struct Deadline<'a> {
+    stream: &'a TcpStream,
+    until: Instant,
+}
+
+impl Read for Deadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        self.stream.set_read_timeout(Some(left.min(READ_TIMEOUT)))?;
+        self.stream.read(buf)
+    }
+}
+
+// A read that ran out of time gets 408; anything else is the client's mistake.
+fn read_failed(err: io::Error, message: &str) -> Response {
+    match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+            Response::error(408, "Request timed out")
+        }
+        _ => Response::error(400, message),
+    }
+}
+
+fn respond(mut stream: TcpStream, response: Response) {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        response.content_type,
+        response.body.len()
+    );
+    let _ = stream
+        .write_all(head.as_bytes())
+        .and_then(|_| stream.write_all(&response.body))
+        .and_then(|_| stream.flush());
+}
+
+// Reads one line into `line`, refusing it with `status` once it passes
+// MAX_LINE_BYTES rather than buffering whatever the client sends.
+fn read_line_capped(
+    reader: &mut impl BufRead,
+    line: &mut String,
+    status: u16,
+) -> Result<(), Response> {
+    line.clear();
+    let read = reader
+        .take(MAX_LINE_BYTES as u64 + 1)
+        .read_line(line)
+        .map_err(|e| read_failed(e, "Malformed request"))?;
+    if read > MAX_LINE_BYTES {
+        return Err(Response::error(status, "Request line too long"));
+    }
+    Ok(())
+}
+
+fn read_request(stream: Deadline) -> Result<Request, Response> {
+    let bad = |message: &str| Response::error(400, message);
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line_capped(&mut reader, &mut line, 414)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Err(bad("Malformed request"));
+    };
+    let (method, target) = (method.to_string(), target.to_string());
+
+    let mut headers = HashMap::new();
+    loop {
+        read_line_capped(&mut reader, &mut line, 431)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if headers.len() == MAX_HEADERS {
+            return Err(bad("Too many headers"));
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = match headers.get("content-length") {
+        Some(value) => value
+            .parse::<usize>()
+            .map_err(|_| bad("Invalid Content-Length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY_BYTES {
+        return Err(Response::error(413, "Request body too large"));
+    }
+    let mut body = vec![0u8; length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|e| read_failed(e, "Truncated request body"))?;
+
+    let (path, query) = target.split_once('?').unwrap_or((target.as_str(), ""));
+    let query = query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (percent_decode(k), percent_decode(v)))
+        .collect();
+    Ok(Request {
+        method,
+        path: path.to_string(),
+        query,
+        headers,
+        body,
+    })
+}
+
+// Query strings arrive form-encoded: `+` is a space and `%XX` a byte. A `%`
+// not followed by two hex digits is kept as is.
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                out.push(byte);
+                i += 3;
+                continue;
+            }
+            (b'+', _) => out.push(b' '),
+            (byte, _) => out.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// Compares every byte so the time taken says nothing about the token.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+// The bearer header, or a `token` query parameter for clients that can only
+// set a URL (an <img> on a second screen).
+fn authorized(request: &Request, token: &str) -> bool {
+    let given = request
+        .headers
+        .get("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .or(request.query.get("token").map(String::as_str));
+    given.is_some_and(|given| tokens_match(given.trim(), token))
+}
+
+fn route(request: &Request, token: &str) -> Response {
+    if !authorized(request, token) {
+        return Response::error(401, "Missing or invalid token");
+    }
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let result = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["v1", "assets"]) => Ok(list_assets()),
+        ("GET", ["v1", "assets", id, "preview"]) => preview(id, &request.query),
+        ("POST", ["v1", "export"]) => export(&request.body),
+        (_, ["v1", "assets"]) | (_, ["v1", "assets", _, "preview"]) | (_, ["v1", "export"]) => {
+            Err(Response::error(405, "Method not allowed"))
+        }
+        _ => Err(Response::error(404, "Not found")),
+    };
+    result.unwrap_or_else(|response| response)
+}
+
+fn list_assets() -> Response {
+    let mut assets: Vec<LocalApiAsset> = ASSET_REGISTRY
+        .iter()
+        .map(|entry| LocalApiAsset {
+            id: entry.key().clone(),
+            path: entry.value().to_string_lossy().to_string(),
+        })
+        .collect();
+    assets.sort_by(|a, b| a.path.cmp(&b.path));
+    Response::json(200, &assets)
+}
+
+// PNG of the asset with its saved recipe, like the editor shows it.
+fn preview(asset_id: &str, query: &HashMap<String, String>) -> Result<Response, Response> {
+    let path = path_for(asset_id).ok_or_else(|| Response::error(404, "Asset not found"))?;
+    let max_dimension = match query.get("max") {
+        Some(value) => value
+            .parse::<u32>()
+            .map_err(|_| Response::error(400, "max must be a pixel count"))?
+            .clamp(PREVIEW_MIN_DIM, PREVIEW_MAX_DIM),
+        None => DEFAULT_PREVIEW_DIM,
+    };
+    let render = || {
+        let recipe = load_recipe_for_asset(&path)?;
+        render_preview_with_recipe(asset_id, &path, recipe, Some(max_dimension), None)
+    };
+    let body = render().map_err(|e| Response::error(500, e))?;
+    Ok(Response {
+        status: 200,
+        content_type: "image/png",
+        body,
+    })
+}
+
+fn export(body: &[u8]) -> Result<Response, Response> {
+    let request: LocalApiExport = serde_json::from_slice(body)
+        .map_err(|e| Response::error(400, format!("Parse export request failed: {e}")))?;
+    let settings = request.settings;
+    match settings.destination_mode {
+        DestinationMode::Fixed => {
+            if settings.destination.trim().is_empty() {
+                return Err(Response::error(400, "Export destination is required"));
+            }
+            ensure_allowed(Path::new(&settings.destination))
+                .map_err(|e| Response::error(400, e))?;
+        }
+        DestinationMode::SourceSubfolder => {}
+        // there is no window to pick a folder in
+        DestinationMode::Ask => {
+            return Err(Response::error(
+                400,
+                "Ask destinations are not available over the local API",
+            ))
+        }
+    }
+    let assets = request
+        .asset_ids
+        .into_iter()
+        .map(|id| path_for(&id).map(|path| (id, path)))
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| Response::error(404, "Asset not found"))?;
+    let job = run_export_job(&assets, &settings).map_err(|e| Response::error(500, e))?;
+    match job.error {
+        Some(err) => Err(Response::error(500, err)),
+        None => Ok(Response::json(200, &job.results)),
+    }
+}
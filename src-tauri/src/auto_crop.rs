@@ -0,0 +1,192 @@
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::blur::gaussian_blur_f32;
+use crate::crop::aspect_crop;
+use crate::image_io::{cached_preview, resize_rgba_preserve_aspect};
+use crate::models::{Crop, CropGravity, CropSuggestion};
+
+const ANALYSIS_DIM: u32 = 256;
+// surround of the centre-surround saliency, relative to the long edge
+const SURROUND_SIGMA_FRACTION: f32 = 0.08;
+const CROP_SCALES: [f32; 3] = [1.0, 0.85, 0.7];
+const POSITION_STEPS: usize = 8;
+const SUGGESTIONS_PER_ASPECT: usize = 3;
+// candidates closer than this (normalized offset) to a better one are dropped
+const MIN_SEPARATION: f32 = 0.08;
+// 2D rule-of-thirds intersections, in crop-relative coordinates
+const THIRDS: [(f32, f32); 4] = [
+    (1.0 / 3.0, 1.0 / 3.0),
+    (2.0 / 3.0, 1.0 / 3.0),
+    (1.0 / 3.0, 2.0 / 3.0),
+    (2.0 / 3.0, 2.0 / 3.0),
+];
+
+// Summed-area table over a w x h plane, (w + 1) x (h + 1) entries.
+struct Integral {
+    w: usize,
+    sums: Vec<f64>,
+}
+
+impl Integral {
+    fn new(plane: &[f32], w: usize, h: usize) -> Self {
+        let mut sums = vec![0.0f64; (w + 1) * (h + 1)];
+        for y in 0..h {
+            let mut row = 0.0f64;
+            for x in 0..w {
+                row += plane[y * w + x] as f64;
+                sums[(y + 1) * (w + 1) + x + 1] = sums[y * (w + 1) + x + 1] + row;
+            }
+        }
+        Self { w, sums }
+    }
+
+    // sum over [x0, x1) x [y0, y1)
+    fn sum(&self, x0: usize, y0: usize, x1: usize, y1: usize) -> f32 {
+        let at = |x: usize, y: usize| self.sums[y * (self.w + 1) + x];
+        (at(x1, y1) - at(x0, y1) - at(x1, y0) + at(x0, y0)) as f32
+    }
+}
+
+// Centre-surround contrast of luma and two opponent colour channels. Stands in
+// for a subject mask: subjects differ from their surroundings.
+fn saliency(asset_id: &str, path: &Path) -> Result<(Vec<f32>, usize, usize), String> {
+    let preview = cached_preview(asset_id, path, ANALYSIS_DIM)?;
+    let small = resize_rgba_preserve_aspect(&preview, ANALYSIS_DIM);
+    let (w, h) = (small.width() as usize, small.height() as usize);
+    let center: Vec<f32> = small
+        .pixels()
+        .flat_map(|px| {
+            let [r, g, b] = [px[0], px[1], px[2]].map(|v| v as f32 / 255.0);
+            [
+                0.2126 * r + 0.7152 * g + 0.0722 * b,
+                r - g,
+                0.5 * (r + g) - b,
+            ]
+        })
+        .collect();
+    let mut surround = center.clone();
+    let sigma = w.max(h) as f32 * SURROUND_SIGMA_FRACTION;
+    gaussian_blur_f32(&mut surround, w, h, 3, sigma);
+    let mut map: Vec<f32> = center
+        .par_chunks(3)
+        .zip(surround.par_chunks(3))
+        .map(|(c, s)| (0..3).map(|i| (c[i] - s[i]).abs()).sum())
+        .collect();
+    gaussian_blur_f32(&mut map, w, h, 1, 1.5);
+    Ok((map, w, h))
+}
+
+struct Scored {
+    crop: Crop,
+    score: f32,
+}
+
+fn score_crop(map: &[f32], table: &Integral, w: usize, h: usize, crop: &Crop, scale: f32) -> f32 {
+    let x0 = (crop.x * w as f32).round() as usize;
+    let y0 = (crop.y * h as f32).round() as usize;
+    let x1 = ((crop.x + crop.width) * w as f32).round().min(w as f32) as usize;
+    let y1 = ((crop.y + crop.height) * h as f32).round().min(h as f32) as usize;
+    if x1 <= x0 + 4 || y1 <= y0 + 4 {
+        return 0.0;
+    }
+    let total = table.sum(0, 0, w, h).max(1e-6);
+    let inside = table.sum(x0, y0, x1, y1);
+    let coverage = inside / total;
+
+    // saliency centroid inside the crop, against the nearest thirds point
+    let (mut mx, mut my) = (0.0f32, 0.0f32);
+    for y in y0..y1 {
+        for x in x0..x1 {
+            let v = map[y * w + x];
+            mx += v * x as f32;
+            my += v * y as f32;
+        }
+    }
+    let cx = (mx / inside.max(1e-6) - x0 as f32) / (x1 - x0) as f32;
+    let cy = (my / inside.max(1e-6) - y0 as f32) / (y1 - y0) as f32;
+    let nearest = THIRDS
+        .iter()
+        .map(|(tx, ty)| ((cx - tx).powi(2) + (cy - ty).powi(2)).sqrt())
+        .fold(f32::MAX, f32::min);
+    let thirds = (1.0 - nearest / 0.5).clamp(0.0, 1.0);
+
+    // a subject cut by the frame edge shows up as dense saliency in the border band
+    let band = 2;
+    let inner = table.sum(x0 + band, y0 + band, x1 - band, y1 - band);
+    let border_area = ((x1 - x0) * (y1 - y0) - (x1 - x0 - 2 * band) * (y1 - y0 - 2 * band)) as f32;
+    let inner_area = ((x1 - x0 - 2 * band) * (y1 - y0 - 2 * band)) as f32;
+    let border_density = (inside - inner) / border_area.max(1.0);
+    let inner_density = inner / inner_area.max(1.0);
+    let cut = (border_density / inner_density.max(1e-6)).clamp(0.0, 1.0);
+
+    0.5 * coverage + 0.25 * thirds + 0.15 * (1.0 - cut) + 0.1 * scale
+}
+
+/// Ranked crop suggestions for each aspect (width / height): candidate rects at a
+/// few sizes and positions, scored on how much of the salient content they keep,
+/// where its centroid falls against the rule of thirds and whether the frame
+/// edge cuts through it. Best first.
+pub fn suggest_crops(
+    asset_id: &str,
+    path: &Path,
+    aspects: &[f32],
+) -> Result<Vec<CropSuggestion>, String> {
+    let (map, w, h) = saliency(asset_id, path)?;
+    let table = Integral::new(&map, w, h);
+    let source_aspect = w as f32 / h as f32;
+
+    let mut suggestions = Vec::new();
+    for &aspect in aspects.iter().filter(|a| a.is_finite() && **a > 0.0) {
+        let largest = aspect_crop(source_aspect, aspect, CropGravity::Center);
+        let mut scored: Vec<Scored> = CROP_SCALES
+            .iter()
+            .flat_map(|&scale| {
+                let (width, height) = (largest.width * scale, largest.height * scale);
+                (0..=POSITION_STEPS).flat_map(move |i| {
+                    (0..=POSITION_STEPS).map(move |j| {
+                        let step = POSITION_STEPS as f32;
+                        let crop = Crop {
+                            x: (1.0 - width) * i as f32 / step,
+                            y: (1.0 - height) * j as f32 / step,
+                            width,
+                            height,
+                            aspect: Some(aspect),
+                            aspect_locked: true,
+                            ..Crop::default()
+                        };
+                        (crop, scale)
+                    })
+                })
+            })
+            .map(|(crop, scale)| Scored {
+                score: score_crop(&map, &table, w, h, &crop, scale),
+                crop,
+            })
+            .collect();
+        scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+        let mut kept: Vec<Scored> = Vec::new();
+        for candidate in scored {
+            let distinct = kept.iter().all(|k| {
+                (k.crop.x - candidate.crop.x).abs()
+                    + (k.crop.y - candidate.crop.y).abs()
+                    + (k.crop.width - candidate.crop.width).abs()
+                    >= MIN_SEPARATION
+            });
+            if distinct {
+                kept.push(candidate);
+            }
+            if kept.len() == SUGGESTIONS_PER_ASPECT {
+                break;
+            }
+        }
+        suggestions.extend(kept.into_iter().map(|s| CropSuggestion {
+            crop: s.crop,
+            score: s.score,
+        }));
+    }
+    suggestions.sort_by(|a, b| b.score.total_cmp(&a.score));
+    Ok(suggestions)
+}
@@ -0,0 +1,227 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::cache::data_root;
+use crate::catalog::{load_catalog, update_catalog};
+use crate::mask::{brush_bitmap_names, is_bare_name};
+use crate::models::{
+    AppSettings, BackupManifest, BackupSidecar, BackupSummary, Catalog, EditRecipe, RestoreSummary,
+};
+use crate::recipe_io::{is_locked, sidecar_path};
+use crate::settings::{current_settings, reload_settings};
+use crate::shutdown::write_atomic;
+use crate::state::{ensure_allowed, ASSET_REGISTRY};
+
+const BACKUP_VERSION: u32 = 1;
+const MANIFEST_ENTRY: &str = "manifest.json";
+// Everything under the data folder worth keeping; presets live in settings.json.
+const DATA_FILES: [&str; 5] = [
+    "settings.json",
+    "catalog.json",
+    "export_history.json",
+    "lens_profiles.json",
+    "noise_profiles.json",
+];
+
+fn zip_err(e: zip::result::ZipError) -> String {
+    format!("Backup archive failed: {e}")
+}
+
+// Originals whose recipes go into the backup: everything the catalog knows plus
+// the folder open now.
+fn sidecar_assets() -> Result<Vec<PathBuf>, String> {
+    let mut paths: BTreeSet<PathBuf> = load_catalog()?
+        .assets
+        .into_keys()
+        .map(PathBuf::from)
+        .collect();
+    paths.extend(ASSET_REGISTRY.iter().map(|entry| entry.value().clone()));
+    Ok(paths
+        .into_iter()
+        .filter(|path| sidecar_path(path).is_file())
+        .collect())
+}
+
+/// Zip the app data files, and with `include_sidecars` the recipe of every
+/// known asset, into `dest`.
+pub fn backup_app_data(dest: &Path, include_sidecars: bool) -> Result<BackupSummary, String> {
+    let root = data_root()?;
+    let file = File::create(dest).map_err(|e| format!("Create backup failed: {e}"))?;
+    let mut zip = ZipWriter::new(BufWriter::new(file));
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut add = |name: &str, bytes: &[u8]| -> Result<(), String> {
+        zip.start_file(name, options).map_err(zip_err)?;
+        zip.write_all(bytes)
+            .map_err(|e| format!("Write backup failed: {e}"))
+    };
+
+    let mut data_files = Vec::new();
+    for name in DATA_FILES {
+        let path = root.join(name);
+        if !path.is_file() {
+            continue;
+        }
+        let bytes = fs::read(&path).map_err(|e| format!("Read {name} failed: {e}"))?;
+        add(&format!("data/{name}"), &bytes)?;
+        data_files.push(name.to_string());
+    }
+
+    let mut sidecars = Vec::new();
+    if include_sidecars {
+        for (idx, asset) in sidecar_assets()?.into_iter().enumerate() {
+            // a sidecar deleted since the scan is simply left out
+            let Ok(bytes) = fs::read(sidecar_path(&asset)) else {
+                continue;
+            };
+            let entry = format!("sidecars/{idx}.lumen.json");
+            add(&entry, &bytes)?;
+            let mut brush_masks = Vec::new();
+            let names = serde_json::from_slice::<EditRecipe>(&bytes)
+                .map(|recipe| brush_bitmap_names(&recipe))
+                .unwrap_or_default();
+            for name in names {
+                let Ok(png) = fs::read(asset.with_file_name(&name)) else {
+                    continue;
+                };
+                add(&format!("{entry}/{name}"), &png)?;
+                brush_masks.push(name);
+            }
+            sidecars.push(BackupSidecar {
+                entry,
+                asset_path: asset.to_string_lossy().to_string(),
+                brush_masks,
+            });
+        }
+    }
+
+    let manifest = BackupManifest {
+        version: BACKUP_VERSION,
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        data_files,
+        sidecars,
+    };
+    let serialized = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| format!("Serialize backup manifest failed: {e}"))?;
+    add(MANIFEST_ENTRY, &serialized)?;
+    zip.finish()
+        .map_err(zip_err)?
+        .flush()
+        .map_err(|e| format!("Write backup failed: {e}"))?;
+    Ok(BackupSummary {
+        data_files: manifest.data_files.len(),
+        sidecars: manifest.sidecars.len(),
+    })
+}
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>, String> {
+    let mut entry = archive.by_name(name).map_err(zip_err)?;
+    let mut bytes = Vec::new();
+    entry
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Read backup entry {name} failed: {e}"))?;
+    Ok(bytes)
+}
+
+/// Put the app data files from a backup back in place, and with
+/// `restore_sidecars` write each recipe beside its original. Data files are all
+/// checked before any is replaced; recipes only go next to originals that exist
+/// here, lie under an opened or allowed folder and are not locked. A backup can
+/// be a file from anywhere, so it never carries the folder allowlist or the local
+/// API (its switch, port and token) over.
+pub fn restore_app_data(src: &Path, restore_sidecars: bool) -> Result<RestoreSummary, String> {
+    let file = File::open(src).map_err(|e| format!("Open backup failed: {e}"))?;
+    let mut archive = ZipArchive::new(file).map_err(zip_err)?;
+    let manifest: BackupManifest =
+        serde_json::from_slice(&read_entry(&mut archive, MANIFEST_ENTRY)?)
+            .map_err(|e| format!("Parse backup manifest failed: {e}"))?;
+    if manifest.version > BACKUP_VERSION {
+        return Err(format!("Unsupported backup version {}", manifest.version));
+    }
+
+    // only the known file names, so a crafted manifest cannot write elsewhere
+    let mut staged = Vec::new();
+    for name in DATA_FILES {
+        if !manifest.data_files.iter().any(|n| n == name) {
+            continue;
+        }
+        let mut bytes = read_entry(&mut archive, &format!("data/{name}"))?;
+        let parsed = if name == "settings.json" {
+            let mut settings = serde_json::from_slice::<AppSettings>(&bytes)
+                .map_err(|e| format!("Backup {name} is damaged: {e}"))?;
+            // folder grants and the API token stay with this machine; only the dialog
+            // adds grants, and a backup's author would know its token
+            let current = current_settings();
+            settings.allowed_roots = current.allowed_roots;
+            settings.local_api = current.local_api;
+            bytes = serde_json::to_vec_pretty(&settings)
+                .map_err(|e| format!("Serialize settings failed: {e}"))?;
+            Ok(())
+        } else if name == "catalog.json" {
+            serde_json::from_slice::<Catalog>(&bytes).map(|_| ())
+        } else {
+            serde_json::from_slice::<serde_json::Value>(&bytes).map(|_| ())
+        };
+        parsed.map_err(|e| format!("Backup {name} is damaged: {e}"))?;
+        staged.push((name, bytes));
+    }
+    let root = data_root()?;
+    let mut summary = RestoreSummary::default();
+    for (name, bytes) in staged {
+        if name == "catalog.json" {
+            // through the catalog lock, so a concurrent catalog update cannot undo it
+            let restored: Catalog = serde_json::from_slice(&bytes)
+                .map_err(|e| format!("Backup {name} is damaged: {e}"))?;
+            update_catalog(|catalog| *catalog = restored)?;
+        } else {
+            write_atomic(&root.join(name), bytes)
+                .map_err(|e| format!("Restore {name} failed: {e}"))?;
+        }
+        summary.data_files += 1;
+    }
+    reload_settings()?;
+
+    if restore_sidecars {
+        for sidecar in &manifest.sidecars {
+            let Ok(asset) = ensure_allowed(Path::new(&sidecar.asset_path)) else {
+                summary.rejected.push(sidecar.asset_path.clone());
+                continue;
+            };
+            let asset = asset.as_path();
+            if !asset.is_file() {
+                summary.missing.push(sidecar.asset_path.clone());
+                continue;
+            }
+            if is_locked(asset) {
+                summary.locked.push(sidecar.asset_path.clone());
+                continue;
+            }
+            let bytes = read_entry(&mut archive, &sidecar.entry)?;
+            if serde_json::from_slice::<EditRecipe>(&bytes).is_err() {
+                summary.rejected.push(sidecar.asset_path.clone());
+                continue;
+            }
+            for name in &sidecar.brush_masks {
+                let plain = is_bare_name(name);
+                let dest = asset.with_file_name(name);
+                if !plain || dest.exists() {
+                    continue;
+                }
+                let png = read_entry(&mut archive, &format!("{}/{name}", sidecar.entry))?;
+                write_atomic(&dest, png).map_err(|e| format!("Write brush mask failed: {e}"))?;
+            }
+            write_atomic(&sidecar_path(asset), bytes)
+                .map_err(|e| format!("Write sidecar failed: {e}"))?;
+            summary.sidecars += 1;
+        }
+    }
+    Ok(summary)
+}
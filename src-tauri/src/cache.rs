@@ -1,7 +1,30 @@
-use std::fs;
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use dirs::cache_dir;
+use dashmap::DashMap;
+use dirs::{cache_dir, data_dir};
+use once_cell::sync::Lazy;
+use tauri::{AppHandle, Emitter};
+use walkdir::WalkDir;
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::models::{CacheSweep, EditRecipe};
+use crate::settings::current_settings;
+use crate::shutdown::begin_job;
+
+// Per-partition list of thumbnails known to exist, loaded from each partition's
+// index file on first use so lookups do not stat the filesystem.
+static THUMB_INDEX: Lazy<DashMap<String, HashSet<String>>> = Lazy::new(DashMap::new);
+const THUMB_INDEX_FILE: &str = "index";
+const DEFAULT_CACHE_CAP_MB: u64 = 4096;
+// Full-resolution RAW decodes run to hundreds of MB each, so they have a budget
+// of their own: a few of them must not push every thumbnail out of the cache.
+const DEMOSAIC_DIR: &str = "demosaic";
+const DEMOSAIC_CACHE_CAP_MB: u64 = 4096;
+const CACHE_SWEEP_EVENT: &str = "cache-sweep";
 
 pub fn cache_root() -> Result<PathBuf, String> {
     let base = cache_dir().ok_or("Unable to resolve cache directory")?;
@@ -10,19 +33,231 @@ pub fn cache_root() -> Result<PathBuf, String> {
     Ok(root)
 }
 
+/// Persistent app data (history, settings); unlike the cache this is never evicted.
+pub fn data_root() -> Result<PathBuf, String> {
+    let base = data_dir().ok_or("Unable to resolve data directory")?;
+    let root = base.join("openroom");
+    fs::create_dir_all(&root).map_err(|e| e.to_string())?;
+    Ok(root)
+}
+
+/// Managed home of derived files (merges, panoramas, denoised intermediates).
+/// Under the data root, so cache eviction never takes them.
+pub fn derived_dir() -> Result<PathBuf, String> {
+    let dir = data_root()?.join("derived");
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir)
+}
+
 pub fn thumbnails_dir() -> Result<PathBuf, String> {
     let dir = cache_root()?.join("thumbs");
     fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
     Ok(dir)
 }
 
-#[allow(dead_code)]
 pub fn previews_dir() -> Result<PathBuf, String> {
     let dir = cache_root()?.join("previews");
     fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
     Ok(dir)
 }
 
-pub fn cached_path(dir: &Path, asset_id: &str, suffix: &str) -> PathBuf {
-    dir.join(format!("{asset_id}.{suffix}"))
+/// Where a source file's thumbnail lives: thumbs/<folder hash>/<2 hex>/<asset hash>.png.
+/// The asset hash covers path, size and mtime, so edited originals get a new slot.
+pub struct ThumbnailSlot {
+    pub path: PathBuf,
+    partition: String,
+    asset: String,
+}
+
+fn hash_hex(bytes: &[u8]) -> String {
+    format!("{:016x}", xxh3_64(bytes))
+}
+
+// (folder hash, path|size|mtime hash) of a source file
+fn source_hashes(source: &Path) -> Result<(String, String), String> {
+    let meta = fs::metadata(source).map_err(|e| format!("Read source metadata failed: {e}"))?;
+    let modified = meta
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    let folder = source.parent().unwrap_or(Path::new(""));
+    let partition = hash_hex(folder.to_string_lossy().as_bytes());
+    let asset =
+        hash_hex(format!("{}|{}|{}", source.to_string_lossy(), meta.len(), modified).as_bytes());
+    Ok((partition, asset))
+}
+
+/// `managed` thumbnails (converted from the source's profile) get their own slots,
+/// so switching the setting never serves the other kind from cache.
+pub fn thumbnail_slot(source: &Path, managed: bool) -> Result<ThumbnailSlot, String> {
+    let (partition, asset) = source_hashes(source)?;
+    let asset = if managed {
+        hash_hex(format!("{asset}|srgb").as_bytes())
+    } else {
+        asset
+    };
+    let path = thumbnails_dir()?
+        .join(&partition)
+        .join(&asset[..2])
+        .join(format!("{asset}.png"));
+    Ok(ThumbnailSlot {
+        path,
+        partition,
+        asset,
+    })
+}
+
+/// Where a source's 1:1 preview lives: previews/<folder hash>/<asset hash>-<recipe hash>.jpg.
+/// Editing the recipe (or the original) moves it to a new slot; the old file ages
+/// out through the cache cap.
+pub fn full_preview_path(source: &Path, recipe: Option<&EditRecipe>) -> Result<PathBuf, String> {
+    let (partition, asset) = source_hashes(source)?;
+    let recipe_json = serde_json::to_string(&recipe).map_err(|e| e.to_string())?;
+    let recipe_hash = hash_hex(recipe_json.as_bytes());
+    Ok(previews_dir()?
+        .join(partition)
+        .join(format!("{asset}-{recipe_hash}.jpg")))
+}
+
+/// Where a source's depth map, framed by `framing` (its lens and crop), lives:
+/// depth/<folder hash>/<asset hash>-<framing hash>.png.
+pub fn depth_map_path(source: &Path, framing: &str) -> Result<PathBuf, String> {
+    let (partition, asset) = source_hashes(source)?;
+    let dir = cache_root()?.join("depth").join(partition);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join(format!("{asset}-{}.png", hash_hex(framing.as_bytes()))))
+}
+
+/// Where a RAW's demosaiced full-resolution decode lives, as half-float linear
+/// samples packed into a 16-bit PNG:
+/// demosaic/<folder hash>/<asset hash>-<settings hash>.png. `settings` covers
+/// the decode options that change the pixels.
+pub fn demosaic_path(source: &Path, settings: &str) -> Result<PathBuf, String> {
+    let (partition, asset) = source_hashes(source)?;
+    let dir = cache_root()?.join(DEMOSAIC_DIR).join(partition);
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join(format!("{asset}-{}.png", hash_hex(settings.as_bytes()))))
+}
+
+fn load_partition_index(partition: &str) -> Result<(), String> {
+    if THUMB_INDEX.contains_key(partition) {
+        return Ok(());
+    }
+    let index_path = thumbnails_dir()?.join(partition).join(THUMB_INDEX_FILE);
+    let known = fs::read_to_string(&index_path)
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default();
+    THUMB_INDEX.entry(partition.to_string()).or_insert(known);
+    Ok(())
+}
+
+/// Index lookup only; a stale entry (file deleted behind our back) shows up as a
+/// failed read and the thumbnail is regenerated.
+pub fn thumbnail_indexed(slot: &ThumbnailSlot) -> bool {
+    load_partition_index(&slot.partition).is_ok()
+        && THUMB_INDEX
+            .get(&slot.partition)
+            .is_some_and(|known| known.contains(&slot.asset))
+}
+
+pub fn record_thumbnail(slot: &ThumbnailSlot) -> Result<(), String> {
+    load_partition_index(&slot.partition)?;
+    let newly_added = THUMB_INDEX
+        .entry(slot.partition.clone())
+        .or_default()
+        .insert(slot.asset.clone());
+    if !newly_added {
+        return Ok(());
+    }
+    let index_path = thumbnails_dir()?
+        .join(&slot.partition)
+        .join(THUMB_INDEX_FILE);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&index_path)
+        .map_err(|e| format!("Open thumbnail index failed: {e}"))?;
+    writeln!(file, "{}", slot.asset).map_err(|e| format!("Write thumbnail index failed: {e}"))
+}
+
+/// Delete the least recently written cache files until usage fits `cap_bytes`.
+/// Thumbnail index files are kept; entries for deleted thumbnails just miss and
+/// get regenerated. Demosaiced decodes are left to `enforce_demosaic_cap`.
+pub fn enforce_cache_cap(cap_bytes: u64) -> Result<CacheSweep, String> {
+    let root = cache_root()?;
+    evict_oldest(&root, Some(&root.join(DEMOSAIC_DIR)), cap_bytes)
+}
+
+/// Hold the demosaic cache to its own budget, oldest decodes first.
+pub fn enforce_demosaic_cap() -> Result<CacheSweep, String> {
+    evict_oldest(
+        &cache_root()?.join(DEMOSAIC_DIR),
+        None,
+        DEMOSAIC_CACHE_CAP_MB * 1024 * 1024,
+    )
+}
+
+// Delete the least recently written files under `root` (but not under `skip`)
+// until they fit `cap_bytes`.
+fn evict_oldest(root: &Path, skip: Option<&Path>, cap_bytes: u64) -> Result<CacheSweep, String> {
+    let mut files: Vec<(PathBuf, u64, SystemTime)> = WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| skip != Some(entry.path()))
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file() && entry.file_name() != THUMB_INDEX_FILE)
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok()?;
+            let modified = meta.modified().unwrap_or(UNIX_EPOCH);
+            Some((entry.into_path(), meta.len(), modified))
+        })
+        .collect();
+    let mut used: u64 = files.iter().map(|(_, len, _)| len).sum();
+    let mut sweep = CacheSweep {
+        used_bytes: used,
+        cap_bytes,
+        reclaimed_bytes: 0,
+        removed_files: 0,
+    };
+    if used <= cap_bytes {
+        return Ok(sweep);
+    }
+
+    files.sort_by_key(|(_, _, modified)| *modified);
+    for (path, len, _) in files {
+        if used <= cap_bytes {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            used -= len;
+            sweep.reclaimed_bytes += len;
+            sweep.removed_files += 1;
+        }
+    }
+    sweep.used_bytes = used;
+    Ok(sweep)
+}
+
+/// `enforce_cache_cap` with the cap from settings.
+pub fn enforce_configured_cap() -> Result<CacheSweep, String> {
+    let cap_mb = current_settings()
+        .cache_cap_mb
+        .unwrap_or(DEFAULT_CACHE_CAP_MB);
+    enforce_cache_cap(cap_mb.saturating_mul(1024 * 1024))
+}
+
+/// Measure the cache off the main thread at startup, evict beyond the configured
+/// cap and report the result to the frontend.
+pub fn spawn_cache_watchdog(app: &AppHandle) {
+    let app = app.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        let Ok(_job_guard) = begin_job() else {
+            return;
+        };
+        if let Ok(sweep) = enforce_configured_cap() {
+            let _ = app.emit(CACHE_SWEEP_EVENT, sweep);
+        }
+        let _ = enforce_demosaic_cap();
+    });
 }
@@ -0,0 +1,489 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use once_cell::sync::Lazy;
+
+use crate::cache::data_root;
+use crate::mask::{brush_bitmap_names, copy_brush_bitmaps, is_bare_name};
+use crate::metadata::read_metadata;
+use crate::models::{
+    BundleEntry, BundleImportSummary, Catalog, CatalogBundle, CatalogEntry, CullMark, EditRecipe,
+    EmbeddedXmp, LutReference, Metadata, ProxySyncSummary, UserFieldFilter,
+};
+use crate::recipe_io::{
+    ensure_valid_recipe, is_locked, load_recipe_for_asset, save_recipe_for_asset, sidecar_path,
+};
+use crate::shutdown::{stopping, write_atomic};
+use crate::xmp::read_embedded_xmp;
+
+// 2: links between files and LUT paths are stored relative to the bundle root
+const BUNDLE_VERSION: u32 = 2;
+
+// serializes read-modify-write of the catalog file
+static CATALOG_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+// The last catalog read or written, with the file stamp it belongs to.
+type CatalogSnapshot = (Option<(SystemTime, u64)>, Arc<Catalog>);
+static CATALOG_SNAPSHOT: Lazy<RwLock<Option<CatalogSnapshot>>> = Lazy::new(|| RwLock::new(None));
+
+/// Catalog entries are keyed by canonical path so the same original matches
+/// regardless of how it was reached.
+pub fn catalog_key(path: &Path) -> String {
+    path.canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+fn catalog_path() -> Result<PathBuf, String> {
+    Ok(data_root()?.join("catalog.json"))
+}
+
+pub fn load_catalog() -> Result<Catalog, String> {
+    let path = catalog_path()?;
+    if !path.exists() {
+        return Ok(Catalog::default());
+    }
+    let data = fs::read_to_string(&path).map_err(|e| format!("Read catalog failed: {e}"))?;
+    serde_json::from_str(&data).map_err(|e| format!("Parse catalog failed: {e}"))
+}
+
+// The file's (mtime, length), which tells a snapshot still matches it.
+fn catalog_stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let meta = fs::metadata(path).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// The catalog for lookups, parsed once and shared until the file changes.
+/// Writes still go through `update_catalog`.
+pub fn catalog_snapshot() -> Result<Arc<Catalog>, String> {
+    let path = catalog_path()?;
+    let stamp = catalog_stamp(&path);
+    if let Some((cached_stamp, catalog)) =
+        CATALOG_SNAPSHOT.read().map_err(|e| e.to_string())?.as_ref()
+    {
+        if *cached_stamp == stamp {
+            return Ok(catalog.clone());
+        }
+    }
+    let catalog = Arc::new(load_catalog()?);
+    *CATALOG_SNAPSHOT.write().map_err(|e| e.to_string())? = Some((stamp, catalog.clone()));
+    Ok(catalog)
+}
+
+/// Load, mutate and write back the catalog under the lock.
+pub fn update_catalog<T>(f: impl FnOnce(&mut Catalog) -> T) -> Result<T, String> {
+    let _guard = CATALOG_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut catalog = load_catalog()?;
+    let out = f(&mut catalog);
+    let serialized = serde_json::to_string_pretty(&catalog)
+        .map_err(|e| format!("Serialize catalog failed: {e}"))?;
+    let path = catalog_path()?;
+    write_atomic(&path, serialized).map_err(|e| format!("Write catalog failed: {e}"))?;
+    *CATALOG_SNAPSHOT.write().map_err(|e| e.to_string())? =
+        Some((catalog_stamp(&path), Arc::new(catalog)));
+    Ok(out)
+}
+
+// Deepest folder containing every path, so the bundle keeps the shoot's layout.
+fn common_root(paths: &[PathBuf]) -> Option<PathBuf> {
+    let mut root = paths.first()?.parent()?.to_path_buf();
+    for path in paths.iter().skip(1) {
+        while !path.starts_with(&root) {
+            root = root.parent()?.to_path_buf();
+        }
+    }
+    Some(root)
+}
+
+fn relative_string(path: &Path, root: &Path) -> Option<String> {
+    let rel = path.strip_prefix(root).ok()?;
+    let parts: Vec<String> = rel
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+    Some(parts.join("/"))
+}
+
+// Only plain components; a bundle must never address files outside the target root.
+fn safe_relative(rel: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(rel);
+    let plain = path.components().all(|c| matches!(c, Component::Normal(_)));
+    (plain && !rel.is_empty()).then_some(path)
+}
+
+// A bundle carries no absolute paths: links to other files (proxies, derived
+// sources) and the LUT a recipe uses are stored relative to the bundle root, and
+// ones leading outside it are left out.
+fn bundled_entry(mut entry: CatalogEntry, root: &Path) -> CatalogEntry {
+    // catalog keys are canonical, so they are compared with the canonical root
+    let root = PathBuf::from(catalog_key(root));
+    let relative = |key: &str| relative_string(Path::new(key), &root);
+    entry.proxy = entry.proxy.as_deref().and_then(relative);
+    entry.proxy_of = entry.proxy_of.as_deref().and_then(relative);
+    if let Some(derived) = &mut entry.derived {
+        derived.sources = derived
+            .sources
+            .iter()
+            .filter_map(|key| relative(key))
+            .collect();
+    }
+    entry
+}
+
+fn bundled_recipe(mut recipe: EditRecipe, root: &Path) -> EditRecipe {
+    recipe.lut = recipe.lut.and_then(|lut| {
+        let canonical = catalog_key(Path::new(&lut.path));
+        let path = relative_string(Path::new(&canonical), Path::new(&catalog_key(root)))?;
+        Some(LutReference { path, ..lut })
+    });
+    recipe
+}
+
+// The reverse on import: relative links resolve under `target_root`; ones that
+// do not (including the absolute paths of version 1 bundles) are dropped.
+fn local_entry(mut entry: CatalogEntry, target_root: &Path) -> CatalogEntry {
+    let local = |rel: &str| safe_relative(rel).map(|rel| catalog_key(&target_root.join(rel)));
+    entry.proxy = entry.proxy.as_deref().and_then(local);
+    entry.proxy_of = entry.proxy_of.as_deref().and_then(local);
+    if let Some(derived) = &mut entry.derived {
+        derived.sources = derived
+            .sources
+            .iter()
+            .filter_map(|rel| local(rel))
+            .collect();
+    }
+    entry
+}
+
+fn local_recipe(mut recipe: EditRecipe, target_root: &Path) -> EditRecipe {
+    recipe.lut = recipe.lut.and_then(|lut| {
+        let path = target_root.join(safe_relative(&lut.path)?);
+        Some(LutReference {
+            path: path.to_string_lossy().to_string(),
+            ..lut
+        })
+    });
+    recipe
+}
+
+/// Write catalog entries, recipes and metadata for `paths` into a single JSON bundle.
+pub fn export_bundle(paths: &[PathBuf], bundle_path: &Path) -> Result<usize, String> {
+    let root = common_root(paths).ok_or("No assets to export")?;
+    let catalog = load_catalog()?;
+    let mut entries = Vec::with_capacity(paths.len());
+    for path in paths {
+        let relative_path = relative_string(path, &root).ok_or("Asset outside bundle root")?;
+        let recipe = load_recipe_for_asset(path)?;
+        // painted masks travel inside the bundle; one gone missing is left out
+        let folder = path.parent().unwrap_or(Path::new(""));
+        let brush_masks = recipe
+            .iter()
+            .flat_map(brush_bitmap_names)
+            .filter_map(|name| Some((name.clone(), fs::read(folder.join(&name)).ok()?)))
+            .collect();
+        entries.push(BundleEntry {
+            relative_path,
+            catalog: catalog
+                .assets
+                .get(&catalog_key(path))
+                .cloned()
+                .map(|entry| bundled_entry(entry, &root)),
+            recipe: recipe.map(|recipe| bundled_recipe(recipe, &root)),
+            metadata: read_metadata(path).ok(),
+            brush_masks,
+        });
+    }
+    let bundle = CatalogBundle {
+        version: BUNDLE_VERSION,
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        entries,
+    };
+    let serialized = serde_json::to_string_pretty(&bundle)
+        .map_err(|e| format!("Serialize bundle failed: {e}"))?;
+    fs::write(bundle_path, serialized).map_err(|e| format!("Write bundle failed: {e}"))?;
+    Ok(bundle.entries.len())
+}
+
+/// Re-attach a bundle to originals under `target_root`: recipes are written as
+/// sidecars and catalog entries re-keyed to the local paths.
+pub fn import_bundle(
+    bundle_path: &Path,
+    target_root: &Path,
+) -> Result<BundleImportSummary, String> {
+    let data = fs::read_to_string(bundle_path).map_err(|e| format!("Read bundle failed: {e}"))?;
+    let bundle: CatalogBundle =
+        serde_json::from_str(&data).map_err(|e| format!("Parse bundle failed: {e}"))?;
+    if bundle.version > BUNDLE_VERSION {
+        return Err(format!("Unsupported bundle version {}", bundle.version));
+    }
+
+    let mut summary = BundleImportSummary {
+        imported: 0,
+        missing: Vec::new(),
+        rejected: Vec::new(),
+        locked: Vec::new(),
+    };
+    let mut imported_entries = Vec::new();
+    for mut entry in bundle.entries {
+        entry.recipe = entry.recipe.map(|recipe| local_recipe(recipe, target_root));
+        let Some(relative) = safe_relative(&entry.relative_path) else {
+            summary.missing.push(entry.relative_path);
+            continue;
+        };
+        let local = target_root.join(relative);
+        if !local.is_file() {
+            summary.missing.push(entry.relative_path);
+            continue;
+        }
+        if let Some(recipe) = &entry.recipe {
+            // a bad recipe skips that entry instead of failing the whole bundle
+            if ensure_valid_recipe(recipe).is_err() {
+                summary.rejected.push(entry.relative_path);
+                continue;
+            }
+            if is_locked(&local) {
+                summary.locked.push(entry.relative_path);
+                continue;
+            }
+            let folder = local.parent().ok_or("Asset has no folder")?;
+            for (name, bytes) in &entry.brush_masks {
+                let plain = is_bare_name(name);
+                let dest = folder.join(name);
+                if plain && !dest.exists() {
+                    fs::write(&dest, bytes).map_err(|e| format!("Write brush mask failed: {e}"))?;
+                }
+            }
+            save_recipe_for_asset(&local, recipe)?;
+        }
+        if let Some(catalog_entry) = entry.catalog {
+            imported_entries.push((catalog_key(&local), local_entry(catalog_entry, target_root)));
+        }
+        summary.imported += 1;
+    }
+
+    if !imported_entries.is_empty() {
+        update_catalog(|catalog| catalog.assets.extend(imported_entries))?;
+    }
+    Ok(summary)
+}
+
+/// Record original <-> proxy links for freshly generated proxies.
+pub fn link_proxies(links: &[(PathBuf, PathBuf)]) -> Result<(), String> {
+    update_catalog(|catalog| {
+        for (original, proxy) in links {
+            let original_key = catalog_key(original);
+            let proxy_key = catalog_key(proxy);
+            catalog
+                .assets
+                .entry(original_key.clone())
+                .or_default()
+                .proxy = Some(proxy_key.clone());
+            catalog.assets.entry(proxy_key).or_default().proxy_of = Some(original_key);
+        }
+    })
+}
+
+/// Copy the recipes edited on proxies back onto their originals. Proxies without
+/// a link are ignored; originals that are offline are reported, not fatal.
+pub fn push_proxy_edits(proxies: &[PathBuf]) -> Result<ProxySyncSummary, String> {
+    let catalog = load_catalog()?;
+    let mut summary = ProxySyncSummary::default();
+    for proxy in proxies {
+        let Some(original) = catalog
+            .assets
+            .get(&catalog_key(proxy))
+            .and_then(|entry| entry.proxy_of.clone())
+        else {
+            continue;
+        };
+        let Some(recipe) = load_recipe_for_asset(proxy)? else {
+            continue;
+        };
+        if !Path::new(&original).is_file() {
+            summary.missing.push(original);
+            continue;
+        }
+        if is_locked(Path::new(&original)) {
+            summary.locked.push(original);
+            continue;
+        }
+        copy_brush_bitmaps(&recipe, proxy, Path::new(&original))?;
+        save_recipe_for_asset(Path::new(&original), &recipe)?;
+        summary.synced += 1;
+    }
+    Ok(summary)
+}
+
+pub fn caption_for(path: &Path) -> Result<Option<String>, String> {
+    Ok(catalog_snapshot()?
+        .assets
+        .get(&catalog_key(path))
+        .and_then(|entry| entry.caption.clone()))
+}
+
+/// Rating, label and keywords found embedded in the file when it was first listed.
+pub fn embedded_xmp_for(path: &Path) -> Result<Option<EmbeddedXmp>, String> {
+    Ok(catalog_snapshot()?
+        .assets
+        .get(&catalog_key(path))
+        .and_then(|entry| entry.xmp.clone()))
+}
+
+/// Read the organization embedded in newly listed files (ratings and keywords
+/// from phones or other apps) and store it with the import mark, in one catalog
+/// write, so files are only marked once what they hold is kept. Files not yet
+/// hashed get their size recorded too. Captions and cull marks the catalog
+/// already has win. Returns false, marking nothing, when shutdown interrupts the
+/// reads.
+pub fn import_embedded_xmp(keys: &[String], paths: &[PathBuf]) -> Result<bool, String> {
+    let mut embedded = Vec::with_capacity(paths.len());
+    for path in paths {
+        if stopping() {
+            return Ok(false);
+        }
+        let size = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        embedded.push((size, read_embedded_xmp(path)));
+    }
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    update_catalog(|catalog| {
+        for (key, (size, xmp)) in keys.iter().zip(embedded) {
+            let entry = catalog.assets.entry(key.clone()).or_default();
+            entry.imported_at = Some(now);
+            // relinking falls back to the size until the file is hashed
+            if entry.checksum.is_none() {
+                entry.size = size;
+            }
+            let Some(xmp) = xmp else {
+                continue;
+            };
+            if entry.caption.is_none() {
+                entry.caption = xmp.description.clone();
+            }
+            if entry.cull.is_none() && xmp.rating == Some(-1) {
+                entry.cull = Some(CullMark::Toss);
+            }
+            entry.xmp = Some(xmp);
+        }
+    })?;
+    Ok(true)
+}
+
+/// The EXIF an optimize pass stored, while the file is unchanged since; else
+/// read from the file.
+pub fn cached_metadata(path: &Path) -> Result<Metadata, String> {
+    let modified = modified_secs(path);
+    let cached = catalog_snapshot()?
+        .assets
+        .get(&catalog_key(path))
+        .filter(|entry| Some(entry.exif_modified) == modified)
+        .and_then(|entry| entry.exif.clone());
+    match cached {
+        Some(metadata) => Ok(metadata),
+        None => read_metadata(path),
+    }
+}
+
+fn modified_secs(path: &Path) -> Option<u64> {
+    fs::metadata(path)
+        .ok()
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+}
+
+fn field_matches(fields: &BTreeMap<String, String>, filter: &UserFieldFilter) -> bool {
+    fields.get(&filter.key).is_some_and(|value| {
+        filter
+            .value
+            .as_ref()
+            .is_none_or(|wanted| value.trim().eq_ignore_ascii_case(wanted.trim()))
+    })
+}
+
+/// Ids of the assets whose user fields pass every filter. Fields come from the
+/// catalog while the sidecar is unchanged since they were stored; otherwise the
+/// sidecar is read and the catalog brought up to date in one write.
+pub fn filter_by_user_fields(
+    assets: &[(String, PathBuf)],
+    filters: &[UserFieldFilter],
+) -> Result<Vec<String>, String> {
+    let catalog = load_catalog()?;
+    let mut fresh = Vec::new();
+    let mut found = Vec::new();
+    for (id, path) in assets {
+        let key = catalog_key(path);
+        let modified = modified_secs(&sidecar_path(path)).unwrap_or(0);
+        let stored = catalog
+            .assets
+            .get(&key)
+            .filter(|entry| entry.user_fields_modified == modified)
+            .and_then(|entry| entry.user_fields.clone());
+        let fields = match stored {
+            Some(fields) => fields,
+            None => {
+                // an unreadable sidecar counts as no fields until it is rewritten
+                let fields = load_recipe_for_asset(path)
+                    .ok()
+                    .flatten()
+                    .map(|recipe| recipe.user_fields)
+                    .unwrap_or_default();
+                fresh.push((key, modified, fields.clone()));
+                fields
+            }
+        };
+        if filters.iter().all(|filter| field_matches(&fields, filter)) {
+            found.push(id.clone());
+        }
+    }
+    if !fresh.is_empty() {
+        update_catalog(|catalog| {
+            for (key, modified, fields) in fresh {
+                let entry = catalog.assets.entry(key).or_default();
+                entry.user_fields = Some(fields);
+                entry.user_fields_modified = modified;
+            }
+        })?;
+    }
+    Ok(found)
+}
+
+/// Record the user fields just written to each asset's sidecar, stamped with
+/// the sidecar's new mtime, in one catalog write.
+pub fn store_user_fields(written: Vec<(PathBuf, BTreeMap<String, String>)>) -> Result<(), String> {
+    if written.is_empty() {
+        return Ok(());
+    }
+    let stamped: Vec<_> = written
+        .into_iter()
+        .map(|(path, fields)| {
+            let modified = modified_secs(&sidecar_path(&path)).unwrap_or(0);
+            (catalog_key(&path), modified, fields)
+        })
+        .collect();
+    update_catalog(|catalog| {
+        for (key, modified, fields) in stamped {
+            let entry = catalog.assets.entry(key).or_default();
+            entry.user_fields = Some(fields);
+            entry.user_fields_modified = modified;
+        }
+    })
+}
+
+/// Set (or clear with None/blank) the caption of every path in one catalog write.
+pub fn set_captions(paths: &[PathBuf], caption: Option<String>) -> Result<(), String> {
+    let caption = caption.filter(|c| !c.trim().is_empty());
+    update_catalog(|catalog| {
+        for path in paths {
+            catalog.assets.entry(catalog_key(path)).or_default().caption = caption.clone();
+        }
+    })
+}
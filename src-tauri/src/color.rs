@@ -0,0 +1,410 @@
+use std::path::Path;
+
+use image::{ImageDecoder, ImageReader, RgbaImage};
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+use rayon::prelude::*;
+
+use crate::models::OutputColorSpace;
+
+pub type Mat3 = [[f32; 3]; 3];
+
+// Formats decoded by the image crate, whose embedded profile describes the pixels.
+const PROFILED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "tif", "tiff", "webp"];
+
+// Linear sRGB (D65) -> XYZ (D65)
+const SRGB_TO_XYZ_D65: Mat3 = [
+    [0.4124564, 0.3575761, 0.1804375],
+    [0.2126729, 0.7151522, 0.072175],
+    [0.0193339, 0.119192, 0.9503041],
+];
+
+// Bradford chromatic adaptation D65 -> D50 (ProPhoto and the ICC PCS are D50)
+const BRADFORD_D65_TO_D50: Mat3 = [
+    [1.0478112, 0.0228866, -0.050127],
+    [0.0295424, 0.9904844, -0.0170491],
+    [-0.0092345, 0.0150436, 0.7521316],
+];
+
+const XYZ_D65_TO_ADOBE_RGB: Mat3 = [
+    [2.041369, -0.5649464, -0.3446944],
+    [-0.969266, 1.8760108, 0.041556],
+    [0.0134474, -0.1183897, 1.0154096],
+];
+
+const XYZ_D65_TO_DISPLAY_P3: Mat3 = [
+    [2.493497, -0.9313836, -0.4027108],
+    [-0.829489, 1.7626641, 0.0236247],
+    [0.0358458, -0.0761724, 0.9568845],
+];
+
+const XYZ_D50_TO_PROPHOTO: Mat3 = [
+    [1.3459433, -0.2556075, -0.0511118],
+    [-0.5445989, 1.5081673, 0.0205351],
+    [0.0, 0.0, 1.2118128],
+];
+
+// RGB -> XYZ (D50) columns are the ICC rXYZ/gXYZ/bXYZ colorants
+const SRGB_TO_XYZ_D50: Mat3 = [
+    [0.4360747, 0.3850649, 0.1430804],
+    [0.2225045, 0.7168786, 0.0606169],
+    [0.0139322, 0.0971045, 0.7141733],
+];
+const ADOBE_RGB_TO_XYZ_D50: Mat3 = [
+    [0.6097559, 0.2052401, 0.149224],
+    [0.3111242, 0.625656, 0.0632197],
+    [0.0194811, 0.0608902, 0.7448387],
+];
+const PROPHOTO_TO_XYZ_D50: Mat3 = [
+    [0.7976749, 0.1351917, 0.0313534],
+    [0.2880402, 0.7118741, 0.0000857],
+    [0.0, 0.0, 0.82521],
+];
+const DISPLAY_P3_TO_XYZ_D50: Mat3 = [
+    [0.515102, 0.291965, 0.157153],
+    [0.241182, 0.692236, 0.066582],
+    [-0.001049, 0.041882, 0.784378],
+];
+
+const D50_WHITE: [f32; 3] = [0.9642, 1.0, 0.8249];
+const ENCODE_LUT_SIZE: usize = 4096;
+
+#[derive(Clone, Copy)]
+enum Transfer {
+    Srgb,
+    Gamma(f32),
+}
+
+fn transfer_for(space: OutputColorSpace) -> Transfer {
+    match space {
+        OutputColorSpace::Srgb | OutputColorSpace::DisplayP3 => Transfer::Srgb,
+        OutputColorSpace::AdobeRgb => Transfer::Gamma(563.0 / 256.0),
+        OutputColorSpace::ProPhoto => Transfer::Gamma(1.8),
+    }
+}
+
+fn mul3(a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut out = [[0.0f32; 3]; 3];
+    for (r, row) in out.iter_mut().enumerate() {
+        for (c, cell) in row.iter_mut().enumerate() {
+            *cell = (0..3).map(|k| a[r][k] * b[k][c]).sum();
+        }
+    }
+    out
+}
+
+fn invert3(m: &Mat3) -> Option<Mat3> {
+    let cofactor =
+        |r0: usize, r1: usize, c0: usize, c1: usize| m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0];
+    let det = m[0][0] * cofactor(1, 2, 1, 2) - m[0][1] * cofactor(1, 2, 0, 2)
+        + m[0][2] * cofactor(1, 2, 0, 1);
+    if det.abs() < 1e-9 || !det.is_finite() {
+        return None;
+    }
+    let adjugate = [
+        [
+            cofactor(1, 2, 1, 2),
+            -cofactor(0, 2, 1, 2),
+            cofactor(0, 1, 1, 2),
+        ],
+        [
+            -cofactor(1, 2, 0, 2),
+            cofactor(0, 2, 0, 2),
+            -cofactor(0, 1, 0, 2),
+        ],
+        [
+            cofactor(1, 2, 0, 1),
+            -cofactor(0, 2, 0, 1),
+            cofactor(0, 1, 0, 1),
+        ],
+    ];
+    Some(adjugate.map(|row| row.map(|v| v / det)))
+}
+
+/// Camera RGB -> linear sRGB from a DNG-style XYZ (D65) -> camera matrix, the way
+/// dcraw builds it: rows are normalized so white-balanced neutrals stay neutral.
+/// None for an all-zero or singular matrix (camera unknown to the decoder).
+pub fn camera_to_srgb(xyz_to_cam: &Mat3) -> Option<Mat3> {
+    let mut srgb_to_cam = mul3(xyz_to_cam, &SRGB_TO_XYZ_D65);
+    for row in srgb_to_cam.iter_mut() {
+        let sum: f32 = row.iter().sum();
+        if sum.abs() < 1e-6 {
+            return None;
+        }
+        *row = row.map(|v| v / sum);
+    }
+    invert3(&srgb_to_cam)
+}
+
+/// Linear sRGB as converted through `stock` (XYZ -> camera) to linear sRGB as
+/// `calibrated` would have converted it, for decoders that apply their own
+/// matrix. None when either matrix is unusable.
+pub fn camera_recalibration(stock: &Mat3, calibrated: &Mat3) -> Option<Mat3> {
+    let undo = invert3(&camera_to_srgb(stock)?)?;
+    Some(mul3(&camera_to_srgb(calibrated)?, &undo))
+}
+
+/// Linear sRGB -> linear destination primaries.
+fn conversion_matrix(space: OutputColorSpace) -> Option<Mat3> {
+    match space {
+        OutputColorSpace::Srgb => None,
+        OutputColorSpace::AdobeRgb => Some(mul3(&XYZ_D65_TO_ADOBE_RGB, &SRGB_TO_XYZ_D65)),
+        OutputColorSpace::DisplayP3 => Some(mul3(&XYZ_D65_TO_DISPLAY_P3, &SRGB_TO_XYZ_D65)),
+        OutputColorSpace::ProPhoto => Some(mul3(
+            &XYZ_D50_TO_PROPHOTO,
+            &mul3(&BRADFORD_D65_TO_D50, &SRGB_TO_XYZ_D65),
+        )),
+    }
+}
+
+fn colorants(space: OutputColorSpace) -> Mat3 {
+    match space {
+        OutputColorSpace::Srgb => SRGB_TO_XYZ_D50,
+        OutputColorSpace::AdobeRgb => ADOBE_RGB_TO_XYZ_D50,
+        OutputColorSpace::ProPhoto => PROPHOTO_TO_XYZ_D50,
+        OutputColorSpace::DisplayP3 => DISPLAY_P3_TO_XYZ_D50,
+    }
+}
+
+pub fn srgb_to_linear(v: f32) -> f32 {
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(l: f32) -> f32 {
+    encode(Transfer::Srgb, l)
+}
+
+fn encode(transfer: Transfer, l: f32) -> f32 {
+    let l = l.clamp(0.0, 1.0);
+    match transfer {
+        Transfer::Srgb => {
+            if l <= 0.0031308 {
+                l * 12.92
+            } else {
+                1.055 * l.powf(1.0 / 2.4) - 0.055
+            }
+        }
+        Transfer::Gamma(g) => l.powf(1.0 / g),
+    }
+}
+
+/// Ordered-dither offset for pixel (x, y), in 8-bit code values (-0.5..0.5): an
+/// 8x8 Bayer threshold built by bit interleaving. Added just before rounding, it
+/// turns the steps of a smooth gradient into a fine, even pattern. The globals
+/// shader computes the same matrix.
+pub fn dither_offset(x: u32, y: u32) -> f32 {
+    let mut index = 0;
+    for bit in 0..3 {
+        let (xb, yb) = ((x >> bit) & 1, (y >> bit) & 1);
+        let shift = 2 * (2 - bit);
+        index |= ((xb ^ yb) << (shift + 1)) | (yb << shift);
+    }
+    (index as f32 + 0.5) / 64.0 - 0.5
+}
+
+/// Convert sRGB-encoded pixels into the destination color space in place, with
+/// an ordered dither on the requantization when `dither` is set. sRGB output is
+/// a no-op since that is what the render pipeline produces.
+pub fn convert_from_srgb(img: &mut RgbaImage, space: OutputColorSpace, dither: bool) {
+    let Some(m) = conversion_matrix(space) else {
+        return;
+    };
+    let transfer = transfer_for(space);
+    let decode_lut: Vec<f32> = (0..256).map(|v| srgb_to_linear(v as f32 / 255.0)).collect();
+    // encoded code values, kept fractional so the dither has something to round
+    let encode_lut: Vec<f32> = (0..ENCODE_LUT_SIZE)
+        .map(|i| {
+            let l = i as f32 / (ENCODE_LUT_SIZE - 1) as f32;
+            encode(transfer, l) * 255.0
+        })
+        .collect();
+    let quantize = |l: f32, offset: f32| -> u8 {
+        let idx = (l.clamp(0.0, 1.0) * (ENCODE_LUT_SIZE - 1) as f32).round() as usize;
+        (encode_lut[idx] + offset).round().clamp(0.0, 255.0) as u8
+    };
+
+    let width = img.width() as usize;
+    let data: &mut [u8] = img.as_mut();
+    data.par_chunks_mut(4).enumerate().for_each(|(idx, px)| {
+        let offset = if dither {
+            dither_offset((idx % width) as u32, (idx / width) as u32)
+        } else {
+            0.0
+        };
+        let r = decode_lut[px[0] as usize];
+        let g = decode_lut[px[1] as usize];
+        let b = decode_lut[px[2] as usize];
+        px[0] = quantize(m[0][0] * r + m[0][1] * g + m[0][2] * b, offset);
+        px[1] = quantize(m[1][0] * r + m[1][1] * g + m[1][2] * b, offset);
+        px[2] = quantize(m[2][0] * r + m[2][1] * g + m[2][2] * b, offset);
+    });
+}
+
+fn s15_fixed16(v: f32) -> [u8; 4] {
+    ((v as f64 * 65536.0).round() as i32).to_be_bytes()
+}
+
+fn xyz_tag(xyz: [f32; 3]) -> Vec<u8> {
+    let mut out = b"XYZ \0\0\0\0".to_vec();
+    for v in xyz {
+        out.extend_from_slice(&s15_fixed16(v));
+    }
+    out
+}
+
+fn text_tag(text: &str) -> Vec<u8> {
+    let mut out = b"text\0\0\0\0".to_vec();
+    out.extend_from_slice(text.as_bytes());
+    out.push(0);
+    out
+}
+
+fn desc_tag(text: &str) -> Vec<u8> {
+    let mut out = b"desc\0\0\0\0".to_vec();
+    out.extend_from_slice(&(text.len() as u32 + 1).to_be_bytes());
+    out.extend_from_slice(text.as_bytes());
+    out.push(0);
+    // empty unicode + scriptcode records
+    out.extend_from_slice(&[0u8; 4 + 4 + 2 + 1 + 67]);
+    out
+}
+
+fn curve_tag(transfer: Transfer) -> Vec<u8> {
+    let mut out = b"curv\0\0\0\0".to_vec();
+    match transfer {
+        Transfer::Gamma(g) => {
+            out.extend_from_slice(&1u32.to_be_bytes());
+            out.extend_from_slice(&((g * 256.0).round() as u16).to_be_bytes());
+        }
+        Transfer::Srgb => {
+            let n = 1024u32;
+            out.extend_from_slice(&n.to_be_bytes());
+            for i in 0..n {
+                let v = srgb_to_linear(i as f32 / (n - 1) as f32);
+                out.extend_from_slice(&((v * 65535.0).round() as u16).to_be_bytes());
+            }
+        }
+    }
+    out
+}
+
+/// The ICC profile embedded in a JPEG/PNG/TIFF/WebP source, if any. RAW files
+/// (DNG is TIFF underneath) are never asked: their colour comes from the camera.
+pub fn source_icc_profile(path: &Path) -> Option<Vec<u8>> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    if !PROFILED_EXTENSIONS.contains(&extension.as_str()) {
+        return None;
+    }
+    let mut decoder = ImageReader::open(path)
+        .ok()?
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    decoder
+        .icc_profile()
+        .ok()
+        .flatten()
+        .filter(|icc| !icc.is_empty())
+}
+
+/// Re-encode pixels described by the embedded profile `icc` as sRGB, in place,
+/// so a wide-gamut source shows with its real saturation on an sRGB path.
+pub fn convert_icc_to_srgb(img: &mut RgbaImage, icc: &[u8]) -> Result<(), String> {
+    let source = ColorProfile::new_from_slice(icc)
+        .map_err(|e| format!("Parse source profile failed: {e}"))?;
+    // grayscale and CMYK sources reach us already expanded to RGB by the decoder
+    if source.color_space != DataColorSpace::Rgb {
+        return Err(format!(
+            "Unsupported source profile colour space: {:?}",
+            source.color_space
+        ));
+    }
+    let transform = source
+        .create_transform_8bit(
+            Layout::Rgba,
+            &ColorProfile::new_srgb(),
+            Layout::Rgba,
+            TransformOptions::default(),
+        )
+        .map_err(|e| format!("Create source transform failed: {e}"))?;
+    // bands of rows, so a full-resolution export never holds a second copy
+    let band = img.width() as usize * 4 * 64;
+    img.as_mut()
+        .par_chunks_mut(band.max(4))
+        .try_for_each(|chunk| {
+            let encoded = chunk.to_vec();
+            transform
+                .transform(&encoded, chunk)
+                .map_err(|e| format!("Convert to sRGB failed: {e}"))
+        })
+}
+
+pub fn profile_name(space: OutputColorSpace) -> &'static str {
+    match space {
+        OutputColorSpace::Srgb => "sRGB IEC61966-2.1",
+        OutputColorSpace::AdobeRgb => "Adobe RGB (1998) compatible",
+        OutputColorSpace::ProPhoto => "ProPhoto RGB compatible",
+        OutputColorSpace::DisplayP3 => "Display P3 compatible",
+    }
+}
+
+/// Build a minimal ICC v2 matrix/TRC display profile describing `space`.
+pub fn icc_profile(space: OutputColorSpace) -> Vec<u8> {
+    let m = colorants(space);
+    let trc = curve_tag(transfer_for(space));
+    let column = |c: usize| [m[0][c], m[1][c], m[2][c]];
+    // rTRC/gTRC/bTRC share one data block
+    let tags: Vec<(&[u8; 4], Option<Vec<u8>>)> = vec![
+        (b"desc", Some(desc_tag(profile_name(space)))),
+        (b"cprt", Some(text_tag("No copyright, use freely"))),
+        (b"wtpt", Some(xyz_tag(D50_WHITE))),
+        (b"rXYZ", Some(xyz_tag(column(0)))),
+        (b"gXYZ", Some(xyz_tag(column(1)))),
+        (b"bXYZ", Some(xyz_tag(column(2)))),
+        (b"rTRC", Some(trc)),
+        (b"gTRC", None),
+        (b"bTRC", None),
+    ];
+
+    let table_len = 4 + tags.len() * 12;
+    let mut table = Vec::with_capacity(table_len);
+    let mut data: Vec<u8> = Vec::new();
+    let mut last = (0u32, 0u32);
+    table.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    for (sig, payload) in &tags {
+        if let Some(bytes) = payload {
+            let offset = (128 + table_len + data.len()) as u32;
+            last = (offset, bytes.len() as u32);
+            data.extend_from_slice(bytes);
+            data.resize(data.len().div_ceil(4) * 4, 0);
+        }
+        table.extend_from_slice(*sig);
+        table.extend_from_slice(&last.0.to_be_bytes());
+        table.extend_from_slice(&last.1.to_be_bytes());
+    }
+
+    let total = (128 + table.len() + data.len()) as u32;
+    let mut header = vec![0u8; 128];
+    header[0..4].copy_from_slice(&total.to_be_bytes());
+    header[8..12].copy_from_slice(&0x0210_0000u32.to_be_bytes());
+    header[12..16].copy_from_slice(b"mntr");
+    header[16..20].copy_from_slice(b"RGB ");
+    header[20..24].copy_from_slice(b"XYZ ");
+    // creation date: 2024-01-01 00:00:00
+    header[24..26].copy_from_slice(&2024u16.to_be_bytes());
+    header[26..28].copy_from_slice(&1u16.to_be_bytes());
+    header[28..30].copy_from_slice(&1u16.to_be_bytes());
+    header[36..40].copy_from_slice(b"acsp");
+    for (i, v) in D50_WHITE.iter().enumerate() {
+        header[68 + i * 4..72 + i * 4].copy_from_slice(&s15_fixed16(*v));
+    }
+
+    let mut out = header;
+    out.extend_from_slice(&table);
+    out.extend_from_slice(&data);
+    out
+}
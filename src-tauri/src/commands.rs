@@ -1,18 +1,86 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 use tauri::async_runtime::spawn_blocking;
+use tauri::AppHandle;
+use tauri_plugin_dialog::{DialogExt, MessageDialogButtons, MessageDialogKind};
 use uuid::Uuid;
 use walkdir::WalkDir;
 
-use crate::image_io::{clear_preview_cache, load_or_create_thumbnail, render_preview_with_recipe};
+use crate::api::sync_server;
+use crate::auto_crop::suggest_crops as rank_crops;
+use crate::backup::{backup_app_data as backup_data, restore_app_data as restore_data};
+use crate::catalog::{
+    cached_metadata, caption_for, embedded_xmp_for, export_bundle,
+    filter_by_user_fields as filter_assets_by_user_fields, import_bundle,
+    push_proxy_edits as sync_proxy_recipes, set_captions,
+};
+use crate::crop::crop_assets;
+use crate::culling::{commit_session, discard_session, mark as set_cull_mark, start_session};
+use crate::curves::evaluate_curve as sample_tone_curve;
+use crate::depth::framed_depth_map;
+use crate::derived::{
+    adopt_derived, group_derived, managed_derived_file, prune_derived, stale_derived,
+};
+use crate::export::{
+    delete_user_preset, export_slideshow as export_slideshow_frames, find_export_job,
+    generate_proxies as write_proxies, list_presets, load_export_history,
+    quick_export as quick_export_assets, run_export_job, save_user_preset,
+};
+use crate::gpu;
+use crate::hooks::{after_import, current_hooks, save_hooks};
+use crate::horizon::{
+    auto_straighten as straighten_and_constrain, detect_horizon as suggest_straighten,
+};
+use crate::image_io::{
+    auto_tone, clear_preview_cache, compute_raw_histogram, emphasize_layer, encode_png_fast,
+    load_display_thumbnail, load_or_create_full_preview,
+    negotiate_preview_size as preview_size_for_viewport, pregenerate_full_previews, profile_render,
+    register_viewport, release_viewport, render_mask_coverage,
+    render_mask_overlay as overlay_layer_mask, render_preview_with_recipe,
+};
+use crate::integrity::{
+    locate_file as relocate_file, relink_assets as relink_moved_assets, verify_files,
+};
+use crate::lens::find_profile;
+use crate::look_match::match_look as match_recipes_to;
+use crate::lut::lut_info;
+use crate::mask::{
+    clear_brush_bitmaps, render_mask_preview, resolve_brush_mask, save_brush_bitmap,
+};
 use crate::metadata::read_metadata as read_exif_metadata;
-use crate::models::{AssetSummary, EditRecipe, FolderIndex, GpuAdapter, Metadata};
-use crate::recipe_io::{load_recipe_for_asset, save_recipe_for_asset};
-use crate::state::{path_for, register_assets};
+use crate::models::{
+    AppSettings, AssetFlags, AssetIntegrity, AssetSummary, BackupSummary, BatchEditSummary,
+    BundleImportSummary, Crop, CropGravity, CropSuggestion, CullMark, CullSession, CullSummary,
+    DerivedKind, DestinationMode, EditRecipe, EmbeddedXmp, ExportJob, ExportPreset, ExportResult,
+    ExportSettings, FolderIndex, FullPreviewSummary, GlobalAdjustments, GpuAdapter, GpuProfile,
+    GpuStatus, HookScript, HookSettings, HueRange, LensProfile, LutInfo, Mask, Metadata,
+    PerfMetric, ProxyResult, ProxySettings, ProxySyncSummary, QuickExportTarget, RawHistogram,
+    RecipeChange, RecipeIssue, RelinkSummary, RestoreSummary, SlideshowSettings, SoftProof,
+    UserFieldFilter,
+};
+use crate::noise::seeded_recipe;
+use crate::optimize::{cancel_optimize as stop_optimize, queue_optimize};
+use crate::palette::filter_by_color as filter_assets_by_color;
+use crate::perf;
+use crate::recipe_io::{
+    apply_default_develop as write_default_develop, diff_recipes as recipe_changes,
+    load_recipe_for_asset, patch_recipe_for_asset, save_recipe_for_asset, set_flags_for_asset,
+    set_user_fields_for_assets, validate_recipe as lint_recipe,
+};
+use crate::scan_rules::{folder_excluded, is_excluded, rules_for};
+use crate::settings::{current_settings, modify_settings};
+use crate::sky::generate_sky_mask as find_sky;
+use crate::state::{
+    allow_root, ensure_allowed, ensure_color_file_allowed, id_for_path, path_for, register_asset,
+    register_assets, resolve_path,
+};
 
 const SUPPORTED_EXTENSIONS: &[&str] = &[
     "dng", "nef", "cr2", "cr3", "arw", "raf", "rw2", "orf", "srw", "heic", "jpg", "jpeg", "png",
 ];
+// File names shown in the prune confirmation before the rest are summed up.
+const PRUNE_LISTED_FILES: usize = 12;
 
 fn is_supported(path: &Path) -> bool {
     path.extension()
@@ -34,14 +102,20 @@ fn to_asset_summary(path: PathBuf) -> Option<AssetSummary> {
         file_name,
         extension,
         path: path.to_string_lossy().to_string(),
+        derived_from: None,
     })
 }
 
 fn collect_assets(folder: &Path) -> Result<Vec<AssetSummary>, String> {
+    let rules = rules_for(folder);
+    if folder_excluded(folder, &rules) {
+        return Ok(Vec::new());
+    }
     let mut assets: Vec<AssetSummary> = WalkDir::new(folder)
         .max_depth(1)
         .follow_links(false)
         .into_iter()
+        .filter_entry(|entry| !is_excluded(folder, entry, &rules))
         .filter_map(Result::ok)
         .filter(|entry| entry.file_type().is_file() && is_supported(entry.path()))
         .filter_map(|entry| to_asset_summary(entry.into_path()))
@@ -52,13 +126,16 @@ fn collect_assets(folder: &Path) -> Result<Vec<AssetSummary>, String> {
 }
 
 #[tauri::command]
-pub async fn open_folder(path: String) -> Result<FolderIndex, String> {
+pub async fn open_folder(app: AppHandle, path: String) -> Result<FolderIndex, String> {
     let res: Result<(PathBuf, Vec<AssetSummary>), String> = spawn_blocking(move || {
-        let path_buf = PathBuf::from(&path);
+        // only folders granted through pick_folder (or configured before) open
+        let path_buf = ensure_allowed(Path::new(&path))?;
         if !path_buf.is_dir() {
             return Err("Provided path is not a directory".into());
         }
-        let assets = collect_assets(&path_buf)?;
+        let assets = group_derived(collect_assets(&path_buf)?)?;
+        // import bookkeeping reports its own failures and must not block the folder
+        after_import(&app, &assets);
         Ok((path_buf, assets))
     })
     .await
@@ -79,10 +156,64 @@ pub async fn open_folder(path: String) -> Result<FolderIndex, String> {
     })
 }
 
+/// Ask for a folder in the native dialog and grant access to it. The grant is
+/// remembered in the settings so the folder can be reopened in later sessions.
+/// None when the dialog is cancelled.
+#[tauri::command]
+pub async fn pick_folder(app: AppHandle) -> Result<Option<String>, String> {
+    spawn_blocking(move || {
+        let Some(picked) = app
+            .dialog()
+            .file()
+            .set_title("Select a photo folder")
+            .blocking_pick_folder()
+        else {
+            return Ok(None);
+        };
+        let picked = picked.into_path().map_err(|e| e.to_string())?;
+        let root = allow_root(&picked)?.to_string_lossy().to_string();
+        let known = current_settings().allowed_roots.contains(&root);
+        if !known {
+            modify_settings(|settings| {
+                if !settings.allowed_roots.contains(&root) {
+                    settings.allowed_roots.push(root.clone());
+                }
+                Ok(())
+            })?;
+        }
+        Ok(Some(root))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Queue a background optimize of every asset in `folder`; returns the job id.
+/// Progress arrives as "optimize-progress", the summary as "optimize-finished".
+#[tauri::command]
+pub async fn optimize_library(app: AppHandle, folder: String) -> Result<String, String> {
+    let folder = ensure_allowed(Path::new(&folder))?;
+    if !folder.is_dir() {
+        return Err("Provided path is not a directory".into());
+    }
+    let assets = spawn_blocking(move || collect_assets(&folder))
+        .await
+        .map_err(|e| e.to_string())??;
+    let paths = assets
+        .into_iter()
+        .map(|asset| PathBuf::from(asset.path))
+        .collect();
+    Ok(queue_optimize(app, paths))
+}
+
+#[tauri::command]
+pub fn cancel_optimize(job_id: String) {
+    stop_optimize(&job_id);
+}
+
 #[tauri::command]
 pub async fn get_thumbnail(asset_id: String) -> Result<Vec<u8>, String> {
     let path = path_for(&asset_id).ok_or("Asset not found")?;
-    spawn_blocking(move || load_or_create_thumbnail(&asset_id, &path))
+    spawn_blocking(move || load_display_thumbnail(&path))
         .await
         .map_err(|e| e.to_string())?
 }
@@ -90,11 +221,92 @@ pub async fn get_thumbnail(asset_id: String) -> Result<Vec<u8>, String> {
 #[tauri::command]
 pub async fn render_preview(
     asset_id: String,
-    recipe: Option<EditRecipe>,
+    mut recipe: Option<EditRecipe>,
     max_dimension: Option<u32>,
+    soft_proof: Option<SoftProof>,
+    emphasize_layer_id: Option<String>,
 ) -> Result<Vec<u8>, String> {
     let path = path_for(&asset_id).ok_or("Asset not found")?;
-    spawn_blocking(move || render_preview_with_recipe(&asset_id, &path, recipe, max_dimension))
+    if let Some(layer_id) = &emphasize_layer_id {
+        emphasize_layer(recipe.as_mut().ok_or("Layer not found")?, layer_id)?;
+    }
+    spawn_blocking(move || {
+        render_preview_with_recipe(&asset_id, &path, recipe, max_dimension, soft_proof.as_ref())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn negotiate_preview_size(
+    viewport_w: u32,
+    viewport_h: u32,
+    dpr: f32,
+    viewport_id: Option<String>,
+) -> u32 {
+    let size = preview_size_for_viewport(viewport_w, viewport_h, dpr);
+    // named viewports keep their size's previews warm while another screen renders
+    if let Some(viewport_id) = viewport_id {
+        register_viewport(&viewport_id, size);
+    }
+    size
+}
+
+#[tauri::command]
+pub fn close_viewport(viewport_id: String) {
+    release_viewport(&viewport_id);
+}
+
+#[tauri::command]
+pub async fn get_full_preview(asset_id: String) -> Result<Vec<u8>, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || load_or_create_full_preview(&path).map(|(bytes, _)| bytes))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn generate_full_previews(
+    app: AppHandle,
+    asset_ids: Vec<String>,
+) -> Result<FullPreviewSummary, String> {
+    let assets = resolve_assets(asset_ids)?;
+    spawn_blocking(move || pregenerate_full_previews(&app, &assets))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn auto_adjust(asset_id: String) -> Result<GlobalAdjustments, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || auto_tone(&asset_id, &path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn detect_horizon(asset_id: String) -> Result<Option<f32>, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || suggest_straighten(&asset_id, &path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// The asset's crop levelled and cut to the largest rect the straighten rotation
+/// leaves valid; lens and document corrections are not considered. The caller
+/// decides whether to save it.
+#[tauri::command]
+pub async fn auto_straighten(asset_id: String) -> Result<Option<Crop>, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || straighten_and_constrain(&asset_id, &path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_raw_histogram(asset_id: String) -> Result<RawHistogram, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || compute_raw_histogram(&path))
         .await
         .map_err(|e| e.to_string())?
 }
@@ -102,11 +314,23 @@ pub async fn render_preview(
 #[tauri::command]
 pub async fn read_metadata(asset_id: String) -> Result<Metadata, String> {
     let path = path_for(&asset_id).ok_or("Asset not found")?;
-    spawn_blocking(move || read_exif_metadata(&path))
+    spawn_blocking(move || cached_metadata(&path))
         .await
         .map_err(|e| e.to_string())?
 }
 
+/// The database profile matching the asset's lens, if any.
+#[tauri::command]
+pub async fn find_lens_profile(asset_id: String) -> Result<Option<LensProfile>, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || match read_exif_metadata(&path)?.lens {
+        Some(model) => find_profile(&model),
+        None => Ok(None),
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
 #[tauri::command]
 pub async fn save_recipe(asset_id: String, recipe: EditRecipe) -> Result<(), String> {
     let path = path_for(&asset_id).ok_or("Asset not found")?;
@@ -118,28 +342,798 @@ pub async fn save_recipe(asset_id: String, recipe: EditRecipe) -> Result<(), Str
 #[tauri::command]
 pub async fn load_recipe(asset_id: String) -> Result<Option<EditRecipe>, String> {
     let path = path_for(&asset_id).ok_or("Asset not found")?;
-    spawn_blocking(move || load_recipe_for_asset(&path))
+    spawn_blocking(move || {
+        // first open: nothing is written until the user edits
+        Ok(load_recipe_for_asset(&path)?.or_else(|| seeded_recipe(&path)))
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn validate_recipe(recipe: EditRecipe) -> Vec<RecipeIssue> {
+    lint_recipe(&recipe)
+}
+
+#[tauri::command]
+pub fn diff_recipes(a: EditRecipe, b: EditRecipe) -> Result<Vec<RecipeChange>, String> {
+    recipe_changes(&a, &b)
+}
+
+#[tauri::command]
+pub async fn preview_mask(
+    mut mask: Mask,
+    width: u32,
+    height: u32,
+    asset_id: Option<String>,
+    recipe: Option<EditRecipe>,
+) -> Result<Vec<u8>, String> {
+    spawn_blocking(move || {
+        // brush bitmaps are named relative to the asset they were painted on, and
+        // luminance ranges are judged on its preview; without one there is nothing
+        // to resolve them against
+        let coverage = match asset_id {
+            Some(asset_id) => {
+                let path = path_for(&asset_id).ok_or("Asset not found")?;
+                resolve_brush_mask(&mut mask, &path);
+                render_mask_coverage(&asset_id, &path, &mask, recipe, width, height)?
+            }
+            None => {
+                clear_brush_bitmaps(&mut mask);
+                render_mask_preview(&mask, width, height, None)
+            }
+        };
+        encode_png_fast(&coverage)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// The preview (of `recipe`, else the saved one) with the layer's mask in red.
+#[tauri::command]
+pub async fn render_mask_overlay(
+    asset_id: String,
+    layer_id: String,
+    recipe: Option<EditRecipe>,
+    max_dimension: Option<u32>,
+) -> Result<Vec<u8>, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || {
+        let recipe = match recipe {
+            Some(recipe) => recipe,
+            None => load_recipe_for_asset(&path)?.ok_or("Layer not found")?,
+        };
+        overlay_layer_mask(&asset_id, &path, recipe, &layer_id, max_dimension)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Whether the asset carries a portrait depth map a depth mask can use.
+#[tauri::command]
+pub async fn has_depth_map(asset_id: String) -> Result<bool, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || framed_depth_map(&path).map(|map| map.is_some()))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn save_brush_mask(asset_id: String, png: Vec<u8>) -> Result<String, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || save_brush_bitmap(&path, &png))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn generate_sky_mask(asset_id: String) -> Result<Option<Mask>, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || find_sky(&asset_id, &path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn evaluate_curve(curve: Vec<(f32, f32)>, samples: usize) -> Vec<f32> {
+    sample_tone_curve(&curve, samples)
+}
+
+#[tauri::command]
+pub async fn set_asset_flags(asset_ids: Vec<String>, flags: AssetFlags) -> Result<(), String> {
+    let assets = resolve_assets(asset_ids)?;
+    spawn_blocking(move || {
+        for (_, path) in &assets {
+            set_flags_for_asset(path, flags)?;
+        }
+        Ok(())
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// Set (Some) or remove (None) the named user fields on every asset, locked
+/// ones included.
+#[tauri::command]
+pub async fn set_user_fields(
+    asset_ids: Vec<String>,
+    changes: BTreeMap<String, Option<String>>,
+) -> Result<(), String> {
+    let paths: Vec<PathBuf> = resolve_assets(asset_ids)?
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    spawn_blocking(move || set_user_fields_for_assets(&paths, &changes))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn patch_recipe(
+    asset_id: String,
+    json_merge_patch: serde_json::Value,
+) -> Result<EditRecipe, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || patch_recipe_for_asset(&path, &json_merge_patch))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+fn resolve_assets(asset_ids: Vec<String>) -> Result<Vec<(String, PathBuf)>, String> {
+    asset_ids
+        .into_iter()
+        .map(|id| {
+            let path = path_for(&id).ok_or("Asset not found")?;
+            Ok((id, path))
+        })
+        .collect()
+}
+
+fn finish_export_job(job: ExportJob) -> Result<Vec<ExportResult>, String> {
+    match job.error {
+        Some(err) => Err(err),
+        None => Ok(job.results),
+    }
+}
+
+#[tauri::command]
+pub async fn export_assets(
+    app: AppHandle,
+    asset_ids: Vec<String>,
+    mut settings: ExportSettings,
+) -> Result<Vec<ExportResult>, String> {
+    let assets = resolve_assets(asset_ids)?;
+    match settings.destination_mode {
+        DestinationMode::Fixed => {
+            if settings.destination.trim().is_empty() {
+                return Err("Export destination is required".into());
+            }
+            ensure_allowed(Path::new(&settings.destination))?;
+        }
+        // originals are already inside an opened root
+        DestinationMode::SourceSubfolder => {}
+        DestinationMode::Ask => {
+            let picked = spawn_blocking(move || {
+                app.dialog()
+                    .file()
+                    .set_title("Export to")
+                    .blocking_pick_folder()
+            })
+            .await
+            .map_err(|e| e.to_string())?
+            .ok_or("Export cancelled")?
+            .into_path()
+            .map_err(|e| e.to_string())?;
+            // a folder picked in the native dialog is an explicit grant
+            let folder = allow_root(&picked)?;
+            // record the concrete folder so reruns don't prompt again
+            settings.destination = folder.to_string_lossy().to_string();
+            settings.destination_mode = DestinationMode::Fixed;
+        }
+    }
+    let job = spawn_blocking(move || run_export_job(&assets, &settings))
+        .await
+        .map_err(|e| e.to_string())??;
+    finish_export_job(job)
+}
+
+#[tauri::command]
+pub async fn quick_export(
+    asset_ids: Vec<String>,
+    target: QuickExportTarget,
+) -> Result<Vec<String>, String> {
+    let assets = resolve_assets(asset_ids)?;
+    spawn_blocking(move || quick_export_assets(&assets, target))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn export_slideshow(
+    asset_ids: Vec<String>,
+    settings: SlideshowSettings,
+) -> Result<Vec<String>, String> {
+    if settings.destination.trim().is_empty() {
+        return Err("Export destination is required".into());
+    }
+    ensure_allowed(Path::new(&settings.destination))?;
+    if let Some(lut_path) = &settings.lut_path {
+        ensure_color_file_allowed(Path::new(lut_path))?;
+    }
+    let assets = resolve_assets(asset_ids)?;
+    spawn_blocking(move || export_slideshow_frames(&assets, &settings))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn generate_proxies(
+    asset_ids: Vec<String>,
+    settings: ProxySettings,
+) -> Result<Vec<ProxyResult>, String> {
+    if settings.destination.trim().is_empty() {
+        return Err("Proxy destination is required".into());
+    }
+    ensure_allowed(Path::new(&settings.destination))?;
+    let assets = resolve_assets(asset_ids)?;
+    spawn_blocking(move || write_proxies(&assets, &settings))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn push_proxy_edits(asset_ids: Vec<String>) -> Result<ProxySyncSummary, String> {
+    let paths: Vec<PathBuf> = resolve_assets(asset_ids)?
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    spawn_blocking(move || sync_proxy_recipes(&paths))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Take a merged, stitched or denoised file into the managed derived folder,
+/// linked to the assets it was made from, and list it after its last source.
+#[tauri::command]
+pub async fn add_derived_asset(
+    path: String,
+    kind: DerivedKind,
+    source_ids: Vec<String>,
+) -> Result<AssetSummary, String> {
+    let file = ensure_allowed(Path::new(&path))?;
+    let sources = resolve_assets(source_ids)?;
+    let (source_id, _) = sources.last().cloned().ok_or("No source assets given")?;
+    let stored = spawn_blocking(move || {
+        let paths: Vec<PathBuf> = sources.into_iter().map(|(_, path)| path).collect();
+        adopt_derived(&file, kind, &paths)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    let mut summary = to_asset_summary(stored.clone()).ok_or("Invalid derived file")?;
+    summary.derived_from = Some(source_id);
+    register_asset(summary.id.clone(), stored);
+    Ok(summary)
+}
+
+/// Delete derived files whose originals are all gone, once the user confirms in
+/// a native dialog; returns what was removed.
+#[tauri::command]
+pub async fn prune_derived_assets(app: AppHandle) -> Result<Vec<String>, String> {
+    spawn_blocking(move || {
+        let stale = stale_derived()?;
+        let files: Vec<String> = stale
+            .iter()
+            .filter(|key| managed_derived_file(key))
+            .map(|key| {
+                Path::new(key)
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default()
+            })
+            .collect();
+        if !files.is_empty() {
+            let mut listed = files
+                .iter()
+                .take(PRUNE_LISTED_FILES)
+                .map(|name| format!("  {name}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            if files.len() > PRUNE_LISTED_FILES {
+                listed.push_str(&format!(
+                    "\n  and {} more",
+                    files.len() - PRUNE_LISTED_FILES
+                ));
+            }
+            let confirmed = app
+                .dialog()
+                .message(format!(
+                    "These derived files no longer have their originals:\n\n{listed}\n\n\
+                     Originals that were only renamed or moved also count as gone. Delete the \
+                     derived files?"
+                ))
+                .title("Delete derived files?")
+                .kind(MessageDialogKind::Warning)
+                .buttons(MessageDialogButtons::OkCancelCustom(
+                    "Delete".to_string(),
+                    "Keep".to_string(),
+                ))
+                .blocking_show();
+            if !confirmed {
+                return Ok(Vec::new());
+            }
+        }
+        prune_derived(&stale)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn load_lut(path: String) -> Result<LutInfo, String> {
+    spawn_blocking(move || lut_info(Path::new(&path)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn list_export_presets() -> Vec<ExportPreset> {
+    list_presets()
+}
+
+#[tauri::command]
+pub async fn save_export_preset(preset: ExportPreset) -> Result<ExportPreset, String> {
+    spawn_blocking(move || save_user_preset(preset))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn delete_export_preset(preset_id: String) -> Result<(), String> {
+    spawn_blocking(move || delete_user_preset(&preset_id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn list_export_history() -> Result<Vec<ExportJob>, String> {
+    spawn_blocking(load_export_history)
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn rerun_export(job_id: String) -> Result<Vec<ExportResult>, String> {
+    let job = spawn_blocking(move || {
+        let previous = find_export_job(&job_id)?.ok_or("Export job not found")?;
+        // the history file lives on disk, so its paths are checked like frontend input
+        if previous.settings.destination_mode == DestinationMode::Fixed {
+            ensure_allowed(Path::new(&previous.settings.destination))?;
+        }
+        // asset ids are per-session, so re-resolve by path and fall back to the recorded id
+        let assets: Vec<(String, PathBuf)> = previous
+            .assets
+            .iter()
+            .map(|asset| {
+                let path = PathBuf::from(&asset.path);
+                ensure_allowed(&path)?;
+                let id = id_for_path(&path).unwrap_or_else(|| asset.asset_id.clone());
+                Ok((id, path))
+            })
+            .collect::<Result<_, String>>()?;
+        run_export_job(&assets, &previous.settings)
+    })
+    .await
+    .map_err(|e| e.to_string())??;
+    finish_export_job(job)
+}
+
+#[tauri::command]
+pub async fn verify_assets(folder: String) -> Result<Vec<AssetIntegrity>, String> {
+    spawn_blocking(move || {
+        let folder = ensure_allowed(Path::new(&folder))?;
+        if !folder.is_dir() {
+            return Err("Provided path is not a directory".into());
+        }
+        let files: Vec<PathBuf> = collect_assets(&folder)?
+            .into_iter()
+            .map(|asset| PathBuf::from(asset.path))
+            .collect();
+        verify_files(&folder, &files)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn relink_assets(old_root: String, new_root: String) -> Result<RelinkSummary, String> {
+    spawn_blocking(move || {
+        let old_root = resolve_path(Path::new(&old_root))?;
+        let new_root = ensure_allowed(Path::new(&new_root))?;
+        if !new_root.is_dir() {
+            return Err("Provided path is not a directory".into());
+        }
+        relink_moved_assets(&old_root, &new_root)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn locate_file(asset_id: String, new_path: String) -> Result<(), String> {
+    let old_path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || {
+        let new_path = ensure_allowed(Path::new(&new_path))?;
+        relocate_file(&old_path, &new_path)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn export_catalog_bundle(
+    asset_ids: Vec<String>,
+    bundle_path: String,
+) -> Result<usize, String> {
+    let bundle_path = ensure_allowed(Path::new(&bundle_path))?;
+    let paths: Vec<PathBuf> = resolve_assets(asset_ids)?
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    spawn_blocking(move || export_bundle(&paths, &bundle_path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn import_catalog_bundle(
+    bundle_path: String,
+    target_root: String,
+) -> Result<BundleImportSummary, String> {
+    let bundle_path = ensure_allowed(Path::new(&bundle_path))?;
+    let target_root = ensure_allowed(Path::new(&target_root))?;
+    spawn_blocking(move || import_bundle(&bundle_path, &target_root))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn backup_app_data(
+    dest: String,
+    include_sidecars: Option<bool>,
+) -> Result<BackupSummary, String> {
+    let dest = ensure_allowed(Path::new(&dest))?;
+    spawn_blocking(move || backup_data(&dest, include_sidecars.unwrap_or(false)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn restore_app_data(
+    src: String,
+    restore_sidecars: Option<bool>,
+) -> Result<RestoreSummary, String> {
+    let src = ensure_allowed(Path::new(&src))?;
+    spawn_blocking(move || restore_data(&src, restore_sidecars.unwrap_or(false)))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_caption(asset_id: String) -> Result<Option<String>, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || caption_for(&path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn get_embedded_xmp(asset_id: String) -> Result<Option<EmbeddedXmp>, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || embedded_xmp_for(&path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn set_caption(asset_id: String, caption: Option<String>) -> Result<(), String> {
+    set_captions_batch(vec![asset_id], caption).await
+}
+
+#[tauri::command]
+pub async fn set_captions_batch(
+    asset_ids: Vec<String>,
+    caption: Option<String>,
+) -> Result<(), String> {
+    let paths: Vec<PathBuf> = resolve_assets(asset_ids)?
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    spawn_blocking(move || set_captions(&paths, caption))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn start_cull_session(asset_ids: Vec<String>) -> Result<CullSession, String> {
+    let assets = resolve_assets(asset_ids)?;
+    spawn_blocking(move || start_session(&assets))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn mark_cull(
+    session_id: String,
+    asset_id: String,
+    mark: Option<CullMark>,
+) -> Result<(), String> {
+    set_cull_mark(&session_id, &asset_id, mark)
+}
+
+#[tauri::command]
+pub async fn commit_cull_session(session_id: String) -> Result<CullSummary, String> {
+    spawn_blocking(move || commit_session(&session_id))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn discard_cull_session(session_id: String) {
+    discard_session(&session_id)
+}
+
+#[tauri::command]
+pub async fn suggest_crops(
+    asset_id: String,
+    aspects: Vec<f32>,
+) -> Result<Vec<CropSuggestion>, String> {
+    let path = path_for(&asset_id).ok_or("Asset not found")?;
+    spawn_blocking(move || rank_crops(&asset_id, &path, &aspects))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn filter_by_color(
+    asset_ids: Vec<String>,
+    hue_range: HueRange,
+) -> Result<Vec<String>, String> {
+    let assets = resolve_assets(asset_ids)?;
+    spawn_blocking(move || filter_assets_by_color(&assets, &hue_range))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn filter_by_user_fields(
+    asset_ids: Vec<String>,
+    filters: Vec<UserFieldFilter>,
+) -> Result<Vec<String>, String> {
+    let assets = resolve_assets(asset_ids)?;
+    spawn_blocking(move || filter_assets_by_user_fields(&assets, &filters))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub async fn apply_crop_batch(
+    asset_ids: Vec<String>,
+    aspect: f32,
+    gravity: Option<CropGravity>,
+) -> Result<BatchEditSummary, String> {
+    let paths: Vec<PathBuf> = resolve_assets(asset_ids)?
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    spawn_blocking(move || crop_assets(&paths, aspect, gravity.unwrap_or_default()))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// Write the configured default develop settings into each asset's recipe.
+#[tauri::command]
+pub async fn apply_default_develop(asset_ids: Vec<String>) -> Result<BatchEditSummary, String> {
+    let defaults = current_settings()
+        .default_develop
+        .ok_or("No default develop settings are configured")?;
+    let paths: Vec<PathBuf> = resolve_assets(asset_ids)?
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    spawn_blocking(move || write_default_develop(&paths, &defaults))
         .await
         .map_err(|e| e.to_string())?
 }
 
+#[tauri::command]
+pub async fn match_look(
+    source_id: String,
+    target_ids: Vec<String>,
+) -> Result<Vec<EditRecipe>, String> {
+    let source = path_for(&source_id).ok_or("Asset not found")?;
+    let targets: Vec<PathBuf> = resolve_assets(target_ids)?
+        .into_iter()
+        .map(|(_, path)| path)
+        .collect();
+    spawn_blocking(move || match_recipes_to(&source, &targets))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn get_settings() -> AppSettings {
+    current_settings()
+}
+
+#[tauri::command]
+pub async fn update_settings(mut settings: AppSettings) -> Result<AppSettings, String> {
+    spawn_blocking(move || {
+        if settings.local_api.enabled && settings.local_api.token.trim().is_empty() {
+            settings.local_api.token = Uuid::new_v4().simple().to_string();
+        }
+        let updated = modify_settings(|current| {
+            // grants only come from the native folder dialog
+            if settings.allowed_roots != current.allowed_roots {
+                return Err(
+                    "Allowed folders can only be added through the folder picker".to_string(),
+                );
+            }
+            // the server goes first so a port that will not bind is refused rather
+            // than saved
+            sync_server(&settings.local_api)?;
+            Ok(std::mem::replace(current, settings.clone()))
+        });
+        // whatever fails, the previous server is put back; nothing was saved, so
+        // the cached settings are still the previous ones
+        let previous = match updated {
+            Ok(previous) => previous,
+            Err(err) => {
+                let _ = sync_server(&current_settings().local_api);
+                return Err(err);
+            }
+        };
+        // cached preview masters were decoded under the old colour handling or
+        // hot-pixel setting
+        if settings.unmanaged_color != previous.unmanaged_color
+            || settings.keep_hot_pixels != previous.keep_hot_pixels
+        {
+            clear_preview_cache();
+        }
+        Ok(settings)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+#[tauri::command]
+pub fn get_hooks() -> HookSettings {
+    current_hooks()
+}
+
+/// Replace the import/export hooks once the user approves the programs in a
+/// native dialog; the webview alone cannot set them.
+#[tauri::command]
+pub async fn set_hooks(app: AppHandle, hooks: HookSettings) -> Result<HookSettings, String> {
+    spawn_blocking(move || {
+        let describe = |label: &str, hook: &Option<HookScript>| match hook {
+            Some(hook) if !hook.program.trim().is_empty() => {
+                format!("{label}: {} {}\n", hook.program, hook.args.join(" "))
+            }
+            _ => format!("{label}: none\n"),
+        };
+        let message = format!(
+            "Openroom will run these programs with your full permissions:\n\n{}{}",
+            describe("After import", &hooks.post_import),
+            describe("After export", &hooks.post_export),
+        );
+        let approved = app
+            .dialog()
+            .message(message)
+            .title("Allow script hooks?")
+            .kind(MessageDialogKind::Warning)
+            .buttons(MessageDialogButtons::OkCancelCustom(
+                "Allow".to_string(),
+                "Cancel".to_string(),
+            ))
+            .blocking_show();
+        if !approved {
+            return Err("Hooks were not approved".to_string());
+        }
+        save_hooks(&hooks)?;
+        Ok(hooks)
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
 #[tauri::command]
 pub fn detect_gpus() -> Result<Vec<GpuAdapter>, String> {
     let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
         backends: wgpu::Backends::all(),
         ..Default::default()
     });
+    let context = gpu::context_adapter();
+    let disabled = gpu::disabled_features();
+    let preferred = current_settings().preferred_gpu;
     let adapters: Vec<GpuAdapter> = instance
         .enumerate_adapters(wgpu::Backends::all())
         .into_iter()
         .map(|adapter: wgpu::Adapter| {
             let info = adapter.get_info();
+            let limits = adapter.limits();
+            let features = adapter.features();
+            let context_active = context.as_ref().is_ok_and(|active| {
+                active.name == info.name
+                    && active.backend == info.backend
+                    && active.device == info.device
+            });
+            let id = gpu::adapter_id(&info);
             GpuAdapter {
+                preferred: preferred.as_ref() == Some(&id),
+                id,
                 name: info.name,
                 backend: format!("{:?}", info.backend),
                 device_type: format!("{:?}", info.device_type),
+                max_texture_dimension: limits.max_texture_dimension_2d,
+                float32_filterable: features.contains(wgpu::Features::FLOAT32_FILTERABLE),
+                timestamp_query: features.contains(wgpu::Features::TIMESTAMP_QUERY),
+                max_buffer_mb: limits.max_buffer_size / (1024 * 1024),
+                context_active,
+                context_error: context.as_ref().err().cloned(),
+                disabled_features: if context_active {
+                    disabled.clone()
+                } else {
+                    Vec::new()
+                },
             }
         })
         .collect();
     Ok(adapters)
 }
+
+/// Persist the adapter the processing context runs on, by its id from
+/// `detect_gpus`, or None for the automatic high-performance pick. The context
+/// is built once per session, so the choice applies from the next launch.
+#[tauri::command]
+pub fn set_preferred_gpu(adapter_id: Option<String>) -> Result<(), String> {
+    if let Some(id) = &adapter_id {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        });
+        let known = instance
+            .enumerate_adapters(wgpu::Backends::all())
+            .iter()
+            .any(|adapter| gpu::adapter_id(&adapter.get_info()) == *id);
+        if !known {
+            return Err("GPU adapter not found".into());
+        }
+    }
+    modify_settings(|settings| {
+        settings.preferred_gpu = adapter_id;
+        Ok(())
+    })
+}
+
+/// Benchmark render timed per stage, and whether previews take the GPU or the
+/// CPU path, for diagnosing slow previews.
+#[tauri::command]
+pub async fn gpu_profile() -> Result<GpuProfile, String> {
+    spawn_blocking(profile_render)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// The active GPU backend, or why there is none, and the GPU calls that fell
+/// back to the CPU this session.
+#[tauri::command]
+pub fn gpu_status() -> GpuStatus {
+    gpu::status()
+}
+
+/// Call counts and recent p50/p95 durations per pipeline stage, for the
+/// performance overlay.
+#[tauri::command]
+pub fn get_perf_metrics() -> Vec<PerfMetric> {
+    perf::metrics()
+}
@@ -0,0 +1,242 @@
+use std::path::{Path, PathBuf};
+
+use image::{imageops, RgbaImage};
+use rayon::prelude::*;
+
+use crate::image_io::{sample_bilinear, source_aspect};
+use crate::models::{BatchEditSummary, Crop, CropGravity};
+use crate::recipe_io::{is_locked, update_recipe_for_asset};
+
+/// Largest rectangle of `aspect` (width / height) that fits a frame of
+/// `source_aspect`, pushed toward `gravity` along the axis that has slack.
+pub fn aspect_crop(source_aspect: f32, aspect: f32, gravity: CropGravity) -> Crop {
+    let (width, height) = if aspect > source_aspect {
+        (1.0, source_aspect / aspect)
+    } else {
+        (aspect / source_aspect, 1.0)
+    };
+    let place = |slack: f32, start: bool, end: bool| {
+        if start {
+            0.0
+        } else if end {
+            slack
+        } else {
+            slack / 2.0
+        }
+    };
+    Crop {
+        x: place(
+            1.0 - width,
+            gravity == CropGravity::Left,
+            gravity == CropGravity::Right,
+        ),
+        y: place(
+            1.0 - height,
+            gravity == CropGravity::Top,
+            gravity == CropGravity::Bottom,
+        ),
+        width,
+        height,
+        aspect: Some(aspect),
+        aspect_locked: true,
+        ..Crop::default()
+    }
+}
+
+// bisection steps when pulling a constrained rect in; 2^-16 of the way is plenty
+const CONSTRAIN_STEPS: usize = 16;
+
+/// Rotate about the centre, zoomed just enough that the rotated frame covers the
+/// whole output and no empty corners show.
+pub fn straighten(img: &RgbaImage, degrees: f32) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let ratio = (w as f32 / h as f32).max(h as f32 / w as f32);
+    rotate(img, degrees, cos.abs() + ratio * sin.abs())
+}
+
+// Rotate about the centre and scale by `zoom`; output pixels that fall outside
+// the source repeat its edge.
+fn rotate(img: &RgbaImage, degrees: f32, zoom: f32) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let mut out = RgbaImage::new(w, h);
+    out.par_chunks_mut(w as usize * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for x in 0..w as usize {
+                let dx = (x as f32 + 0.5 - cx) / zoom;
+                let dy = (y as f32 + 0.5 - cy) / zoom;
+                let sx = (dx * cos + dy * sin + cx - 0.5).clamp(0.0, (w - 1) as f32);
+                let sy = (-dx * sin + dy * cos + cy - 0.5).clamp(0.0, (h - 1) as f32);
+                row[x * 4..x * 4 + 4].copy_from_slice(&sample_bilinear(img, sx, sy));
+            }
+        });
+    out
+}
+
+// With a locked aspect, trim the rect about its centre until its pixel ratio
+// matches, so exports come out at exactly the chosen ratio.
+fn locked_rect(crop: &Crop, w: u32, h: u32) -> (f32, f32, f32, f32) {
+    let (mut x, mut y, mut width, mut height) = (crop.x, crop.y, crop.width, crop.height);
+    if let Some(aspect) = crop
+        .aspect
+        .filter(|a| crop.aspect_locked && a.is_finite() && *a > 0.0)
+    {
+        let current = (width * w as f32) / (height * h as f32).max(1e-6);
+        if current > aspect {
+            let trimmed = width * aspect / current;
+            x += (width - trimmed) / 2.0;
+            width = trimmed;
+        } else if current < aspect {
+            let trimmed = height * current / aspect;
+            y += (height - trimmed) / 2.0;
+            height = trimmed;
+        }
+    }
+    (x, y, width, height)
+}
+
+// The crop's straighten angle, None when level enough to skip the resample.
+fn straighten_angle(crop: &Crop) -> Option<f32> {
+    (crop.angle.is_finite() && crop.angle.abs() >= 0.01).then(|| crop.angle.clamp(-45.0, 45.0))
+}
+
+// Whether every corner of the normalized `rect` samples inside a w x h frame
+// rotated by sin/cos about its centre without zoom.
+fn rect_inside(rect: (f32, f32, f32, f32), w: f32, h: f32, sin: f32, cos: f32) -> bool {
+    let (x, y, width, height) = rect;
+    [
+        (x, y),
+        (x + width, y),
+        (x, y + height),
+        (x + width, y + height),
+    ]
+    .iter()
+    .all(|&(px, py)| {
+        let (dx, dy) = ((px - 0.5) * w, (py - 0.5) * h);
+        let sx = dx * cos + dy * sin;
+        let sy = -dx * sin + dy * cos;
+        sx.abs() <= w * 0.5001 && sy.abs() <= h * 0.5001
+    })
+}
+
+// Largest centred rect of `aspect` (pixel width / height) inside a w x h frame
+// rotated by sin/cos without zoom, normalized to the frame.
+fn largest_rotated_rect(w: f32, h: f32, sin: f32, cos: f32, aspect: f32) -> (f32, f32, f32, f32) {
+    let (sin, cos) = (sin.abs(), cos.abs());
+    let half_h = (w / 2.0 / (aspect * cos + sin)).min(h / 2.0 / (aspect * sin + cos));
+    let (width, height) = (2.0 * aspect * half_h / w, 2.0 * half_h / h);
+    ((1.0 - width) / 2.0, (1.0 - height) / 2.0, width, height)
+}
+
+/// The crop's rect in a w x h frame straightened without zoom, pulled toward the
+/// largest centred rect of its aspect just far enough that none of the corners the
+/// straighten rotation empties shows. A rect that already fits is kept as drawn.
+/// Only the straighten angle is accounted for: lens distortion scales itself to
+/// cover the frame and document deskew fills its corners with paper white.
+pub fn rotation_constrained_rect(crop: &Crop, w: u32, h: u32) -> (f32, f32, f32, f32) {
+    let rect = locked_rect(crop, w, h);
+    let (sin, cos) = straighten_angle(crop).unwrap_or(0.0).to_radians().sin_cos();
+    let (w, h) = (w as f32, h as f32);
+    if rect_inside(rect, w, h, sin, cos) {
+        return rect;
+    }
+    let aspect = (rect.2 * w).max(1e-6) / (rect.3 * h).max(1e-6);
+    let target = largest_rotated_rect(w, h, sin, cos, aspect);
+    // corners move linearly and the frame is convex, so the rects that fit are
+    // the ones past some point of the way
+    let toward = |t: f32| {
+        (
+            rect.0 + (target.0 - rect.0) * t,
+            rect.1 + (target.1 - rect.1) * t,
+            rect.2 + (target.2 - rect.2) * t,
+            rect.3 + (target.3 - rect.3) * t,
+        )
+    };
+    let (mut lo, mut hi) = (0.0f32, 1.0f32);
+    for _ in 0..CONSTRAIN_STEPS {
+        let mid = 0.5 * (lo + hi);
+        if rect_inside(toward(mid), w, h, sin, cos) {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    toward(hi)
+}
+
+/// Mirror `img` as the crop asks; everything else in the crop works on the
+/// mirrored frame.
+pub fn apply_flips(mut img: RgbaImage, crop: &Crop) -> RgbaImage {
+    if crop.flip_horizontal {
+        imageops::flip_horizontal_in_place(&mut img);
+    }
+    if crop.flip_vertical {
+        imageops::flip_vertical_in_place(&mut img);
+    }
+    img
+}
+
+/// Mirror, straighten by the crop angle, then cut the crop out of `img`; a
+/// full-frame, level, unmirrored crop returns the image untouched. Constrained
+/// crops straighten without zoom and cut `rotation_constrained_rect`.
+pub fn apply_crop(img: RgbaImage, crop: &Crop) -> RgbaImage {
+    let img = apply_flips(img, crop);
+    let img = match straighten_angle(crop) {
+        Some(angle) if crop.constrain => rotate(&img, angle, 1.0),
+        Some(angle) => straighten(&img, angle),
+        None => img,
+    };
+    let (w, h) = img.dimensions();
+    let (x, y, width, height) = if crop.constrain {
+        rotation_constrained_rect(crop, w, h)
+    } else {
+        locked_rect(crop, w, h)
+    };
+    let x0 = x.clamp(0.0, 1.0);
+    let y0 = y.clamp(0.0, 1.0);
+    let x1 = (x + width).clamp(x0, 1.0);
+    let y1 = (y + height).clamp(y0, 1.0);
+    let left = ((x0 * w as f32).round() as u32).min(w.saturating_sub(1));
+    let top = ((y0 * h as f32).round() as u32).min(h.saturating_sub(1));
+    let right = ((x1 * w as f32).round() as u32).clamp(left + 1, w.max(1));
+    let bottom = ((y1 * h as f32).round() as u32).clamp(top + 1, h.max(1));
+    if left == 0 && top == 0 && right == w && bottom == h {
+        return img;
+    }
+    imageops::crop_imm(&img, left, top, right - left, bottom - top).to_image()
+}
+
+fn crop_asset(path: &Path, aspect: f32, gravity: CropGravity) -> Result<(), String> {
+    let source = source_aspect(path)?;
+    update_recipe_for_asset(path, |recipe| {
+        recipe.crop = Some(aspect_crop(source, aspect, gravity));
+        Ok(())
+    })
+    .map(|_| ())
+}
+
+/// Write a crop of the same aspect into every asset's recipe, replacing any
+/// existing crop. Locked assets are left alone and reported.
+pub fn crop_assets(
+    paths: &[PathBuf],
+    aspect: f32,
+    gravity: CropGravity,
+) -> Result<BatchEditSummary, String> {
+    if !aspect.is_finite() || aspect <= 0.0 {
+        return Err("Crop aspect must be a positive ratio".into());
+    }
+    let mut summary = BatchEditSummary::default();
+    for path in paths {
+        if is_locked(path) {
+            summary.locked.push(path.to_string_lossy().to_string());
+            continue;
+        }
+        crop_asset(path, aspect, gravity)
+            .map_err(|e| format!("Crop {} failed: {e}", path.display()))?;
+        summary.applied += 1;
+    }
+    Ok(summary)
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+use crate::catalog::{catalog_key, catalog_snapshot, load_catalog, update_catalog};
+use crate::models::{CullMark, CullSession, CullSummary};
+use crate::state::path_for;
+
+// A mark as committed (from the catalog) and as it stands in the session.
+struct Pending {
+    path: PathBuf,
+    committed: Option<CullMark>,
+    current: Option<CullMark>,
+}
+
+// Open sessions by id. Marks live here until commit, so a keystroke costs a map
+// write instead of a catalog rewrite.
+static SESSIONS: Lazy<DashMap<String, HashMap<String, Pending>>> = Lazy::new(DashMap::new);
+
+/// Open a session over `assets`, seeded with their committed marks.
+pub fn start_session(assets: &[(String, PathBuf)]) -> Result<CullSession, String> {
+    let catalog = load_catalog()?;
+    let mut pending = HashMap::with_capacity(assets.len());
+    let mut marks = HashMap::new();
+    for (id, path) in assets {
+        let committed = catalog
+            .assets
+            .get(&catalog_key(path))
+            .and_then(|entry| entry.cull);
+        if let Some(mark) = committed {
+            marks.insert(id.clone(), mark);
+        }
+        pending.insert(
+            id.clone(),
+            Pending {
+                path: path.clone(),
+                committed,
+                current: committed,
+            },
+        );
+    }
+    let id = Uuid::new_v4().to_string();
+    SESSIONS.insert(id.clone(), pending);
+    Ok(CullSession { id, marks })
+}
+
+/// Set (or with None, clear) an asset's mark in memory only. Assets outside the
+/// session's starting set may be marked too.
+pub fn mark(session_id: &str, asset_id: &str, mark: Option<CullMark>) -> Result<(), String> {
+    if let Some(pending) = SESSIONS
+        .get_mut(session_id)
+        .ok_or("Culling session not found")?
+        .get_mut(asset_id)
+    {
+        pending.current = mark;
+        return Ok(());
+    }
+    // the catalog is read with no session locked, so other marks are not held up
+    let path = path_for(asset_id).ok_or("Asset not found")?;
+    let committed = catalog_snapshot()?
+        .assets
+        .get(&catalog_key(&path))
+        .and_then(|entry| entry.cull);
+    SESSIONS
+        .get_mut(session_id)
+        .ok_or("Culling session not found")?
+        .entry(asset_id.to_string())
+        .or_insert(Pending {
+            path,
+            committed,
+            current: None,
+        })
+        .current = mark;
+    Ok(())
+}
+
+/// Write the changed marks to the catalog in one update and close the session.
+/// The session stays open when the write fails, so the marks can be committed
+/// again.
+pub fn commit_session(session_id: &str) -> Result<CullSummary, String> {
+    let changed: Vec<(PathBuf, Option<CullMark>)> = SESSIONS
+        .get(session_id)
+        .ok_or("Culling session not found")?
+        .values()
+        .filter(|p| p.current != p.committed)
+        .map(|p| (p.path.clone(), p.current))
+        .collect();
+    let changed: Vec<(String, Option<CullMark>)> = changed
+        .into_iter()
+        .map(|(path, mark)| (catalog_key(&path), mark))
+        .collect();
+    let mut summary = CullSummary::default();
+    for (_, mark) in &changed {
+        match mark {
+            Some(CullMark::Keep) => summary.kept += 1,
+            Some(CullMark::Toss) => summary.tossed += 1,
+            None => summary.cleared += 1,
+        }
+    }
+    if !changed.is_empty() {
+        update_catalog(|catalog| {
+            for (key, mark) in changed {
+                catalog.assets.entry(key).or_default().cull = mark;
+            }
+        })?;
+    }
+    SESSIONS.remove(session_id);
+    Ok(summary)
+}
+
+/// Drop a session without writing anything.
+pub fn discard_session(session_id: &str) {
+    SESSIONS.remove(session_id);
+}
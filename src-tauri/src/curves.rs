@@ -0,0 +1,178 @@
+use rayon::prelude::*;
+
+use crate::gpu;
+use crate::image_io::Working;
+use crate::models::{Levels, LevelsChannel, ToneCurves};
+
+/// Entries per channel in the baked curve tables.
+pub const CURVE_LUT_SIZE: usize = 1024;
+// bounds for the frontend's curve widget samples
+const MIN_EVALUATE_SAMPLES: usize = 2;
+const MAX_EVALUATE_SAMPLES: usize = 4096;
+
+/// Sample a curve through `points` (x, y in 0..1) at `samples` evenly spaced
+/// inputs. Monotone cubic (Fritsch-Carlson) between points, flat beyond the ends;
+/// fewer than two points is the identity.
+pub fn sample_curve(points: &[(f32, f32)], samples: usize) -> Vec<f32> {
+    let step = 1.0 / samples.saturating_sub(1).max(1) as f32;
+    let mut pts: Vec<(f32, f32)> = points
+        .iter()
+        .filter(|(x, y)| x.is_finite() && y.is_finite())
+        .map(|&(x, y)| (x.clamp(0.0, 1.0), y.clamp(0.0, 1.0)))
+        .collect();
+    pts.sort_by(|a, b| a.0.total_cmp(&b.0));
+    pts.dedup_by(|b, a| (b.0 - a.0).abs() < 1e-6);
+    if pts.len() < 2 {
+        return (0..samples).map(|i| i as f32 * step).collect();
+    }
+
+    let n = pts.len();
+    let secants: Vec<f32> = pts
+        .windows(2)
+        .map(|w| (w[1].1 - w[0].1) / (w[1].0 - w[0].0))
+        .collect();
+    let mut tangents = vec![0f32; n];
+    tangents[0] = secants[0];
+    tangents[n - 1] = secants[n - 2];
+    for i in 1..n - 1 {
+        tangents[i] = if secants[i - 1] * secants[i] <= 0.0 {
+            0.0
+        } else {
+            (secants[i - 1] + secants[i]) * 0.5
+        };
+    }
+    // limit the tangents so each segment stays monotone
+    for i in 0..n - 1 {
+        if secants[i] == 0.0 {
+            tangents[i] = 0.0;
+            tangents[i + 1] = 0.0;
+            continue;
+        }
+        let a = tangents[i] / secants[i];
+        let b = tangents[i + 1] / secants[i];
+        let len = a.hypot(b);
+        if len > 3.0 {
+            tangents[i] = 3.0 / len * a * secants[i];
+            tangents[i + 1] = 3.0 / len * b * secants[i];
+        }
+    }
+
+    (0..samples)
+        .map(|i| {
+            let x = i as f32 * step;
+            if x <= pts[0].0 {
+                return pts[0].1;
+            }
+            if x >= pts[n - 1].0 {
+                return pts[n - 1].1;
+            }
+            let k = pts.partition_point(|p| p.0 <= x) - 1;
+            let (x0, y0) = pts[k];
+            let (x1, y1) = pts[k + 1];
+            let h = x1 - x0;
+            let t = (x - x0) / h;
+            let t2 = t * t;
+            let t3 = t2 * t;
+            let y = (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                + (t3 - 2.0 * t2 + t) * h * tangents[k]
+                + (-2.0 * t3 + 3.0 * t2) * y1
+                + (t3 - t2) * h * tangents[k + 1];
+            y.clamp(0.0, 1.0)
+        })
+        .collect()
+}
+
+/// The curve as the renderer sees it, sampled at `samples` evenly spaced inputs,
+/// so the curve widget draws exactly what gets applied.
+pub fn evaluate_curve(points: &[(f32, f32)], samples: usize) -> Vec<f32> {
+    sample_curve(
+        points,
+        samples.clamp(MIN_EVALUATE_SAMPLES, MAX_EVALUATE_SAMPLES),
+    )
+}
+
+fn is_identity(points: &[(f32, f32)]) -> bool {
+    points.len() < 2 || points.iter().all(|(x, y)| (x - y).abs() < 1e-4)
+}
+
+pub fn curves_are_identity(curves: &ToneCurves) -> bool {
+    is_identity(&curves.master)
+        && is_identity(&curves.red)
+        && is_identity(&curves.green)
+        && is_identity(&curves.blue)
+}
+
+// linear interpolation into a baked table, input in 0..1
+fn lookup(table: &[f32], v: f32) -> f32 {
+    let pos = v.clamp(0.0, 1.0) * (table.len() - 1) as f32;
+    let i = (pos.floor() as usize).min(table.len() - 2);
+    let f = pos - i as f32;
+    table[i] + (table[i + 1] - table[i]) * f
+}
+
+/// Per-channel tables with the master curve folded in (channel curve applied
+/// after the master).
+pub fn bake_curves(curves: &ToneCurves) -> [Vec<f32>; 3] {
+    let master = sample_curve(&curves.master, CURVE_LUT_SIZE);
+    [&curves.red, &curves.green, &curves.blue].map(|points| {
+        let channel = sample_curve(points, CURVE_LUT_SIZE);
+        master.iter().map(|&m| lookup(&channel, m)).collect()
+    })
+}
+
+fn levels_channel_is_identity(channel: &LevelsChannel) -> bool {
+    *channel == LevelsChannel::default()
+}
+
+pub fn levels_are_identity(levels: &Levels) -> bool {
+    [&levels.master, &levels.red, &levels.green, &levels.blue]
+        .into_iter()
+        .all(levels_channel_is_identity)
+}
+
+fn apply_levels_channel(channel: &LevelsChannel, v: f32) -> f32 {
+    let span = (channel.input_white - channel.input_black).max(1e-4);
+    let t = ((v - channel.input_black) / span).clamp(0.0, 1.0);
+    let t = t.powf(1.0 / channel.gamma.max(0.01));
+    (channel.output_black + t * (channel.output_white - channel.output_black)).clamp(0.0, 1.0)
+}
+
+/// Per-channel tables for the levels, master folded in like `bake_curves`.
+pub fn bake_levels(levels: &Levels) -> [Vec<f32>; 3] {
+    [&levels.red, &levels.green, &levels.blue].map(|channel| {
+        (0..CURVE_LUT_SIZE)
+            .map(|i| {
+                let v = i as f32 / (CURVE_LUT_SIZE - 1) as f32;
+                apply_levels_channel(channel, apply_levels_channel(&levels.master, v))
+            })
+            .collect()
+    })
+}
+
+// Shared by curves and levels: per-channel tables over encoded values.
+fn apply_tables(img: &mut Working, tables: &[Vec<f32>; 3]) {
+    if let Some(out) = img
+        .upload()
+        .and_then(|bytes| gpu::apply_curves_rgba(&bytes, tables))
+    {
+        *img = Working::Bytes(out);
+        return;
+    }
+    img.float().as_mut().par_chunks_mut(4).for_each(|px| {
+        for c in 0..3 {
+            px[c] = lookup(&tables[c], px[c]);
+        }
+    });
+}
+
+/// Apply the levels to display-referred (sRGB-encoded) pixels, through the same
+/// table path as the curves.
+pub fn apply_levels(img: &mut Working, levels: &Levels) {
+    apply_tables(img, &bake_levels(levels))
+}
+
+/// Apply the curves to display-referred (sRGB-encoded) pixels, on the GPU when
+/// possible.
+pub fn apply_curves(img: &mut Working, curves: &ToneCurves) {
+    apply_tables(img, &bake_curves(curves))
+}
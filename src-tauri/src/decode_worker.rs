@@ -0,0 +1,86 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::image_io::decode_raw_fallbacks;
+
+// The backend re-runs its own executable with this flag to decode one file.
+const WORKER_FLAG: &str = "--decode-worker";
+// stdout frame: magic, width (u32 LE), height (u32 LE), then RGBA8 pixels
+const FRAME_MAGIC: &[u8; 4] = b"ORDW";
+const HEADER_LEN: usize = 12;
+
+fn decode_in_worker(path: &Path) -> Result<RgbaImage, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read image bytes: {e}"))?;
+    let primary = image::load_from_memory(&bytes)
+        .err()
+        .map(|e| e.to_string())
+        .unwrap_or_default();
+    Ok(decode_raw_fallbacks(path, bytes, &primary, false)?.to_rgba8())
+}
+
+/// Worker entry point: returns the exit code when the process was started as a
+/// decode worker, None for a normal app launch.
+pub fn run_from_args() -> Option<i32> {
+    let mut args = std::env::args_os().skip(1);
+    if args.next()? != WORKER_FLAG {
+        return None;
+    }
+    let Some(path) = args.next().map(PathBuf::from) else {
+        eprintln!("Missing path for {WORKER_FLAG}");
+        return Some(2);
+    };
+    let img = match decode_in_worker(&path) {
+        Ok(img) => img,
+        Err(err) => {
+            eprintln!("{err}");
+            return Some(1);
+        }
+    };
+    let mut out = io::stdout().lock();
+    let written = out
+        .write_all(FRAME_MAGIC)
+        .and_then(|_| out.write_all(&img.width().to_le_bytes()))
+        .and_then(|_| out.write_all(&img.height().to_le_bytes()))
+        .and_then(|_| out.write_all(img.as_raw()))
+        .and_then(|_| out.flush());
+    Some(if written.is_ok() { 0 } else { 1 })
+}
+
+/// Decode `path` with the native RAW decoders in a child process. A decoder
+/// crash surfaces as an error here instead of aborting the app.
+pub fn decode_isolated(path: &Path) -> Result<DynamicImage, String> {
+    let exe = std::env::current_exe().map_err(|e| format!("Locate decode worker failed: {e}"))?;
+    let mut command = Command::new(exe);
+    command.arg(WORKER_FLAG).arg(path).stdin(Stdio::null());
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    let output = command
+        .output()
+        .map_err(|e| format!("Start decode worker failed: {e}"))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match output.status.code() {
+            Some(_) => format!("Decode worker failed: {}", stderr.trim()),
+            None => format!("Decode worker crashed ({})", output.status),
+        });
+    }
+
+    let frame = output.stdout;
+    if frame.len() < HEADER_LEN || &frame[..4] != FRAME_MAGIC {
+        return Err("Decode worker returned an invalid frame".into());
+    }
+    let width = u32::from_le_bytes([frame[4], frame[5], frame[6], frame[7]]);
+    let height = u32::from_le_bytes([frame[8], frame[9], frame[10], frame[11]]);
+    let pixels = frame[HEADER_LEN..].to_vec();
+    RgbaImage::from_raw(width, height, pixels)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| "Decode worker returned a truncated frame".into())
+}
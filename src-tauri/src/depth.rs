@@ -0,0 +1,278 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+use image::{DynamicImage, GrayImage, RgbaImage};
+use once_cell::sync::Lazy;
+
+use crate::cache::depth_map_path;
+use crate::crop::apply_crop;
+use crate::image_io::apply_exif_orientation;
+use crate::lens::{correct_lens, resolve_profile};
+use crate::recipe_io::load_recipe_for_asset;
+
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const MPF_HEADER: &[u8] = b"MPF\0";
+const MP_ENTRY_TAG: u16 = 0xB002;
+const MP_ENTRY_LEN: usize = 16;
+
+// Files already searched without finding a depth map, so renders do not re-read them.
+static NO_DEPTH: Lazy<DashMap<PathBuf, SystemTime>> = Lazy::new(DashMap::new);
+
+// (marker, offset of the payload in `bytes`, payload) of each segment before the
+// scan data.
+fn jpeg_segments(bytes: &[u8]) -> Vec<(u8, usize, &[u8])> {
+    let mut segments = Vec::new();
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return segments;
+    }
+    let mut pos = 2;
+    while pos + 4 <= bytes.len() && bytes[pos] == 0xFF {
+        let marker = bytes[pos + 1];
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = u16::from_be_bytes([bytes[pos + 2], bytes[pos + 3]]) as usize;
+        let (start, end) = (pos + 4, pos + 2 + length);
+        if length < 2 || end > bytes.len() {
+            break;
+        }
+        segments.push((marker, start, &bytes[start..end]));
+        pos = end;
+    }
+    segments
+}
+
+fn jpeg_xmp(bytes: &[u8]) -> Option<String> {
+    jpeg_segments(bytes)
+        .into_iter()
+        .filter(|(marker, _, _)| *marker == 0xE1)
+        .find_map(|(_, _, data)| data.strip_prefix(JPEG_XMP_HEADER))
+        .map(|packet| String::from_utf8_lossy(packet).to_string())
+}
+
+// Byte ranges of the images after the first in a Multi-Picture Format index.
+// Offsets count from the MPF TIFF header.
+fn mpf_images(bytes: &[u8]) -> Vec<(usize, usize)> {
+    let Some((base, tiff)) = jpeg_segments(bytes)
+        .into_iter()
+        .filter(|(marker, _, _)| *marker == 0xE2)
+        .find_map(|(_, start, data)| {
+            data.strip_prefix(MPF_HEADER)
+                .map(|tiff| (start + MPF_HEADER.len(), tiff))
+        })
+    else {
+        return Vec::new();
+    };
+    let little = tiff.starts_with(b"II");
+    let u16_at = |at: usize| {
+        let b: [u8; 2] = tiff.get(at..at + 2)?.try_into().ok()?;
+        Some(if little {
+            u16::from_le_bytes(b)
+        } else {
+            u16::from_be_bytes(b)
+        })
+    };
+    let u32_at = |at: usize| {
+        let b: [u8; 4] = tiff.get(at..at + 4)?.try_into().ok()?;
+        Some(if little {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        } as usize)
+    };
+    let entries = (|| {
+        let ifd = u32_at(4)?;
+        let count = u16_at(ifd)? as usize;
+        let tag = (0..count).find(|i| u16_at(ifd + 2 + i * 12) == Some(MP_ENTRY_TAG))?;
+        let field = ifd + 2 + tag * 12;
+        Some((u32_at(field + 4)? / MP_ENTRY_LEN, u32_at(field + 8)?))
+    })();
+    let Some((images, at)) = entries else {
+        return Vec::new();
+    };
+    (1..images)
+        .filter_map(|i| {
+            let entry = at + i * MP_ENTRY_LEN;
+            let (size, offset) = (u32_at(entry + 4)?, u32_at(entry + 8)?);
+            let start = base + offset;
+            (offset > 0 && start + size <= bytes.len()).then_some((start, start + size))
+        })
+        .collect()
+}
+
+// An attribute of the XML element starting at `element`.
+fn attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("{name}=\"");
+    let start = element.find(&key)? + key.len();
+    let end = element[start..].find('"')? + start;
+    Some(&element[start..end])
+}
+
+// Dynamic Depth (Android portrait shots): the primary's XMP lists the items
+// appended after the primary image, in order, with their lengths.
+fn container_depth(bytes: &[u8], xmp: &str) -> Option<GrayImage> {
+    let items: Vec<(&str, usize)> = xmp
+        .split("<Container:Item")
+        .skip(1)
+        .map(|element| {
+            let element = &element[..element.find('>').unwrap_or(element.len())];
+            let semantic = attribute(element, "Item:Semantic").unwrap_or_default();
+            let length = attribute(element, "Item:Length")
+                .and_then(|l| l.parse().ok())
+                .unwrap_or(0);
+            (semantic, length)
+        })
+        .collect();
+    let depth = items
+        .iter()
+        .position(|(semantic, _)| *semantic == "Depth")?;
+    // the first item is the primary image itself; the rest are packed at the end
+    let trailing: usize = items[depth..].iter().map(|(_, length)| length).sum();
+    let start = bytes.len().checked_sub(trailing)?;
+    let end = start + items[depth].1;
+    let mut map = image::load_from_memory(bytes.get(start..end)?)
+        .ok()?
+        .to_luma8();
+    // stored as normalized range, nearest darkest
+    image::imageops::invert(&mut map);
+    Some(map)
+}
+
+// Portrait JPEGs from iPhones carry the disparity map as an extra MPF image
+// tagged in its own XMP; nearer is brighter already.
+fn jpeg_depth(path: &Path) -> Result<Option<GrayImage>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Read depth map failed: {e}"))?;
+    let map = jpeg_xmp(&bytes)
+        .and_then(|xmp| container_depth(&bytes, &xmp))
+        .or_else(|| {
+            mpf_images(&bytes).into_iter().find_map(|(start, end)| {
+                let image = &bytes[start..end];
+                let tagged = jpeg_xmp(image).is_some_and(|xmp| {
+                    let xmp = xmp.to_ascii_lowercase();
+                    xmp.contains("depth") || xmp.contains("disparity")
+                });
+                tagged
+                    .then(|| image::load_from_memory(image).ok())
+                    .flatten()
+                    .map(|map| map.to_luma8())
+            })
+        });
+    // secondary images are stored like the primary, before EXIF rotation
+    Ok(map.map(|map| apply_exif_orientation(DynamicImage::ImageLuma8(map), path).to_luma8()))
+}
+
+// libheif decodes the depth auxiliary image with the item's own transforms.
+#[cfg(feature = "heic")]
+fn heic_depth(path: &Path) -> Result<Option<GrayImage>, String> {
+    use image::Luma;
+    use libheif_rs::{ColorSpace, HeifContext, LibHeif};
+
+    let heif_err = |e: libheif_rs::HeifError| format!("Read depth map failed: {e}");
+    let ctx = HeifContext::read_from_file(&path.to_string_lossy()).map_err(heif_err)?;
+    let primary = ctx.primary_image_handle().map_err(heif_err)?;
+    if !primary.has_depth_image() {
+        return Ok(None);
+    }
+    let mut ids = vec![0; primary.number_of_depth_images().max(0) as usize];
+    let found = primary.depth_image_ids(&mut ids);
+    let Some(&id) = ids[..found].first() else {
+        return Ok(None);
+    };
+    let handle = primary.depth_image_handle(id).map_err(heif_err)?;
+    let image = LibHeif::new()
+        .decode(&handle, ColorSpace::Monochrome, None)
+        .map_err(heif_err)?;
+    let Some(plane) = image.planes().y else {
+        return Ok(None);
+    };
+    // deeper planes are scaled down to 8 bits, little-endian samples
+    let shift = plane.bits_per_pixel.saturating_sub(8);
+    let wide = plane.storage_bits_per_pixel > 8;
+    Ok(Some(GrayImage::from_fn(
+        plane.width,
+        plane.height,
+        |x, y| {
+            let at = y as usize * plane.stride;
+            let v = if wide {
+                let i = at + x as usize * 2;
+                u16::from_le_bytes([plane.data[i], plane.data[i + 1]])
+            } else {
+                plane.data[at + x as usize] as u16
+            };
+            Luma([(v >> shift).min(255) as u8])
+        },
+    )))
+}
+
+#[cfg(not(feature = "heic"))]
+fn heic_depth(_path: &Path) -> Result<Option<GrayImage>, String> {
+    Ok(None)
+}
+
+/// The depth map embedded in a portrait-mode JPEG or HEIC, upright, with nearer
+/// subjects brighter. None for files without one.
+pub fn extract_depth_map(path: &Path) -> Result<Option<GrayImage>, String> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "jpg" | "jpeg" => jpeg_depth(path),
+        "heic" | "heif" => heic_depth(path),
+        _ => Ok(None),
+    }
+}
+
+// Stretch the map over the full 0..255 range, so thresholds mean the same from
+// one file to the next whatever units the camera stored.
+fn normalize(map: &mut GrayImage) {
+    let (low, high) = map.pixels().fold((u8::MAX, u8::MIN), |(lo, hi), px| {
+        (lo.min(px[0]), hi.max(px[0]))
+    });
+    if high <= low {
+        return;
+    }
+    let span = (high - low) as f32;
+    for px in map.pixels_mut() {
+        px[0] = ((px[0] - low) as f32 / span * 255.0).round() as u8;
+    }
+}
+
+/// The file's depth map as a PNG framed by its saved lens correction and crop,
+/// so it lines up with layer masks like a brush bitmap. Cached per framing;
+/// None for files without a depth map.
+pub fn framed_depth_map(path: &Path) -> Result<Option<PathBuf>, String> {
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_err(|e| format!("Read source metadata failed: {e}"))?;
+    if NO_DEPTH.get(path).is_some_and(|hit| *hit == modified) {
+        return Ok(None);
+    }
+    let recipe = load_recipe_for_asset(path)?;
+    let framing = serde_json::to_string(&recipe.as_ref().map(|r| (&r.lens, &r.crop)))
+        .map_err(|e| e.to_string())?;
+    let target = depth_map_path(path, &framing)?;
+    if target.is_file() {
+        return Ok(Some(target));
+    }
+    let Some(mut map) = extract_depth_map(path)? else {
+        NO_DEPTH.insert(path.to_path_buf(), modified);
+        return Ok(None);
+    };
+    normalize(&mut map);
+    if let Some(mut recipe) = recipe {
+        resolve_profile(&mut recipe.lens, path);
+        let mut frame: RgbaImage = DynamicImage::ImageLuma8(map).to_rgba8();
+        frame = correct_lens(frame, &recipe);
+        if let Some(crop) = &recipe.crop {
+            frame = apply_crop(frame, crop);
+        }
+        map = DynamicImage::ImageRgba8(frame).to_luma8();
+    }
+    map.save(&target)
+        .map_err(|e| format!("Write depth map failed: {e}"))?;
+    Ok(Some(target))
+}
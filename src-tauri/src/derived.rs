@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+
+use crate::cache::derived_dir;
+use crate::catalog::{catalog_key, load_catalog, update_catalog};
+use crate::models::{AssetSummary, DerivedAsset, DerivedKind};
+use crate::recipe_io::sidecar_path;
+
+fn kind_suffix(kind: DerivedKind) -> &'static str {
+    match kind {
+        DerivedKind::Hdr => "hdr",
+        DerivedKind::Panorama => "pano",
+        DerivedKind::Denoised => "denoised",
+    }
+}
+
+/// Move a freshly made file into the managed derived folder and link it to the
+/// originals it came from. Returns where it now lives.
+pub fn adopt_derived(
+    file: &Path,
+    kind: DerivedKind,
+    sources: &[PathBuf],
+) -> Result<PathBuf, String> {
+    let first = sources
+        .first()
+        .ok_or("A derived file needs at least one source")?;
+    if !file.is_file() {
+        return Err("Derived file not found".into());
+    }
+    let stem = first
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "derived".to_string());
+    let extension = file
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let id = Uuid::new_v4().simple().to_string();
+    let target = derived_dir()?.join(format!(
+        "{stem}.{}-{}.{extension}",
+        kind_suffix(kind),
+        &id[..8]
+    ));
+    // a rename fails across volumes; copy then remove instead, and undo the copy
+    // if the original cannot go so the file is never in both places
+    if fs::rename(file, &target).is_err() {
+        fs::copy(file, &target).map_err(|e| format!("Store derived file failed: {e}"))?;
+        if let Err(err) = fs::remove_file(file) {
+            let _ = fs::remove_file(&target);
+            return Err(format!("Move derived file failed: {err}"));
+        }
+    }
+    let derived = DerivedAsset {
+        kind,
+        sources: sources.iter().map(|source| catalog_key(source)).collect(),
+        created_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    };
+    let key = catalog_key(&target);
+    update_catalog(|catalog| catalog.assets.entry(key).or_default().derived = Some(derived))?;
+    Ok(target)
+}
+
+/// `assets` with the derived files of any listed original inserted after it,
+/// each pointing back at that original's id. A file made from several listed
+/// originals follows the last of them.
+pub fn group_derived(assets: Vec<AssetSummary>) -> Result<Vec<AssetSummary>, String> {
+    let catalog = load_catalog()?;
+    let positions: HashMap<String, usize> = assets
+        .iter()
+        .enumerate()
+        .map(|(idx, asset)| (catalog_key(Path::new(&asset.path)), idx))
+        .collect();
+    let mut attached: Vec<Vec<AssetSummary>> = vec![Vec::new(); assets.len()];
+    let mut derived: Vec<(&String, &DerivedAsset)> = catalog
+        .assets
+        .iter()
+        .filter_map(|(key, entry)| entry.derived.as_ref().map(|derived| (key, derived)))
+        .collect();
+    derived.sort_by_key(|(key, derived)| (derived.created_at, key.as_str()));
+    for (key, entry) in derived {
+        let path = Path::new(key);
+        let Some(idx) = entry
+            .sources
+            .iter()
+            .filter_map(|source| positions.get(source).copied())
+            .max()
+        else {
+            continue;
+        };
+        if !path.is_file() {
+            continue;
+        }
+        attached[idx].push(AssetSummary {
+            id: Uuid::new_v4().to_string(),
+            file_name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            extension: path
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .unwrap_or_default()
+                .to_ascii_uppercase(),
+            path: key.clone(),
+            derived_from: Some(assets[idx].id.clone()),
+        });
+    }
+    Ok(assets
+        .into_iter()
+        .zip(attached)
+        .flat_map(|(asset, derived)| std::iter::once(asset).chain(derived))
+        .collect())
+}
+
+// Deleted, not just offline: the folder is still there but the file is not. A
+// source on an unmounted drive keeps its derived files.
+fn source_deleted(source: &str) -> bool {
+    let path = Path::new(source);
+    !path.exists() && path.parent().is_some_and(Path::is_dir)
+}
+
+/// Catalog keys of derived files whose originals all look deleted, and of
+/// entries whose derived file is gone. A renamed original looks deleted too, so
+/// nothing here is removed without the user's say.
+pub fn stale_derived() -> Result<Vec<String>, String> {
+    Ok(load_catalog()?
+        .assets
+        .iter()
+        .filter(|(key, entry)| {
+            entry.derived.as_ref().is_some_and(|derived| {
+                !Path::new(key).is_file()
+                    || derived.sources.iter().all(|source| source_deleted(source))
+            })
+        })
+        .map(|(key, _)| key.clone())
+        .collect())
+}
+
+/// Whether `key` is a file in the managed derived folder, where `adopt_derived`
+/// puts everything it records. The catalog can come from a restored backup, so a
+/// `derived` record alone is no licence to delete a file.
+pub fn managed_derived_file(key: &str) -> bool {
+    let path = Path::new(key);
+    let (Ok(dir), Ok(file)) = (
+        derived_dir().and_then(|d| d.canonicalize().map_err(|e| e.to_string())),
+        path.canonicalize(),
+    ) else {
+        return false;
+    };
+    path.is_file() && file.starts_with(dir)
+}
+
+/// Remove the `stale` derived files found by `stale_derived`, along with their
+/// sidecars and catalog entries. Records pointing outside the managed folder
+/// only lose their entry. Returns the paths removed.
+pub fn prune_derived(stale: &[String]) -> Result<Vec<String>, String> {
+    if stale.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut removed = Vec::new();
+    for key in stale.iter().filter(|key| managed_derived_file(key)) {
+        let path = Path::new(key);
+        fs::remove_file(path).map_err(|e| format!("Remove derived file failed: {e}"))?;
+        removed.push(key.clone());
+        let _ = fs::remove_file(sidecar_path(path));
+    }
+    update_catalog(|catalog| {
+        for key in stale {
+            catalog.assets.remove(key);
+        }
+    })?;
+    Ok(removed)
+}
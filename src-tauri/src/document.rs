@@ -0,0 +1,182 @@
+use image::{Rgba, Rgba32FImage};
+use rayon::prelude::*;
+
+use crate::blur::gaussian_blur_f32;
+use crate::image_io::{quantize, resize_rgba_preserve_aspect};
+use crate::models::DocumentMode;
+
+// Skew is estimated on a fixed-size copy so previews and exports agree.
+const DESKEW_ANALYSIS_DIM: u32 = 800;
+const DESKEW_MAX_DEGREES: f32 = 10.0;
+// below this the page is treated as straight
+const DESKEW_MIN_DEGREES: f32 = 0.1;
+const SHARPEN_SIGMA_FRACTION: f32 = 0.0008;
+
+/// Deskew, white-point normalization, sharpening and grayscale for scanned
+/// pages and receipts, in that order.
+pub fn apply_document_mode(img: &mut Rgba32FImage, doc: &DocumentMode) {
+    if doc.deskew {
+        let angle = estimate_skew_degrees(img);
+        if angle.abs() >= DESKEW_MIN_DEGREES {
+            *img = rotate_onto_white(img, -angle);
+        }
+    }
+    if doc.normalize_white {
+        normalize_white_point(img);
+    }
+    if doc.sharpen > 0.0 {
+        unsharp_mask(img, doc.sharpen / 100.0 * 2.0);
+    }
+    if doc.grayscale {
+        img.as_mut().par_chunks_mut(4).for_each(|px| {
+            let l = 0.2126 * px[0] + 0.7152 * px[1] + 0.0722 * px[2];
+            px[0] = l;
+            px[1] = l;
+            px[2] = l;
+        });
+    }
+}
+
+// Projection-profile search: text lines give the sharpest row histogram of ink
+// pixels when the rotation that undoes the skew is applied.
+fn estimate_skew_degrees(img: &Rgba32FImage) -> f32 {
+    let small = resize_rgba_preserve_aspect(&quantize(img, false), DESKEW_ANALYSIS_DIM);
+    let (w, h) = small.dimensions();
+    let luma: Vec<f32> = small
+        .pixels()
+        .map(|px| 0.2126 * px[0] as f32 + 0.7152 * px[1] as f32 + 0.0722 * px[2] as f32)
+        .collect();
+    let mean = luma.iter().sum::<f32>() / luma.len().max(1) as f32;
+    // ink is anything clearly darker than the average page
+    let threshold = mean * 0.6;
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let ink: Vec<(f32, f32)> = luma
+        .iter()
+        .enumerate()
+        .filter(|(_, &l)| l < threshold)
+        .map(|(i, _)| ((i as u32 % w) as f32 - cx, (i as u32 / w) as f32 - cy))
+        .collect();
+    if ink.len() < 64 {
+        return 0.0;
+    }
+
+    let rows = (w.max(h) as f32 * 1.5) as usize;
+    let score = |degrees: f32| {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let mut profile = vec![0f32; rows];
+        for &(x, y) in &ink {
+            let ry = x * sin + y * cos + rows as f32 / 2.0;
+            if let Some(bin) = profile.get_mut(ry.max(0.0) as usize) {
+                *bin += 1.0;
+            }
+        }
+        profile.iter().map(|v| v * v).sum::<f32>()
+    };
+    // coarse sweep, then refine around the best angle
+    let best_in = |from: f32, to: f32, step: f32| {
+        let steps = ((to - from) / step).round() as i32;
+        (0..=steps)
+            .into_par_iter()
+            .map(|i| from + i as f32 * step)
+            .map(|a| (a, score(a)))
+            .reduce(|| (0.0, f32::MIN), |a, b| if b.1 > a.1 { b } else { a })
+            .0
+    };
+    let coarse = best_in(-DESKEW_MAX_DEGREES, DESKEW_MAX_DEGREES, 0.5);
+    -best_in(coarse - 0.5, coarse + 0.5, 0.05)
+}
+
+// Bilinear sample of a float image at a position inside it.
+fn sample_bilinear(img: &Rgba32FImage, sx: f32, sy: f32) -> [f32; 4] {
+    let (w, h) = img.dimensions();
+    let (x0, y0) = (sx.floor() as u32, sy.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (fx, fy) = (sx - x0 as f32, sy - y0 as f32);
+    let (p00, p10) = (img.get_pixel(x0, y0), img.get_pixel(x1, y0));
+    let (p01, p11) = (img.get_pixel(x0, y1), img.get_pixel(x1, y1));
+    std::array::from_fn(|c| {
+        let top = p00[c] + (p10[c] - p00[c]) * fx;
+        let bottom = p01[c] + (p11[c] - p01[c]) * fx;
+        top + (bottom - top) * fy
+    })
+}
+
+// Bilinear rotation about the centre; uncovered corners become paper white.
+fn rotate_onto_white(img: &Rgba32FImage, degrees: f32) -> Rgba32FImage {
+    let (w, h) = img.dimensions();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let mut out = Rgba32FImage::from_pixel(w, h, Rgba([1.0; 4]));
+    out.par_chunks_mut(w as usize * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for x in 0..w as usize {
+                let dx = x as f32 + 0.5 - cx;
+                let dy = y as f32 + 0.5 - cy;
+                let sx = dx * cos + dy * sin + cx - 0.5;
+                let sy = -dx * sin + dy * cos + cy - 0.5;
+                if sx < 0.0 || sy < 0.0 || sx > (w - 1) as f32 || sy > (h - 1) as f32 {
+                    continue;
+                }
+                row[x * 4..x * 4 + 4].copy_from_slice(&sample_bilinear(img, sx, sy));
+            }
+        });
+    out
+}
+
+// Paper is the bulk of the bright pixels: map its per-channel level (90th
+// percentile) to white, which also removes a colour cast from the scanner light,
+// and the darkest ink to black.
+fn normalize_white_point(img: &mut Rgba32FImage) {
+    let mut histograms = [[0u64; 256]; 3];
+    for px in img.pixels() {
+        for c in 0..3 {
+            histograms[c][(px[c].clamp(0.0, 1.0) * 255.0).round() as usize] += 1;
+        }
+    }
+    let total = (img.width() as u64 * img.height() as u64).max(1);
+    let percentile = |hist: &[u64; 256], p: f64| {
+        let target = (total as f64 * p) as u64;
+        let mut seen = 0u64;
+        for (v, &count) in hist.iter().enumerate() {
+            seen += count;
+            if seen > target {
+                return v as f32;
+            }
+        }
+        255.0
+    };
+    let ranges: Vec<(f32, f32)> = histograms
+        .iter()
+        .map(|hist| {
+            let black = percentile(hist, 0.005);
+            let white = percentile(hist, 0.9).max(black + 16.0);
+            (black / 255.0, white / 255.0)
+        })
+        .collect();
+    img.as_mut().par_chunks_mut(4).for_each(|px| {
+        for c in 0..3 {
+            let (black, white) = ranges[c];
+            px[c] = ((px[c] - black) / (white - black)).clamp(0.0, 1.0);
+        }
+    });
+}
+
+fn unsharp_mask(img: &mut Rgba32FImage, amount: f32) {
+    let (w, h) = img.dimensions();
+    let sigma = (w.max(h) as f32 * SHARPEN_SIGMA_FRACTION).max(0.8);
+    let mut blurred: Vec<f32> = img
+        .as_raw()
+        .par_chunks(4)
+        .flat_map_iter(|px| [px[0], px[1], px[2]])
+        .collect();
+    gaussian_blur_f32(&mut blurred, w as usize, h as usize, 3, sigma);
+    img.as_mut()
+        .par_chunks_mut(4)
+        .zip(blurred.par_chunks(3))
+        .for_each(|(px, soft)| {
+            for c in 0..3 {
+                px[c] = (px[c] + (px[c] - soft[c]) * amount).clamp(0.0, 1.0);
+            }
+        });
+}
@@ -0,0 +1,841 @@
+use std::fs::{self, File};
+use std::io::{Cursor, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use image::codecs::jpeg::{JpegEncoder, PixelDensity};
+use image::codecs::png::PngEncoder;
+use image::{DynamicImage, ImageEncoder, RgbaImage};
+use once_cell::sync::Lazy;
+use tiff::encoder::{colortype, Rational, TiffEncoder};
+use tiff::tags::{ResolutionUnit, Tag};
+use uuid::Uuid;
+
+use crate::cache::data_root;
+use crate::catalog::{caption_for, link_proxies};
+use crate::color::{convert_from_srgb, icc_profile, source_icc_profile};
+use crate::crop::apply_crop;
+use crate::grain::resolve_seed;
+use crate::hooks::after_export;
+use crate::hot_pixels::repair_pixels;
+use crate::image_io::{
+    apply_recipe, apply_recipe_balanced, apply_white_balance, decode_full_resolution,
+    resize_rgba_preserve_aspect, target_size,
+};
+use crate::lens::{correct_lens, resolve_profile};
+use crate::lut::{apply_lut_rgba, cached_lut};
+use crate::mask::{copy_brush_bitmaps, resolve_brush_masks};
+use crate::metadata::{
+    apply_privacy_zone, encode_exif, export_exif_fields, insert_jpeg_iptc, iptc_caption_block,
+    read_metadata, set_caption_field, write_tiff_exif_ifds, write_tiff_exif_tags,
+};
+use crate::models::{
+    CollisionPolicy, DestinationMode, ExportFormat, ExportJob, ExportJobAsset, ExportPreset,
+    ExportResize, ExportResult, ExportSettings, MetadataPolicy, OutputColorSpace, ProxyResult,
+    ProxySettings, QuickExportTarget, ResizeMode, SlideshowSettings,
+};
+use crate::naming::{
+    needs_metadata, render_template, resolve_collision, NamingContext, DEFAULT_TEMPLATE,
+};
+use crate::noise::{record_noise, seeded_recipe};
+use crate::perf;
+use crate::recipe_io::{load_recipe_for_asset, replace_recipe_for_asset};
+use crate::retouch::apply_retouch;
+use crate::settings::{current_settings, modify_settings};
+use crate::shutdown::{begin_job, stopping, write_atomic};
+use crate::watermark::burn_review_watermark;
+
+const HISTORY_LIMIT: usize = 200;
+const SOURCE_EXPORT_SUBFOLDER: &str = "exports";
+const SCREEN_DPI: u16 = 72;
+const PRINT_DPI: u16 = 300;
+
+// serializes read-modify-write of the history file between concurrent exports
+static HISTORY_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn extension_for(format: ExportFormat) -> &'static str {
+    match format {
+        ExportFormat::Jpeg => "jpg",
+        ExportFormat::Png => "png",
+        ExportFormat::Tiff => "tif",
+        ExportFormat::Heic => "heic",
+    }
+}
+
+/// Long edge the export should be scaled to, or None to keep the source size.
+/// Exports never upscale.
+fn export_long_edge(w: u32, h: u32, resize: &ExportResize) -> Option<u32> {
+    let long = w.max(h) as f32;
+    let short = w.min(h).max(1) as f32;
+    if resize.value <= 0.0 {
+        return None;
+    }
+    let target = match resize.mode {
+        ResizeMode::None => return None,
+        ResizeMode::LongEdge => resize.value,
+        ResizeMode::ShortEdge => long * (resize.value / short),
+        ResizeMode::Megapixels => {
+            let pixels = (w as f32) * (h as f32);
+            long * ((resize.value * 1_000_000.0) / pixels.max(1.0)).sqrt()
+        }
+    };
+    let target = target.floor() as u32;
+    if target == 0 || target >= w.max(h) {
+        None
+    } else {
+        Some(target)
+    }
+}
+
+// Standard CRC-32 (IEEE) as used by PNG chunks.
+fn png_crc(bytes: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Insert a pHYs chunk right after IHDR; the PNG encoder has no density option.
+fn insert_png_dpi(png: &mut Vec<u8>, dpi: u16) {
+    // 8-byte signature + IHDR (4 length + 4 type + 13 data + 4 crc)
+    const AFTER_IHDR: usize = 8 + 25;
+    if png.len() < AFTER_IHDR {
+        return;
+    }
+    let ppm = (dpi as f64 / 0.0254).round() as u32;
+    let mut body = b"pHYs".to_vec();
+    body.extend_from_slice(&ppm.to_be_bytes());
+    body.extend_from_slice(&ppm.to_be_bytes());
+    body.push(1); // unit: metre
+    let mut chunk = 9u32.to_be_bytes().to_vec();
+    chunk.extend_from_slice(&body);
+    chunk.extend_from_slice(&png_crc(&body).to_be_bytes());
+    png.splice(AFTER_IHDR..AFTER_IHDR, chunk);
+}
+
+// TIFF tag holding the IPTC-IIM block (IPTC/NAA)
+const TIFF_IPTC_TAG: u16 = 33723;
+
+// The profile to embed: the generated one for the target space, since decodes
+// convert tagged sources to sRGB first. With colour management turned off the
+// pixels stay in the source's encoding, so an sRGB export keeps its profile.
+fn export_icc(path: &Path, space: OutputColorSpace) -> Vec<u8> {
+    (space == OutputColorSpace::Srgb && current_settings().unmanaged_color)
+        .then(|| source_icc_profile(path))
+        .flatten()
+        .unwrap_or_else(|| icc_profile(space))
+}
+
+fn encode_to_file(
+    img: &RgbaImage,
+    path: &Path,
+    settings: &ExportSettings,
+    icc: Vec<u8>,
+    exif_fields: &[exif::Field],
+    caption: Option<&str>,
+) -> Result<(), String> {
+    let _timer = perf::Timer::start("encode");
+    let (w, h) = img.dimensions();
+    let encode_err = |e: image::ImageError| format!("Failed to encode export: {e}");
+    let exif_block = encode_exif(exif_fields);
+    let iptc = caption.map(iptc_caption_block);
+    // encoded in memory first so a failed encode never leaves a file behind
+    let bytes = match settings.format {
+        ExportFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let rgb = DynamicImage::ImageRgba8(img.clone()).to_rgb8();
+            let mut jpeg = Vec::new();
+            let mut encoder =
+                JpegEncoder::new_with_quality(&mut jpeg, settings.quality.clamp(1, 100));
+            if let Some(dpi) = settings.dpi {
+                encoder.set_pixel_density(PixelDensity::dpi(dpi));
+            }
+            encoder
+                .set_icc_profile(icc)
+                .map_err(|e| format!("Failed to embed ICC profile: {e}"))?;
+            if let Some(block) = exif_block {
+                encoder
+                    .set_exif_metadata(block)
+                    .map_err(|e| format!("Failed to embed EXIF: {e}"))?;
+            }
+            encoder
+                .write_image(rgb.as_raw(), w, h, image::ExtendedColorType::Rgb8)
+                .map_err(encode_err)?;
+            if let Some(iim) = &iptc {
+                insert_jpeg_iptc(&mut jpeg, iim);
+            }
+            jpeg
+        }
+        ExportFormat::Png => {
+            let mut png = Vec::new();
+            let mut encoder = PngEncoder::new(&mut png);
+            encoder
+                .set_icc_profile(icc)
+                .map_err(|e| format!("Failed to embed ICC profile: {e}"))?;
+            if let Some(block) = exif_block {
+                encoder
+                    .set_exif_metadata(block)
+                    .map_err(|e| format!("Failed to embed EXIF: {e}"))?;
+            }
+            encoder
+                .write_image(img.as_raw(), w, h, image::ExtendedColorType::Rgba8)
+                .map_err(encode_err)?;
+            if let Some(dpi) = settings.dpi {
+                insert_png_dpi(&mut png, dpi);
+            }
+            png
+        }
+        ExportFormat::Tiff => {
+            let rgb = DynamicImage::ImageRgba8(img.clone()).to_rgb8();
+            let tiff_err = |e: tiff::TiffError| format!("Failed to encode export: {e}");
+            let mut tiff = Cursor::new(Vec::new());
+            let mut encoder = TiffEncoder::new(&mut tiff).map_err(tiff_err)?;
+            let offsets = write_tiff_exif_ifds(&mut encoder, exif_fields).map_err(tiff_err)?;
+            let mut image = encoder
+                .new_image::<colortype::RGB8>(w, h)
+                .map_err(tiff_err)?;
+            image
+                .encoder()
+                .write_tag(Tag::IccProfile, icc.as_slice())
+                .map_err(tiff_err)?;
+            write_tiff_exif_tags(image.encoder(), exif_fields, &offsets).map_err(tiff_err)?;
+            if let Some(iim) = &iptc {
+                image
+                    .encoder()
+                    .write_tag(Tag::from_u16_exhaustive(TIFF_IPTC_TAG), iim.as_slice())
+                    .map_err(tiff_err)?;
+            }
+            if let Some(dpi) = settings.dpi {
+                image.resolution(
+                    ResolutionUnit::Inch,
+                    Rational {
+                        n: dpi as u32,
+                        d: 1,
+                    },
+                );
+            }
+            image.write_data(rgb.as_raw()).map_err(tiff_err)?;
+            tiff.into_inner()
+        }
+        ExportFormat::Heic => encode_heic(img, settings.quality, icc, exif_block)?,
+    };
+    let mut file = File::create(path).map_err(|e| format!("Create export file failed: {e}"))?;
+    if let Err(e) = file.write_all(&bytes) {
+        drop(file);
+        let _ = fs::remove_file(path);
+        return Err(format!("Write export file failed: {e}"));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "heic")]
+fn heic_image(img: &RgbaImage) -> libheif_rs::Result<libheif_rs::Image> {
+    use libheif_rs::{Channel, ColorSpace, Image, RgbChroma};
+
+    let (w, h) = img.dimensions();
+    let mut image = Image::new(w, h, ColorSpace::Rgb(RgbChroma::Rgb))?;
+    image.create_plane(Channel::Interleaved, w, h, 8)?;
+    let planes = image.planes_mut();
+    let Some(plane) = planes.interleaved else {
+        return Ok(image);
+    };
+    for (y, row) in plane
+        .data
+        .chunks_mut(plane.stride)
+        .take(h as usize)
+        .enumerate()
+    {
+        let src = &img.as_raw()[y * w as usize * 4..(y + 1) * w as usize * 4];
+        for (x, px) in src.chunks_exact(4).enumerate() {
+            row[x * 3..x * 3 + 3].copy_from_slice(&px[..3]);
+        }
+    }
+    Ok(image)
+}
+
+/// HEVC-in-HEIF at 8 bits: exports render 8-bit, and a 10-bit file would only
+/// widen the same samples.
+#[cfg(feature = "heic")]
+fn encode_heic(
+    img: &RgbaImage,
+    quality: u8,
+    icc: Vec<u8>,
+    exif_block: Option<Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    use libheif_rs::{
+        color_profile_types, ColorProfileRaw, CompressionFormat, EncoderQuality, HeifContext,
+        LibHeif,
+    };
+
+    let heif_err = |e: libheif_rs::HeifError| format!("Failed to encode HEIC: {e}");
+    let lib = LibHeif::new();
+    let mut image = heic_image(img).map_err(heif_err)?;
+    image
+        .set_color_profile_raw(&ColorProfileRaw::new(color_profile_types::PROF, icc))
+        .map_err(heif_err)?;
+    let mut ctx = HeifContext::new().map_err(heif_err)?;
+    let mut encoder = lib
+        .encoder_for_format(CompressionFormat::Hevc)
+        .map_err(heif_err)?;
+    encoder
+        .set_quality(EncoderQuality::Lossy(quality.clamp(1, 100)))
+        .map_err(heif_err)?;
+    let handle = ctx
+        .encode_image(&image, &mut encoder, None)
+        .map_err(heif_err)?;
+    if let Some(block) = &exif_block {
+        ctx.add_exif_metadata(&handle, block).map_err(heif_err)?;
+    }
+    ctx.write_to_bytes().map_err(heif_err)
+}
+
+#[cfg(not(feature = "heic"))]
+fn encode_heic(
+    _img: &RgbaImage,
+    _quality: u8,
+    _icc: Vec<u8>,
+    _exif_block: Option<Vec<u8>>,
+) -> Result<Vec<u8>, String> {
+    Err("HEIC export is not available in this build (enable the `heic` feature)".into())
+}
+
+fn destination_dir(source: &Path, settings: &ExportSettings) -> Result<PathBuf, String> {
+    match settings.destination_mode {
+        DestinationMode::SourceSubfolder => Ok(source
+            .parent()
+            .ok_or("Asset has no parent folder")?
+            .join(SOURCE_EXPORT_SUBFOLDER)),
+        // Ask is resolved to a concrete folder before the job starts
+        DestinationMode::Fixed | DestinationMode::Ask => {
+            if settings.destination.trim().is_empty() {
+                Err("Export destination is required".into())
+            } else {
+                Ok(PathBuf::from(&settings.destination))
+            }
+        }
+    }
+}
+
+fn output_path_for(
+    source: &Path,
+    seq: usize,
+    settings: &ExportSettings,
+) -> Result<Option<PathBuf>, String> {
+    let metadata = if needs_metadata(&settings.filename_template) {
+        read_metadata(source).ok()
+    } else {
+        None
+    };
+    let ctx = NamingContext {
+        source,
+        seq,
+        metadata: metadata.as_ref(),
+    };
+    let stem = render_template(&settings.filename_template, &ctx);
+    let dir = destination_dir(source, settings)?;
+    fs::create_dir_all(&dir).map_err(|e| format!("Create export folder failed: {e}"))?;
+    Ok(resolve_collision(
+        &dir,
+        &stem,
+        extension_for(settings.format),
+        settings.collision,
+    ))
+}
+
+/// Render an asset at full resolution with its saved recipe, resize per the
+/// export settings and write it into the destination folder. `seq` is the
+/// 1-based position within the batch, used by the `{seq}` filename token.
+pub fn export_asset(
+    asset_id: &str,
+    path: &Path,
+    seq: usize,
+    settings: &ExportSettings,
+) -> Result<ExportResult, String> {
+    let _timer = perf::Timer::start("export");
+    let Some(out_path) = output_path_for(path, seq, settings)? else {
+        return Ok(ExportResult {
+            asset_id: asset_id.to_string(),
+            output_path: String::new(),
+            width: 0,
+            height: 0,
+            skipped: true,
+            hook_error: None,
+        });
+    };
+
+    // an untouched file goes out with the noise reduction the editor showed it with
+    let mut recipe = load_recipe_for_asset(path)?.or_else(|| seeded_recipe(path));
+    if let Some(recipe) = recipe.as_mut() {
+        resolve_seed(&mut recipe.grain, path);
+        resolve_brush_masks(recipe, path);
+        resolve_profile(&mut recipe.lens, path);
+    }
+    let mut working = decode_full_resolution(path)?;
+    let mut frame_long_edge = working.width().max(working.height()) as f32;
+    // the first original of each camera and ISO that goes out teaches its noise profile
+    record_noise(path, &working);
+    // white balance, dead pixels, spot removal, lens corrections and crop go in before the
+    // resize in the order previews use, so a dual-illuminant mask lands on the same frame
+    if let Some(recipe) = &recipe {
+        apply_white_balance(&mut working, &recipe.globals);
+        repair_pixels(&mut working, &recipe.dead_pixels);
+        apply_retouch(&mut working, &recipe.retouch);
+        working = correct_lens(working, recipe);
+    }
+    if let Some(crop) = recipe.as_ref().and_then(|r| r.crop.as_ref()) {
+        working = apply_crop(working, crop);
+    }
+    let long_edge = export_long_edge(working.width(), working.height(), &settings.resize);
+    match &recipe {
+        // the recipe's GPU stages pick up from the resize without a readback
+        Some(recipe) => {
+            let resize = long_edge.map(|long_edge| {
+                let before = working.width().max(working.height()).max(1) as f32;
+                let (w, h) = target_size(working.width(), working.height(), long_edge);
+                frame_long_edge *= w.max(h) as f32 / before;
+                (w, h)
+            });
+            working = apply_recipe_balanced(working, recipe, None, frame_long_edge, resize);
+        }
+        None => {
+            if let Some(long_edge) = long_edge {
+                working = resize_rgba_preserve_aspect(&working, long_edge);
+            }
+        }
+    }
+    let app_settings = current_settings();
+    // review copies go to clients before sign-off: marked, and carrying nothing
+    // about the shoot
+    let metadata = if settings.review {
+        burn_review_watermark(&mut working, &app_settings.review_watermark);
+        MetadataPolicy::StripAll
+    } else {
+        settings.metadata
+    };
+    convert_from_srgb(&mut working, settings.color_space, settings.dither);
+    let mut exif_fields = export_exif_fields(path, metadata);
+    apply_privacy_zone(&mut exif_fields, &app_settings.privacy_zone);
+    let caption = match metadata {
+        MetadataPolicy::StripAll => None,
+        _ => caption_for(path)?,
+    };
+    if let Some(caption) = &caption {
+        set_caption_field(&mut exif_fields, caption);
+    }
+    encode_to_file(
+        &working,
+        &out_path,
+        settings,
+        export_icc(path, settings.color_space),
+        &exif_fields,
+        caption.as_deref(),
+    )?;
+
+    Ok(ExportResult {
+        asset_id: asset_id.to_string(),
+        output_path: out_path.to_string_lossy().to_string(),
+        width: working.width(),
+        height: working.height(),
+        skipped: false,
+        hook_error: None,
+    })
+}
+
+fn history_path() -> Result<PathBuf, String> {
+    Ok(data_root()?.join("export_history.json"))
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Completed export jobs, newest first.
+pub fn load_export_history() -> Result<Vec<ExportJob>, String> {
+    let path = history_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(&path).map_err(|e| format!("Read export history failed: {e}"))?;
+    serde_json::from_str(&data).map_err(|e| format!("Parse export history failed: {e}"))
+}
+
+fn record_export_job(job: &ExportJob) -> Result<(), String> {
+    let _guard = HISTORY_LOCK.lock().map_err(|e| e.to_string())?;
+    let path = history_path()?;
+    let mut jobs: Vec<ExportJob> = match fs::read_to_string(&path) {
+        Ok(data) => match serde_json::from_str(&data) {
+            Ok(jobs) => jobs,
+            // a history that no longer parses is moved aside, not overwritten
+            Err(_) => {
+                let aside =
+                    path.with_file_name(format!("export_history.damaged-{}.json", unix_now()));
+                fs::rename(&path, &aside)
+                    .map_err(|e| format!("Move damaged export history failed: {e}"))?;
+                Vec::new()
+            }
+        },
+        Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(format!("Read export history failed: {e}")),
+    };
+    jobs.insert(0, job.clone());
+    jobs.truncate(HISTORY_LIMIT);
+    let serialized = serde_json::to_string_pretty(&jobs)
+        .map_err(|e| format!("Serialize export history failed: {e}"))?;
+    write_atomic(&path, serialized).map_err(|e| format!("Write export history failed: {e}"))
+}
+
+pub fn find_export_job(job_id: &str) -> Result<Option<ExportJob>, String> {
+    Ok(load_export_history()?
+        .into_iter()
+        .find(|job| job.id == job_id))
+}
+
+/// Export every asset with the same settings and record the outcome in the history.
+pub fn run_export_job(
+    assets: &[(String, PathBuf)],
+    settings: &ExportSettings,
+) -> Result<ExportJob, String> {
+    let _job_guard = begin_job()?;
+    let mut job = ExportJob {
+        id: Uuid::new_v4().to_string(),
+        created_at: unix_now(),
+        settings: settings.clone(),
+        assets: assets
+            .iter()
+            .map(|(id, path)| ExportJobAsset {
+                asset_id: id.clone(),
+                path: path.to_string_lossy().to_string(),
+            })
+            .collect(),
+        results: Vec::new(),
+        error: None,
+    };
+
+    for (idx, (id, path)) in assets.iter().enumerate() {
+        // finish the current file, but do not start another once the app is exiting
+        if stopping() {
+            job.error = Some("Export interrupted by shutdown".into());
+            break;
+        }
+        match export_asset(id, path, idx + 1, settings) {
+            Ok(mut result) => {
+                if !result.skipped {
+                    result.hook_error = after_export(path, &result).err();
+                }
+                job.results.push(result)
+            }
+            Err(err) => {
+                job.error = Some(err);
+                break;
+            }
+        }
+    }
+
+    record_export_job(&job)?;
+    Ok(job)
+}
+
+fn quick_export_settings(target: QuickExportTarget) -> ExportSettings {
+    // shared images leave the machine, so location is dropped for email/web
+    let (name, long_edge, quality, metadata) = match target {
+        QuickExportTarget::Email => ("email", 1600.0, 75, MetadataPolicy::StripGps),
+        QuickExportTarget::Web => ("web", 2048.0, 80, MetadataPolicy::StripGps),
+        QuickExportTarget::Full => ("full", 0.0, 95, MetadataPolicy::Copy),
+    };
+    let mode = if long_edge > 0.0 {
+        ResizeMode::LongEdge
+    } else {
+        ResizeMode::None
+    };
+    ExportSettings {
+        format: ExportFormat::Jpeg,
+        quality,
+        resize: ExportResize {
+            mode,
+            value: long_edge,
+        },
+        color_space: OutputColorSpace::Srgb,
+        destination_mode: DestinationMode::Fixed,
+        destination: std::env::temp_dir()
+            .join("openroom-share")
+            .join(name)
+            .to_string_lossy()
+            .to_string(),
+        filename_template: DEFAULT_TEMPLATE.into(),
+        // the share folder outlives a session, so an earlier export of the same
+        // name may still be there (and may be open in a mail draft)
+        collision: CollisionPolicy::Unique,
+        metadata,
+        dpi: Some(SCREEN_DPI),
+        dither: false,
+        review: false,
+    }
+}
+
+/// Built-in export presets; they ask for the destination on every export.
+pub fn builtin_presets() -> Vec<ExportPreset> {
+    let shareable = |target| ExportSettings {
+        destination_mode: DestinationMode::Ask,
+        destination: String::new(),
+        ..quick_export_settings(target)
+    };
+    vec![
+        ExportPreset {
+            id: "email".into(),
+            name: "Email 1600px sRGB q75".into(),
+            settings: shareable(QuickExportTarget::Email),
+        },
+        ExportPreset {
+            id: "web".into(),
+            name: "Web 2048px sRGB q80".into(),
+            settings: shareable(QuickExportTarget::Web),
+        },
+        ExportPreset {
+            id: "print".into(),
+            name: "Print 300dpi TIFF".into(),
+            settings: ExportSettings {
+                format: ExportFormat::Tiff,
+                dpi: Some(PRINT_DPI),
+                destination_mode: DestinationMode::Ask,
+                ..ExportSettings::default()
+            },
+        },
+    ]
+}
+
+/// Built-in presets followed by the user's saved ones.
+pub fn list_presets() -> Vec<ExportPreset> {
+    let mut presets = builtin_presets();
+    presets.extend(current_settings().export_presets);
+    presets
+}
+
+/// Create or replace a user preset by id; built-in ids are reserved.
+pub fn save_user_preset(mut preset: ExportPreset) -> Result<ExportPreset, String> {
+    if builtin_presets().iter().any(|p| p.id == preset.id) {
+        return Err("Built-in presets cannot be modified".into());
+    }
+    if preset.id.trim().is_empty() {
+        preset.id = Uuid::new_v4().to_string();
+    }
+    modify_settings(|settings| {
+        match settings
+            .export_presets
+            .iter_mut()
+            .find(|p| p.id == preset.id)
+        {
+            Some(existing) => *existing = preset.clone(),
+            None => settings.export_presets.push(preset.clone()),
+        }
+        Ok(())
+    })?;
+    Ok(preset)
+}
+
+pub fn delete_user_preset(id: &str) -> Result<(), String> {
+    modify_settings(|settings| {
+        let before = settings.export_presets.len();
+        settings.export_presets.retain(|p| p.id != id);
+        if settings.export_presets.len() == before {
+            return Err("Export preset not found".into());
+        }
+        Ok(())
+    })
+}
+
+/// Ad-hoc export into a temp share folder with a built-in target. Not recorded
+/// in the export history; returns the written file paths.
+pub fn quick_export(
+    assets: &[(String, PathBuf)],
+    target: QuickExportTarget,
+) -> Result<Vec<String>, String> {
+    let settings = quick_export_settings(target);
+    assets
+        .iter()
+        .enumerate()
+        .map(|(idx, (id, path))| export_asset(id, path, idx + 1, &settings).map(|r| r.output_path))
+        .collect()
+}
+
+// Deepest folder that contains every asset, so proxies keep the layout below it.
+fn common_root(paths: &[&Path]) -> PathBuf {
+    let mut root = paths
+        .first()
+        .and_then(|p| p.parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    for path in paths.iter().skip(1) {
+        while !path.starts_with(&root) {
+            if !root.pop() {
+                return PathBuf::new();
+            }
+        }
+    }
+    root
+}
+
+/// Write a resized JPEG proxy of each original under `settings.destination`,
+/// mirroring the folders below the selection's common root. Proxies are named
+/// after the full original file name ("IMG_1.CR3.jpg") so RAW+JPEG pairs do not
+/// collide; the current recipe is copied alongside and the pair is linked in
+/// the catalog so edits can be pushed back later.
+pub fn generate_proxies(
+    assets: &[(String, PathBuf)],
+    settings: &ProxySettings,
+) -> Result<Vec<ProxyResult>, String> {
+    let _job_guard = begin_job()?;
+    let destination = PathBuf::from(&settings.destination);
+    let paths: Vec<&Path> = assets.iter().map(|(_, p)| p.as_path()).collect();
+    let root = common_root(&paths);
+    let jpeg = ExportSettings {
+        quality: settings.quality,
+        metadata: MetadataPolicy::Copy,
+        ..ExportSettings::default()
+    };
+
+    let mut results = Vec::with_capacity(assets.len());
+    let mut links = Vec::with_capacity(assets.len());
+    let mut failure = None;
+    for (id, path) in assets {
+        if stopping() {
+            break;
+        }
+        let relative = path.strip_prefix(&root).unwrap_or(path.as_path());
+        let mut file_name = relative.file_name().unwrap_or_default().to_os_string();
+        file_name.push(".jpg");
+        let out_path = destination.join(relative).with_file_name(file_name);
+        if let Err(err) = write_proxy(path, &out_path, settings.long_edge, &jpeg) {
+            failure = Some(err);
+            break;
+        }
+        links.push((path.clone(), out_path.clone()));
+        results.push(ProxyResult {
+            asset_id: id.clone(),
+            proxy_path: out_path.to_string_lossy().to_string(),
+        });
+        if let Err(err) = copy_proxy_recipe(path, &out_path) {
+            failure = Some(err);
+            break;
+        }
+    }
+    // linked even when a later asset failed, so no proxy on disk is left orphaned
+    link_proxies(&links)?;
+    failure.map_or(Ok(results), Err)
+}
+
+// Render one proxy JPEG of `path` at `out_path`.
+fn write_proxy(
+    path: &Path,
+    out_path: &Path,
+    long_edge: u32,
+    jpeg: &ExportSettings,
+) -> Result<(), String> {
+    if let Some(parent) = out_path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("Create proxy folder failed: {e}"))?;
+    }
+    let mut working = decode_full_resolution(path)?;
+    if long_edge > 0 && working.width().max(working.height()) > long_edge {
+        working = resize_rgba_preserve_aspect(&working, long_edge);
+    }
+    let caption = caption_for(path)?;
+    let mut exif_fields = export_exif_fields(path, MetadataPolicy::Copy);
+    if let Some(caption) = &caption {
+        set_caption_field(&mut exif_fields, caption);
+    }
+    let icc = export_icc(path, jpeg.color_space);
+    encode_to_file(
+        &working,
+        out_path,
+        jpeg,
+        icc,
+        &exif_fields,
+        caption.as_deref(),
+    )
+}
+
+// Give the proxy a copy of the original's recipe and brush bitmaps.
+fn copy_proxy_recipe(path: &Path, out_path: &Path) -> Result<(), String> {
+    if let Some(mut recipe) = load_recipe_for_asset(path)? {
+        // the proxy is the copy meant for editing, and regenerating replaces it
+        recipe.flags.locked = false;
+        copy_brush_bitmaps(&recipe, path, out_path)?;
+        replace_recipe_for_asset(out_path, &recipe)?;
+    }
+    Ok(())
+}
+
+// Scale to fit inside the frame (up or down) and center on the background color.
+fn letterbox(img: &RgbaImage, settings: &SlideshowSettings) -> RgbaImage {
+    let (fw, fh) = (settings.width.max(1), settings.height.max(1));
+    let (w, h) = img.dimensions();
+    let scale = (fw as f32 / w.max(1) as f32).min(fh as f32 / h.max(1) as f32);
+    let long_edge = ((w.max(h) as f32) * scale).round() as u32;
+    let fitted = resize_rgba_preserve_aspect(img, long_edge.min(fw.max(fh)));
+    let [r, g, b] = settings.background;
+    let mut frame = RgbaImage::from_pixel(fw, fh, image::Rgba([r, g, b, 255]));
+    let x = (fw.saturating_sub(fitted.width()) / 2) as i64;
+    let y = (fh.saturating_sub(fitted.height()) / 2) as i64;
+    image::imageops::overlay(&mut frame, &fitted, x, y);
+    frame
+}
+
+/// Render assets as a numbered, letterboxed sRGB JPEG sequence for playback
+/// tools, optionally burning in a `.cube` LUT. Returns the written paths.
+pub fn export_slideshow(
+    assets: &[(String, PathBuf)],
+    settings: &SlideshowSettings,
+) -> Result<Vec<String>, String> {
+    if settings.width == 0 || settings.height == 0 {
+        return Err("Slideshow resolution must be non-zero".into());
+    }
+    let lut = match &settings.lut_path {
+        Some(path) if !path.trim().is_empty() => Some(cached_lut(Path::new(path))?),
+        _ => None,
+    };
+    let dir = PathBuf::from(&settings.destination);
+    fs::create_dir_all(&dir).map_err(|e| format!("Create export folder failed: {e}"))?;
+    let jpeg = ExportSettings {
+        quality: settings.quality,
+        metadata: MetadataPolicy::StripAll,
+        ..ExportSettings::default()
+    };
+    // wide enough that players sort the sequence lexically
+    let width = assets.len().to_string().len().max(4);
+
+    let mut written = Vec::with_capacity(assets.len());
+    for (idx, (_, path)) in assets.iter().enumerate() {
+        let mut working = decode_full_resolution(path)?;
+        if let Some(mut recipe) = load_recipe_for_asset(path)? {
+            resolve_seed(&mut recipe.grain, path);
+            resolve_brush_masks(&mut recipe, path);
+            resolve_profile(&mut recipe.lens, path);
+            working = apply_recipe(working, &recipe);
+        }
+        if let Some(lut) = &lut {
+            apply_lut_rgba(&mut working, lut);
+        }
+        let frame = letterbox(&working, settings);
+        let out_path = dir.join(format!(
+            "{}_{:0width$}.jpg",
+            settings.prefix,
+            idx + 1,
+            width = width
+        ));
+        let icc = icc_profile(jpeg.color_space);
+        encode_to_file(&frame, &out_path, &jpeg, icc, &[], None)?;
+        written.push(out_path.to_string_lossy().to_string());
+    }
+    Ok(written)
+}
@@ -1,68 +1,579 @@
+use std::collections::VecDeque;
 use std::panic::catch_unwind;
-use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use once_cell::sync::OnceCell;
 use pollster::block_on;
 use wgpu::util::DeviceExt;
 
+use tauri::{AppHandle, Emitter};
+
+use crate::models::{GpuFallback, GpuFeatureFailure, GpuStatus};
+use crate::perf;
+use crate::settings::current_settings;
+
 // GPU context is created lazily; if creation fails we simply skip GPU resizing.
 struct GpuContext {
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
-    pipeline_resize: wgpu::RenderPipeline,
-    pipeline_globals: wgpu::RenderPipeline,
+    pipeline_resize: wgpu::ComputePipeline,
+    // resize writing Rgba16Float, for chain stages; the plain one encodes a chain's output
+    pipeline_resize_float: wgpu::ComputePipeline,
+    // Everything else is None when the driver rejected its shader; that feature
+    // then runs on the CPU.
+    // cs_globals variants indexed by the stage mask they were compiled with, plus
+    // GLOBALS_FLOAT for those writing Rgba16Float
+    pipelines_globals: Vec<Option<wgpu::ComputePipeline>>,
+    pipeline_blur: Option<wgpu::ComputePipeline>,
+    // same blur writing Rgba16Float, for intermediates that are not colours
+    pipeline_blur_float: Option<wgpu::ComputePipeline>,
+    pipeline_local_contrast: Option<wgpu::ComputePipeline>,
+    pipeline_dehaze: Option<wgpu::ComputePipeline>,
+    pipeline_nr_pack: Option<wgpu::ComputePipeline>,
+    pipeline_nr_coeffs: Option<wgpu::ComputePipeline>,
+    pipeline_nr_combine: Option<wgpu::ComputePipeline>,
+    pipeline_lut: Option<wgpu::ComputePipeline>,
+    pipeline_curves: Option<wgpu::ComputePipeline>,
+    pipeline_layer: Option<wgpu::ComputePipeline>,
+    pipeline_layer_float: Option<wgpu::ComputePipeline>,
+    // features switched off because their shader or pipeline failed to build
+    disabled: Vec<GpuFeatureFailure>,
     bind_layout_resize: wgpu::BindGroupLayout,
     bind_layout_globals: wgpu::BindGroupLayout,
+    bind_layout_blur: wgpu::BindGroupLayout,
+    bind_layout_local_contrast: wgpu::BindGroupLayout,
+    bind_layout_dehaze: wgpu::BindGroupLayout,
+    bind_layout_lut: wgpu::BindGroupLayout,
+    bind_layout_curves: wgpu::BindGroupLayout,
+    bind_layout_layer: wgpu::BindGroupLayout,
+    // group 1 of every pipeline: the storage texture it writes
+    bind_layout_store_srgb: wgpu::BindGroupLayout,
+    bind_layout_store_float: wgpu::BindGroupLayout,
     max_safe_dim: u32,
     max_safe_pixels: u64,
+    adapter_info: wgpu::AdapterInfo,
+    // idle readback buffers, reused across calls instead of allocating per render
+    staging: Mutex<Vec<wgpu::Buffer>>,
+    // source uploads kept between renders, least recently used first
+    resident: Mutex<VecDeque<Resident>>,
+    // idle uploads, targets and intermediates, least recently released first
+    textures: Arc<Mutex<Vec<wgpu::Texture>>>,
+    // the last LUT uploaded, kept while the LUT cache hands out the same table
+    lut_table: Mutex<Option<(Arc<crate::lut::Lut3d>, Arc<wgpu::Texture>)>>,
+}
+
+/// Names the pixels a render starts from, so their upload can stay on the GPU
+/// between renders: the asset, and a stamp that changes whenever the pixels do.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceKey {
+    pub asset_id: String,
+    pub stamp: u64,
+}
+
+struct Resident {
+    key: SourceKey,
+    texture: Arc<wgpu::Texture>,
+    bytes: u64,
 }
 
 static GPU_CONTEXT: OnceCell<Result<Arc<GpuContext>, String>> = OnceCell::new();
+// Emitted when an op falls back to the CPU: the first time each kind of failure
+// happens, then at doubling counts so a broken GPU cannot flood the frontend.
+const GPU_FALLBACK_EVENT: &str = "gpu-fallback";
+// set at startup; fallbacks before that are only counted
+static FALLBACK_EVENTS: OnceCell<AppHandle> = OnceCell::new();
+// one entry per kind of failure, holding its latest detail
+static FALLBACKS: Mutex<Vec<GpuFallback>> = Mutex::new(Vec::new());
 const GLOBALS_UBO_SIZE: u64 = (12 * 4) as u64; // 12 f32 values in Globals = 48 bytes
+const BLUR_UBO_SIZE: u64 = (8 * 4) as u64; // 8 f32 values in BlurParams = 32 bytes
+const LOCAL_CONTRAST_UBO_SIZE: u64 = (4 * 4) as u64; // clarity, texture + padding
+const DEHAZE_UBO_SIZE: u64 = (4 * 4) as u64; // atmospheric light rgb + amount
+const LUT_UBO_SIZE: u64 = (8 * 4) as u64; // domain min + size, domain max + strength
+const LAYER_UBO_SIZE: u64 = (4 * 4) as u64; // exposure multiplier, temp, tint, saturation
+const MAX_BLUR_TAPS: f32 = 48.0; // per side, per pass
 
-fn init_gpu_context() -> Result<Arc<GpuContext>, String> {
-    // Headless instance; use all backends to maximize compatibility.
-    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
-        ..Default::default()
-    });
+// Two staging buffers let one render copy out while the next is already submitted.
+const STAGING_POOL_SIZE: usize = 2;
+// How long a readback waits for its map between non-blocking polls of the device.
+const READBACK_POLL_INTERVAL: Duration = Duration::from_micros(500);
+// Video memory held by resident source uploads; at most one per asset.
+const RESIDENT_BUDGET_BYTES: u64 = 256 * 1024 * 1024;
+// Video memory held by idle pooled textures; previews reuse the same few sizes.
+const TEXTURE_POOL_BYTES: u64 = 192 * 1024 * 1024;
+// Every shader runs one invocation per output pixel in 8x8 workgroups.
+const WORKGROUP_SIZE: u32 = 8;
 
-    // Request an adapter; prefer high-performance if available.
-    let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        compatible_surface: None,
-        force_fallback_adapter: false,
-    }))
-    .ok_or_else(|| "No suitable GPU adapter found".to_string())?;
+// Output half appended to each shader below. Storage textures cannot be sRGB, so
+// colour targets are written through a plain rgba8unorm view and encoded here.
+const STORE_SRGB: &str = r#"
+@group(1) @binding(0) var dst : texture_storage_2d<rgba8unorm, write>;
 
-    // Request the full adapter limits so we can handle large RAWs on capable GPUs (e.g. RTX 30xx).
-    let adapter_limits = adapter.limits();
-    let (device, queue) = block_on(adapter.request_device(
-        &wgpu::DeviceDescriptor {
-            label: Some("openroom-gpu-device"),
-            required_features: wgpu::Features::empty(),
-            required_limits: adapter_limits,
-        },
-        None,
-    ))
-    .map_err(|e| format!("Failed to create GPU device: {e:?}"))?;
+fn inside(coord : vec2i) -> bool {
+  return all(coord < vec2i(textureDimensions(dst)));
+}
 
-    let device: Arc<wgpu::Device> = Arc::new(device);
-    let queue: Arc<wgpu::Queue> = Arc::new(queue);
+fn store(coord : vec2i, c : vec4f) {
+  let l = clamp(c.rgb, vec3f(0.0), vec3f(1.0));
+  let e = select(1.055 * pow(l, vec3f(1.0 / 2.4)) - 0.055, l * 12.92, l <= vec3f(0.0031308));
+  textureStore(dst, coord, vec4f(e, clamp(c.a, 0.0, 1.0)));
+}
+"#;
+
+// Rgba16Float intermediates hold linear light or data and are stored as is,
+// without clamping.
+const STORE_FLOAT: &str = r#"
+@group(1) @binding(0) var dst : texture_storage_2d<rgba16float, write>;
+
+fn inside(coord : vec2i) -> bool {
+  return all(coord < vec2i(textureDimensions(dst)));
+}
+
+fn store(coord : vec2i, c : vec4f) {
+  textureStore(dst, coord, c);
+}
+"#;
+
+// Bilinear between the four source texels around each destination pixel's centre.
+const RESIZE_SHADER: &str = r#"
+@group(0) @binding(0) var tex : texture_2d<f32>;
+
+@compute @workgroup_size(8, 8)
+fn cs_resize(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let src_dims = vec2f(textureDimensions(tex));
+  let pos = (vec2f(id.xy) + 0.5) * src_dims / vec2f(textureDimensions(dst)) - 0.5;
+  let base = floor(pos);
+  let f = pos - base;
+  let last = vec2i(src_dims) - 1;
+  let p0 = clamp(vec2i(base), vec2i(0), last);
+  let p1 = clamp(vec2i(base) + 1, vec2i(0), last);
+  let top = mix(textureLoad(tex, p0, 0), textureLoad(tex, vec2i(p1.x, p0.y), 0), f.x);
+  let bottom = mix(textureLoad(tex, vec2i(p0.x, p1.y), 0), textureLoad(tex, p1, 0), f.x);
+  store(coord, mix(top, bottom, f.y));
+}
+"#;
+
+const BLUR_SHADER: &str = r#"
+@group(0) @binding(0) var tex : texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params : BlurParams;
+
+struct BlurParams {
+  direction : vec2f,
+  sigma : f32,
+  taps : f32,
+  step : f32,
+  box_width : f32,
+  _pad0 : f32,
+  _pad1 : f32,
+};
+
+@compute @workgroup_size(8, 8)
+fn cs_blur(@builtin(global_invocation_id) id : vec3u) {
+  let center = vec2i(id.xy);
+  if (!inside(center)) {
+    return;
+  }
+  let dims = vec2i(textureDimensions(tex));
+  let dir = vec2i(params.direction);
+  // box prefilter: averages away what strided taps would otherwise alias
+  let radius = i32(params.box_width) / 2;
+  if (radius > 0) {
+    var box_sum = vec4f(0.0, 0.0, 0.0, 0.0);
+    for (var i = -radius; i <= radius; i = i + 1) {
+      let coord = clamp(center + dir * i, vec2i(0, 0), dims - vec2i(1, 1));
+      box_sum = box_sum + textureLoad(tex, coord, 0);
+    }
+    store(center, box_sum / f32(2 * radius + 1));
+    return;
+  }
+  let taps = i32(params.taps);
+  let step = i32(params.step);
+  let inv = 1.0 / (2.0 * params.sigma * params.sigma);
+  var sum = vec4f(0.0, 0.0, 0.0, 0.0);
+  var weight_sum = 0.0;
+  for (var i = -taps; i <= taps; i = i + 1) {
+    let offset = i * step;
+    let coord = clamp(center + dir * offset, vec2i(0, 0), dims - vec2i(1, 1));
+    let d = f32(offset);
+    let w = exp(-d * d * inv);
+    sum = sum + textureLoad(tex, coord, 0) * w;
+    weight_sum = weight_sum + w;
+  }
+  store(center, sum / weight_sum);
+}
+"#;
+
+// Combines the source with a coarse and a fine blur of itself; mirrors
+// image_io::apply_local_contrast_in_place.
+const LOCAL_CONTRAST_SHADER: &str = r#"
+@group(0) @binding(0) var src : texture_2d<f32>;
+@group(0) @binding(1) var coarse : texture_2d<f32>;
+@group(0) @binding(2) var fine : texture_2d<f32>;
+@group(0) @binding(3) var<uniform> params : LocalContrast;
+
+struct LocalContrast {
+  clarity : f32,
+  texture : f32,
+  _pad0 : f32,
+  _pad1 : f32,
+};
+
+fn perceptual(c : vec3f) -> f32 {
+  let l = dot(c, vec3f(0.2126, 0.7152, 0.0722));
+  return pow(max(l, 0.0), 1.0 / 2.2);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_local_contrast(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let c = textureLoad(src, coord, 0);
+  let l = dot(c.rgb, vec3f(0.2126, 0.7152, 0.0722));
+  let p = pow(max(l, 0.0), 1.0 / 2.2);
+  let pc = perceptual(textureLoad(coarse, coord, 0).rgb);
+  let pf = perceptual(textureLoad(fine, coord, 0).rgb);
+  let d = 2.0 * p - 1.0;
+  let midtones = 1.0 - d * d;
+  let q = max(p + params.clarity * (p - pc) * midtones + params.texture * (p - pf), 0.0);
+  let scale = pow(q, 2.2) / max(l, 1e-5);
+  store(coord, vec4f(c.rgb * scale, c.a));
+}
+"#;
+
+// Dark-channel dehaze against a blurred copy of the source; mirrors
+// image_io::apply_dehaze_in_place. All math is in linear light.
+const DEHAZE_SHADER: &str = r#"
+@group(0) @binding(0) var src : texture_2d<f32>;
+@group(0) @binding(1) var blurred : texture_2d<f32>;
+@group(0) @binding(2) var<uniform> params : Dehaze;
+
+struct Dehaze {
+  airlight : vec3f,
+  amount : f32,
+};
+
+@compute @workgroup_size(8, 8)
+fn cs_dehaze(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let c = textureLoad(src, coord, 0);
+  let a = max(params.airlight, vec3f(1e-3));
+  let n = textureLoad(blurred, coord, 0).rgb / a;
+  let haze = clamp(min(n.r, min(n.g, n.b)), 0.0, 1.0);
+  var rgb = c.rgb;
+  if (params.amount >= 0.0) {
+    let t = max(1.0 - params.amount * 0.95 * haze, 0.1);
+    rgb = (rgb - a) / t + a;
+  } else {
+    rgb = mix(rgb, a, -params.amount * 0.6);
+  }
+  store(coord, vec4f(rgb, c.a));
+}
+"#;
+
+// 3D LUT lookup, mirroring lut::Lut3d::sample. The table is stored unfiltered
+// (Rgba32Float is not filterable everywhere) so the trilinear blend is done by
+// hand on sRGB-encoded values, like .cube files expect.
+const LUT_SHADER: &str = r#"
+@group(0) @binding(0) var src : texture_2d<f32>;
+@group(0) @binding(1) var lut : texture_3d<f32>;
+@group(0) @binding(2) var<uniform> params : LutParams;
+
+struct LutParams {
+  domain_min : vec3f,
+  size : f32,
+  domain_max : vec3f,
+  strength : f32,
+};
+
+fn encode_srgb(c : vec3f) -> vec3f {
+  let lo = c * 12.92;
+  let hi = 1.055 * pow(max(c, vec3f(0.0)), vec3f(1.0 / 2.4)) - 0.055;
+  return select(hi, lo, c <= vec3f(0.0031308));
+}
 
-    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-        label: Some("openroom-gpu-shader"),
-        source: wgpu::ShaderSource::Wgsl(
-            r#"
-@group(0) @binding(0) var samp : sampler;
-@group(0) @binding(1) var tex : texture_2d<f32>;
-@group(0) @binding(2) var<uniform> globals : Globals;
-
-struct VsOut {
-  @builtin(position) pos : vec4f,
-  @location(0) uv : vec2f,
+fn decode_srgb(c : vec3f) -> vec3f {
+  let lo = c / 12.92;
+  let hi = pow((max(c, vec3f(0.0)) + 0.055) / 1.055, vec3f(2.4));
+  return select(hi, lo, c <= vec3f(0.04045));
+}
+
+fn at(p : vec3i) -> vec3f {
+  return textureLoad(lut, p, 0).rgb;
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_lut(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let c = textureLoad(src, coord, 0);
+  let e = clamp(encode_srgb(c.rgb), vec3f(0.0), vec3f(1.0));
+  let maxi = params.size - 1.0;
+  let span = max(params.domain_max - params.domain_min, vec3f(1e-6));
+  let pos = clamp((e - params.domain_min) / span, vec3f(0.0), vec3f(1.0)) * maxi;
+  let base = min(floor(pos), vec3f(maxi - 1.0));
+  let f = pos - base;
+  let i = vec3i(base);
+  let c00 = mix(at(i), at(i + vec3i(1, 0, 0)), f.x);
+  let c10 = mix(at(i + vec3i(0, 1, 0)), at(i + vec3i(1, 1, 0)), f.x);
+  let c01 = mix(at(i + vec3i(0, 0, 1)), at(i + vec3i(1, 0, 1)), f.x);
+  let c11 = mix(at(i + vec3i(0, 1, 1)), at(i + vec3i(1, 1, 1)), f.x);
+  let looked = mix(mix(c00, c10, f.y), mix(c01, c11, f.y), f.z);
+  let out = clamp(mix(e, looked, params.strength), vec3f(0.0), vec3f(1.0));
+  store(coord, vec4f(decode_srgb(out), c.a));
+}
+"#;
+
+// Per-channel tone curves baked into a 1D table (r, g, b per entry), mirroring
+// curves::apply_curves. Looked up with manual linear interpolation on the
+// sRGB-encoded values.
+const CURVES_SHADER: &str = r#"
+@group(0) @binding(0) var src : texture_2d<f32>;
+@group(0) @binding(1) var table : texture_1d<f32>;
+
+fn encode_srgb(c : vec3f) -> vec3f {
+  let lo = c * 12.92;
+  let hi = 1.055 * pow(max(c, vec3f(0.0)), vec3f(1.0 / 2.4)) - 0.055;
+  return select(hi, lo, c <= vec3f(0.0031308));
+}
+
+fn decode_srgb(c : vec3f) -> vec3f {
+  let lo = c / 12.92;
+  let hi = pow((max(c, vec3f(0.0)) + 0.055) / 1.055, vec3f(2.4));
+  return select(hi, lo, c <= vec3f(0.04045));
+}
+
+fn lookup(v : f32, channel : i32) -> f32 {
+  let last = f32(textureDimensions(table) - 1u);
+  let pos = clamp(v, 0.0, 1.0) * last;
+  let i = min(floor(pos), last - 1.0);
+  let f = pos - i;
+  let a = textureLoad(table, i32(i), 0)[channel];
+  let b = textureLoad(table, i32(i) + 1, 0)[channel];
+  return mix(a, b, f);
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_curves(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let c = textureLoad(src, coord, 0);
+  let e = clamp(encode_srgb(c.rgb), vec3f(0.0), vec3f(1.0));
+  let out = vec3f(lookup(e.r, 0), lookup(e.g, 1), lookup(e.b, 2));
+  store(coord, vec4f(decode_srgb(out), c.a));
+}
+"#;
+
+// One local adjustment layer, mirroring image_io::apply_local_layer_in_place on
+// the sRGB-encoded values. Its coverage (opacity included) is rasterized on the
+// CPU into an r32float mask the size of the image.
+const LAYER_SHADER: &str = r#"
+@group(0) @binding(0) var src : texture_2d<f32>;
+@group(0) @binding(1) var mask : texture_2d<f32>;
+@group(0) @binding(2) var<uniform> params : LayerParams;
+
+struct LayerParams {
+  exposure_mul : f32,
+  temp : f32,
+  tint : f32,
+  saturation : f32,
+};
+
+fn encode_srgb(c : vec3f) -> vec3f {
+  let lo = c * 12.92;
+  let hi = 1.055 * pow(max(c, vec3f(0.0)), vec3f(1.0 / 2.4)) - 0.055;
+  return select(hi, lo, c <= vec3f(0.0031308));
+}
+
+fn decode_srgb(c : vec3f) -> vec3f {
+  let lo = c / 12.92;
+  let hi = pow((max(c, vec3f(0.0)) + 0.055) / 1.055, vec3f(2.4));
+  return select(hi, lo, c <= vec3f(0.04045));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_layer(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let c = textureLoad(src, coord, 0);
+  let m = textureLoad(mask, coord, 0).r;
+  let e = clamp(encode_srgb(c.rgb), vec3f(0.0), vec3f(1.0));
+  var a = e * params.exposure_mul;
+  a = a * vec3f(
+    1.0 + params.temp * 0.5 + params.tint * 0.2,
+    1.0 - params.tint * 0.2,
+    1.0 - params.temp * 0.5 + params.tint * 0.2,
+  );
+  let l = 0.2126 * a.r + 0.7152 * a.g + 0.0722 * a.b;
+  a = clamp(l + (a - l) * (1.0 + params.saturation), vec3f(0.0), vec3f(1.0));
+  store(coord, vec4f(decode_srgb(mix(e, a, m)), c.a));
+}
+"#;
+
+// Noise reduction, mirroring image_io::apply_noise_reduction_in_place. Values are
+// split into display-referred luma and two colour differences; luma goes through
+// a self-guided filter (pack -> blur -> coeffs -> blur -> combine), the colour
+// differences are simply blurred.
+const NR_PREP_SHADER: &str = r#"
+@group(0) @binding(0) var tex : texture_2d<f32>;
+@group(0) @binding(1) var<uniform> params : NrParams;
+
+struct NrParams {
+  eps : f32,
+  _pad0 : f32,
+  _pad1 : f32,
+  _pad2 : f32,
+  _pad3 : f32,
+  _pad4 : f32,
+  _pad5 : f32,
+  _pad6 : f32,
 };
 
+fn encode_srgb(l : vec3f) -> vec3f {
+  let c = clamp(l, vec3f(0.0), vec3f(1.0));
+  return select(1.055 * pow(c, vec3f(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3f(0.0031308));
+}
+
+// (luma, luma^2, r - luma, b - luma)
+@compute @workgroup_size(8, 8)
+fn cs_nr_pack(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let rgb = encode_srgb(textureLoad(tex, coord, 0).rgb);
+  let y = dot(rgb, vec3f(0.2126, 0.7152, 0.0722));
+  store(coord, vec4f(y, y * y, rgb.r - y, rgb.b - y));
+}
+
+// guided filter coefficients (a, b) from the local luma mean and mean square
+@compute @workgroup_size(8, 8)
+fn cs_nr_coeffs(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let m = textureLoad(tex, coord, 0);
+  let variance = max(m.g - m.r * m.r, 0.0);
+  let a = variance / (variance + params.eps);
+  store(coord, vec4f(a, m.r - a * m.r, 0.0, 1.0));
+}
+"#;
+
+const NR_COMBINE_SHADER: &str = r#"
+@group(0) @binding(0) var src : texture_2d<f32>;
+@group(0) @binding(1) var coeffs : texture_2d<f32>;
+@group(0) @binding(2) var chroma : texture_2d<f32>;
+@group(0) @binding(3) var<uniform> params : NrCombine;
+
+struct NrCombine {
+  luma_on : f32,
+  detail : f32,
+  chroma_on : f32,
+  _pad0 : f32,
+};
+
+fn encode_srgb(l : vec3f) -> vec3f {
+  let c = clamp(l, vec3f(0.0), vec3f(1.0));
+  return select(1.055 * pow(c, vec3f(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3f(0.0031308));
+}
+
+fn decode_srgb(v : vec3f) -> vec3f {
+  let c = clamp(v, vec3f(0.0), vec3f(1.0));
+  return select(pow((c + 0.055) / 1.055, vec3f(2.4)), c / 12.92, c <= vec3f(0.04045));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_nr_combine(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let c = textureLoad(src, coord, 0);
+  let rgb = encode_srgb(c.rgb);
+  let y = dot(rgb, vec3f(0.2126, 0.7152, 0.0722));
+  var yo = y;
+  if (params.luma_on > 0.5) {
+    let ab = textureLoad(coeffs, coord, 0).rg;
+    let q = ab.x * y + ab.y;
+    yo = q + (y - q) * params.detail;
+  }
+  var diff = vec2f(rgb.r - y, rgb.b - y);
+  if (params.chroma_on > 0.5) {
+    diff = textureLoad(chroma, coord, 0).ba;
+  }
+  let g = yo - (0.2126 * diff.x + 0.0722 * diff.y) / 0.7152;
+  let out = vec3f(yo + diff.x, g, yo + diff.y);
+  store(coord, vec4f(decode_srgb(out), c.a));
+}
+"#;
+
+// `body` reads group 0 and hands its result to the `store` that `output` defines.
+fn compute_shader(
+    device: &wgpu::Device,
+    body: &str,
+    output: &str,
+    label: &str,
+) -> wgpu::ShaderModule {
+    device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(format!("{body}{output}").into()),
+    })
+}
+
+fn create_compute_pipeline(
+    device: &wgpu::Device,
+    layouts: &[&wgpu::BindGroupLayout],
+    module: &wgpu::ShaderModule,
+    entry_point: &str,
+    label: &str,
+) -> wgpu::ComputePipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some(label),
+        bind_group_layouts: layouts,
+        push_constant_ranges: &[],
+    });
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(&pipeline_layout),
+        module,
+        entry_point,
+    })
+}
+
+// Globals stages; a variant of cs_globals is generated per combination of
+// non-identity stages so untouched sliders cost nothing per pixel.
+// White balance is not a stage: image_io applies it in linear light before resizing.
+const STAGE_EXPOSURE: u32 = 1 << 0;
+const STAGE_TONE: u32 = 1 << 1;
+const STAGE_LEVELS: u32 = 1 << 2;
+const STAGE_CONTRAST: u32 = 1 << 3;
+const STAGE_COLOR: u32 = 1 << 4;
+const STAGE_DITHER: u32 = 1 << 5;
+// not a stage: the variant writes Rgba16Float instead of encoded sRGB
+const GLOBALS_FLOAT: u32 = 1 << 6;
+
+const GLOBALS_PRELUDE: &str = r#"
+@group(0) @binding(0) var tex : texture_2d<f32>;
+@group(0) @binding(1) var<uniform> globals : Globals;
+@group(0) @binding(2) var tone_fine : texture_2d<f32>;
+@group(0) @binding(3) var tone_coarse : texture_2d<f32>;
+
 struct Globals {
   exposure_mul : f32,
   contrast : f32,
@@ -72,600 +583,2264 @@ struct Globals {
   blacks : f32,
   vibrance : f32,
   saturation : f32,
-  temp : f32,
-  tint : f32,
-  _pad0 : f32,
+  tone_range : f32,
   _pad1 : f32,
+  _pad2 : f32,
+  _pad3 : f32,
 };
 
-@vertex
-fn vs(@builtin(vertex_index) idx : u32) -> VsOut {
-  var positions = array<vec2f, 3>(
-    vec2f(-1.0, -3.0),
-    vec2f(3.0, 1.0),
-    vec2f(-1.0, 1.0)
-  );
-  var out : VsOut;
-  let pos = positions[idx];
-  out.pos = vec4f(pos, 0.0, 1.0);
-  out.uv = (pos + 1.0) * 0.5;
-  return out;
-}
-
-@fragment
-fn fs_resize(in: VsOut) -> @location(0) vec4f {
-  // clamp UV for safety and flip Y to match image origin (top-left)
-  let uv = clamp(in.uv, vec2f(0.0, 0.0), vec2f(1.0, 1.0));
-  let uv_flipped = vec2f(uv.x, 1.0 - uv.y);
-  return textureSample(tex, samp, uv_flipped);
-}
-
-@fragment
-fn fs_globals(in: VsOut) -> @location(0) vec4f {
-  let uv = clamp(in.uv, vec2f(0.0, 0.0), vec2f(1.0, 1.0));
-  let uv_flipped = vec2f(uv.x, 1.0 - uv.y);
-  var c = textureSample(tex, samp, uv_flipped);
+// 8x8 Bayer threshold centred on zero, in code values; mirrors color::dither_offset.
+fn dither_offset(pos : vec2u) -> f32 {
+  let p = pos % 8u;
+  var index = 0u;
+  for (var bit = 0u; bit < 3u; bit = bit + 1u) {
+    let xb = (p.x >> bit) & 1u;
+    let yb = (p.y >> bit) & 1u;
+    let shift = 2u * (2u - bit);
+    index = index | ((xb ^ yb) << (shift + 1u)) | (yb << shift);
+  }
+  return (f32(index) + 0.5) / 64.0 - 0.5;
+}
+
+fn srgb_encode(l : vec3f) -> vec3f {
+  let lo = l * 12.92;
+  let hi = 1.055 * pow(l, vec3f(1.0 / 2.4)) - 0.055;
+  return select(hi, lo, l <= vec3f(0.0031308));
+}
+
+fn srgb_decode(v : vec3f) -> vec3f {
+  let lo = v / 12.92;
+  let hi = pow((v + 0.055) / 1.055, vec3f(2.4));
+  return select(hi, lo, v <= vec3f(0.04045));
+}
+
+@compute @workgroup_size(8, 8)
+fn cs_globals(@builtin(global_invocation_id) id : vec3u) {
+  let coord = vec2i(id.xy);
+  if (!inside(coord)) {
+    return;
+  }
+  let c = textureLoad(tex, coord, 0);
   var rgb = c.rgb;
+"#;
 
-  // apply globals (mirrors CPU path)
+// Stage bodies in pipeline order (mirrors the CPU path).
+const GLOBALS_STAGES: [(u32, &str); 6] = [
+    (
+        STAGE_EXPOSURE,
+        r#"
   rgb = rgb * globals.exposure_mul;
-  rgb.r = rgb.r * (1.0 + globals.temp * 0.5 + globals.tint * 0.2);
-  rgb.b = rgb.b * (1.0 - globals.temp * 0.5 + globals.tint * 0.2);
-  rgb.g = rgb.g * (1.0 - globals.tint * 0.2);
-
-  let l = 0.2126 * rgb.r + 0.7152 * rgb.g + 0.0722 * rgb.b;
-  let highlights_mask = max(l - 0.5, 0.0) * 2.0;
-  let shadows_mask = max(0.5 - l, 0.0) * 2.0;
-  rgb = rgb * (1.0 + globals.highlights * highlights_mask);
-  rgb = rgb * (1.0 + globals.shadows * shadows_mask);
+"#,
+    ),
+    (
+        STAGE_TONE,
+        r#"
+  let fine = textureLoad(tone_fine, coord, 0).r;
+  let coarse = textureLoad(tone_coarse, coord, 0).r;
+  let guide = 0.5 * (fine + coarse) * globals.exposure_mul;
+  let highlights_mask = smoothstep(0.4, 1.0, guide);
+  let shadows_mask = 1.0 - smoothstep(0.0, 0.6, guide);
+  rgb = rgb * exp2(globals.tone_range * (globals.highlights * highlights_mask + globals.shadows * shadows_mask));
+"#,
+    ),
+    (
+        STAGE_LEVELS,
+        r#"
   rgb = rgb + globals.whites * 0.1;
   rgb = rgb - globals.blacks * 0.1;
+"#,
+    ),
+    (
+        STAGE_CONTRAST,
+        r#"
   rgb = (rgb - vec3f(0.5,0.5,0.5)) * (1.0 + globals.contrast) + vec3f(0.5,0.5,0.5);
-
+"#,
+    ),
+    (
+        STAGE_COLOR,
+        r#"
   let l2 = 0.2126 * rgb.r + 0.7152 * rgb.g + 0.0722 * rgb.b;
   let vib_mask = clamp(1.0 - (abs(rgb.r - l2) + abs(rgb.g - l2) + abs(rgb.b - l2)) / 3.0, 0.0, 1.0);
   let vib_factor = 1.0 + globals.vibrance * vib_mask;
   let sat_factor = 1.0 + globals.saturation;
   rgb = l2 + (rgb - l2) * sat_factor * vib_factor;
-  rgb = clamp(rgb, vec3f(0.0,0.0,0.0), vec3f(1.0,1.0,1.0));
-  return vec4f(rgb, c.a);
+"#,
+    ),
+    (
+        STAGE_DITHER,
+        r#"
+  // store rounds encoded values, so the offset goes in encoded space
+  let encoded = srgb_encode(clamp(rgb, vec3f(0.0,0.0,0.0), vec3f(1.0,1.0,1.0)));
+  rgb = srgb_decode(clamp(encoded + dither_offset(id.xy) / 255.0, vec3f(0.0,0.0,0.0), vec3f(1.0,1.0,1.0)));
+"#,
+    ),
+];
+
+const GLOBALS_EPILOGUE: &str = r#"
+  store(coord, vec4f(rgb, c.a));
+}
+"#;
+
+fn globals_shader_source(stages: u32) -> String {
+    let mut source = String::from(GLOBALS_PRELUDE);
+    for (stage, body) in GLOBALS_STAGES {
+        if stages & stage != 0 {
+            source.push_str(body);
+        }
+    }
+    source.push_str(GLOBALS_EPILOGUE);
+    source
+}
+
+fn globals_stage_mask(globals: &crate::models::GlobalAdjustments) -> u32 {
+    let active = |v: f32| v.abs() >= 1e-4;
+    let mut stages = 0;
+    if active(globals.exposure_ev) {
+        stages |= STAGE_EXPOSURE;
+    }
+    if active(globals.highlights) || active(globals.shadows) {
+        stages |= STAGE_TONE;
+    }
+    if active(globals.whites) || active(globals.blacks) {
+        stages |= STAGE_LEVELS;
+    }
+    if active(globals.contrast) {
+        stages |= STAGE_CONTRAST;
+    }
+    if active(globals.vibrance) || active(globals.saturation) {
+        stages |= STAGE_COLOR;
+    }
+    if globals.dither {
+        stages |= STAGE_DITHER;
+    }
+    stages
+}
+
+fn create_globals_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    stages: u32,
+) -> wgpu::ComputePipeline {
+    let output = if stages & GLOBALS_FLOAT != 0 {
+        STORE_FLOAT
+    } else {
+        STORE_SRGB
+    };
+    let shader = compute_shader(
+        device,
+        &globals_shader_source(stages),
+        output,
+        "openroom-gpu-globals-shader",
+    );
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("openroom-gpu-compute-globals"),
+        layout: Some(layout),
+        module: &shader,
+        entry_point: "cs_globals",
+    })
+}
+
+fn globals_pipeline(ctx: &GpuContext, stages: u32) -> Option<&wgpu::ComputePipeline> {
+    enabled(ctx.pipelines_globals.get(stages as usize)?, "globals")
+}
+
+// A feature's pipeline, counting the op as a "pipeline" fallback when it failed
+// to build and the caller has to run on the CPU.
+fn enabled<'a>(
+    pipeline: &'a Option<wgpu::ComputePipeline>,
+    feature: &str,
+) -> Option<&'a wgpu::ComputePipeline> {
+    if pipeline.is_none() {
+        report_fallback("pipeline", format!("{feature} is disabled on this adapter"));
+    }
+    pipeline.as_ref()
+}
+
+// Runs shader and pipeline creation under error scopes. Without them a WGSL the
+// driver rejects goes to wgpu's default error handler, which panics. Scopes are
+// device-wide, so this only runs while the context is being built, before any
+// render can submit work whose errors the scope would catch.
+fn capture<T>(device: &wgpu::Device, create: impl FnOnce() -> T) -> Result<T, String> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let value = create();
+    match block_on(device.pop_error_scope()) {
+        Some(err) => Err(err.to_string()),
+        None => Ok(value),
+    }
+}
+
+// One optional GPU feature: None (and an entry in `disabled`) when it failed to build.
+fn build_feature<T>(
+    device: &wgpu::Device,
+    feature: &str,
+    disabled: &mut Vec<GpuFeatureFailure>,
+    create: impl FnOnce() -> T,
+) -> Option<T> {
+    match capture(device, create) {
+        Ok(value) => Some(value),
+        Err(error) => {
+            report_fallback("pipeline", format!("{feature}: {error}"));
+            disabled.push(GpuFeatureFailure {
+                feature: feature.to_string(),
+                error,
+            });
+            None
+        }
+    }
+}
+
+/// Identifies an adapter across launches: backend, PCI vendor and device, and the
+/// driver's name for it (the only distinction on backends that report no ids).
+pub fn adapter_id(info: &wgpu::AdapterInfo) -> String {
+    format!(
+        "{:?}:{:04x}:{:04x}:{}",
+        info.backend, info.vendor, info.device, info.name
+    )
+    .to_lowercase()
+}
+
+// Every op is a compute shader, and colour targets are read back through an
+// sRGB view of a plain storage texture.
+fn capable(adapter: &wgpu::Adapter) -> bool {
+    let required = wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::VIEW_FORMATS;
+    adapter
+        .get_downlevel_capabilities()
+        .flags
+        .contains(required)
 }
-"#
-            .into(),
-        ),
+
+fn init_gpu_context() -> Result<Arc<GpuContext>, String> {
+    // Headless instance; use all backends to maximize compatibility.
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        ..Default::default()
+    });
+
+    // The adapter picked in settings when it is present and capable, else the
+    // high-performance one.
+    let preferred = current_settings().preferred_gpu.and_then(|id| {
+        instance
+            .enumerate_adapters(wgpu::Backends::all())
+            .into_iter()
+            .find(|adapter| adapter_id(&adapter.get_info()) == id && capable(adapter))
     });
+    let adapter = match preferred {
+        Some(adapter) => adapter,
+        None => block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }))
+        .ok_or_else(|| "No suitable GPU adapter found".to_string())?,
+    };
+    let adapter_info = adapter.get_info();
+    if !capable(&adapter) {
+        return Err(format!(
+            "GPU adapter {} lacks compute shaders or texture view formats",
+            adapter_info.name
+        ));
+    }
+
+    // Request the full adapter limits so we can handle large RAWs on capable GPUs (e.g. RTX 30xx).
+    let adapter_limits = adapter.limits();
+    let (device, queue) = block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("openroom-gpu-device"),
+            required_features: wgpu::Features::empty(),
+            required_limits: adapter_limits,
+        },
+        None,
+    ))
+    .map_err(|e| format!("Failed to create GPU device: {e:?}"))?;
+
+    let device: Arc<wgpu::Device> = Arc::new(device);
+    let queue: Arc<wgpu::Queue> = Arc::new(queue);
+
+    let texture_entry = |binding: u32| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            multisampled: false,
+            view_dimension: wgpu::TextureViewDimension::D2,
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        },
+        count: None,
+    };
+    let uniform_entry = |binding: u32, size: u64| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: std::num::NonZeroU64::new(size),
+        },
+        count: None,
+    };
+    let store_layout = |format: wgpu::TextureFormat, label: &str| {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(label),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            }],
+        })
+    };
 
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let bind_layout_store_srgb = store_layout(
+        wgpu::TextureFormat::Rgba8Unorm,
+        "openroom-gpu-bind-store-srgb",
+    );
+    let bind_layout_store_float = store_layout(
+        wgpu::TextureFormat::Rgba16Float,
+        "openroom-gpu-bind-store-float",
+    );
     let bind_layout_resize = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         label: Some("openroom-gpu-bind-resize"),
+        entries: &[texture_entry(0)],
+    });
+    let shader = compute_shader(&device, RESIZE_SHADER, STORE_SRGB, "openroom-gpu-shader");
+    let pipeline_resize = create_compute_pipeline(
+        &device,
+        &[&bind_layout_resize, &bind_layout_store_srgb],
+        &shader,
+        "cs_resize",
+        "openroom-gpu-compute-resize",
+    );
+    let shader = compute_shader(
+        &device,
+        RESIZE_SHADER,
+        STORE_FLOAT,
+        "openroom-gpu-float-shader",
+    );
+    let pipeline_resize_float = create_compute_pipeline(
+        &device,
+        &[&bind_layout_resize, &bind_layout_store_float],
+        &shader,
+        "cs_resize",
+        "openroom-gpu-compute-resize-float",
+    );
+    // resizing is what every other path falls back to, so a driver that rejects
+    // its shader gets no context at all
+    if let Some(err) = block_on(device.pop_error_scope()) {
+        return Err(format!("GPU resize pipeline failed: {err}"));
+    }
+
+    let bind_layout_globals = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("openroom-gpu-bind-globals"),
         entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                count: None,
-            },
+            texture_entry(0),
+            uniform_entry(1, GLOBALS_UBO_SIZE),
+            // fine and coarse blurred luminance guiding highlights/shadows
+            texture_entry(2),
+            texture_entry(3),
+        ],
+    });
+
+    let pipeline_layout_globals = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("openroom-gpu-pipeline-globals"),
+        bind_group_layouts: &[&bind_layout_globals, &bind_layout_store_srgb],
+        push_constant_ranges: &[],
+    });
+    let pipeline_layout_globals_float =
+        device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("openroom-gpu-pipeline-globals-float"),
+            bind_group_layouts: &[&bind_layout_globals, &bind_layout_store_float],
+            push_constant_ranges: &[],
+        });
+
+    // every variant is compiled up front: building one on first use would open an
+    // error scope while other renders are in flight
+    let mut disabled = Vec::new();
+    let pipelines_globals = (0..GLOBALS_FLOAT << 1)
+        .map(|stages| {
+            let layout = if stages & GLOBALS_FLOAT != 0 {
+                &pipeline_layout_globals_float
+            } else {
+                &pipeline_layout_globals
+            };
+            build_feature(
+                &device,
+                &format!("globals variant {stages:#04x}"),
+                &mut disabled,
+                || create_globals_pipeline(&device, layout, stages),
+            )
+        })
+        .collect();
+
+    let bind_layout_blur = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("openroom-gpu-bind-blur"),
+        entries: &[texture_entry(0), uniform_entry(1, BLUR_UBO_SIZE)],
+    });
+
+    // the storage format is part of the shader, so each output format gets its own module
+    let blur = build_feature(&device, "blur", &mut disabled, || {
+        let blur_shader =
+            compute_shader(&device, BLUR_SHADER, STORE_SRGB, "openroom-gpu-blur-shader");
+        let blur_float_shader = compute_shader(
+            &device,
+            BLUR_SHADER,
+            STORE_FLOAT,
+            "openroom-gpu-blur-float-shader",
+        );
+        let pipeline_blur = create_compute_pipeline(
+            &device,
+            &[&bind_layout_blur, &bind_layout_store_srgb],
+            &blur_shader,
+            "cs_blur",
+            "openroom-gpu-compute-blur",
+        );
+        let pipeline_blur_float = create_compute_pipeline(
+            &device,
+            &[&bind_layout_blur, &bind_layout_store_float],
+            &blur_float_shader,
+            "cs_blur",
+            "openroom-gpu-compute-blur-float",
+        );
+        (pipeline_blur, pipeline_blur_float)
+    });
+    let (pipeline_blur, pipeline_blur_float) = blur.unzip();
+
+    let bind_layout_local_contrast =
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("openroom-gpu-bind-local-contrast"),
+            entries: &[
+                texture_entry(0),
+                texture_entry(1),
+                texture_entry(2),
+                uniform_entry(3, LOCAL_CONTRAST_UBO_SIZE),
+            ],
+        });
+
+    let pipeline_local_contrast = build_feature(&device, "local contrast", &mut disabled, || {
+        let local_contrast_shader = compute_shader(
+            &device,
+            LOCAL_CONTRAST_SHADER,
+            STORE_SRGB,
+            "openroom-gpu-local-contrast-shader",
+        );
+        create_compute_pipeline(
+            &device,
+            &[&bind_layout_local_contrast, &bind_layout_store_srgb],
+            &local_contrast_shader,
+            "cs_local_contrast",
+            "openroom-gpu-compute-local-contrast",
+        )
+    });
+
+    let bind_layout_dehaze = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("openroom-gpu-bind-dehaze"),
+        entries: &[
+            texture_entry(0),
+            texture_entry(1),
+            uniform_entry(2, DEHAZE_UBO_SIZE),
+        ],
+    });
+
+    let pipeline_dehaze = build_feature(&device, "dehaze", &mut disabled, || {
+        let dehaze_shader = compute_shader(
+            &device,
+            DEHAZE_SHADER,
+            STORE_SRGB,
+            "openroom-gpu-dehaze-shader",
+        );
+        create_compute_pipeline(
+            &device,
+            &[&bind_layout_dehaze, &bind_layout_store_srgb],
+            &dehaze_shader,
+            "cs_dehaze",
+            "openroom-gpu-compute-dehaze",
+        )
+    });
+
+    let noise_reduction = build_feature(&device, "noise reduction", &mut disabled, || {
+        let nr_prep_shader = compute_shader(
+            &device,
+            NR_PREP_SHADER,
+            STORE_FLOAT,
+            "openroom-gpu-nr-prep-shader",
+        );
+        let nr_combine_shader = compute_shader(
+            &device,
+            NR_COMBINE_SHADER,
+            STORE_SRGB,
+            "openroom-gpu-nr-combine-shader",
+        );
+        // prep passes take one texture + 32-byte uniform and combine three textures +
+        // 16-byte uniform, the same shapes as the blur and local contrast layouts
+        let pipeline_nr_pack = create_compute_pipeline(
+            &device,
+            &[&bind_layout_blur, &bind_layout_store_float],
+            &nr_prep_shader,
+            "cs_nr_pack",
+            "openroom-gpu-compute-nr-pack",
+        );
+        let pipeline_nr_coeffs = create_compute_pipeline(
+            &device,
+            &[&bind_layout_blur, &bind_layout_store_float],
+            &nr_prep_shader,
+            "cs_nr_coeffs",
+            "openroom-gpu-compute-nr-coeffs",
+        );
+        let pipeline_nr_combine = create_compute_pipeline(
+            &device,
+            &[&bind_layout_local_contrast, &bind_layout_store_srgb],
+            &nr_combine_shader,
+            "cs_nr_combine",
+            "openroom-gpu-compute-nr-combine",
+        );
+        (pipeline_nr_pack, pipeline_nr_coeffs, pipeline_nr_combine)
+    });
+    let (pipeline_nr_pack, pipeline_nr_coeffs, pipeline_nr_combine) = match noise_reduction {
+        Some((pack, coeffs, combine)) => (Some(pack), Some(coeffs), Some(combine)),
+        None => (None, None, None),
+    };
+
+    let bind_layout_lut = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("openroom-gpu-bind-lut"),
+        entries: &[
+            texture_entry(0),
             wgpu::BindGroupLayoutEntry {
                 binding: 1,
-                visibility: wgpu::ShaderStages::FRAGMENT,
+                visibility: wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::Texture {
                     multisampled: false,
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
                 },
                 count: None,
             },
+            uniform_entry(2, LUT_UBO_SIZE),
         ],
     });
+    let pipeline_lut = build_feature(&device, "lut", &mut disabled, || {
+        let lut_shader = compute_shader(&device, LUT_SHADER, STORE_SRGB, "openroom-gpu-lut-shader");
+        create_compute_pipeline(
+            &device,
+            &[&bind_layout_lut, &bind_layout_store_srgb],
+            &lut_shader,
+            "cs_lut",
+            "openroom-gpu-compute-lut",
+        )
+    });
 
-    let bind_layout_globals = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        label: Some("openroom-gpu-bind-globals"),
+    let bind_layout_curves = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("openroom-gpu-bind-curves"),
         entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
-                count: None,
-            },
+            texture_entry(0),
             wgpu::BindGroupLayoutEntry {
                 binding: 1,
-                visibility: wgpu::ShaderStages::FRAGMENT,
+                visibility: wgpu::ShaderStages::COMPUTE,
                 ty: wgpu::BindingType::Texture {
                     multisampled: false,
-                    view_dimension: wgpu::TextureViewDimension::D2,
-                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D1,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
                 },
                 count: None,
             },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::FRAGMENT,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: std::num::NonZeroU64::new(GLOBALS_UBO_SIZE),
-                    },
-                    count: None,
-                },
-            ],
-        });
-
-    let pipeline_layout_resize = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("openroom-gpu-pipeline-resize"),
-        bind_group_layouts: &[&bind_layout_resize],
-        push_constant_ranges: &[],
+        ],
+    });
+    let pipeline_curves = build_feature(&device, "curves", &mut disabled, || {
+        let curves_shader = compute_shader(
+            &device,
+            CURVES_SHADER,
+            STORE_SRGB,
+            "openroom-gpu-curves-shader",
+        );
+        create_compute_pipeline(
+            &device,
+            &[&bind_layout_curves, &bind_layout_store_srgb],
+            &curves_shader,
+            "cs_curves",
+            "openroom-gpu-compute-curves",
+        )
     });
 
-    let pipeline_layout_globals = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("openroom-gpu-pipeline-globals"),
-        bind_group_layouts: &[&bind_layout_globals],
-        push_constant_ranges: &[],
+    let bind_layout_layer = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("openroom-gpu-bind-layer"),
+        entries: &[
+            texture_entry(0),
+            // the r32float mask is not filterable
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    multisampled: false,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                },
+                count: None,
+            },
+            uniform_entry(2, LAYER_UBO_SIZE),
+        ],
+    });
+    let layer = build_feature(&device, "layers", &mut disabled, || {
+        let layer_shader = compute_shader(
+            &device,
+            LAYER_SHADER,
+            STORE_SRGB,
+            "openroom-gpu-layer-shader",
+        );
+        let layer_float_shader = compute_shader(
+            &device,
+            LAYER_SHADER,
+            STORE_FLOAT,
+            "openroom-gpu-layer-float-shader",
+        );
+        let pipeline_layer = create_compute_pipeline(
+            &device,
+            &[&bind_layout_layer, &bind_layout_store_srgb],
+            &layer_shader,
+            "cs_layer",
+            "openroom-gpu-compute-layer",
+        );
+        let pipeline_layer_float = create_compute_pipeline(
+            &device,
+            &[&bind_layout_layer, &bind_layout_store_float],
+            &layer_float_shader,
+            "cs_layer",
+            "openroom-gpu-compute-layer-float",
+        );
+        (pipeline_layer, pipeline_layer_float)
+    });
+    let (pipeline_layer, pipeline_layer_float) = layer.unzip();
+
+    let max_dim = device.limits().max_texture_dimension_2d;
+    let max_safe_dim = max_dim.min(8192);
+    let max_safe_pixels = 150_000_000; // ~150 MP guardrail
+
+    Ok(Arc::new(GpuContext {
+        device,
+        queue,
+        pipeline_resize,
+        pipeline_resize_float,
+        pipelines_globals,
+        pipeline_blur,
+        pipeline_blur_float,
+        pipeline_local_contrast,
+        pipeline_dehaze,
+        pipeline_nr_pack,
+        pipeline_nr_coeffs,
+        pipeline_nr_combine,
+        pipeline_lut,
+        pipeline_curves,
+        pipeline_layer,
+        pipeline_layer_float,
+        bind_layout_resize,
+        bind_layout_globals,
+        bind_layout_blur,
+        bind_layout_local_contrast,
+        bind_layout_dehaze,
+        bind_layout_lut,
+        bind_layout_curves,
+        bind_layout_layer,
+        bind_layout_store_srgb,
+        bind_layout_store_float,
+        max_safe_dim,
+        max_safe_pixels,
+        adapter_info,
+        staging: Mutex::new(Vec::with_capacity(STAGING_POOL_SIZE)),
+        resident: Mutex::new(VecDeque::new()),
+        textures: Arc::new(Mutex::new(Vec::new())),
+        lut_table: Mutex::new(None),
+        disabled,
+    }))
+}
+
+fn gpu_context() -> Option<Arc<GpuContext>> {
+    let res = GPU_CONTEXT.get_or_init(|| {
+        catch_unwind(|| init_gpu_context()).unwrap_or_else(|_| {
+            Err("GPU context init panicked; GPU path disabled for this session".to_string())
+        })
+    });
+    match res {
+        Ok(ctx) => Some(ctx.clone()),
+        Err(_) => None,
+    }
+}
+
+// The context for an op, reporting a context that failed to start as a fallback.
+fn render_context() -> Option<Arc<GpuContext>> {
+    let ctx = gpu_context();
+    if ctx.is_none() {
+        report_fallback("init", context_adapter().err().unwrap_or_default());
+    }
+    ctx
+}
+
+/// Send `gpu-fallback` events to the frontend from now on.
+pub fn watch_fallbacks(app: &AppHandle) {
+    let _ = FALLBACK_EVENTS.set(app.clone());
+}
+
+// Count an op that fell back to the CPU; `kind` is "init", "limits", "readback"
+// or "pipeline" (a feature whose shader failed to build).
+fn report_fallback(kind: &str, detail: String) {
+    let report = {
+        let mut fallbacks = FALLBACKS.lock().unwrap_or_else(|e| e.into_inner());
+        let pos = match fallbacks.iter().position(|f| f.kind == kind) {
+            Some(pos) => pos,
+            None => {
+                fallbacks.push(GpuFallback {
+                    kind: kind.to_string(),
+                    detail: String::new(),
+                    count: 0,
+                });
+                fallbacks.len() - 1
+            }
+        };
+        let entry = &mut fallbacks[pos];
+        entry.count += 1;
+        entry.detail = detail;
+        entry.count.is_power_of_two().then(|| entry.clone())
+    };
+    if let (Some(fallback), Some(app)) = (report, FALLBACK_EVENTS.get()) {
+        let _ = app.emit(GPU_FALLBACK_EVENT, fallback);
+    }
+}
+
+/// The processing context's adapter and backend, or why it failed to start,
+/// with the fallbacks counted this session.
+pub fn status() -> GpuStatus {
+    let adapter = context_adapter();
+    GpuStatus {
+        active: adapter.is_ok(),
+        adapter: adapter.as_ref().ok().map(|info| info.name.clone()),
+        backend: adapter
+            .as_ref()
+            .ok()
+            .map(|info| format!("{:?}", info.backend)),
+        init_error: adapter.err(),
+        fallbacks: FALLBACKS
+            .lock()
+            .map(|fallbacks| fallbacks.clone())
+            .unwrap_or_default(),
+        disabled_features: disabled_features(),
+    }
+}
+
+pub fn available() -> bool {
+    gpu_context().is_some()
+}
+
+/// Adapter hosting the processing context, or why the context failed to start.
+pub fn context_adapter() -> Result<wgpu::AdapterInfo, String> {
+    gpu_context();
+    match GPU_CONTEXT.get() {
+        Some(Ok(ctx)) => Ok(ctx.adapter_info.clone()),
+        Some(Err(err)) => Err(err.clone()),
+        None => Err("GPU context not initialized".into()),
+    }
+}
+
+/// GPU features that failed to build on this driver and run on the CPU instead.
+pub fn disabled_features() -> Vec<GpuFeatureFailure> {
+    match gpu_context() {
+        Some(ctx) => ctx.disabled.clone(),
+        None => Vec::new(),
+    }
+}
+
+// Respect device limits; very large RAWs may exceed max texture dimension.
+fn fits_limits(ctx: &GpuContext, w: u32, h: u32) -> bool {
+    w <= ctx.max_safe_dim && h <= ctx.max_safe_dim && (w as u64) * (h as u64) <= ctx.max_safe_pixels
+}
+
+// fits_limits for an op that falls back to the CPU when it does not fit.
+fn within_limits(ctx: &GpuContext, w: u32, h: u32) -> bool {
+    let within = fits_limits(ctx, w, h);
+    if !within {
+        report_fallback(
+            "limits",
+            format!("{w}x{h} is over the adapter's texture limits"),
+        );
+    }
+    within
+}
+
+// A texture borrowed from the context's pool and handed back when dropped. Drop
+// it only once every pass using it is recorded: a later pass may then get it.
+// Queue writes land before the whole submission, so an upload is held until then.
+struct PooledTexture {
+    pool: Arc<Mutex<Vec<wgpu::Texture>>>,
+    texture: Option<wgpu::Texture>,
+}
+
+impl PooledTexture {
+    // Keep the texture for good instead of returning it to the pool.
+    fn into_inner(mut self) -> wgpu::Texture {
+        self.texture.take().expect("pooled texture already taken")
+    }
+}
+
+impl std::ops::Deref for PooledTexture {
+    type Target = wgpu::Texture;
+
+    fn deref(&self) -> &wgpu::Texture {
+        self.texture.as_ref().expect("pooled texture already taken")
+    }
+}
+
+impl Drop for PooledTexture {
+    fn drop(&mut self) {
+        let Some(texture) = self.texture.take() else {
+            return;
+        };
+        let mut idle = self.pool.lock().unwrap_or_else(|e| e.into_inner());
+        idle.push(texture);
+        let mut bytes: u64 = idle.iter().map(texture_bytes).sum();
+        while bytes > TEXTURE_POOL_BYTES && !idle.is_empty() {
+            bytes -= texture_bytes(&idle.remove(0));
+        }
+    }
+}
+
+fn texture_bytes(texture: &wgpu::Texture) -> u64 {
+    let pixel = match texture.format() {
+        wgpu::TextureFormat::Rgba16Float => 8,
+        _ => 4,
+    };
+    pixel * texture.width() as u64 * texture.height() as u64
+}
+
+// An idle pooled texture matching `desc`, or a new one when none is free. Labels
+// are not compared, so a reused texture keeps the label it was created with.
+fn pooled_texture(ctx: &GpuContext, desc: &wgpu::TextureDescriptor) -> PooledTexture {
+    let reused = {
+        let mut idle = ctx.textures.lock().unwrap_or_else(|e| e.into_inner());
+        idle.iter()
+            .rposition(|texture| {
+                texture.size() == desc.size
+                    && texture.format() == desc.format
+                    && texture.usage() == desc.usage
+            })
+            .map(|pos| idle.remove(pos))
+    };
+    PooledTexture {
+        pool: ctx.textures.clone(),
+        texture: Some(reused.unwrap_or_else(|| ctx.device.create_texture(desc))),
+    }
+}
+
+fn upload_rgba(ctx: &GpuContext, src: &image::RgbaImage, label: &str) -> PooledTexture {
+    let size = wgpu::Extent3d {
+        width: src.width(),
+        height: src.height(),
+        depth_or_array_layers: 1,
+    };
+    let texture = pooled_texture(
+        ctx,
+        &wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+    );
+    ctx.queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        src.as_raw(),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * src.width()),
+            rows_per_image: Some(src.height()),
+        },
+        size,
+    );
+    texture
+}
+
+// The texture a render reads `src` from: the asset's resident upload when `key`
+// still names the same pixels, otherwise a fresh upload that becomes resident
+// (evicting the least recently used over budget). Unkeyed sources are not kept.
+fn source_texture(
+    ctx: &GpuContext,
+    src: &image::RgbaImage,
+    key: Option<&SourceKey>,
+    label: &str,
+) -> Arc<wgpu::Texture> {
+    let Some(key) = key else {
+        return Arc::new(upload_rgba(ctx, src, label).into_inner());
+    };
+    {
+        let mut resident = ctx.resident.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(pos) = resident
+            .iter()
+            .position(|entry| entry.key.asset_id == key.asset_id)
+        {
+            // a stale upload of the asset goes either way
+            if let Some(entry) = resident.remove(pos) {
+                let texture = entry.texture.clone();
+                if entry.key == *key
+                    && texture.width() == src.width()
+                    && texture.height() == src.height()
+                {
+                    resident.push_back(entry);
+                    return texture;
+                }
+            }
+        }
+    }
+    let texture = Arc::new(upload_rgba(ctx, src, label).into_inner());
+    let bytes = 4 * src.width() as u64 * src.height() as u64;
+    let mut resident = ctx.resident.lock().unwrap_or_else(|e| e.into_inner());
+    resident.retain(|entry| entry.key.asset_id != key.asset_id);
+    resident.push_back(Resident {
+        key: key.clone(),
+        texture: texture.clone(),
+        bytes,
+    });
+    // the newest upload stays even when it alone is over budget
+    while resident.len() > 1
+        && resident.iter().map(|entry| entry.bytes).sum::<u64>() > RESIDENT_BUDGET_BYTES
+    {
+        resident.pop_front();
+    }
+    texture
+}
+
+/// Drop every resident source upload and idle pooled texture, e.g. when the
+/// preview caches are cleared.
+pub fn clear_resident() {
+    if let Some(Ok(ctx)) = GPU_CONTEXT.get() {
+        ctx.resident
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+        ctx.textures
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
+
+// sRGB colour written by a shader: the output of an op, encoded once on the way
+// out. Stored as plain Rgba8Unorm (storage textures cannot be sRGB) and read back
+// through an sRGB view; see input_view.
+fn color_target(ctx: &GpuContext, w: u32, h: u32, label: &str) -> PooledTexture {
+    pooled_texture(
+        ctx,
+        &wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[wgpu::TextureFormat::Rgba8UnormSrgb],
+        },
+    )
+}
+
+// Half-float intermediate: linear light between the passes of an op, or data that
+// must not be sRGB-encoded or clamped to 0..1.
+fn float_target(ctx: &GpuContext, w: u32, h: u32, label: &str) -> PooledTexture {
+    pooled_texture(
+        ctx,
+        &wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: w,
+                height: h,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba16Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        },
+    )
+}
+
+// View for reading `texture` in a shader; colour targets decode as sRGB like uploads do.
+fn input_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    let format = (texture.format() == wgpu::TextureFormat::Rgba8Unorm)
+        .then_some(wgpu::TextureFormat::Rgba8UnormSrgb);
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        format,
+        ..Default::default()
+    })
+}
+
+// Run `pipeline` once per pixel of `target`, which is bound as its group 1 output.
+fn dispatch(
+    ctx: &GpuContext,
+    encoder: &mut wgpu::CommandEncoder,
+    target: &wgpu::Texture,
+    pipeline: &wgpu::ComputePipeline,
+    bind_group: &wgpu::BindGroup,
+    label: &str,
+) {
+    let layout = if target.format() == wgpu::TextureFormat::Rgba16Float {
+        &ctx.bind_layout_store_float
+    } else {
+        &ctx.bind_layout_store_srgb
+    };
+    let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+    let output = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&view),
+        }],
+    });
+    let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some(label),
+        timestamp_writes: None,
+    });
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.set_bind_group(1, &output, &[]);
+    pass.dispatch_workgroups(
+        target.width().div_ceil(WORKGROUP_SIZE),
+        target.height().div_ceil(WORKGROUP_SIZE),
+        1,
+    );
+}
+
+fn padded_bytes_per_row(width: u32) -> usize {
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
+    (4 * width as usize).div_ceil(align) * align
+}
+
+// Take the smallest pooled staging buffer that fits, or allocate a new one.
+fn acquire_staging(ctx: &GpuContext, size: u64, label: &str) -> wgpu::Buffer {
+    if let Ok(mut pool) = ctx.staging.lock() {
+        let best = pool
+            .iter()
+            .enumerate()
+            .filter(|(_, buf)| buf.size() >= size)
+            .min_by_key(|(_, buf)| buf.size())
+            .map(|(idx, _)| idx);
+        if let Some(idx) = best {
+            return pool.swap_remove(idx);
+        }
+    }
+    ctx.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Return an unmapped staging buffer to the pool, evicting the smallest when full.
+fn release_staging(ctx: &GpuContext, buffer: wgpu::Buffer) {
+    let Ok(mut pool) = ctx.staging.lock() else {
+        return;
+    };
+    pool.push(buffer);
+    if pool.len() > STAGING_POOL_SIZE {
+        if let Some(idx) = pool
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, buf)| buf.size())
+            .map(|(idx, _)| idx)
+        {
+            pool.swap_remove(idx);
+        }
+    }
+}
+
+// Copy `texture` into a pooled staging buffer, submit the encoder and read the pixels back.
+fn readback_rgba(
+    ctx: &GpuContext,
+    mut encoder: wgpu::CommandEncoder,
+    texture: &wgpu::Texture,
+    w: u32,
+    h: u32,
+    label: &str,
+) -> Option<image::RgbaImage> {
+    // the op's passes run on submission, so this spans the whole GPU pass
+    let _timer = perf::Timer::start("gpu");
+    let bytes_per_row = 4 * w as usize;
+    let padded = padded_bytes_per_row(w);
+    let size = (padded * h as usize) as u64;
+    let output_buffer = acquire_staging(ctx, size, label);
+
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyBuffer {
+            buffer: &output_buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded as u32),
+                rows_per_image: Some(h),
+            },
+        },
+        wgpu::Extent3d {
+            width: w,
+            height: h,
+            depth_or_array_layers: 1,
+        },
+    );
+
+    ctx.queue.submit(Some(encoder.finish()));
+
+    // A blocking poll holds the device until the GPU is done, which would stall
+    // every other render's encoding and submission behind this one. Polling
+    // without waiting lets them interleave while this thread waits for its map.
+    let buffer_slice = output_buffer.slice(..size);
+    let (tx, rx) = mpsc::channel();
+    buffer_slice.map_async(wgpu::MapMode::Read, move |res| {
+        let _ = tx.send(res);
+    });
+    let mapped = loop {
+        ctx.device.poll(wgpu::Maintain::Poll);
+        match rx.recv_timeout(READBACK_POLL_INTERVAL) {
+            Ok(res) => break res.map_err(|err| err.to_string()),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break Err("map callback dropped".into()),
+        }
+    };
+    // a failed map leaves the buffer in an unknown state, so it is dropped rather than pooled
+    if let Err(reason) = mapped {
+        report_fallback("readback", format!("{label}: {reason}"));
+        return None;
+    }
+
+    let data = buffer_slice.get_mapped_range();
+    let mut out = image::RgbaImage::new(w, h);
+    for (y, row) in out.as_mut().chunks_exact_mut(bytes_per_row).enumerate() {
+        let src_start = y * padded;
+        row.copy_from_slice(&data[src_start..src_start + bytes_per_row]);
+    }
+    drop(data);
+    output_buffer.unmap();
+    release_staging(ctx, output_buffer);
+
+    Some(out)
+}
+
+// The texture a chain is working on: a resident upload or one of its own.
+enum ChainTexture {
+    Resident(Arc<wgpu::Texture>),
+    Pooled(PooledTexture),
+}
+
+impl std::ops::Deref for ChainTexture {
+    type Target = wgpu::Texture;
+
+    fn deref(&self) -> &wgpu::Texture {
+        match self {
+            ChainTexture::Resident(texture) => texture,
+            ChainTexture::Pooled(texture) => texture,
+        }
+    }
+}
+
+/// Pixels kept on the GPU across several ops: each records its passes into one
+/// encoder, and `finish` submits them together and reads back once. Between ops
+/// the pixels stay linear in Rgba16Float, unclamped, and are encoded as sRGB only
+/// on the way out. An op that returns false leaves the chain as it was, so the
+/// caller can finish and do that op on the CPU.
+pub struct Chain {
+    ctx: Arc<GpuContext>,
+    encoder: wgpu::CommandEncoder,
+    current: ChainTexture,
+    // textures filled by queue writes, which must outlive the submission
+    held: Vec<PooledTexture>,
+}
+
+impl Chain {
+    /// Start from `src`, reusing its resident upload when `source` names it.
+    pub fn start(src: &image::RgbaImage, source: Option<&SourceKey>) -> Option<Chain> {
+        let ctx = render_context()?;
+        let (w, h) = src.dimensions();
+        if w == 0 || h == 0 || !within_limits(&ctx, w, h) {
+            return None;
+        }
+        let current = match source {
+            Some(_) => {
+                ChainTexture::Resident(source_texture(&ctx, src, source, "openroom-gpu-chain-src"))
+            }
+            None => ChainTexture::Pooled(upload_rgba(&ctx, src, "openroom-gpu-chain-src")),
+        };
+        let encoder = ctx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("openroom-gpu-chain-encoder"),
+            });
+        Some(Chain {
+            ctx,
+            encoder,
+            current,
+            held: Vec::new(),
+        })
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.current.width(), self.current.height())
+    }
+
+    fn advance(&mut self, next: PooledTexture) {
+        if let ChainTexture::Pooled(previous) =
+            std::mem::replace(&mut self.current, ChainTexture::Pooled(next))
+        {
+            if previous.usage().contains(wgpu::TextureUsages::COPY_DST) {
+                self.held.push(previous);
+            }
+        }
+    }
+
+    /// Resample to `w` x `h`.
+    pub fn resize(&mut self, w: u32, h: u32) -> bool {
+        if w == 0 || h == 0 || !within_limits(&self.ctx, w, h) {
+            return false;
+        }
+        let next = encode_resize(&self.ctx, &mut self.encoder, &self.current, w, h, true);
+        self.advance(next);
+        true
+    }
+
+    /// Global adjustments; `tone_sigmas` are the (fine, coarse) blur radii of the
+    /// highlights/shadows guide.
+    pub fn globals(
+        &mut self,
+        globals: &crate::models::GlobalAdjustments,
+        tone_sigmas: (f32, f32),
+    ) -> bool {
+        match encode_globals(
+            &self.ctx,
+            &mut self.encoder,
+            &self.current,
+            globals,
+            tone_sigmas,
+            true,
+        ) {
+            Some(next) => {
+                self.advance(next);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// One local layer through `mask`, its coverage per pixel in row order with
+    /// the layer's opacity already applied.
+    pub fn layer(&mut self, mask: &[f32], adjustments: &crate::models::LocalAdjustments) -> bool {
+        match encode_layer(
+            &self.ctx,
+            &mut self.encoder,
+            &self.current,
+            mask,
+            adjustments,
+            true,
+        ) {
+            Some((next, mask_texture)) => {
+                self.held.push(mask_texture);
+                self.advance(next);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Submit everything recorded and read the result back as sRGB.
+    pub fn finish(mut self) -> Option<image::RgbaImage> {
+        let (w, h) = self.dimensions();
+        let output = match &self.current {
+            ChainTexture::Pooled(texture)
+                if texture.format() == wgpu::TextureFormat::Rgba16Float =>
+            {
+                // a same-size resample reads each texel exactly once
+                let encoded = encode_resize(&self.ctx, &mut self.encoder, texture, w, h, false);
+                self.advance(encoded);
+                &self.current
+            }
+            _ => &self.current,
+        };
+        readback_rgba(
+            &self.ctx,
+            self.encoder,
+            output,
+            w,
+            h,
+            "openroom-gpu-chain-readback",
+        )
+    }
+}
+
+// Resize an RGBA8 image using the GPU. Returns None if GPU is unavailable or any step fails.
+pub fn resize_rgba(
+    src: &image::RgbaImage,
+    target_w: u32,
+    target_h: u32,
+) -> Option<image::RgbaImage> {
+    let mut chain = Chain::start(src, None)?;
+    if !chain.resize(target_w, target_h) {
+        return None;
+    }
+    chain.finish()
+}
+
+// Bilinear resample of `src_texture` to `w` x `h`, into a linear Rgba16Float
+// target when `float`, else an sRGB colour target.
+fn encode_resize(
+    ctx: &GpuContext,
+    encoder: &mut wgpu::CommandEncoder,
+    src_texture: &wgpu::Texture,
+    w: u32,
+    h: u32,
+    float: bool,
+) -> PooledTexture {
+    let src_view = input_view(src_texture);
+    let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("openroom-gpu-bind-resize"),
+        layout: &ctx.bind_layout_resize,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::TextureView(&src_view),
+        }],
+    });
+    let (dst_texture, pipeline) = if float {
+        (
+            float_target(ctx, w, h, "openroom-gpu-dst"),
+            &ctx.pipeline_resize_float,
+        )
+    } else {
+        (
+            color_target(ctx, w, h, "openroom-gpu-dst"),
+            &ctx.pipeline_resize,
+        )
+    };
+    dispatch(
+        ctx,
+        encoder,
+        &dst_texture,
+        pipeline,
+        &bind_group,
+        "openroom-gpu-pass",
+    );
+    dst_texture
+}
+
+// One local layer over `src_texture`, written like encode_resize's. The mask
+// upload comes back with the output because it has to be held until the encoder
+// is submitted.
+fn encode_layer(
+    ctx: &GpuContext,
+    encoder: &mut wgpu::CommandEncoder,
+    src_texture: &wgpu::Texture,
+    mask: &[f32],
+    adjustments: &crate::models::LocalAdjustments,
+    float: bool,
+) -> Option<(PooledTexture, PooledTexture)> {
+    let pipeline = if float {
+        enabled(&ctx.pipeline_layer_float, "layers")?
+    } else {
+        enabled(&ctx.pipeline_layer, "layers")?
+    };
+    let (w, h) = (src_texture.width(), src_texture.height());
+    if mask.len() != w as usize * h as usize {
+        return None;
+    }
+    let size = wgpu::Extent3d {
+        width: w,
+        height: h,
+        depth_or_array_layers: 1,
+    };
+    let mask_texture = pooled_texture(
+        ctx,
+        &wgpu::TextureDescriptor {
+            label: Some("openroom-gpu-layer-mask"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        },
+    );
+    let texels: Vec<u8> = mask.iter().flat_map(|v| v.to_ne_bytes()).collect();
+    ctx.queue.write_texture(
+        wgpu::ImageCopyTexture {
+            texture: &mask_texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d::ZERO,
+            aspect: wgpu::TextureAspect::All,
+        },
+        &texels,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * w),
+            rows_per_image: Some(h),
+        },
+        size,
+    );
+
+    let uniform = uniform_from_f32(
+        ctx,
+        &[
+            2f32.powf(adjustments.exposure_ev),
+            adjustments.temp / 100.0,
+            adjustments.tint / 100.0,
+            adjustments.saturation / 100.0,
+        ],
+        "openroom-gpu-layer-uniform",
+    );
+    let src_view = input_view(src_texture);
+    let mask_view = input_view(&mask_texture);
+    let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("openroom-gpu-bind-layer"),
+        layout: &ctx.bind_layout_layer,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&mask_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: uniform.as_entire_binding(),
+            },
+        ],
+    });
+    let dst_texture = if float {
+        float_target(ctx, w, h, "openroom-gpu-layer-dst")
+    } else {
+        color_target(ctx, w, h, "openroom-gpu-layer-dst")
+    };
+    dispatch(
+        ctx,
+        encoder,
+        &dst_texture,
+        pipeline,
+        &bind_group,
+        "openroom-gpu-layer-pass",
+    );
+    Some((dst_texture, mask_texture))
+}
+
+// Record the globals passes over `src_texture` into `encoder`, returning the
+// target they write, chosen like encode_resize's. None when a pipeline it needs
+// is disabled.
+fn encode_globals(
+    ctx: &GpuContext,
+    encoder: &mut wgpu::CommandEncoder,
+    src_texture: &wgpu::Texture,
+    globals: &crate::models::GlobalAdjustments,
+    tone_sigmas: (f32, f32),
+    float: bool,
+) -> Option<PooledTexture> {
+    let (w, h) = (src_texture.width(), src_texture.height());
+    let stages = globals_stage_mask(globals);
+    let src_view = input_view(src_texture);
+
+    // the guide is only rendered when the tone stage reads it; other variants get a 1x1 stand-in
+    let (fine, coarse) = if stages & STAGE_TONE != 0 {
+        let packed = float_target(ctx, w, h, "openroom-gpu-tone-packed");
+        let mid = float_target(ctx, w, h, "openroom-gpu-tone-mid");
+        let fine = float_target(ctx, w, h, "openroom-gpu-tone-fine");
+        let coarse = float_target(ctx, w, h, "openroom-gpu-tone-coarse");
+        let pack = blur_bind_group(ctx, src_texture, &[0.0; 8], "openroom-gpu-bind-tone-pack");
+        dispatch(
+            ctx,
+            encoder,
+            &packed,
+            enabled(&ctx.pipeline_nr_pack, "noise reduction")?,
+            &pack,
+            "openroom-gpu-tone-pack",
+        );
+        encode_blur(ctx, encoder, &packed, &mid, &fine, tone_sigmas.0)?;
+        encode_blur(ctx, encoder, &packed, &mid, &coarse, tone_sigmas.1)?;
+        (fine, coarse)
+    } else {
+        (
+            float_target(ctx, 1, 1, "openroom-gpu-tone-unused-fine"),
+            float_target(ctx, 1, 1, "openroom-gpu-tone-unused-coarse"),
+        )
+    };
+    let fine_view = input_view(&fine);
+    let coarse_view = input_view(&coarse);
+
+    // Pack globals into a uniform buffer (align to 16-byte multiples).
+    let data_f32 = [
+        2f32.powf(globals.exposure_ev),
+        globals.contrast / 100.0,
+        globals.highlights / 100.0,
+        globals.shadows / 100.0,
+        globals.whites / 100.0,
+        globals.blacks / 100.0,
+        globals.vibrance / 100.0,
+        globals.saturation / 100.0,
+        crate::image_io::TONE_RANGE_EV,
+        0.0,
+        0.0,
+        0.0,
+    ];
+    let uniform_buffer = uniform_from_f32(ctx, &data_f32, "openroom-gpu-globals-uniform");
+
+    let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("openroom-gpu-bind-globals"),
+        layout: &ctx.bind_layout_globals,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: uniform_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&fine_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: wgpu::BindingResource::TextureView(&coarse_view),
+            },
+        ],
     });
 
-    let pipeline_resize = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("openroom-gpu-render-resize"),
-        layout: Some(&pipeline_layout_resize),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs",
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_resize",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-    });
-
-    let pipeline_globals = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("openroom-gpu-render-globals"),
-        layout: Some(&pipeline_layout_globals),
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs",
-            buffers: &[],
-        },
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_globals",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: wgpu::TextureFormat::Rgba8UnormSrgb,
-                blend: Some(wgpu::BlendState::REPLACE),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-        }),
-        primitive: wgpu::PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-    });
+    let dst_texture = if float {
+        float_target(ctx, w, h, "openroom-gpu-globals-dst")
+    } else {
+        color_target(ctx, w, h, "openroom-gpu-globals-dst")
+    };
+    let pipeline = globals_pipeline(ctx, stages | if float { GLOBALS_FLOAT } else { 0 })?;
+    dispatch(
+        ctx,
+        encoder,
+        &dst_texture,
+        pipeline,
+        &bind_group,
+        "openroom-gpu-globals-pass",
+    );
+    Some(dst_texture)
+}
+
+/// Milliseconds spent in each stage of one GPU render.
+pub struct StageTimings {
+    pub upload_ms: f32,
+    pub pass_ms: f32,
+    pub readback_ms: f32,
+}
+
+// Submit `encoder` (or just the pending queue writes) and wait for it to finish.
+fn finish(ctx: &GpuContext, encoder: Option<wgpu::CommandEncoder>) {
+    let submission = ctx.queue.submit(encoder.map(|encoder| encoder.finish()));
+    ctx.device
+        .poll(wgpu::Maintain::WaitForSubmissionIndex(submission));
+}
+
+/// Time one globals render of `src` stage by stage, waiting for each stage before
+/// the next starts. Err says why renders would not take the GPU path.
+pub fn profile_globals(
+    src: &image::RgbaImage,
+    globals: &crate::models::GlobalAdjustments,
+    tone_sigmas: (f32, f32),
+) -> Result<StageTimings, String> {
+    let Some(ctx) = gpu_context() else {
+        return Err(context_adapter().err().unwrap_or_default());
+    };
+    let (w, h) = src.dimensions();
+    // checked without the reporting lookups: a profile is not a render that fell back
+    if !fits_limits(&ctx, w, h) {
+        return Err(format!("{w}x{h} exceeds the adapter's texture limits"));
+    }
+    let stages = globals_stage_mask(globals);
+    if !matches!(ctx.pipelines_globals.get(stages as usize), Some(Some(_))) {
+        return Err("The globals shader is disabled on this adapter".into());
+    }
+    if stages & STAGE_TONE != 0
+        && (ctx.pipeline_nr_pack.is_none() || ctx.pipeline_blur_float.is_none())
+    {
+        return Err("A blur pipeline is disabled on this adapter".into());
+    }
+    let ms = |start: Instant| start.elapsed().as_secs_f32() * 1000.0;
+
+    let start = Instant::now();
+    let src_texture = upload_rgba(&ctx, src, "openroom-gpu-profile-src");
+    finish(&ctx, None);
+    let upload_ms = ms(start);
+
+    let start = Instant::now();
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("openroom-gpu-profile-encoder"),
+        });
+    let dst_texture = encode_globals(
+        &ctx,
+        &mut encoder,
+        &src_texture,
+        globals,
+        tone_sigmas,
+        false,
+    )
+    .ok_or("A blur pipeline is disabled on this adapter")?;
+    finish(&ctx, Some(encoder));
+    let pass_ms = ms(start);
 
-    let max_dim = device.limits().max_texture_dimension_2d;
-    let max_safe_dim = max_dim.min(8192);
-    let max_safe_pixels = 150_000_000; // ~150 MP guardrail
+    let start = Instant::now();
+    let encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("openroom-gpu-profile-readback-encoder"),
+        });
+    readback_rgba(
+        &ctx,
+        encoder,
+        &dst_texture,
+        w,
+        h,
+        "openroom-gpu-profile-readback",
+    )
+    .ok_or("Reading the render back from the GPU failed")?;
+    let readback_ms = ms(start);
 
-    Ok(Arc::new(GpuContext {
-        device,
-        queue,
-        pipeline_resize,
-        pipeline_globals,
-        bind_layout_resize,
-        bind_layout_globals,
-        max_safe_dim,
-        max_safe_pixels,
-    }))
+    Ok(StageTimings {
+        upload_ms,
+        pass_ms,
+        readback_ms,
+    })
 }
 
-fn gpu_context() -> Option<Arc<GpuContext>> {
-    let res = GPU_CONTEXT.get_or_init(|| {
-        catch_unwind(|| init_gpu_context()).unwrap_or_else(|_| {
-            Err("GPU context init panicked; GPU path disabled for this session".to_string())
+fn uniform_from_f32(ctx: &GpuContext, values: &[f32], label: &str) -> wgpu::Buffer {
+    let mut raw_bytes = Vec::with_capacity(values.len() * 4);
+    for f in values {
+        raw_bytes.extend_from_slice(&f.to_ne_bytes());
+    }
+    ctx.device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(label),
+            contents: &raw_bytes,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         })
-    });
-    match res {
-        Ok(ctx) => Some(ctx.clone()),
-        Err(_) => None,
+}
+
+fn blur_bind_group(
+    ctx: &GpuContext,
+    src: &wgpu::Texture,
+    params: &[f32; 8],
+    label: &str,
+) -> wgpu::BindGroup {
+    let view = input_view(src);
+    let uniform = uniform_from_f32(ctx, params, "openroom-gpu-blur-uniform");
+    ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(label),
+        layout: &ctx.bind_layout_blur,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: uniform.as_entire_binding(),
+            },
+        ],
+    })
+}
+
+// The blur variant that writes `target`'s format.
+fn blur_pipeline<'a>(
+    ctx: &'a GpuContext,
+    target: &wgpu::Texture,
+) -> Option<&'a wgpu::ComputePipeline> {
+    if target.format() == wgpu::TextureFormat::Rgba16Float {
+        enabled(&ctx.pipeline_blur_float, "blur")
+    } else {
+        enabled(&ctx.pipeline_blur, "blur")
     }
 }
 
-pub fn available() -> bool {
-    gpu_context().is_some()
+// Record a horizontal then vertical blur pass of `src` into `dst`, using `mid` as
+// scratch. `mid` should be Rgba16Float so the half-blurred image keeps its precision.
+// Past MAX_BLUR_TAPS per side the taps are strided to keep the cost bounded; each
+// axis then gets a box prefilter as wide as the stride first, so the strided taps
+// do not alias, and the gaussian gives up the variance the box adds.
+fn encode_blur(
+    ctx: &GpuContext,
+    encoder: &mut wgpu::CommandEncoder,
+    src: &wgpu::Texture,
+    mid: &wgpu::Texture,
+    dst: &wgpu::Texture,
+    sigma: f32,
+) -> Option<()> {
+    let reach = (sigma.max(0.1) * 3.0).ceil();
+    let step = (reach / MAX_BLUR_TAPS).ceil().max(1.0);
+    let taps = (reach / step).ceil();
+    let gauss = |sigma: f32, dx: f32, dy: f32| [dx, dy, sigma, taps, step, 0.0, 0.0, 0.0];
+    let scratch =
+        (step > 1.0).then(|| float_target(ctx, src.width(), src.height(), "openroom-gpu-blur-box"));
+    let passes = match scratch.as_deref() {
+        None => vec![
+            (src, mid, gauss(sigma, 1.0, 0.0), "openroom-gpu-blur-h"),
+            (mid, dst, gauss(sigma, 0.0, 1.0), "openroom-gpu-blur-v"),
+        ],
+        Some(scratch) => {
+            let box_width = (step as u32 | 1) as f32;
+            let boxed = |dx: f32, dy: f32| [dx, dy, 0.0, 0.0, 1.0, box_width, 0.0, 0.0];
+            let sigma = (sigma * sigma - (box_width * box_width - 1.0) / 12.0)
+                .max(0.01)
+                .sqrt();
+            vec![
+                (src, scratch, boxed(1.0, 0.0), "openroom-gpu-blur-box-h"),
+                (scratch, mid, gauss(sigma, 1.0, 0.0), "openroom-gpu-blur-h"),
+                (mid, scratch, boxed(0.0, 1.0), "openroom-gpu-blur-box-v"),
+                (scratch, dst, gauss(sigma, 0.0, 1.0), "openroom-gpu-blur-v"),
+            ]
+        }
+    };
+    for (from, to, params, label) in passes {
+        let bind_group = blur_bind_group(ctx, from, &params, label);
+        dispatch(
+            ctx,
+            encoder,
+            to,
+            blur_pipeline(ctx, to)?,
+            &bind_group,
+            label,
+        );
+    }
+    Some(())
 }
 
-// Resize an RGBA8 image using the GPU. Returns None if GPU is unavailable or any step fails.
-pub fn resize_rgba(
+// Clarity/texture in four blur passes plus a combine pass, all in one submission.
+// `clarity`/`texture` are the slider values scaled to -1..1.
+pub fn local_contrast_rgba(
     src: &image::RgbaImage,
-    target_w: u32,
-    target_h: u32,
+    source: Option<&SourceKey>,
+    clarity: f32,
+    texture: f32,
+    coarse_sigma: f32,
+    fine_sigma: f32,
 ) -> Option<image::RgbaImage> {
-    let ctx = gpu_context()?;
-    if target_w == 0 || target_h == 0 {
+    let ctx = render_context()?;
+    let (w, h) = src.dimensions();
+    if w == 0 || h == 0 || !within_limits(&ctx, w, h) {
         return None;
     }
 
-    // Respect device limits; very large RAWs may exceed max texture dimension.
-    if src.width() > ctx.max_safe_dim
-        || src.height() > ctx.max_safe_dim
-        || target_w > ctx.max_safe_dim
-        || target_h > ctx.max_safe_dim
-    {
-        return None;
-    }
-    let pixels = (src.width() as u64) * (src.height() as u64);
-    if pixels > ctx.max_safe_pixels {
+    let src_texture = source_texture(&ctx, src, source, "openroom-gpu-local-contrast-src");
+    let mid_texture = float_target(&ctx, w, h, "openroom-gpu-local-contrast-mid");
+    let coarse_texture = float_target(&ctx, w, h, "openroom-gpu-local-contrast-coarse");
+    let fine_texture = float_target(&ctx, w, h, "openroom-gpu-local-contrast-fine");
+    let dst_texture = color_target(&ctx, w, h, "openroom-gpu-local-contrast-dst");
+
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("openroom-gpu-local-contrast-encoder"),
+        });
+    encode_blur(
+        &ctx,
+        &mut encoder,
+        &src_texture,
+        &mid_texture,
+        &coarse_texture,
+        coarse_sigma,
+    )?;
+    encode_blur(
+        &ctx,
+        &mut encoder,
+        &src_texture,
+        &mid_texture,
+        &fine_texture,
+        fine_sigma,
+    )?;
+
+    let src_view = input_view(&src_texture);
+    let coarse_view = input_view(&coarse_texture);
+    let fine_view = input_view(&fine_texture);
+    let uniform = uniform_from_f32(
+        &ctx,
+        &[clarity, texture, 0.0, 0.0],
+        "openroom-gpu-local-contrast-uniform",
+    );
+    let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("openroom-gpu-bind-local-contrast"),
+        layout: &ctx.bind_layout_local_contrast,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&coarse_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::TextureView(&fine_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: uniform.as_entire_binding(),
+            },
+        ],
+    });
+    dispatch(
+        &ctx,
+        &mut encoder,
+        &dst_texture,
+        enabled(&ctx.pipeline_local_contrast, "local contrast")?,
+        &bind_group,
+        "openroom-gpu-local-contrast-pass",
+    );
+
+    readback_rgba(
+        &ctx,
+        encoder,
+        &dst_texture,
+        w,
+        h,
+        "openroom-gpu-local-contrast-readback",
+    )
+}
+
+// Dehaze as a blur pass pair plus a combine pass. `airlight` is linear RGB,
+// `amount` the slider scaled to -1..1.
+pub fn dehaze_rgba(
+    src: &image::RgbaImage,
+    airlight: [f32; 3],
+    amount: f32,
+    sigma: f32,
+) -> Option<image::RgbaImage> {
+    let ctx = render_context()?;
+    let (w, h) = src.dimensions();
+    if w == 0 || h == 0 || !within_limits(&ctx, w, h) {
         return None;
     }
 
-    let device = &ctx.device;
-    let queue = &ctx.queue;
+    let src_texture = upload_rgba(&ctx, src, "openroom-gpu-dehaze-src");
+    let mid_texture = float_target(&ctx, w, h, "openroom-gpu-dehaze-mid");
+    let blurred_texture = float_target(&ctx, w, h, "openroom-gpu-dehaze-blurred");
+    let dst_texture = color_target(&ctx, w, h, "openroom-gpu-dehaze-dst");
 
-    let src_size = wgpu::Extent3d {
-        width: src.width(),
-        height: src.height(),
-        depth_or_array_layers: 1,
-    };
-    let dst_size = wgpu::Extent3d {
-        width: target_w,
-        height: target_h,
-        depth_or_array_layers: 1,
-    };
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("openroom-gpu-dehaze-encoder"),
+        });
+    encode_blur(
+        &ctx,
+        &mut encoder,
+        &src_texture,
+        &mid_texture,
+        &blurred_texture,
+        sigma,
+    )?;
+
+    let src_view = input_view(&src_texture);
+    let blurred_view = input_view(&blurred_texture);
+    let uniform = uniform_from_f32(
+        &ctx,
+        &[airlight[0], airlight[1], airlight[2], amount],
+        "openroom-gpu-dehaze-uniform",
+    );
+    let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("openroom-gpu-bind-dehaze"),
+        layout: &ctx.bind_layout_dehaze,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&blurred_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: uniform.as_entire_binding(),
+            },
+        ],
+    });
+    dispatch(
+        &ctx,
+        &mut encoder,
+        &dst_texture,
+        enabled(&ctx.pipeline_dehaze, "dehaze")?,
+        &bind_group,
+        "openroom-gpu-dehaze-pass",
+    );
+
+    readback_rgba(
+        &ctx,
+        encoder,
+        &dst_texture,
+        w,
+        h,
+        "openroom-gpu-dehaze-readback",
+    )
+}
 
-    let src_texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("openroom-gpu-src"),
-        size: src_size,
+// The LUT as a 3D texture, uploaded once per table: `lut::cached_lut` returns
+// the same Arc until the file changes.
+fn lut_texture(ctx: &GpuContext, lut: &Arc<crate::lut::Lut3d>) -> Arc<wgpu::Texture> {
+    let mut cached = ctx.lut_table.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((table, texture)) = cached.as_ref() {
+        if Arc::ptr_eq(table, lut) {
+            return texture.clone();
+        }
+    }
+    let n = lut.size as u32;
+    let lut_size = wgpu::Extent3d {
+        width: n,
+        height: n,
+        depth_or_array_layers: n,
+    };
+    let lut_texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("openroom-gpu-lut-table"),
+        size: lut_size,
         mip_level_count: 1,
         sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        dimension: wgpu::TextureDimension::D3,
+        format: wgpu::TextureFormat::Rgba32Float,
         usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         view_formats: &[],
     });
-
-    queue.write_texture(
+    // .cube order is red fastest, then green, then blue: x, y, z of the texture
+    let mut table = Vec::with_capacity(lut.data.len() * 16);
+    for rgb in &lut.data {
+        for v in [rgb[0], rgb[1], rgb[2], 1.0] {
+            table.extend_from_slice(&v.to_ne_bytes());
+        }
+    }
+    ctx.queue.write_texture(
         wgpu::ImageCopyTexture {
-            texture: &src_texture,
+            texture: &lut_texture,
             mip_level: 0,
             origin: wgpu::Origin3d::ZERO,
             aspect: wgpu::TextureAspect::All,
         },
-        src.as_raw(),
+        &table,
         wgpu::ImageDataLayout {
             offset: 0,
-            bytes_per_row: Some(4 * src.width()),
-            rows_per_image: Some(src.height()),
+            bytes_per_row: Some(16 * n),
+            rows_per_image: Some(n),
         },
-        src_size,
+        lut_size,
     );
+    let lut_texture = Arc::new(lut_texture);
+    *cached = Some((lut.clone(), lut_texture.clone()));
+    lut_texture
+}
 
-    let src_view = src_texture.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-        label: Some("openroom-gpu-sampler"),
-        address_mode_u: wgpu::AddressMode::ClampToEdge,
-        address_mode_v: wgpu::AddressMode::ClampToEdge,
-        address_mode_w: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Linear,
-        min_filter: wgpu::FilterMode::Linear,
-        mipmap_filter: wgpu::FilterMode::Linear,
-        ..Default::default()
-    });
+// Apply a 3D LUT, blended over the source by `strength` (0..1).
+pub fn apply_lut_rgba(
+    src: &image::RgbaImage,
+    lut: &Arc<crate::lut::Lut3d>,
+    strength: f32,
+) -> Option<image::RgbaImage> {
+    let ctx = render_context()?;
+    let (w, h) = src.dimensions();
+    let n = lut.size as u32;
+    if w == 0 || h == 0 || !within_limits(&ctx, w, h) {
+        return None;
+    }
+    if n < 2 || n > ctx.device.limits().max_texture_dimension_3d {
+        return None;
+    }
 
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("openroom-gpu-bind-resize"),
-        layout: &ctx.bind_layout_resize,
+    let lut_texture = lut_texture(&ctx, lut);
+    let src_texture = upload_rgba(&ctx, src, "openroom-gpu-lut-src");
+    let dst_texture = color_target(&ctx, w, h, "openroom-gpu-lut-dst");
+    let src_view = input_view(&src_texture);
+    let lut_view = input_view(&lut_texture);
+    let uniform = uniform_from_f32(
+        &ctx,
+        &[
+            lut.domain_min[0],
+            lut.domain_min[1],
+            lut.domain_min[2],
+            lut.size as f32,
+            lut.domain_max[0],
+            lut.domain_max[1],
+            lut.domain_max[2],
+            strength,
+        ],
+        "openroom-gpu-lut-uniform",
+    );
+    let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("openroom-gpu-bind-lut"),
+        layout: &ctx.bind_layout_lut,
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::Sampler(&sampler),
+                resource: wgpu::BindingResource::TextureView(&src_view),
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: wgpu::BindingResource::TextureView(&src_view),
+                resource: wgpu::BindingResource::TextureView(&lut_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: uniform.as_entire_binding(),
             },
         ],
     });
 
-    let dst_texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("openroom-gpu-dst"),
-        size: dst_size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-        view_formats: &[],
-    });
-    let dst_view = dst_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-        label: Some("openroom-gpu-encoder"),
-    });
-
-    {
-        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("openroom-gpu-pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &dst_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            occlusion_query_set: None,
-            timestamp_writes: None,
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("openroom-gpu-lut-encoder"),
         });
-        pass.set_pipeline(&ctx.pipeline_resize);
-        pass.set_bind_group(0, &bind_group, &[]);
-        pass.draw(0..3, 0..1);
-    }
-
-    // Buffer for readback
-    let bytes_per_row = 4 * target_w;
-    let padded_bytes_per_row = ((bytes_per_row as usize
-        + (wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize - 1))
-        / wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize)
-        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
-    let output_buffer_size = (padded_bytes_per_row * target_h as usize) as u64;
-    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("openroom-gpu-readback"),
-        size: output_buffer_size,
-        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-
-    encoder.copy_texture_to_buffer(
-        wgpu::ImageCopyTexture {
-            texture: &dst_texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-            aspect: wgpu::TextureAspect::All,
-        },
-        wgpu::ImageCopyBuffer {
-            buffer: &output_buffer,
-            layout: wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(padded_bytes_per_row as u32),
-                rows_per_image: Some(target_h),
-            },
-        },
-        dst_size,
+    dispatch(
+        &ctx,
+        &mut encoder,
+        &dst_texture,
+        enabled(&ctx.pipeline_lut, "lut")?,
+        &bind_group,
+        "openroom-gpu-lut-pass",
     );
 
-    queue.submit(Some(encoder.finish()));
-
-    // Wait for GPU work to finish and map the buffer.
-    let buffer_slice = output_buffer.slice(..);
-    let (tx, rx) =
-        futures_intrusive::channel::shared::oneshot_channel::<Result<(), wgpu::BufferAsyncError>>();
-    buffer_slice.map_async(wgpu::MapMode::Read, move |res| {
-        let _ = tx.send(res);
-    });
-    device.poll(wgpu::Maintain::Wait);
-    let _ = block_on(rx.receive());
-
-    let data = buffer_slice.get_mapped_range();
-    let mut out = image::RgbaImage::new(target_w, target_h);
-
-    for y in 0..target_h as usize {
-        let src_start = y * padded_bytes_per_row;
-        let src_end = src_start + bytes_per_row as usize;
-        let row = &data[src_start..src_end];
-        let dst_start = y * (bytes_per_row as usize);
-        let dst_end = dst_start + (bytes_per_row as usize);
-        out.as_mut()[dst_start..dst_end].copy_from_slice(row);
-    }
-
-    drop(data);
-    output_buffer.unmap();
-
-    Some(out)
+    readback_rgba(
+        &ctx,
+        encoder,
+        &dst_texture,
+        w,
+        h,
+        "openroom-gpu-lut-readback",
+    )
 }
 
-pub fn apply_globals_rgba(
+// Apply baked per-channel curve tables (equal lengths, input and output 0..1).
+pub fn apply_curves_rgba(
     src: &image::RgbaImage,
-    globals: &crate::models::GlobalAdjustments,
+    tables: &[Vec<f32>; 3],
 ) -> Option<image::RgbaImage> {
-    let ctx = gpu_context()?;
-    if src.width() > ctx.max_safe_dim || src.height() > ctx.max_safe_dim {
+    let ctx = render_context()?;
+    let (w, h) = src.dimensions();
+    let len = tables[0].len() as u32;
+    if w == 0 || h == 0 || !within_limits(&ctx, w, h) {
         return None;
     }
-    let pixels = (src.width() as u64) * (src.height() as u64);
-    if pixels > ctx.max_safe_pixels {
+    if len < 2 || len > ctx.device.limits().max_texture_dimension_1d {
         return None;
     }
 
-    let device = &ctx.device;
-    let queue = &ctx.queue;
-
-    let size = wgpu::Extent3d {
-        width: src.width(),
-        height: src.height(),
+    let table_size = wgpu::Extent3d {
+        width: len,
+        height: 1,
         depth_or_array_layers: 1,
     };
-
-    let src_texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("openroom-gpu-globals-src"),
-        size,
+    let table_texture = ctx.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("openroom-gpu-curves-table"),
+        size: table_size,
         mip_level_count: 1,
         sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        dimension: wgpu::TextureDimension::D1,
+        format: wgpu::TextureFormat::Rgba32Float,
         usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
         view_formats: &[],
     });
-
-    queue.write_texture(
+    let mut texels = Vec::with_capacity(len as usize * 16);
+    for ((r, g), b) in tables[0].iter().zip(&tables[1]).zip(&tables[2]) {
+        for v in [*r, *g, *b, 1.0] {
+            texels.extend_from_slice(&v.to_ne_bytes());
+        }
+    }
+    ctx.queue.write_texture(
         wgpu::ImageCopyTexture {
-            texture: &src_texture,
+            texture: &table_texture,
             mip_level: 0,
             origin: wgpu::Origin3d::ZERO,
             aspect: wgpu::TextureAspect::All,
         },
-        src.as_raw(),
+        &texels,
         wgpu::ImageDataLayout {
             offset: 0,
-            bytes_per_row: Some(4 * src.width()),
-            rows_per_image: Some(src.height()),
+            bytes_per_row: Some(16 * len),
+            rows_per_image: Some(1),
         },
-        size,
+        table_size,
     );
 
-    let src_view = src_texture.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-        label: Some("openroom-gpu-globals-sampler"),
-        address_mode_u: wgpu::AddressMode::ClampToEdge,
-        address_mode_v: wgpu::AddressMode::ClampToEdge,
-        address_mode_w: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Linear,
-        min_filter: wgpu::FilterMode::Linear,
-        mipmap_filter: wgpu::FilterMode::Linear,
-        ..Default::default()
+    let src_texture = upload_rgba(&ctx, src, "openroom-gpu-curves-src");
+    let dst_texture = color_target(&ctx, w, h, "openroom-gpu-curves-dst");
+    let src_view = input_view(&src_texture);
+    let table_view = input_view(&table_texture);
+    let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("openroom-gpu-bind-curves"),
+        layout: &ctx.bind_layout_curves,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&src_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&table_view),
+            },
+        ],
     });
 
-    // Pack globals into a uniform buffer (align to 16-byte multiples).
-    let to_f32 = |v: f32| v;
-    let data_f32 = [
-        to_f32(2f32.powf(globals.exposure_ev)),
-        to_f32(globals.contrast / 100.0),
-        to_f32(globals.highlights / 100.0),
-        to_f32(globals.shadows / 100.0),
-        to_f32(globals.whites / 100.0),
-        to_f32(globals.blacks / 100.0),
-        to_f32(globals.vibrance / 100.0),
-        to_f32(globals.saturation / 100.0),
-        to_f32(globals.temp / 100.0),
-        to_f32(globals.tint / 100.0),
-        0.0,
-        0.0,
-    ];
-    let mut raw_bytes = Vec::with_capacity(data_f32.len() * 4);
-    for f in data_f32 {
-        raw_bytes.extend_from_slice(&f.to_ne_bytes());
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("openroom-gpu-curves-encoder"),
+        });
+    dispatch(
+        &ctx,
+        &mut encoder,
+        &dst_texture,
+        enabled(&ctx.pipeline_curves, "curves")?,
+        &bind_group,
+        "openroom-gpu-curves-pass",
+    );
+
+    readback_rgba(
+        &ctx,
+        encoder,
+        &dst_texture,
+        w,
+        h,
+        "openroom-gpu-curves-readback",
+    )
+}
+
+// Luminance (self-guided filter) and colour (chroma blur) noise reduction.
+// `eps` is the guided filter regulariser, `detail` how much of the removed luma
+// texture is blended back; a sigma of None skips that half.
+pub fn noise_reduction_rgba(
+    src: &image::RgbaImage,
+    source: Option<&SourceKey>,
+    luma_sigma: Option<f32>,
+    eps: f32,
+    detail: f32,
+    chroma_sigma: Option<f32>,
+) -> Option<image::RgbaImage> {
+    let ctx = render_context()?;
+    let (w, h) = src.dimensions();
+    if w == 0 || h == 0 || !within_limits(&ctx, w, h) {
+        return None;
     }
 
-    let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("openroom-gpu-globals-uniform"),
-        contents: &raw_bytes,
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-    });
+    let src_texture = source_texture(&ctx, src, source, "openroom-gpu-nr-src");
+    let packed = float_target(&ctx, w, h, "openroom-gpu-nr-packed");
+    let mid = float_target(&ctx, w, h, "openroom-gpu-nr-mid");
+    let means = float_target(&ctx, w, h, "openroom-gpu-nr-means");
+    let coeffs = float_target(&ctx, w, h, "openroom-gpu-nr-coeffs");
+    let coeff_means = float_target(&ctx, w, h, "openroom-gpu-nr-coeff-means");
+    let chroma = float_target(&ctx, w, h, "openroom-gpu-nr-chroma");
+    let dst_texture = color_target(&ctx, w, h, "openroom-gpu-nr-dst");
 
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        label: Some("openroom-gpu-bind-globals"),
-        layout: &ctx.bind_layout_globals,
+    let mut encoder = ctx
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("openroom-gpu-nr-encoder"),
+        });
+    let pack = blur_bind_group(&ctx, &src_texture, &[0.0; 8], "openroom-gpu-bind-nr-pack");
+    dispatch(
+        &ctx,
+        &mut encoder,
+        &packed,
+        enabled(&ctx.pipeline_nr_pack, "noise reduction")?,
+        &pack,
+        "openroom-gpu-nr-pack",
+    );
+    if let Some(sigma) = luma_sigma {
+        encode_blur(&ctx, &mut encoder, &packed, &mid, &means, sigma)?;
+        let coeff_group = blur_bind_group(
+            &ctx,
+            &means,
+            &[eps, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            "openroom-gpu-bind-nr-coeffs",
+        );
+        dispatch(
+            &ctx,
+            &mut encoder,
+            &coeffs,
+            enabled(&ctx.pipeline_nr_coeffs, "noise reduction")?,
+            &coeff_group,
+            "openroom-gpu-nr-coeffs",
+        );
+        encode_blur(&ctx, &mut encoder, &coeffs, &mid, &coeff_means, sigma)?;
+    }
+    if let Some(sigma) = chroma_sigma {
+        encode_blur(&ctx, &mut encoder, &packed, &mid, &chroma, sigma)?;
+    }
+
+    let src_view = input_view(&src_texture);
+    let coeff_view = input_view(&coeff_means);
+    let chroma_view = input_view(&chroma);
+    let flag = |on: bool| if on { 1.0 } else { 0.0 };
+    let uniform = uniform_from_f32(
+        &ctx,
+        &[
+            flag(luma_sigma.is_some()),
+            detail,
+            flag(chroma_sigma.is_some()),
+            0.0,
+        ],
+        "openroom-gpu-nr-uniform",
+    );
+    let bind_group = ctx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("openroom-gpu-bind-nr-combine"),
+        layout: &ctx.bind_layout_local_contrast,
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::Sampler(&sampler),
+                resource: wgpu::BindingResource::TextureView(&src_view),
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: wgpu::BindingResource::TextureView(&src_view),
+                resource: wgpu::BindingResource::TextureView(&coeff_view),
             },
             wgpu::BindGroupEntry {
                 binding: 2,
-                resource: uniform_buffer.as_entire_binding(),
+                resource: wgpu::BindingResource::TextureView(&chroma_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: uniform.as_entire_binding(),
             },
         ],
     });
-
-    let dst_texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: Some("openroom-gpu-globals-dst"),
-        size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Rgba8UnormSrgb,
-        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-        view_formats: &[],
-    });
-    let dst_view = dst_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
-    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-        label: Some("openroom-gpu-globals-encoder"),
-    });
-
-    {
-        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("openroom-gpu-globals-pass"),
-            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &dst_view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-                    store: wgpu::StoreOp::Store,
-                },
-            })],
-            depth_stencil_attachment: None,
-            occlusion_query_set: None,
-            timestamp_writes: None,
-        });
-        pass.set_pipeline(&ctx.pipeline_globals);
-        pass.set_bind_group(0, &bind_group, &[]);
-        pass.draw(0..3, 0..1);
-    }
-
-    let bytes_per_row = 4 * src.width();
-    let padded_bytes_per_row = ((bytes_per_row as usize
-        + (wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize - 1))
-        / wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize)
-        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
-    let output_buffer_size = (padded_bytes_per_row * src.height() as usize) as u64;
-    let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: Some("openroom-gpu-globals-readback"),
-        size: output_buffer_size,
-        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-
-    encoder.copy_texture_to_buffer(
-        wgpu::ImageCopyTexture {
-            texture: &dst_texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-            aspect: wgpu::TextureAspect::All,
-        },
-        wgpu::ImageCopyBuffer {
-            buffer: &output_buffer,
-            layout: wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(padded_bytes_per_row as u32),
-                rows_per_image: Some(src.height()),
-            },
-        },
-        size,
+    dispatch(
+        &ctx,
+        &mut encoder,
+        &dst_texture,
+        enabled(&ctx.pipeline_nr_combine, "noise reduction")?,
+        &bind_group,
+        "openroom-gpu-nr-combine",
     );
 
-    queue.submit(Some(encoder.finish()));
-
-    let buffer_slice = output_buffer.slice(..);
-    let (tx, rx) =
-        futures_intrusive::channel::shared::oneshot_channel::<Result<(), wgpu::BufferAsyncError>>();
-    buffer_slice.map_async(wgpu::MapMode::Read, move |res| {
-        let _ = tx.send(res);
-    });
-    device.poll(wgpu::Maintain::Wait);
-    let _ = block_on(rx.receive());
-
-    let data = buffer_slice.get_mapped_range();
-    let mut out = image::RgbaImage::new(src.width(), src.height());
-    for y in 0..src.height() as usize {
-        let src_start = y * padded_bytes_per_row;
-        let src_end = src_start + bytes_per_row as usize;
-        let row = &data[src_start..src_end];
-        let dst_start = y * (bytes_per_row as usize);
-        let dst_end = dst_start + (bytes_per_row as usize);
-        out.as_mut()[dst_start..dst_end].copy_from_slice(row);
-    }
-    drop(data);
-    output_buffer.unmap();
-
-    Some(out)
+    readback_rgba(
+        &ctx,
+        encoder,
+        &dst_texture,
+        w,
+        h,
+        "openroom-gpu-nr-readback",
+    )
 }
@@ -0,0 +1,231 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use tauri::{AppHandle, Emitter};
+
+use crate::cache::data_root;
+use crate::catalog::{catalog_key, import_embedded_xmp, load_catalog};
+use crate::models::{AssetSummary, ExportResult, HookScript, HookSettings};
+use crate::shutdown::{begin_job, write_atomic};
+
+// Hooks run arbitrary programs, so they are kept out of the settings the webview
+// writes: hooks.json only changes through set_hooks after a native confirmation.
+static HOOKS: Lazy<RwLock<HookSettings>> =
+    Lazy::new(|| RwLock::new(read_hooks_file().unwrap_or_default()));
+
+// Variables passed through from the app's environment; everything else is dropped
+// so scripts do not inherit tokens or paths they have no business seeing. This is
+// not a sandbox: a hook runs with the user's full privileges.
+const PASSTHROUGH_ENV: [&str; 8] = [
+    "PATH",
+    "HOME",
+    "USERPROFILE",
+    "TMPDIR",
+    "TEMP",
+    "TMP",
+    "LANG",
+    "SystemRoot",
+];
+const WAIT_POLL: Duration = Duration::from_millis(50);
+const IMPORT_FAILED_EVENT: &str = "import-failed";
+// Post-import hooks run with no command waiting on them; their failures go here.
+const HOOK_FAILED_EVENT: &str = "hook-failed";
+
+// Catalog keys an import thread is still working on, so reopening the folder in
+// the meantime does not import them (and run the hook) a second time.
+static IMPORTING: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+fn hooks_path() -> Result<PathBuf, String> {
+    Ok(data_root()?.join("hooks.json"))
+}
+
+fn read_hooks_file() -> Result<HookSettings, String> {
+    let path = hooks_path()?;
+    if !path.exists() {
+        return Ok(HookSettings::default());
+    }
+    let data = fs::read_to_string(&path).map_err(|e| format!("Read hooks failed: {e}"))?;
+    serde_json::from_str(&data).map_err(|e| format!("Parse hooks failed: {e}"))
+}
+
+pub fn current_hooks() -> HookSettings {
+    HOOKS.read().map(|hooks| hooks.clone()).unwrap_or_default()
+}
+
+/// Persist hooks the user has confirmed; callers must have asked first.
+pub fn save_hooks(hooks: &HookSettings) -> Result<(), String> {
+    let serialized =
+        serde_json::to_string_pretty(hooks).map_err(|e| format!("Serialize hooks failed: {e}"))?;
+    write_atomic(&hooks_path()?, serialized).map_err(|e| format!("Write hooks failed: {e}"))?;
+    let mut cached = HOOKS.write().map_err(|e| e.to_string())?;
+    *cached = hooks.clone();
+    Ok(())
+}
+
+/// Run one hook over `path` and wait for it, killing it once the timeout passes.
+/// Output is discarded; a non-zero exit is an error.
+fn run_hook(hook: &HookScript, event: &str, path: &Path, metadata: Value) -> Result<(), String> {
+    if hook.program.trim().is_empty() {
+        return Ok(());
+    }
+    let mut command = Command::new(&hook.program);
+    command
+        .args(&hook.args)
+        .arg(path)
+        .arg(metadata.to_string())
+        .env_clear()
+        .envs(
+            PASSTHROUGH_ENV
+                .iter()
+                .filter_map(|key| std::env::var_os(key).map(|value| (key.to_string(), value))),
+        )
+        .env("OPENROOM_HOOK", event)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    if let Some(dir) = path.parent() {
+        command.current_dir(dir);
+    }
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        const CREATE_NO_WINDOW: u32 = 0x0800_0000;
+        command.creation_flags(CREATE_NO_WINDOW);
+    }
+    let mut child = command
+        .spawn()
+        .map_err(|e| format!("Start {event} hook failed: {e}"))?;
+
+    let deadline = Instant::now() + Duration::from_secs(hook.timeout_secs.max(1));
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return Ok(()),
+            Ok(Some(status)) => return Err(format!("The {event} hook exited with {status}")),
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!(
+                    "The {event} hook timed out after {}s",
+                    hook.timeout_secs.max(1)
+                ));
+            }
+            Ok(None) => thread::sleep(WAIT_POLL),
+            Err(e) => return Err(format!("Wait for {event} hook failed: {e}")),
+        }
+    }
+}
+
+/// The post-export hook for one written file, if one is configured.
+pub fn after_export(source: &Path, result: &ExportResult) -> Result<(), String> {
+    let Some(hook) = current_hooks().post_export else {
+        return Ok(());
+    };
+    let metadata = json!({
+        "event": "export",
+        "assetId": result.asset_id,
+        "source": source.to_string_lossy(),
+        "width": result.width,
+        "height": result.height,
+    });
+    run_hook(&hook, "export", Path::new(&result.output_path), metadata)
+}
+
+// Keys the catalog has not marked imported and no import thread holds, now held.
+fn claim_unimported(assets: &[AssetSummary]) -> Result<Vec<(String, AssetSummary)>, String> {
+    let catalog = load_catalog()?;
+    let mut importing = IMPORTING.lock().map_err(|e| e.to_string())?;
+    let fresh: Vec<(String, AssetSummary)> = assets
+        .iter()
+        .map(|asset| (catalog_key(Path::new(&asset.path)), asset))
+        .filter(|(key, _)| {
+            !importing.contains(key)
+                && catalog
+                    .assets
+                    .get(key)
+                    .is_none_or(|entry| entry.imported_at.is_none())
+        })
+        .map(|(key, asset)| (key, asset.clone()))
+        .collect();
+    importing.extend(fresh.iter().map(|(key, _)| key.clone()));
+    Ok(fresh)
+}
+
+fn release(keys: &[String]) {
+    let mut importing = IMPORTING.lock().unwrap_or_else(|e| e.into_inner());
+    for key in keys {
+        importing.remove(key);
+    }
+}
+
+// Store the embedded XMP with the import mark, then run the post-import hook over
+// the files that were marked.
+fn import_in_background(app: &AppHandle, fresh: Vec<(String, AssetSummary)>) {
+    let (keys, fresh): (Vec<String>, Vec<AssetSummary>) = fresh.into_iter().unzip();
+    let paths: Vec<PathBuf> = fresh
+        .iter()
+        .map(|asset| PathBuf::from(&asset.path))
+        .collect();
+    let imported = match begin_job() {
+        Ok(_job) => import_embedded_xmp(&keys, &paths),
+        Err(_) => Ok(false),
+    };
+    release(&keys);
+    match imported {
+        Ok(true) => {}
+        // shutting down: the unmarked files are picked up by the next import
+        Ok(false) => return,
+        Err(err) => {
+            let _ = app.emit(IMPORT_FAILED_EVENT, err);
+            return;
+        }
+    }
+    let Some(hook) = current_hooks().post_import else {
+        return;
+    };
+    for asset in fresh {
+        let metadata = json!({
+            "event": "import",
+            "assetId": asset.id,
+            "fileName": asset.file_name,
+            "extension": asset.extension,
+        });
+        if let Err(err) = run_hook(&hook, "import", &PathBuf::from(&asset.path), metadata) {
+            let failure = json!({
+                "event": "import",
+                "assetId": asset.id,
+                "path": asset.path,
+                "error": err,
+            });
+            let _ = app.emit(HOOK_FAILED_EVENT, failure);
+        }
+    }
+}
+
+/// Import the assets the catalog has not listed before on a background thread:
+/// their embedded XMP organization is stored with the import mark, then the
+/// post-import hook, when one is configured, runs over each of them and reports
+/// failures as `hook-failed` events. A file is only marked once the catalog
+/// write succeeds, so a failed one is retried on the next open and reported as
+/// an `import-failed` event; a marked file never fires the hook twice.
+pub fn after_import(app: &AppHandle, assets: &[AssetSummary]) {
+    let fresh = match claim_unimported(assets) {
+        Ok(fresh) => fresh,
+        Err(err) => {
+            let _ = app.emit(IMPORT_FAILED_EVENT, err);
+            return;
+        }
+    };
+    // reopening a known folder costs no catalog write
+    if fresh.is_empty() {
+        return;
+    }
+    let app = app.clone();
+    thread::spawn(move || import_in_background(&app, fresh));
+}
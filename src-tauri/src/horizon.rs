@@ -0,0 +1,121 @@
+use std::path::Path;
+
+use image::RgbaImage;
+use rayon::prelude::*;
+
+use crate::crop::{rotation_constrained_rect, straighten};
+use crate::image_io::cached_preview;
+use crate::models::Crop;
+use crate::recipe_io::load_recipe_for_asset;
+
+// Analysed on a small cached variant so the answer does not depend on the viewport.
+const HORIZON_ANALYSIS_DIM: u32 = 720;
+const MAX_TILT_DEGREES: f32 = 15.0;
+const ANGLE_STEP_DEGREES: f32 = 0.1;
+// only the strongest gradients vote
+const EDGE_PERCENTILE: f32 = 0.9;
+const MIN_EDGE_MAGNITUDE: f32 = 0.08;
+// a horizon has to span this share of the frame width to be trusted
+const MIN_LINE_FRACTION: f32 = 0.3;
+
+/// Suggested straighten angle (degrees clockwise, as `Crop::angle`) that levels
+/// the dominant near-horizontal line, from a Hough vote over the cached preview's
+/// edges. None when no line is long enough to be a horizon.
+pub fn detect_horizon(asset_id: &str, path: &Path) -> Result<Option<f32>, String> {
+    let preview = cached_preview(asset_id, path, HORIZON_ANALYSIS_DIM)?;
+    Ok(horizon_angle(&preview))
+}
+
+fn horizon_angle(preview: &RgbaImage) -> Option<f32> {
+    let (w, h) = (preview.width() as usize, preview.height() as usize);
+    if w < 3 || h < 3 {
+        return None;
+    }
+    let luma: Vec<f32> = preview
+        .pixels()
+        .map(|px| (0.2126 * px[0] as f32 + 0.7152 * px[1] as f32 + 0.0722 * px[2] as f32) / 255.0)
+        .collect();
+
+    // Sobel; keep edges that run roughly horizontally (gradient mostly vertical)
+    let mut edges: Vec<(f32, f32, f32)> = Vec::new();
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let at = |dx: usize, dy: usize| luma[(y + dy - 1) * w + (x + dx - 1)];
+            let gx =
+                (at(2, 0) + 2.0 * at(2, 1) + at(2, 2)) - (at(0, 0) + 2.0 * at(0, 1) + at(0, 2));
+            let gy =
+                (at(0, 2) + 2.0 * at(1, 2) + at(2, 2)) - (at(0, 0) + 2.0 * at(1, 0) + at(2, 0));
+            if gx.abs() <= gy.abs() * 0.5 {
+                edges.push((x as f32, y as f32, (gx * gx + gy * gy).sqrt()));
+            }
+        }
+    }
+    let mut magnitudes: Vec<f32> = edges.iter().map(|e| e.2).collect();
+    if magnitudes.is_empty() {
+        return None;
+    }
+    magnitudes.sort_by(f32::total_cmp);
+    let cutoff = magnitudes[((magnitudes.len() - 1) as f32 * EDGE_PERCENTILE) as usize]
+        .max(MIN_EDGE_MAGNITUDE);
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let points: Vec<(f32, f32)> = edges
+        .into_iter()
+        .filter(|e| e.2 >= cutoff)
+        .map(|(x, y, _)| (x - cx, y - cy))
+        .collect();
+
+    // per tilt, the pixel count of the best line (3 px wide to absorb aliasing)
+    let bins = ((w * w + h * h) as f32).sqrt().ceil() as usize + 2;
+    let steps = (2.0 * MAX_TILT_DEGREES / ANGLE_STEP_DEGREES).round() as i32;
+    let (tilt, votes) = (0..=steps)
+        .into_par_iter()
+        .map(|i| {
+            let tilt = -MAX_TILT_DEGREES + i as f32 * ANGLE_STEP_DEGREES;
+            let (sin, cos) = tilt.to_radians().sin_cos();
+            let mut accumulator = vec![0u32; bins];
+            for &(x, y) in &points {
+                let rho = -x * sin + y * cos + bins as f32 / 2.0;
+                if let Some(bin) = accumulator.get_mut(rho.max(0.0) as usize) {
+                    *bin += 1;
+                }
+            }
+            let best = accumulator
+                .windows(3)
+                .map(|win| win.iter().sum::<u32>())
+                .max()
+                .unwrap_or(0);
+            (tilt, best)
+        })
+        .reduce(|| (0.0, 0), |a, b| if b.1 > a.1 { b } else { a });
+
+    if (votes as f32) < w as f32 * MIN_LINE_FRACTION {
+        return None;
+    }
+    // the line descends to the right by `tilt` (y points down); turning the image
+    // back by the same amount levels it
+    Some((-tilt * 10.0).round() / 10.0)
+}
+
+/// The asset's crop straightened to level the horizon and constrained to the
+/// largest rect the straighten rotation leaves valid; other geometry corrections
+/// are not considered. A second vote over the straightened preview corrects what
+/// the first left over. None when no horizon is found.
+pub fn auto_straighten(asset_id: &str, path: &Path) -> Result<Option<Crop>, String> {
+    let preview = cached_preview(asset_id, path, HORIZON_ANALYSIS_DIM)?;
+    let Some(first) = horizon_angle(&preview) else {
+        return Ok(None);
+    };
+    let residual = horizon_angle(&straighten(&preview, first)).unwrap_or(0.0);
+    let angle = ((first + residual) * 10.0).round() / 10.0;
+
+    let mut crop = load_recipe_for_asset(path)?
+        .and_then(|recipe| recipe.crop)
+        .unwrap_or_default();
+    // the angle applies to the mirrored frame, where a single flip reverses it
+    let mirrored = crop.flip_horizontal != crop.flip_vertical;
+    crop.angle = if mirrored { -angle } else { angle }.clamp(-MAX_TILT_DEGREES, MAX_TILT_DEGREES);
+    crop.constrain = true;
+    let (w, h) = preview.dimensions();
+    (crop.x, crop.y, crop.width, crop.height) = rotation_constrained_rect(&crop, w, h);
+    Ok(Some(crop))
+}
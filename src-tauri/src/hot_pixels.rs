@@ -0,0 +1,94 @@
+use image::RgbaImage;
+use rayon::prelude::*;
+
+// A sample is hot when it is this many times brighter than its brightest
+// same-colour neighbour and clears it by the margin (normalized 0..1 units).
+// Real highlights are never a single sample wide, so both hold only for defects.
+const HOT_RATIO: f32 = 2.0;
+const HOT_MARGIN: f32 = 0.1;
+// Radius, in pixels of the image being repaired, around a listed dead pixel.
+const REPAIR_RADIUS_FRACTION: f32 = 1.0 / 4000.0;
+
+// Upper median; callers never pass an empty slice.
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(f32::total_cmp);
+    values[values.len() / 2]
+}
+
+fn is_hot(value: f32, brightest_neighbor: f32) -> bool {
+    value > brightest_neighbor * HOT_RATIO && value - brightest_neighbor > HOT_MARGIN
+}
+
+/// Replace hot sensels of one CFA colour plane (normalized samples, `mask` marking
+/// the sites of that colour) by the median of the same-colour sites two pixels
+/// away. Runs before demosaicing so a defect cannot bleed into its neighbours.
+pub fn suppress_hot_sensels(plane: &mut [f32], mask: &[bool], w: usize, h: usize) {
+    let source = plane.to_vec();
+    let fixed: Vec<(usize, f32)> = (0..w * h)
+        .into_par_iter()
+        .filter(|&idx| mask[idx])
+        .filter_map(|idx| {
+            let (x, y) = ((idx % w) as isize, (idx / w) as isize);
+            let mut neighbors: Vec<f32> = [-2isize, 0, 2]
+                .iter()
+                .flat_map(|&dy| [-2isize, 0, 2].map(move |dx| (dx, dy)))
+                .filter(|&(dx, dy)| dx != 0 || dy != 0)
+                .filter_map(|(dx, dy)| {
+                    let (nx, ny) = (x + dx, y + dy);
+                    let inside = nx >= 0 && ny >= 0 && (nx as usize) < w && (ny as usize) < h;
+                    let n = ny as usize * w + nx as usize;
+                    (inside && mask[n]).then(|| source[n])
+                })
+                .collect();
+            let brightest = neighbors.iter().copied().fold(f32::MIN, f32::max);
+            (neighbors.len() >= 3 && is_hot(source[idx], brightest))
+                .then(|| (idx, median(&mut neighbors)))
+        })
+        .collect();
+    for (idx, value) in fixed {
+        plane[idx] = value;
+    }
+}
+
+/// Fill the listed dead pixels (normalized positions on the uncropped frame) with
+/// the median of the ring just outside them. The patch scales with the image so
+/// previews and full-size exports hide the same spot.
+pub fn repair_pixels(img: &mut RgbaImage, points: &[(f32, f32)]) {
+    let (w, h) = img.dimensions();
+    if points.is_empty() || w < 3 || h < 3 {
+        return;
+    }
+    let radius = ((w.max(h) as f32 * REPAIR_RADIUS_FRACTION).ceil() as i64).max(1);
+    for &(px, py) in points {
+        if !(0.0..=1.0).contains(&px) || !(0.0..=1.0).contains(&py) {
+            continue;
+        }
+        let cx = ((px * w as f32) as i64).min(w as i64 - 1);
+        let cy = ((py * h as f32) as i64).min(h as i64 - 1);
+        let inside = |x: i64, y: i64| x >= 0 && y >= 0 && x < w as i64 && y < h as i64;
+        let ring: Vec<(u32, u32)> = (-radius - 1..=radius + 1)
+            .flat_map(|dy| (-radius - 1..=radius + 1).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| dx.abs().max(dy.abs()) == radius + 1)
+            .map(|(dx, dy)| (cx + dx, cy + dy))
+            .filter(|&(x, y)| inside(x, y))
+            .map(|(x, y)| (x as u32, y as u32))
+            .collect();
+        if ring.is_empty() {
+            continue;
+        }
+        let mut fill = [0u8; 3];
+        for (c, value) in fill.iter_mut().enumerate() {
+            let mut values: Vec<f32> = ring
+                .iter()
+                .map(|&(x, y)| img.get_pixel(x, y)[c] as f32)
+                .collect();
+            *value = median(&mut values).round() as u8;
+        }
+        for y in (cy - radius + 1).max(0)..=(cy + radius - 1).min(h as i64 - 1) {
+            for x in (cx - radius + 1).max(0)..=(cx + radius - 1).min(w as i64 - 1) {
+                let pixel = img.get_pixel_mut(x as u32, y as u32);
+                pixel.0[..3].copy_from_slice(&fill);
+            }
+        }
+    }
+}
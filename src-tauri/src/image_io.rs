@@ -1,22 +1,64 @@
-use std::collections::VecDeque;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::fs;
-use std::io::Cursor;
-use std::path::Path;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io::{BufWriter, Cursor};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 use dashmap::DashMap;
+use image::codecs::jpeg::JpegEncoder;
 use image::codecs::png::{CompressionType, FilterType, PngEncoder};
 use image::imageops::{self, FilterType as ResizeFilter};
-use image::{ColorType, DynamicImage, ImageEncoder, Rgba, RgbaImage};
-use libraw::{ProcessedImage, Processor};
+use image::metadata::Orientation;
+use image::{
+    ColorType, DynamicImage, ImageBuffer, ImageEncoder, Rgb, Rgb32FImage, Rgba, Rgba32FImage,
+    RgbaImage,
+};
+use libraw::Processor;
 use once_cell::sync::Lazy;
 use rawloader::decode_file as decode_raw_file;
 use rawloader::{decode_dummy, RawImage, RawImageData};
 use rayon::prelude::*;
-
-use crate::cache::{cached_path, thumbnails_dir};
+use tauri::{AppHandle, Emitter};
+
+use crate::blur::gaussian_blur_f32;
+use crate::cache::{
+    demosaic_path, enforce_demosaic_cap, full_preview_path, record_thumbnail, thumbnail_indexed,
+    thumbnail_slot,
+};
+use crate::color::{
+    camera_recalibration, camera_to_srgb, convert_icc_to_srgb, dither_offset, linear_to_srgb,
+    source_icc_profile, srgb_to_linear, Mat3,
+};
+use crate::crop::{apply_crop, apply_flips};
+use crate::curves::{apply_curves, apply_levels, curves_are_identity, levels_are_identity};
+use crate::decode_worker::decode_isolated;
+use crate::document::apply_document_mode;
 use crate::gpu;
-use crate::models::{AdjustmentLayer, EditRecipe, GlobalAdjustments};
+use crate::grain::{apply_grain_rgba, resolve_seed};
+use crate::hot_pixels::{repair_pixels, suppress_hot_sensels};
+use crate::jpeg_scaled::decode_jpeg_scaled;
+use crate::lens::{correct_lens, resolve_profile};
+use crate::libraw_mosaic::process_16bit_without_hot_sensels;
+use crate::lut::{apply_lut_blended, cached_lut};
+use crate::mask::{
+    overlay_mask, render_mask_preview, resolve_brush_mask, resolve_brush_masks, MaskSampler,
+};
+use crate::metadata::read_orientation;
+use crate::models::{
+    AdjustmentLayer, BlackAndWhite, ChannelHistogram, DualIlluminant, EditRecipe,
+    FullPreviewProgress, FullPreviewSummary, GlobalAdjustments, GpuProfile, IlluminantBlend, Mask,
+    PaperTone, RawHistogram, SoftProof,
+};
+use crate::perf;
+use crate::proof::apply_soft_proof;
+use crate::recipe_io::load_recipe_for_asset;
+use crate::retouch::apply_retouch;
+use crate::settings::current_settings;
+use crate::shutdown::{begin_job, stopping, write_atomic};
 
 // cache decoded previews to avoid re-decoding per slider move
 type PreviewBuf = Arc<RgbaImage>;
@@ -24,24 +66,102 @@ type PreviewBuf = Arc<RgbaImage>;
 struct CachedPreview {
     buf: PreviewBuf,
     max_dim: u32,
+    generation: u64, // of the master it came from; new per decode
 }
+static PREVIEW_GENERATION: AtomicU64 = AtomicU64::new(0);
 static PREVIEW_MASTERS: Lazy<DashMap<String, CachedPreview>> = Lazy::new(DashMap::new);
 static PREVIEW_VARIANTS: Lazy<DashMap<String, PreviewBuf>> = Lazy::new(DashMap::new);
+// one white-balanced master (and its variants) per asset, rebuilt when temp/tint change
+struct BalancedPreview {
+    wb: (f32, f32, DualIlluminant),
+    master: CachedPreview,
+    variants: HashMap<u32, PreviewBuf>,
+}
+static PREVIEW_BALANCED: Lazy<DashMap<String, BalancedPreview>> = Lazy::new(DashMap::new);
 static PREVIEW_LRU: Lazy<Mutex<VecDeque<String>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+// Negotiated preview size of every open viewport (main window, second screen),
+// by viewport id. Masters are decoded big enough for all of them and their
+// variants are kept while other sizes are pruned.
+static ACTIVE_VIEWPORTS: Lazy<Mutex<HashMap<String, u32>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
 const PREVIEW_CACHE_ASSETS: usize = 2;
-const PREVIEW_MIN_DIM: u32 = 480;
-const PREVIEW_MAX_DIM: u32 = 3200;
+pub(crate) const PREVIEW_MIN_DIM: u32 = 480;
+pub(crate) const PREVIEW_MAX_DIM: u32 = 3200;
 const PREVIEW_MASTER_BASE: u32 = 1920;
+// requested sizes snap up to these, so nearby viewport sizes share one cached variant
+const PREVIEW_SIZE_LADDER: [u32; 8] = [480, 720, 960, 1280, 1440, 1920, 2560, 3200];
+// blur radii for clarity/texture, relative to the long edge so previews match exports
+const CLARITY_SIGMA_FRACTION: f32 = 0.02;
+const TEXTURE_SIGMA_FRACTION: f32 = 0.0025;
+const DEHAZE_SIGMA_FRACTION: f32 = 0.01;
+// highlights/shadows masks follow the luminance blurred at two scales, so local
+// contrast rides on top of the tonal shift instead of being flattened by it
+const TONE_FINE_SIGMA_FRACTION: f32 = 0.004;
+const TONE_COARSE_SIGMA_FRACTION: f32 = 0.03;
+// gain of the highlights/shadows sliders at full strength, in stops
+pub(crate) const TONE_RANGE_EV: f32 = 1.5;
+// noise reduction windows at full strength, relative to the long edge
+const NR_LUMA_SIGMA_FRACTION: f32 = 0.0015;
+const NR_CHROMA_SIGMA_FRACTION: f32 = 0.004;
+// samples used to estimate the atmospheric light; the haziest 0.1% are averaged
+const AIRLIGHT_SAMPLES: usize = 65_536;
+// the benchmark frame of profile_render, about a large preview
+const PROFILE_SIZE: (u32, u32) = (2048, 1365);
+// 1:1 previews are for judging focus and noise, not for export
+const FULL_PREVIEW_QUALITY: u8 = 90;
+const FULL_PREVIEW_EVENT: &str = "full-preview-progress";
 
 fn cache_key(asset_id: &str, max_dimension: u32) -> String {
     format!("{asset_id}:{max_dimension}")
 }
 
 fn normalize_dimension(dim: u32) -> u32 {
-    dim.clamp(PREVIEW_MIN_DIM, PREVIEW_MAX_DIM)
+    let dim = dim.clamp(PREVIEW_MIN_DIM, PREVIEW_MAX_DIM);
+    PREVIEW_SIZE_LADDER
+        .into_iter()
+        .find(|&step| step >= dim)
+        .unwrap_or(PREVIEW_MAX_DIM)
+}
+
+/// Preview long edge for a viewport in CSS pixels at device pixel ratio `dpr`:
+/// the smallest cached size that covers the viewport's physical pixels.
+pub fn negotiate_preview_size(viewport_w: u32, viewport_h: u32, dpr: f32) -> u32 {
+    let dpr = if dpr.is_finite() && dpr > 0.0 {
+        dpr.clamp(0.5, 4.0)
+    } else {
+        1.0
+    };
+    normalize_dimension((viewport_w.max(viewport_h) as f32 * dpr).ceil() as u32)
+}
+
+/// Record the preview size a viewport renders at, replacing its previous size.
+pub fn register_viewport(viewport_id: &str, size: u32) {
+    if let Ok(mut active) = ACTIVE_VIEWPORTS.lock() {
+        active.insert(viewport_id.to_string(), normalize_dimension(size));
+    }
+}
+
+/// Forget a closed viewport; its variants go with the next pruning.
+pub fn release_viewport(viewport_id: &str) {
+    if let Ok(mut active) = ACTIVE_VIEWPORTS.lock() {
+        active.remove(viewport_id);
+    }
+}
+
+fn active_viewport_sizes() -> Vec<u32> {
+    ACTIVE_VIEWPORTS
+        .lock()
+        .map(|active| active.values().copied().collect())
+        .unwrap_or_default()
+}
+
+// A variant survives when some open viewport uses its size or it was just asked for.
+fn keep_variant(size: u32, requested: u32, active: &[u32]) -> bool {
+    size == requested || active.contains(&size)
 }
 
-fn target_size(w: u32, h: u32, max_dimension: u32) -> (u32, u32) {
+/// `w` x `h` scaled so the long edge is `max_dimension`.
+pub fn target_size(w: u32, h: u32, max_dimension: u32) -> (u32, u32) {
     if w == 0 || h == 0 {
         return (1, 1);
     }
@@ -76,17 +196,19 @@ fn evict_if_needed() {
     }
     for id in evicted {
         PREVIEW_MASTERS.remove(&id);
+        PREVIEW_BALANCED.remove(&id);
         let prefix = format!("{id}:");
         PREVIEW_VARIANTS.retain(|k, _| !k.starts_with(&prefix));
     }
 }
 
 fn drop_variants_for(asset_id: &str) {
+    PREVIEW_BALANCED.remove(asset_id);
     let prefix = format!("{asset_id}:");
     PREVIEW_VARIANTS.retain(|k, _| !k.starts_with(&prefix));
 }
 
-fn resize_rgba_preserve_aspect(img: &RgbaImage, max_dimension: u32) -> RgbaImage {
+pub fn resize_rgba_preserve_aspect(img: &RgbaImage, max_dimension: u32) -> RgbaImage {
     let max_dimension = max_dimension.max(1);
     let (nw, nh) = target_size(img.width(), img.height(), max_dimension);
     if nw == img.width() && nh == img.height() {
@@ -102,11 +224,28 @@ fn resize_rgba_preserve_aspect(img: &RgbaImage, max_dimension: u32) -> RgbaImage
     imageops::resize(img, nw, nh, ResizeFilter::CatmullRom)
 }
 
+/// Bilinear sample of `img` at (sx, sy) in pixel-centre coordinates, which the
+/// caller keeps within 0..=w-1 and 0..=h-1.
+pub fn sample_bilinear(img: &RgbaImage, sx: f32, sy: f32) -> [u8; 4] {
+    let (w, h) = img.dimensions();
+    let (x0, y0) = (sx.floor() as u32, sy.floor() as u32);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (fx, fy) = (sx - x0 as f32, sy - y0 as f32);
+    let (p00, p10) = (img.get_pixel(x0, y0), img.get_pixel(x1, y0));
+    let (p01, p11) = (img.get_pixel(x0, y1), img.get_pixel(x1, y1));
+    std::array::from_fn(|c| {
+        let top = p00[c] as f32 + (p10[c] as f32 - p00[c] as f32) * fx;
+        let bottom = p01[c] as f32 + (p11[c] as f32 - p01[c] as f32) * fx;
+        (top + (bottom - top) * fy).round() as u8
+    })
+}
+
 fn store_master(asset_id: &str, img: RgbaImage) -> CachedPreview {
     let max_dim = img.width().max(img.height()).max(1);
     let entry = CachedPreview {
         buf: Arc::new(img),
         max_dim,
+        generation: PREVIEW_GENERATION.fetch_add(1, Ordering::Relaxed),
     };
     PREVIEW_MASTERS.insert(asset_id.to_string(), entry.clone());
     drop_variants_for(asset_id);
@@ -128,32 +267,113 @@ fn master_preview(
         }
     }
 
-    let decode_target = target.max(PREVIEW_MASTER_BASE).min(PREVIEW_MAX_DIM);
+    // big enough for every open viewport, so switching screens does not re-decode
+    // and throw away the other screen's variants
+    let largest_viewport = active_viewport_sizes().into_iter().max().unwrap_or(0);
+    let decode_target = target
+        .max(largest_viewport)
+        .max(PREVIEW_MASTER_BASE)
+        .min(PREVIEW_MAX_DIM);
     let decoded = render_resized(path, decode_target)?;
     Ok(store_master(asset_id, decoded))
 }
 
-fn scaled_preview(asset_id: &str, path: &Path, requested_dim: u32) -> Result<PreviewBuf, String> {
+fn scaled_preview(
+    asset_id: &str,
+    path: &Path,
+    requested_dim: u32,
+) -> Result<CachedPreview, String> {
     let target = normalize_dimension(requested_dim);
     let master = master_preview(asset_id, path, target)?;
     let master_dim = master.max_dim;
 
     if target >= master_dim.saturating_sub(4) {
-        return Ok(master.buf);
+        return Ok(master);
     }
+    let variant = |buf: PreviewBuf| CachedPreview {
+        buf,
+        max_dim: target,
+        generation: master.generation,
+    };
 
     let key = cache_key(asset_id, target);
     if let Some(existing) = PREVIEW_VARIANTS.get(&key) {
         touch_asset(asset_id);
-        return Ok(existing.clone());
+        return Ok(variant(existing.clone()));
     }
 
     let resized = resize_rgba_preserve_aspect(&master.buf, target);
     let arc = Arc::new(resized);
+    let active = active_viewport_sizes();
+    let prefix = format!("{asset_id}:");
+    PREVIEW_VARIANTS.retain(|k, _| {
+        k.strip_prefix(&prefix)
+            .and_then(|size| size.parse().ok())
+            .is_none_or(|size| keep_variant(size, target, &active))
+    });
     PREVIEW_VARIANTS.insert(key, arc.clone());
     touch_asset(asset_id);
     evict_if_needed();
-    Ok(arc)
+    Ok(variant(arc))
+}
+
+// Like scaled_preview, but white balance is applied to the master in linear light
+// before any downscale so every preview size shares the exact same cast.
+fn balanced_preview(
+    asset_id: &str,
+    path: &Path,
+    requested_dim: u32,
+    globals: &GlobalAdjustments,
+) -> Result<CachedPreview, String> {
+    let target = normalize_dimension(requested_dim);
+    let master = master_preview(asset_id, path, target)?;
+    let wb = (globals.temp, globals.tint, globals.dual_illuminant.clone());
+
+    let stale = !PREVIEW_BALANCED
+        .get(asset_id)
+        .is_some_and(|hit| hit.wb == wb && hit.master.max_dim == master.max_dim);
+    if stale {
+        let mut img = (*master.buf).clone();
+        apply_white_balance(&mut img, globals);
+        PREVIEW_BALANCED.insert(
+            asset_id.to_string(),
+            BalancedPreview {
+                wb,
+                master: CachedPreview {
+                    buf: Arc::new(img),
+                    max_dim: master.max_dim,
+                    generation: master.generation,
+                },
+                variants: HashMap::new(),
+            },
+        );
+    }
+    let Some(balanced) = PREVIEW_BALANCED.get(asset_id).map(|hit| hit.master.clone()) else {
+        return Err("Balanced preview was evicted".into());
+    };
+    if target >= balanced.max_dim.saturating_sub(4) {
+        return Ok(balanced);
+    }
+    let variant = |buf: PreviewBuf| CachedPreview {
+        buf,
+        max_dim: target,
+        generation: balanced.generation,
+    };
+
+    if let Some(existing) = PREVIEW_BALANCED
+        .get(asset_id)
+        .and_then(|hit| hit.variants.get(&target).cloned())
+    {
+        return Ok(variant(existing));
+    }
+    let resized = Arc::new(resize_rgba_preserve_aspect(&balanced.buf, target));
+    if let Some(mut hit) = PREVIEW_BALANCED.get_mut(asset_id) {
+        let active = active_viewport_sizes();
+        hit.variants
+            .retain(|&size, _| keep_variant(size, target, &active));
+        hit.variants.insert(target, resized.clone());
+    }
+    Ok(variant(resized))
 }
 
 fn channels_from_len(len: usize, w: u32, h: u32) -> Option<usize> {
@@ -169,53 +389,25 @@ fn channels_from_len(len: usize, w: u32, h: u32) -> Option<usize> {
     }
 }
 
-fn libraw_to_rgba_u8(img: &ProcessedImage<u8>) -> Result<RgbaImage, String> {
-    let w = img.width();
-    let h = img.height();
-    let data: &[u8] = img;
-    let channels = channels_from_len(data.len(), w, h).ok_or_else(|| {
-        format!(
-            "LibRaw returned unexpected buffer size ({} bytes for {}x{})",
-            data.len(),
-            w,
-            h
-        )
-    })?;
-
-    let mut rgba = RgbaImage::new(w, h);
-    for (idx, pixel) in rgba.pixels_mut().enumerate() {
-        let base = idx * channels;
-        let (r, g, b, a) = match channels {
-            1 => {
-                let v = *data.get(base).unwrap_or(&0);
-                (v, v, v, 255)
-            }
-            2 => {
-                let v = *data.get(base).unwrap_or(&0);
-                let a = *data.get(base + 1).unwrap_or(&255);
-                (v, v, v, a)
-            }
-            _ => {
-                let r = *data.get(base).unwrap_or(&0);
-                let g = *data.get(base + 1).unwrap_or(&r);
-                let b = *data.get(base + 2).unwrap_or(&g);
-                let a = if channels > 3 {
-                    *data.get(base + 3).unwrap_or(&255)
-                } else {
-                    255
-                };
-                (r, g, b, a)
-            }
-        };
-        *pixel = Rgba([r, g, b, a]);
+// LibRaw's default output curve: power 0.45 with a linear toe of slope 4.5 (the
+// BT.709 curve), not sRGB's. Inverted exactly so LibRaw decodes land in the same
+// linear light as rawloader ones.
+fn libraw_curve_to_linear(v: f32) -> f32 {
+    if v < 4.5 * 0.018 {
+        v / 4.5
+    } else {
+        ((v + 0.099) / 1.099).powf(1.0 / 0.45)
     }
-    Ok(rgba)
 }
 
-fn libraw_to_rgba_u16(img: &ProcessedImage<u16>) -> Result<RgbaImage, String> {
-    let w = img.width();
-    let h = img.height();
-    let data: &[u16] = img;
+// LibRaw output (gamma-encoded, `max` at white) to linear light. Alpha is
+// dropped; a RAW has none.
+fn libraw_to_linear<T: Copy + Into<u32> + Sync>(
+    data: &[T],
+    w: u32,
+    h: u32,
+    max: u32,
+) -> Result<Rgb32FImage, String> {
     let channels = channels_from_len(data.len(), w, h).ok_or_else(|| {
         format!(
             "LibRaw returned unexpected buffer size ({} samples for {}x{})",
@@ -224,96 +416,276 @@ fn libraw_to_rgba_u16(img: &ProcessedImage<u16>) -> Result<RgbaImage, String> {
             h
         )
     })?;
-
-    let mut rgba = RgbaImage::new(w, h);
-    let to_byte = |v: u16| -> u8 { (v >> 8) as u8 };
-
-    for (idx, pixel) in rgba.pixels_mut().enumerate() {
-        let base = idx * channels;
-        let (r16, g16, b16, a16) = match channels {
-            1 => {
-                let v = *data.get(base).unwrap_or(&0);
-                (v, v, v, 65535)
-            }
-            2 => {
-                let v = *data.get(base).unwrap_or(&0);
-                let a = *data.get(base + 1).unwrap_or(&65535);
-                (v, v, v, a)
-            }
-            _ => {
-                let r = *data.get(base).unwrap_or(&0);
-                let g = *data.get(base + 1).unwrap_or(&r);
-                let b = *data.get(base + 2).unwrap_or(&g);
-                let a = if channels > 3 {
-                    *data.get(base + 3).unwrap_or(&65535)
-                } else {
-                    65535
-                };
-                (r, g, b, a)
-            }
-        };
-        *pixel = Rgba([to_byte(r16), to_byte(g16), to_byte(b16), to_byte(a16)]);
-    }
-    Ok(rgba)
+    let to_linear: Vec<f32> = (0..=max)
+        .map(|v| libraw_curve_to_linear(v as f32 / max as f32))
+        .collect();
+    let sample = |v: T| to_linear[(v.into() as usize).min(max as usize)];
+
+    let mut linear = Rgb32FImage::new(w, h);
+    linear
+        .par_chunks_exact_mut(3)
+        .zip(data.par_chunks_exact(channels))
+        .for_each(|(px, src)| {
+            let rgb = match channels {
+                1 | 2 => [src[0]; 3],
+                _ => [src[0], src[1], src[2]],
+            };
+            px.copy_from_slice(&rgb.map(sample));
+        });
+    Ok(linear)
 }
 
-fn decode_with_libraw(bytes: &[u8]) -> Result<DynamicImage, String> {
-    match Processor::new().process_16bit(bytes) {
-        Ok(processed) => {
-            let rgba = libraw_to_rgba_u16(&processed)?;
-            Ok(DynamicImage::ImageRgba8(rgba))
-        }
-        Err(err16) => match Processor::new().process_8bit(bytes) {
+fn decode_with_libraw(bytes: &[u8]) -> Result<Rgb32FImage, String> {
+    // hot sensels are fixed in LibRaw's own mosaic, before it demosaics; if that
+    // route fails the plain processing below reports the error
+    let fixed = (!current_settings().keep_hot_pixels)
+        .then(|| process_16bit_without_hot_sensels(bytes).ok())
+        .flatten();
+    let mut linear = match fixed {
+        Some((w, h, samples)) => libraw_to_linear(&samples, w, h, 65535)?,
+        None => match Processor::new().process_16bit(bytes) {
             Ok(processed) => {
-                let rgba = libraw_to_rgba_u8(&processed)?;
-                Ok(DynamicImage::ImageRgba8(rgba))
+                libraw_to_linear(&processed, processed.width(), processed.height(), 65535)?
             }
-            Err(err8) => Err(format!(
-                "LibRaw decode failed (16-bit: {err16}; 8-bit: {err8})"
-            )),
+            Err(err16) => match Processor::new().process_8bit(bytes) {
+                Ok(processed) => {
+                    libraw_to_linear(&processed, processed.width(), processed.height(), 255)?
+                }
+                Err(err8) => {
+                    return Err(format!(
+                        "LibRaw decode failed (16-bit: {err16}; 8-bit: {err8})"
+                    ))
+                }
+            },
         },
+    };
+    reconstruct_clipped(&mut linear);
+    if let Some(matrix) = libraw_recalibration(bytes) {
+        linear.par_chunks_exact_mut(3).for_each(|px| {
+            px.copy_from_slice(&camera_rgb_to_linear([px[0], px[1], px[2]], Some(&matrix)));
+        });
     }
+    Ok(linear)
 }
 
-fn load_dynamic_image(path: &Path) -> Result<DynamicImage, String> {
+// Turn a decode upright per the EXIF Orientation tag. The `image` crate and
+// rawloader hand back pixels as stored; LibRaw output is already rotated.
+pub(crate) fn apply_exif_orientation(mut img: DynamicImage, path: &Path) -> DynamicImage {
+    if let Some(orientation) = read_orientation(path).and_then(Orientation::from_exif) {
+        img.apply_orientation(orientation);
+    }
+    img
+}
+
+// `keep_demosaic` asks for a RAW decode to be cached; only full-resolution
+// decodes (exports, 1:1 previews) are worth the disk space.
+fn load_dynamic_image(path: &Path, keep_demosaic: bool) -> Result<DynamicImage, String> {
+    let _timer = perf::Timer::start("decode");
     match image::open(path) {
-        Ok(img) => Ok(img),
+        Ok(img) => Ok(apply_exif_orientation(img, path)),
         Err(primary) => {
+            // A RAW decoded before comes back from the cache without demosaicing
+            if let Some(linear) = load_demosaiced(path) {
+                return Ok(DynamicImage::ImageRgba8(encode_linear(&linear)));
+            }
+
             // Fallback 1: try loading from raw bytes to handle uppercase/ext edge cases
             let bytes = fs::read(path).map_err(|e| format!("Failed to read image bytes: {e}"))?;
             if let Ok(img_mem) = image::load_from_memory(&bytes) {
-                return Ok(img_mem);
+                return Ok(apply_exif_orientation(img_mem, path));
             }
 
-            // Fallback 2: LibRaw for broad RAW coverage (ARW/DNG/CR3...)
-            let libraw_err = match decode_with_libraw(&bytes) {
-                Ok(img) => return Ok(img),
-                Err(err) => err,
-            };
+            // The remaining fallbacks run native RAW decoders; optionally keep them
+            // out of this process so a decoder crash cannot take the app down. The
+            // worker only hands back 8-bit pixels, so those decodes are not cached.
+            if current_settings().isolate_raw_decodes {
+                return decode_isolated(path)
+                    .map_err(|e| format!("Failed to decode image: {primary}; {e}"));
+            }
+            decode_raw_fallbacks(path, bytes, &primary.to_string(), keep_demosaic)
+        }
+    }
+}
 
-            // Fallback 3: rawloader for RAW formats
-            match decode_raw_file(path) {
-                Ok(raw) => raw_to_rgba(raw),
-                Err(raw_err) => {
-                    let mut hint = format!("{raw_err}");
-                    if hint.contains("Couldn't find camera") {
-                        hint = format!(
-                            "{hint}. Try converting to DNG (lossless) or using a supported camera profile."
-                        );
-                    }
-                    let libraw_hint = format!("; LibRaw fallback: {libraw_err}");
-                    // Try a dummy decode as a last resort (may lack accurate WB/colors but shows pixels)
-                    let mut reader = Cursor::new(bytes);
-                    decode_dummy(&mut reader)
-                        .map_err(|e| {
-                            format!(
-                                "Failed to decode image: {primary}{libraw_hint}; raw decode: {hint}; dummy decode: {e}"
-                            )
-                        })
-                        .and_then(raw_to_rgba)
-                }
+// Bumped when the cached sample format changes, so old files just miss.
+const DEMOSAIC_FORMAT: u32 = 2;
+// One cache write at a time; a decode finishing while another is being written
+// is simply not kept, which bounds the memory held by pending writes.
+static DEMOSAIC_WRITING: AtomicBool = AtomicBool::new(false);
+
+// The settings a RAW decode's pixels depend on, part of its cache key.
+fn decode_fingerprint() -> String {
+    let settings = current_settings();
+    // sorted: a HashMap's order changes between launches, and so would the key
+    let calibrations: BTreeMap<_, _> = settings.camera_calibrations.iter().collect();
+    serde_json::to_string(&(DEMOSAIC_FORMAT, settings.keep_hot_pixels, calibrations))
+        .unwrap_or_default()
+}
+
+// IEEE half-float bits, rounded to nearest; out of range saturates to infinity.
+fn f16_bits(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exp == 0xff {
+        return sign | 0x7c00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let half_exp = exp - 127 + 15;
+    if half_exp >= 0x1f {
+        return sign | 0x7c00;
+    }
+    if half_exp <= 0 {
+        if half_exp < -10 {
+            return sign;
+        }
+        let full = mantissa | 0x80_0000;
+        let shift = (14 - half_exp) as u32;
+        let rounded = (full >> shift) + ((full >> (shift - 1)) & 1);
+        return sign | rounded as u16;
+    }
+    // a carry out of the mantissa correctly bumps the exponent
+    let packed = ((half_exp as u32) << 10 | mantissa >> 13) + ((mantissa >> 12) & 1);
+    sign | packed as u16
+}
+
+fn f16_value(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exp = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    match exp {
+        0 => {
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            if sign != 0 {
+                -magnitude
+            } else {
+                magnitude
             }
         }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exp + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+// Linear light to the sRGB-encoded RGBA the editing pipeline runs on.
+fn encode_linear(linear: &Rgb32FImage) -> RgbaImage {
+    let mut rgba = RgbaImage::new(linear.width(), linear.height());
+    rgba.as_mut()
+        .par_chunks_exact_mut(4)
+        .zip(linear.par_chunks_exact(3))
+        .for_each(|(px, src)| {
+            for c in 0..3 {
+                px[c] = (linear_to_srgb(src[c].clamp(0.0, 1.0)) * 255.0).round() as u8;
+            }
+            px[3] = 255;
+        });
+    rgba
+}
+
+fn load_demosaiced(path: &Path) -> Option<Rgb32FImage> {
+    let slot = demosaic_path(path, &decode_fingerprint()).ok()?;
+    if !slot.exists() {
+        return None;
+    }
+    let packed = image::open(&slot).ok()?.into_rgb16();
+    let samples: Vec<f32> = packed.as_raw().par_iter().map(|&v| f16_value(v)).collect();
+    Rgb32FImage::from_raw(packed.width(), packed.height(), samples)
+}
+
+// Write half-float samples, packed as 16-bit PNG so deflate can squeeze them.
+fn write_demosaiced(slot: &Path, packed: &ImageBuffer<Rgb<u16>, Vec<u16>>) -> Result<(), String> {
+    // written aside and renamed, so a reader never sees a partial file
+    let partial = slot.with_extension("part");
+    let file =
+        fs::File::create(&partial).map_err(|e| format!("Write demosaic cache failed: {e}"))?;
+    packed
+        .write_with_encoder(PngEncoder::new_with_quality(
+            BufWriter::new(file),
+            CompressionType::Default,
+            FilterType::Adaptive,
+        ))
+        .map_err(|e| format!("Encode demosaic cache failed: {e}"))?;
+    fs::rename(&partial, slot).map_err(|e| format!("Write demosaic cache failed: {e}"))
+}
+
+// Keep a full-resolution RAW decode as linear half floats, so exports and 1:1
+// previews of the file skip demosaicing next time. The samples are packed here
+// and written on a background thread, which then holds the cache to its cap.
+fn store_demosaiced(path: &Path, linear: &Rgb32FImage) {
+    let Ok(slot) = demosaic_path(path, &decode_fingerprint()) else {
+        return;
+    };
+    if slot.exists() || DEMOSAIC_WRITING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    let samples: Vec<u16> = linear
+        .as_raw()
+        .par_iter()
+        .map(|&v| f16_bits(v.clamp(0.0, 65504.0)))
+        .collect();
+    let Some(packed) = ImageBuffer::from_raw(linear.width(), linear.height(), samples) else {
+        DEMOSAIC_WRITING.store(false, Ordering::Release);
+        return;
+    };
+    std::thread::spawn(move || {
+        // the cache only saves time; a failed write just means decoding again
+        if let Ok(_job_guard) = begin_job() {
+            if write_demosaiced(&slot, &packed).is_ok() {
+                let _ = enforce_demosaic_cap();
+            }
+        }
+        DEMOSAIC_WRITING.store(false, Ordering::Release);
+    });
+}
+
+/// RAW decoders tried after the `image` crate gave up: LibRaw, then rawloader, then
+/// rawloader's dummy decode. Also the body of the isolated decode worker. With
+/// `keep_demosaic` the decode is also stored in the demosaic cache.
+pub fn decode_raw_fallbacks(
+    path: &Path,
+    bytes: Vec<u8>,
+    primary: &str,
+    keep_demosaic: bool,
+) -> Result<DynamicImage, String> {
+    let linear = decode_raw_linear(path, bytes, primary)?;
+    if keep_demosaic {
+        store_demosaiced(path, &linear);
+    }
+    Ok(DynamicImage::ImageRgba8(encode_linear(&linear)))
+}
+
+fn decode_raw_linear(path: &Path, bytes: Vec<u8>, primary: &str) -> Result<Rgb32FImage, String> {
+    // Fallback 2: LibRaw for broad RAW coverage (ARW/DNG/CR3...)
+    let libraw_err = match decode_with_libraw(&bytes) {
+        Ok(img) => return Ok(img),
+        Err(err) => err,
+    };
+
+    let upright = |linear: Rgb32FImage| {
+        apply_exif_orientation(DynamicImage::ImageRgb32F(linear), path).into_rgb32f()
+    };
+    // Fallback 3: rawloader for RAW formats
+    match decode_raw_file(path) {
+        Ok(raw) => raw_to_linear(raw).map(upright),
+        Err(raw_err) => {
+            let mut hint = format!("{raw_err}");
+            if hint.contains("Couldn't find camera") {
+                hint = format!(
+                    "{hint}. Try converting to DNG (lossless) or using a supported camera profile."
+                );
+            }
+            let libraw_hint = format!("; LibRaw fallback: {libraw_err}");
+            // Try a dummy decode as a last resort (may lack accurate WB/colors but shows pixels)
+            let mut reader = Cursor::new(bytes);
+            decode_dummy(&mut reader)
+                .map_err(|e| {
+                    format!(
+                        "Failed to decode image: {primary}{libraw_hint}; raw decode: {hint}; dummy decode: {e}"
+                    )
+                })
+                .and_then(raw_to_linear)
+                .map(upright)
+        }
     }
 }
 
@@ -324,7 +696,207 @@ fn normalize_sample(val: f32, black: f32, white: f32) -> f32 {
     ((val - black) / (white - black)).clamp(0.0, 1.0)
 }
 
-fn raw_to_rgba(raw: RawImage) -> Result<DynamicImage, String> {
+// Sensor values at or above this (normalized) are treated as clipped.
+const HIGHLIGHT_CLIP: f32 = 0.995;
+// Reconstructed highlights are rolled off above this level to fit below white.
+const HIGHLIGHT_KNEE: f32 = 0.8;
+// Reach of the neighbourhood a clipped LibRaw channel takes its colour from, as
+// a fraction of the long edge.
+const HIGHLIGHT_NEIGHBOURHOOD: f32 = 0.004;
+
+// Camera white balance multipliers scaled so the smallest is 1, or None when the
+// decoder has no usable as-shot coefficients for this body.
+fn wb_multipliers(raw: &RawImage) -> Option<[f32; 3]> {
+    let wb = [raw.wb_coeffs[0], raw.wb_coeffs[1], raw.wb_coeffs[2]];
+    if wb.iter().any(|m| !m.is_finite() || *m <= 0.0) {
+        return None;
+    }
+    let min = wb[0].min(wb[1]).min(wb[2]);
+    Some(wb.map(|m| m / min))
+}
+
+// White balance the channels with the camera multipliers `mul`, rebuilding
+// clipped channels from the unclipped ones on the way: balanced is where a
+// neutral highlight has equal channels, so a clipped channel is raised to the
+// brightest unclipped one, and fully clipped pixels to the highest clip level.
+// Balancing and rebuilding push values past 1, so values above the knee are
+// compressed to keep the recovered gradation below white, where the highlights
+// slider can reach it.
+fn balance_and_reconstruct(r: &mut [f32], g: &mut [f32], b: &mut [f32], mul: [f32; 3]) {
+    let top = mul[0].max(mul[1]).max(mul[2]);
+
+    let peak = r
+        .par_iter_mut()
+        .zip(g.par_iter_mut())
+        .zip(b.par_iter_mut())
+        .map(|((r, g), b)| {
+            let raw = [*r, *g, *b];
+            let clipped = raw.map(|v| v >= HIGHLIGHT_CLIP);
+            let balanced = [raw[0] * mul[0], raw[1] * mul[1], raw[2] * mul[2]];
+            let mut out = balanced;
+            if clipped.iter().any(|&c| c) {
+                let known = (0..3)
+                    .filter(|&c| !clipped[c])
+                    .map(|c| balanced[c])
+                    .fold(None, |acc: Option<f32>, v| {
+                        Some(acc.map_or(v, |a| a.max(v)))
+                    });
+                for c in 0..3 {
+                    if clipped[c] {
+                        out[c] = known.unwrap_or(top).max(balanced[c]);
+                    }
+                }
+            }
+            *r = out[0];
+            *g = out[1];
+            *b = out[2];
+            out[0].max(out[1]).max(out[2])
+        })
+        .reduce(|| 0.0, f32::max);
+
+    for plane in [r, g, b] {
+        roll_off_highlights(plane, peak);
+    }
+}
+
+// Compress values above the knee so `peak` lands on white: t / (1 + k t), slope 1
+// at the knee. Nothing to do when the peak is already at or below white.
+fn roll_off_highlights(values: &mut [f32], peak: f32) {
+    if peak <= 1.0 {
+        return;
+    }
+    let span = 1.0 - HIGHLIGHT_KNEE;
+    let peak_t = (peak - HIGHLIGHT_KNEE) / span;
+    let k = (peak_t - 1.0) / peak_t;
+    values.par_iter_mut().for_each(|v| {
+        if *v > HIGHLIGHT_KNEE {
+            let t = (*v - HIGHLIGHT_KNEE) / span;
+            *v = HIGHLIGHT_KNEE + span * t / (1.0 + k * t);
+        }
+    });
+}
+
+// LibRaw clips every channel at one level after white balancing, so a clipped
+// channel there is never below the others and the neutral rebuild above has
+// nothing to raise. Instead a clipped channel takes its ratio to the unclipped
+// ones from the unclipped pixels around it, fully clipped pixels go to the
+// brightest level rebuilt, and the result is rolled off like the rawloader path.
+fn reconstruct_clipped(linear: &mut Rgb32FImage) {
+    let (w, h) = (linear.width() as usize, linear.height() as usize);
+    let clipped = |px: &[f32]| px.iter().filter(|&&v| v >= HIGHLIGHT_CLIP).count();
+    if !linear.par_chunks_exact(3).any(|px| clipped(px) > 0) {
+        return;
+    }
+    let full: Vec<bool> = linear
+        .par_chunks_exact(3)
+        .map(|px| clipped(px) == 3)
+        .collect();
+    // colour of the unclipped neighbourhood: blurred channels plus the blurred mask
+    let mut around = vec![0f32; w * h * 4];
+    around
+        .par_chunks_exact_mut(4)
+        .zip(linear.par_chunks_exact(3))
+        .for_each(|(sum, px)| {
+            if clipped(px) == 0 {
+                sum[..3].copy_from_slice(px);
+                sum[3] = 1.0;
+            }
+        });
+    let sigma = (w.max(h) as f32 * HIGHLIGHT_NEIGHBOURHOOD).max(2.0);
+    gaussian_blur_f32(&mut around, w, h, 4, sigma);
+
+    let peak = linear
+        .par_chunks_exact_mut(3)
+        .zip(around.par_chunks_exact(4))
+        .map(|(px, around)| {
+            let count = clipped(px);
+            if count > 0 && count < 3 && around[3] > 1e-6 {
+                let known = [px[0], px[1], px[2]];
+                for c in 0..3 {
+                    if known[c] < HIGHLIGHT_CLIP {
+                        continue;
+                    }
+                    let estimate = (0..3)
+                        .filter(|&k| known[k] < HIGHLIGHT_CLIP && around[k] > 1e-6)
+                        .map(|k| known[k] * around[c] / around[k])
+                        .fold(0.0, f32::max);
+                    px[c] = px[c].max(estimate);
+                }
+            }
+            px[0].max(px[1]).max(px[2])
+        })
+        .reduce(|| 0.0, f32::max);
+    if peak <= 1.0 {
+        return;
+    }
+    linear
+        .par_chunks_exact_mut(3)
+        .zip(full.par_iter())
+        .filter(|(_, full)| **full)
+        .for_each(|(px, _)| px.fill(peak));
+    roll_off_highlights(linear, peak);
+}
+
+// LibRaw converts with its built-in matrix and the binding takes no other, so a
+// calibration from settings is applied to its output instead: back to camera RGB
+// through rawloader's stock matrix for the body (both come from Adobe's DNG
+// tables), then forward through the override. rawloader's dummy decode only
+// reads the metadata.
+fn libraw_recalibration(bytes: &[u8]) -> Option<Mat3> {
+    let calibrations = current_settings().camera_calibrations;
+    if calibrations.is_empty() {
+        return None;
+    }
+    let raw = decode_dummy(&mut Cursor::new(bytes)).ok()?;
+    let calibration = calibrations.get(&format!("{} {}", raw.clean_make, raw.clean_model))?;
+    let stock = [raw.xyz_to_cam[0], raw.xyz_to_cam[1], raw.xyz_to_cam[2]];
+    camera_recalibration(&stock, &calibration.color_matrix)
+}
+
+// Camera RGB -> linear sRGB for a rawloader decode: the per-camera override from
+// settings, else the decoder's own matrix.
+fn camera_matrix(raw: &RawImage) -> Option<Mat3> {
+    let camera = format!("{} {}", raw.clean_make, raw.clean_model);
+    let xyz_to_cam = match current_settings().camera_calibrations.get(&camera) {
+        Some(calibration) => calibration.color_matrix,
+        None => [raw.xyz_to_cam[0], raw.xyz_to_cam[1], raw.xyz_to_cam[2]],
+    };
+    camera_to_srgb(&xyz_to_cam)
+}
+
+// Camera RGB to linear sRGB primaries, clipped to 0..1; without a matrix the
+// channels are taken as sRGB primaries.
+fn camera_rgb_to_linear(rgb: [f32; 3], matrix: Option<&Mat3>) -> [f32; 3] {
+    let linear = match matrix {
+        Some(m) => [0, 1, 2].map(|r| (0..3).map(|c| m[r][c] * rgb[c]).sum::<f32>()),
+        None => rgb,
+    };
+    linear.map(|v| v.clamp(0.0, 1.0))
+}
+
+// Demosaiced (or natively RGB) camera planes to linear sRGB: white balanced
+// first when the multipliers are known, since the camera matrix expects
+// balanced input.
+fn camera_planes_to_linear(
+    (w, h): (u32, u32),
+    [mut r, mut g, mut b]: [Vec<f32>; 3],
+    mul: Option<[f32; 3]>,
+    matrix: Option<&Mat3>,
+) -> Rgb32FImage {
+    if let Some(mul) = mul {
+        balance_and_reconstruct(&mut r, &mut g, &mut b, mul);
+    }
+    let mut linear = Rgb32FImage::new(w, h);
+    linear
+        .par_chunks_exact_mut(3)
+        .enumerate()
+        .for_each(|(idx, px)| {
+            px.copy_from_slice(&camera_rgb_to_linear([r[idx], g[idx], b[idx]], matrix));
+        });
+    linear
+}
+
+fn raw_to_linear(raw: RawImage) -> Result<Rgb32FImage, String> {
     let w = raw.width as u32;
     let h = raw.height as u32;
 
@@ -336,194 +908,253 @@ fn raw_to_rgba(raw: RawImage) -> Result<DynamicImage, String> {
         channel_white[i] = raw.whitelevels.get(i).copied().unwrap_or(65535) as f32;
     }
 
+    let matrix = camera_matrix(&raw);
+    let mul = wb_multipliers(&raw);
+    let suppress_hot = !current_settings().keep_hot_pixels;
+
+    let len = (w as usize) * (h as usize);
+    let sample = |idx: usize| match &raw.data {
+        RawImageData::Integer(data) => data.get(idx).copied().unwrap_or(0) as f32,
+        RawImageData::Float(data) => data.get(idx).copied().unwrap_or(0.0),
+    };
+
     // If cpp==3, treat as already-RGB
     if raw.cpp == 3 {
-        let mut rgba = RgbaImage::new(w, h);
-        match raw.data {
-            RawImageData::Integer(data) => {
-                for (idx, pixel) in rgba.pixels_mut().enumerate() {
-                    let base = idx * 3;
-                    let r = normalize_sample(
-                        data.get(base).copied().unwrap_or(0) as f32,
-                        channel_black[0],
-                        channel_white[0],
-                    );
-                    let g = normalize_sample(
-                        data.get(base + 1).copied().unwrap_or(0) as f32,
-                        channel_black[1],
-                        channel_white[1],
-                    );
-                    let b = normalize_sample(
-                        data.get(base + 2).copied().unwrap_or(0) as f32,
-                        channel_black[2],
-                        channel_white[2],
-                    );
-                    *pixel = Rgba([(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8, 255]);
-                }
-            }
-            RawImageData::Float(data) => {
-                for (idx, pixel) in rgba.pixels_mut().enumerate() {
-                    let base = idx * 3;
-                    let r = normalize_sample(
-                        data.get(base).copied().unwrap_or(0.0),
-                        channel_black[0],
-                        channel_white[0],
-                    );
-                    let g = normalize_sample(
-                        data.get(base + 1).copied().unwrap_or(0.0),
-                        channel_black[1],
-                        channel_white[1],
-                    );
-                    let b = normalize_sample(
-                        data.get(base + 2).copied().unwrap_or(0.0),
-                        channel_black[2],
-                        channel_white[2],
-                    );
-                    *pixel = Rgba([(r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8, 255]);
-                }
-            }
-        }
-        return Ok(DynamicImage::ImageRgba8(rgba));
+        let planes = [0, 1, 2].map(|c| {
+            (0..len)
+                .into_par_iter()
+                .map(|idx| {
+                    normalize_sample(sample(idx * 3 + c), channel_black[c], channel_white[c])
+                })
+                .collect()
+        });
+        return Ok(camera_planes_to_linear(
+            (w, h),
+            planes,
+            mul,
+            matrix.as_ref(),
+        ));
     }
 
-    // Simple Bayer-ish demosaic: accumulate channels then fill missing with neighborhood average
-    let len = (w as usize) * (h as usize);
-    let mut r = vec![0f32; len];
-    let mut g = vec![0f32; len];
-    let mut b = vec![0f32; len];
-    let mut r_mask = vec![false; len];
-    let mut g_mask = vec![false; len];
-    let mut b_mask = vec![false; len];
-
-    let get_val = |idx: usize, data_int: Option<&Vec<u16>>, data_f: Option<&Vec<f32>>| -> f32 {
-        if let Some(d) = data_int {
-            d.get(idx).copied().unwrap_or(0) as f32
-        } else if let Some(d) = data_f {
-            d.get(idx).copied().unwrap_or(0.0)
-        } else {
-            0.0
-        }
+    // Simple Bayer-ish demosaic: split the mosaic into sparse channel planes, then
+    // fill missing sites with the average of their known 3x3 neighbours. Both
+    // passes work on rows in parallel.
+    let (width, height) = (w as usize, h as usize);
+    let row_len = width.max(1);
+    // fourth CFA colours (the E of RGBE) are taken as green
+    let channel_at = |y: usize, x: usize| match raw.cfa.color_at(y, x) {
+        c @ 0..=2 => c,
+        _ => 1,
     };
-
-    match &raw.data {
-        RawImageData::Integer(data) => {
-            for y in 0..h as usize {
-                for x in 0..w as usize {
-                    let idx = y * w as usize + x;
-                    let val = get_val(idx, Some(data), None);
-                    let color = raw.cfa.color_at(y, x);
-                    match color {
-                        0 => {
-                            r[idx] = normalize_sample(val, channel_black[0], channel_white[0]);
-                            r_mask[idx] = true;
-                        }
-                        1 => {
-                            g[idx] = normalize_sample(val, channel_black[1], channel_white[1]);
-                            g_mask[idx] = true;
-                        }
-                        2 => {
-                            b[idx] = normalize_sample(val, channel_black[2], channel_white[2]);
-                            b_mask[idx] = true;
-                        }
-                        _ => {
-                            g[idx] = normalize_sample(val, channel_black[1], channel_white[1]);
-                            g_mask[idx] = true;
-                        }
+    let mosaic = |c: usize| {
+        let mut plane = vec![0f32; len];
+        let mut mask = vec![false; len];
+        plane
+            .par_chunks_mut(row_len)
+            .zip(mask.par_chunks_mut(row_len))
+            .enumerate()
+            .for_each(|(y, (plane_row, mask_row))| {
+                for x in 0..width {
+                    if channel_at(y, x) == c {
+                        let val = sample(y * width + x);
+                        plane_row[x] = normalize_sample(val, channel_black[c], channel_white[c]);
+                        mask_row[x] = true;
                     }
                 }
-            }
-        }
-        RawImageData::Float(data) => {
-            for y in 0..h as usize {
-                for x in 0..w as usize {
-                    let idx = y * w as usize + x;
-                    let val = get_val(idx, None, Some(data));
-                    let color = raw.cfa.color_at(y, x);
-                    match color {
-                        0 => {
-                            r[idx] = normalize_sample(val, channel_black[0], channel_white[0]);
-                            r_mask[idx] = true;
-                        }
-                        1 => {
-                            g[idx] = normalize_sample(val, channel_black[1], channel_white[1]);
-                            g_mask[idx] = true;
-                        }
-                        2 => {
-                            b[idx] = normalize_sample(val, channel_black[2], channel_white[2]);
-                            b_mask[idx] = true;
-                        }
-                        _ => {
-                            g[idx] = normalize_sample(val, channel_black[1], channel_white[1]);
-                            g_mask[idx] = true;
+            });
+        (plane, mask)
+    };
+    let (mut r, r_mask) = mosaic(0);
+    let (mut g, g_mask) = mosaic(1);
+    let (mut b, b_mask) = mosaic(2);
+
+    // Hot sensels are fixed on the mosaic, before interpolation smears them
+    if suppress_hot {
+        suppress_hot_sensels(&mut r, &r_mask, width, height);
+        suppress_hot_sensels(&mut g, &g_mask, width, height);
+        suppress_hot_sensels(&mut b, &b_mask, width, height);
+    }
+
+    let fill_channel = |chan: &[f32], mask: &[bool]| {
+        let mut out = chan.to_vec();
+        out.par_chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, out_row)| {
+                for x in 0..width {
+                    if mask[y * width + x] {
+                        continue;
+                    }
+                    let mut sum = 0.0;
+                    let mut count = 0.0;
+                    for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                        for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                            let nidx = ny * width + nx;
+                            if mask[nidx] {
+                                sum += chan[nidx];
+                                count += 1.0;
+                            }
                         }
                     }
+                    if count > 0.0 {
+                        out_row[x] = sum / count;
+                    }
                 }
-            }
+            });
+        out
+    };
+
+    let planes = [
+        fill_channel(&r, &r_mask),
+        fill_channel(&g, &g_mask),
+        fill_channel(&b, &b_mask),
+    ];
+    Ok(camera_planes_to_linear(
+        (w, h),
+        planes,
+        mul,
+        matrix.as_ref(),
+    ))
+}
+
+const HISTOGRAM_BINS: usize = 256;
+// where auto tone puts the median, and the black/white points it stretches to
+// (display-referred, like the globals sliders)
+const AUTO_TONE_MEDIAN: f32 = 0.46;
+const AUTO_TONE_BLACK: f32 = 0.03;
+const AUTO_TONE_WHITE: f32 = 0.97;
+
+struct HistogramAccumulator {
+    bins: [[u32; HISTOGRAM_BINS]; 3],
+    clipped: [u64; 3],
+    samples: [u64; 3],
+}
+
+impl HistogramAccumulator {
+    fn new() -> Self {
+        Self {
+            bins: [[0; HISTOGRAM_BINS]; 3],
+            clipped: [0; 3],
+            samples: [0; 3],
         }
     }
 
-    let neighbors = |x: usize, y: usize, w: usize, h: usize| {
-        let mut out = Vec::with_capacity(9);
-        let xi = x as isize;
-        let yi = y as isize;
-        for dy in -1..=1 {
-            for dx in -1..=1 {
-                let nx = xi + dx;
-                let ny = yi + dy;
-                if nx >= 0 && ny >= 0 && (nx as usize) < w && (ny as usize) < h {
-                    out.push((nx as usize, ny as usize));
-                }
+    fn add(&mut self, channel: usize, normalized: f32, clipped: bool) {
+        let bin = (normalized.clamp(0.0, 1.0) * (HISTOGRAM_BINS - 1) as f32).round() as usize;
+        self.bins[channel][bin] += 1;
+        self.samples[channel] += 1;
+        if clipped {
+            self.clipped[channel] += 1;
+        }
+    }
+
+    fn merge(mut self, other: Self) -> Self {
+        for c in 0..3 {
+            for (dst, src) in self.bins[c].iter_mut().zip(other.bins[c].iter()) {
+                *dst += *src;
             }
+            self.clipped[c] += other.clipped[c];
+            self.samples[c] += other.samples[c];
         }
-        out
-    };
+        self
+    }
 
-    let fill_channel = |chan: &mut [f32], mask: &[bool]| {
-        let (w_usize, h_usize) = (w as usize, h as usize);
-        let mut out = chan.to_vec();
-        for y in 0..h_usize {
-            for x in 0..w_usize {
-                let idx = y * w_usize + x;
-                if mask[idx] {
-                    continue;
-                }
-                let mut sum = 0.0;
-                let mut count = 0.0;
-                for (nx, ny) in neighbors(x, y, w_usize, h_usize) {
-                    let nidx = ny * w_usize + nx;
-                    if mask[nidx] {
-                        sum += chan[nidx];
-                        count += 1.0;
-                    }
-                }
-                if count > 0.0 {
-                    out[idx] = sum / count;
+    fn into_histogram(self, source: &str) -> RawHistogram {
+        let channel = |c: usize| ChannelHistogram {
+            bins: self.bins[c].to_vec(),
+            clipped: self.clipped[c],
+            samples: self.samples[c],
+        };
+        RawHistogram {
+            source: source.to_string(),
+            red: channel(0),
+            green: channel(1),
+            blue: channel(2),
+        }
+    }
+}
+
+fn accumulate_sensor<T: Copy + Sync>(
+    raw: &RawImage,
+    data: &[T],
+    to_f32: fn(T) -> f32,
+) -> HistogramAccumulator {
+    let mut channel_black = [0.0f32; 3];
+    let mut channel_white = [65535.0f32; 3];
+    for i in 0..3 {
+        channel_black[i] = raw.blacklevels.get(i).copied().unwrap_or(0) as f32;
+        channel_white[i] = raw.whitelevels.get(i).copied().unwrap_or(65535) as f32;
+    }
+
+    let cpp = raw.cpp.max(1);
+    let row_len = (raw.width * cpp).max(1);
+    data.par_chunks(row_len)
+        .enumerate()
+        .fold(HistogramAccumulator::new, |mut acc, (y, row)| {
+            for (i, sample) in row.iter().enumerate() {
+                // cpp == 3 is already RGB; otherwise map CFA colors (4th color counts as green)
+                let channel = if cpp == 3 {
+                    i % 3
                 } else {
-                    out[idx] = chan[idx];
-                }
+                    match raw.cfa.color_at(y, i) {
+                        0 => 0,
+                        2 => 2,
+                        _ => 1,
+                    }
+                };
+                let val = to_f32(*sample);
+                acc.add(
+                    channel,
+                    normalize_sample(val, channel_black[channel], channel_white[channel]),
+                    val >= channel_white[channel],
+                );
             }
-        }
-        out
+            acc
+        })
+        .reduce(HistogramAccumulator::new, HistogramAccumulator::merge)
+}
+
+fn sensor_histogram(raw: &RawImage) -> RawHistogram {
+    let acc = match &raw.data {
+        RawImageData::Integer(data) => accumulate_sensor(raw, data, |v| v as f32),
+        RawImageData::Float(data) => accumulate_sensor(raw, data, |v| v),
     };
+    acc.into_histogram("sensor")
+}
 
-    let r_filled = fill_channel(&mut r, &r_mask);
-    let g_filled = fill_channel(&mut g, &g_mask);
-    let b_filled = fill_channel(&mut b, &b_mask);
-
-    let mut rgba = RgbaImage::new(w, h);
-    for (idx, pixel) in rgba.pixels_mut().enumerate() {
-        let rr = r_filled[idx].clamp(0.0, 1.0);
-        let gg = g_filled[idx].clamp(0.0, 1.0);
-        let bb = b_filled[idx].clamp(0.0, 1.0);
-        *pixel = Rgba([
-            (rr * 255.0) as u8,
-            (gg * 255.0) as u8,
-            (bb * 255.0) as u8,
-            255,
-        ]);
+fn rgb16_histogram(data: &[u16], channels: usize, source: &str) -> RawHistogram {
+    let channels = channels.max(1);
+    data.par_chunks(channels)
+        .fold(HistogramAccumulator::new, |mut acc, px| {
+            for c in 0..3 {
+                let v = px.get(c.min(px.len() - 1)).copied().unwrap_or(0);
+                acc.add(c, v as f32 / 65535.0, v == u16::MAX);
+            }
+            acc
+        })
+        .reduce(HistogramAccumulator::new, HistogramAccumulator::merge)
+        .into_histogram(source)
+}
+
+/// Per-channel histogram of the unadjusted high-bit decode, so sensor clipping
+/// can be told apart from clipping introduced by the adjustments.
+pub fn compute_raw_histogram(path: &Path) -> Result<RawHistogram, String> {
+    if let Ok(raw) = decode_raw_file(path) {
+        return Ok(sensor_histogram(&raw));
+    }
+
+    let bytes = fs::read(path).map_err(|e| format!("Failed to read image bytes: {e}"))?;
+    if let Ok(processed) = Processor::new().process_16bit(&bytes) {
+        let data: &[u16] = &processed;
+        if let Some(channels) = channels_from_len(data.len(), processed.width(), processed.height())
+        {
+            return Ok(rgb16_histogram(data, channels, "libraw16"));
+        }
     }
 
-    Ok(DynamicImage::ImageRgba8(rgba))
+    let img =
+        image::load_from_memory(&bytes).map_err(|e| format!("Failed to decode image: {e}"))?;
+    let rgb = img.to_rgb16();
+    Ok(rgb16_histogram(rgb.as_raw(), 3, "decoded"))
 }
 
 fn placeholder_image() -> DynamicImage {
@@ -552,39 +1183,94 @@ fn write_png_to_path(img: &RgbaImage, path: &Path) -> Result<Vec<u8>, String> {
     Ok(buffer)
 }
 
+// A JPEG at least twice the size asked for decodes straight to a fraction of its
+// size, the IDCT doing most of the downscale.
+fn decode_jpeg_draft(path: &Path, target: u32) -> Option<DynamicImage> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    if !matches!(extension.as_str(), "jpg" | "jpeg") || current_settings().full_jpeg_decode {
+        return None;
+    }
+    let bytes = fs::read(path).ok()?;
+    let _timer = perf::Timer::start("decode");
+    let img = decode_jpeg_scaled(&bytes, target)?;
+    Some(apply_exif_orientation(DynamicImage::ImageRgba8(img), path))
+}
+
 fn render_resized(path: &Path, max_dimension: u32) -> Result<RgbaImage, String> {
     let target = max_dimension.max(1);
-    let img = load_dynamic_image(path)?;
+    let img = match decode_jpeg_draft(path, target) {
+        Some(img) => img,
+        None => load_dynamic_image(path, false)?,
+    };
     let rgba = img.to_rgba8();
     let source_max = rgba.width().max(rgba.height()).max(1);
     let clamped_target = target.min(source_max);
-    Ok(resize_rgba_preserve_aspect(&rgba, clamped_target))
+    let mut resized = resize_rgba_preserve_aspect(&rgba, clamped_target);
+    manage_source_color(&mut resized, path);
+    Ok(resized)
 }
 
-/// Clear all in-memory preview caches (masters, scaled variants, LRU list).
+/// Clear all in-memory preview caches (masters, balanced masters, scaled variants, LRU list).
 pub fn clear_preview_cache() {
     PREVIEW_MASTERS.clear();
     PREVIEW_VARIANTS.clear();
+    PREVIEW_BALANCED.clear();
     if let Ok(mut lru) = PREVIEW_LRU.lock() {
         lru.clear();
     }
+    gpu::clear_resident();
 }
 
-pub fn load_or_create_thumbnail(asset_id: &str, path: &Path) -> Result<Vec<u8>, String> {
-    let dir = thumbnails_dir()?;
-    let thumb_path = cached_path(&dir, asset_id, "png");
-    if thumb_path.exists() {
-        return fs::read(&thumb_path).map_err(|e| e.to_string());
+// RAW decodes already leave through the camera matrix as sRGB; JPEG/PNG/TIFF
+// pixels are in whatever space their embedded profile names. Untagged files are
+// sRGB by convention, and an unreadable profile leaves the pixels as they are.
+// Thumbnails, previews and exports all decode through here, so they agree.
+fn manage_source_color(img: &mut RgbaImage, path: &Path) {
+    if current_settings().unmanaged_color {
+        return;
     }
+    if let Some(icc) = source_icc_profile(path) {
+        let _ = convert_icc_to_srgb(img, &icc);
+    }
+}
 
-    let img = render_resized(path, 360).unwrap_or_else(|_| {
-        let ph = placeholder_rgba();
-        resize_rgba_preserve_aspect(&ph, 360)
-    });
-    write_png_to_path(&img, &thumb_path)
+/// The cached 360 px sRGB thumbnail, the input of the grid and of analyses.
+pub fn load_or_create_thumbnail(path: &Path) -> Result<Vec<u8>, String> {
+    let slot = thumbnail_slot(path, !current_settings().unmanaged_color)?;
+    if thumbnail_indexed(&slot) {
+        if let Ok(bytes) = fs::read(&slot.path) {
+            return Ok(bytes);
+        }
+    }
+
+    let img = render_resized(path, 360)
+        .unwrap_or_else(|_| resize_rgba_preserve_aspect(&placeholder_rgba(), 360));
+    let bytes = write_png_to_path(&img, &slot.path)?;
+    record_thumbnail(&slot)?;
+    Ok(bytes)
+}
+
+/// The grid thumbnail: the cached source thumbnail, mirrored when the recipe
+/// flips the image so the grid matches the editor. Analyses that apply a whole
+/// recipe use `load_or_create_thumbnail` instead.
+pub fn load_display_thumbnail(path: &Path) -> Result<Vec<u8>, String> {
+    let bytes = load_or_create_thumbnail(path)?;
+    let crop = load_recipe_for_asset(path)
+        .ok()
+        .flatten()
+        .and_then(|r| r.crop);
+    match crop {
+        Some(crop) if crop.flip_horizontal || crop.flip_vertical => {
+            let thumb = image::load_from_memory(&bytes)
+                .map_err(|e| format!("Failed to decode thumbnail: {e}"))?
+                .to_rgba8();
+            encode_png_fast(&apply_flips(thumb, &crop))
+        }
+        _ => Ok(bytes),
+    }
 }
 
-fn apply_globals_in_place(data: &mut [u8], globals: &GlobalAdjustments) {
+fn apply_globals_in_place(img: &mut Rgba32FImage, globals: &GlobalAdjustments) {
     let exposure_mul = 2f32.powf(globals.exposure_ev);
     let contrast = globals.contrast / 100.0;
     let highlights = globals.highlights / 100.0;
@@ -593,61 +1279,84 @@ fn apply_globals_in_place(data: &mut [u8], globals: &GlobalAdjustments) {
     let blacks = globals.blacks / 100.0;
     let vibrance = globals.vibrance / 100.0;
     let saturation = globals.saturation / 100.0;
-    let temp = globals.temp / 100.0; // -1..1 approx
-    let tint = globals.tint / 100.0; // -1..1 approx
-
-    data.par_chunks_mut(4).for_each(|px| {
-        let mut c = [
-            px[0] as f32 / 255.0,
-            px[1] as f32 / 255.0,
-            px[2] as f32 / 255.0,
-            px[3] as f32 / 255.0,
-        ];
-        let a = c[3];
+    let guide = (highlights.abs() >= 1e-4 || shadows.abs() >= 1e-4).then(|| tone_guide(img));
 
-        for i in 0..3 {
-            c[i] *= exposure_mul;
-        }
-        c[0] *= 1.0 + temp * 0.5 + tint * 0.2;
-        c[2] *= 1.0 - temp * 0.5 + tint * 0.2;
-        c[1] *= 1.0 - tint * 0.2;
+    img.as_mut()
+        .par_chunks_mut(4)
+        .enumerate()
+        .for_each(|(idx, px)| {
+            let mut c = [px[0], px[1], px[2]];
 
-        let l = 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
+            for i in 0..3 {
+                c[i] *= exposure_mul;
+            }
 
-        let highlights_mask = (l - 0.5).max(0.0f32) * 2.0;
-        let shadows_mask = (0.5 - l).max(0.0f32) * 2.0;
-        for i in 0..3 {
-            c[i] *= 1.0 + highlights * highlights_mask;
-            c[i] *= 1.0 + shadows * shadows_mask;
-        }
+            if let Some(guide) = &guide {
+                let gain = tone_gain(guide[idx] * exposure_mul, highlights, shadows);
+                for i in 0..3 {
+                    c[i] *= gain;
+                }
+            }
 
-        for i in 0..3 {
-            c[i] = c[i] + whites * 0.1;
-            c[i] = c[i] - blacks * 0.1;
-        }
+            for i in 0..3 {
+                c[i] = c[i] + whites * 0.1;
+                c[i] = c[i] - blacks * 0.1;
+            }
 
-        for i in 0..3 {
-            c[i] = (c[i] - 0.5) * (1.0 + contrast) + 0.5;
-        }
+            for i in 0..3 {
+                c[i] = (c[i] - 0.5) * (1.0 + contrast) + 0.5;
+            }
 
-        let l = 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
-        let sat_factor = 1.0 + saturation;
-        let vib_mask = (1.0 - ((c[0] - l).abs() + (c[1] - l).abs() + (c[2] - l).abs()) / 3.0)
-            .clamp(0.0f32, 1.0);
-        let vib_factor = 1.0 + vibrance * vib_mask;
-        for i in 0..3 {
-            c[i] = l + (c[i] - l) * sat_factor * vib_factor;
-        }
+            let l = 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2];
+            let sat_factor = 1.0 + saturation;
+            let vib_mask = (1.0 - ((c[0] - l).abs() + (c[1] - l).abs() + (c[2] - l).abs()) / 3.0)
+                .clamp(0.0f32, 1.0);
+            let vib_factor = 1.0 + vibrance * vib_mask;
+            for i in 0..3 {
+                c[i] = l + (c[i] - l) * sat_factor * vib_factor;
+            }
 
-        for i in 0..3 {
-            c[i] = c[i].clamp(0.0, 1.0);
-        }
+            for i in 0..3 {
+                px[i] = c[i].clamp(0.0, 1.0);
+            }
+        });
+}
 
-        px[0] = (c[0] * 255.0).round() as u8;
-        px[1] = (c[1] * 255.0).round() as u8;
-        px[2] = (c[2] * 255.0).round() as u8;
-        px[3] = (a * 255.0).round() as u8;
-    });
+fn tone_sigmas(w: u32, h: u32) -> (f32, f32) {
+    let long_edge = w.max(h) as f32;
+    (
+        (long_edge * TONE_FINE_SIGMA_FRACTION).max(1.0),
+        (long_edge * TONE_COARSE_SIGMA_FRACTION).max(1.0),
+    )
+}
+
+// Mean of the display-referred luminance blurred at the fine and coarse scale.
+fn tone_guide(img: &Rgba32FImage) -> Vec<f32> {
+    let (w, h) = img.dimensions();
+    let (fine_sigma, coarse_sigma) = tone_sigmas(w, h);
+    let luma: Vec<f32> = img.as_raw().par_chunks(4).map(float_luma).collect();
+    let mut fine = luma.clone();
+    gaussian_blur_f32(&mut fine, w as usize, h as usize, 1, fine_sigma);
+    let mut coarse = luma;
+    gaussian_blur_f32(&mut coarse, w as usize, h as usize, 1, coarse_sigma);
+    fine.par_iter_mut()
+        .zip(coarse.par_iter())
+        .for_each(|(f, c)| *f = 0.5 * (*f + *c));
+    fine
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Exposure-style gain for the highlights/shadows sliders; mirrors STAGE_TONE in gpu.rs.
+// Scaling keeps the ratios between neighbouring pixels, and the smooth masks
+// over the blurred guide avoid the banding of a per-pixel threshold.
+fn tone_gain(guide: f32, highlights: f32, shadows: f32) -> f32 {
+    let highlights_mask = smoothstep(0.4, 1.0, guide);
+    let shadows_mask = 1.0 - smoothstep(0.0, 0.6, guide);
+    (TONE_RANGE_EV * (highlights * highlights_mask + shadows * shadows_mask)).exp2()
 }
 
 fn globals_are_identity(globals: &GlobalAdjustments) -> bool {
@@ -658,26 +1367,356 @@ fn globals_are_identity(globals: &GlobalAdjustments) -> bool {
         && globals.shadows.abs() < eps
         && globals.whites.abs() < eps
         && globals.blacks.abs() < eps
-        && globals.temp.abs() < eps
-        && globals.tint.abs() < eps
         && globals.vibrance.abs() < eps
         && globals.saturation.abs() < eps
 }
 
-fn layers_have_effect(layers: &[AdjustmentLayer]) -> bool {
-    layers
-        .iter()
-        .any(|layer| layer.enabled && layer.opacity > 0.0)
+fn local_contrast_sigmas(w: u32, h: u32) -> (f32, f32) {
+    let long_edge = w.max(h) as f32;
+    (
+        (long_edge * CLARITY_SIGMA_FRACTION).max(1.0),
+        (long_edge * TEXTURE_SIGMA_FRACTION).max(1.0),
+    )
+}
+
+fn perceptual(l: f32) -> f32 {
+    l.max(0.0).powf(1.0 / 2.2)
+}
+
+// Clarity boosts midtone contrast against a wide blur of the luminance, texture
+// boosts detail against a narrow one. Blurs run in linear light; the detail is
+// measured in a gamma-2.2 space so shadows and highlights get a fair share.
+fn apply_local_contrast_in_place(img: &mut Rgba32FImage, clarity: f32, texture: f32) {
+    let (w, h) = img.dimensions();
+    let (coarse_sigma, fine_sigma) = local_contrast_sigmas(w, h);
+    let luma: Vec<f32> = img.as_raw().par_chunks(4).map(linear_luma).collect();
+    let blurred = |sigma: f32, amount: f32| {
+        let mut plane = luma.clone();
+        if amount != 0.0 {
+            gaussian_blur_f32(&mut plane, w as usize, h as usize, 1, sigma);
+        }
+        plane
+    };
+    let coarse = blurred(coarse_sigma, clarity);
+    let fine = blurred(fine_sigma, texture);
+
+    img.as_mut()
+        .par_chunks_mut(4)
+        .enumerate()
+        .for_each(|(idx, px)| {
+            let l = luma[idx];
+            let p = perceptual(l);
+            let d = 2.0 * p - 1.0;
+            let midtones = 1.0 - d * d;
+            let q = (p
+                + clarity * (p - perceptual(coarse[idx])) * midtones
+                + texture * (p - perceptual(fine[idx])))
+            .max(0.0);
+            let scale = q.powf(2.2) / l.max(1e-5);
+            for c in px.iter_mut().take(3) {
+                *c = linear_to_srgb(srgb_to_linear(*c) * scale);
+            }
+        });
+}
+
+fn srgb_lut() -> Vec<f32> {
+    (0..=255u8)
+        .map(|v| srgb_to_linear(v as f32 / 255.0))
+        .collect()
+}
+
+// Luminance of a float pixel's display-referred values.
+fn float_luma(px: &[f32]) -> f32 {
+    0.2126 * px[0] + 0.7152 * px[1] + 0.0722 * px[2]
+}
+
+// Linear-light luminance of a float pixel.
+fn linear_luma(px: &[f32]) -> f32 {
+    0.2126 * srgb_to_linear(px[0]) + 0.7152 * srgb_to_linear(px[1]) + 0.0722 * srgb_to_linear(px[2])
+}
+
+// Atmospheric light: mean linear colour of the pixels with the highest dark channel.
+fn estimate_airlight(img: &Working) -> [f32; 3] {
+    let (w, h) = img.dimensions();
+    let stride = (w as usize * h as usize / AIRLIGHT_SAMPLES).max(1);
+    let mut samples: Vec<[f32; 3]> = match img {
+        Working::Bytes(img) => {
+            let to_linear = srgb_lut();
+            img.as_raw()
+                .chunks_exact(4)
+                .step_by(stride)
+                .map(|px| [0, 1, 2].map(|i| to_linear[px[i] as usize]))
+                .collect()
+        }
+        Working::Float(img) => img
+            .as_raw()
+            .chunks_exact(4)
+            .step_by(stride)
+            .map(|px| [0, 1, 2].map(|i| srgb_to_linear(px[i])))
+            .collect(),
+    };
+    if samples.is_empty() {
+        return [1.0; 3];
+    }
+    let dark = |c: &[f32; 3]| c[0].min(c[1]).min(c[2]);
+    samples.sort_unstable_by(|a, b| dark(b).total_cmp(&dark(a)));
+    let top = &samples[..(samples.len() / 1000).max(1)];
+    let mut sum = [0.0f32; 3];
+    for c in top {
+        for i in 0..3 {
+            sum[i] += c[i];
+        }
+    }
+    sum.map(|v| v / top.len() as f32)
+}
+
+// Dark-channel-prior approximation: the haze estimate is the minimum channel of a
+// blurred copy normalised by the atmospheric light, instead of a min filter.
+fn apply_dehaze_in_place(img: &mut Rgba32FImage, airlight: [f32; 3], amount: f32, sigma: f32) {
+    let (w, h) = img.dimensions();
+    let mut blurred: Vec<f32> = img
+        .as_raw()
+        .par_chunks(4)
+        .flat_map_iter(|px| [0, 1, 2].map(|i| srgb_to_linear(px[i])))
+        .collect();
+    gaussian_blur_f32(&mut blurred, w as usize, h as usize, 3, sigma);
+    let a = airlight.map(|v| v.max(1e-3));
+
+    img.as_mut()
+        .par_chunks_mut(4)
+        .zip(blurred.par_chunks(3))
+        .for_each(|(px, b)| {
+            let haze = (0..3)
+                .map(|i| b[i] / a[i])
+                .fold(f32::MAX, f32::min)
+                .clamp(0.0, 1.0);
+            let t = (1.0 - amount * 0.95 * haze).max(0.1);
+            for i in 0..3 {
+                let c = srgb_to_linear(px[i]);
+                let v = if amount >= 0.0 {
+                    (c - a[i]) / t + a[i]
+                } else {
+                    c + (a[i] - c) * (-amount * 0.6)
+                };
+                px[i] = linear_to_srgb(v);
+            }
+        });
+}
+
+fn apply_dehaze(working: &mut Working, dehaze: f32) {
+    let amount = (dehaze / 100.0).clamp(-1.0, 1.0);
+    let airlight = estimate_airlight(working);
+    let (w, h) = working.dimensions();
+    let sigma = (w.max(h) as f32 * DEHAZE_SIGMA_FRACTION).max(1.0);
+    match working
+        .upload()
+        .and_then(|bytes| gpu::dehaze_rgba(&bytes, airlight, amount, sigma))
+    {
+        Some(gpu_img) => *working = Working::Bytes(gpu_img),
+        None => apply_dehaze_in_place(working.float(), airlight, amount, sigma),
+    }
+}
+
+// Per-channel gamma curves over display luminance: selenium cools the shadows
+// towards aubergine, sepia warms everything, cyanotype pushes towards Prussian blue.
+fn paper_tone_rgb(paper: PaperTone, l: f32) -> [f32; 3] {
+    let gammas = match paper {
+        PaperTone::Neutral => return [l; 3],
+        PaperTone::Selenium => [1.05, 1.12, 1.0],
+        PaperTone::Sepia => [0.85, 1.0, 1.25],
+        PaperTone::Cyanotype => [1.6, 1.15, 0.8],
+    };
+    gammas.map(|g| l.powf(g))
+}
+
+// Monochrome output depends only on the display-referred luminance.
+fn apply_black_and_white_in_place(img: &mut Rgba32FImage, bw: &BlackAndWhite) {
+    let strength = (bw.tone_strength / 100.0).clamp(0.0, 1.0);
+    img.as_mut().par_chunks_mut(4).for_each(|px| {
+        let l = linear_to_srgb(linear_luma(px));
+        let toned = paper_tone_rgb(bw.paper, l).map(|c| (l + (c - l) * strength).clamp(0.0, 1.0));
+        px[..3].copy_from_slice(&toned);
+    });
 }
 
-fn apply_local_layer_in_place(data: &mut [u8], w: u32, h: u32, layer: &AdjustmentLayer) {
+struct NoiseReduction {
+    luma_sigma: Option<f32>,
+    eps: f32,
+    detail: f32,
+    chroma_sigma: Option<f32>,
+}
+
+fn noise_reduction_params(globals: &GlobalAdjustments, w: u32, h: u32) -> Option<NoiseReduction> {
+    let luma = (globals.nr_luminance / 100.0).clamp(0.0, 1.0);
+    let chroma = (globals.nr_color / 100.0).clamp(0.0, 1.0);
+    if luma <= 1e-4 && chroma <= 1e-4 {
+        return None;
+    }
+    let long_edge = w.max(h) as f32;
+    Some(NoiseReduction {
+        luma_sigma: (luma > 1e-4).then(|| (long_edge * NR_LUMA_SIGMA_FRACTION).max(1.0)),
+        // regulariser ~ the squared noise amplitude the filter treats as flat
+        eps: (luma * 0.08).powi(2),
+        detail: (globals.nr_luminance_detail / 100.0).clamp(0.0, 1.0) * 0.5,
+        chroma_sigma: (chroma > 1e-4)
+            .then(|| (long_edge * NR_CHROMA_SIGMA_FRACTION * chroma).max(1.0)),
+    })
+}
+
+// CPU twin of gpu::noise_reduction_rgba. Works on display-referred values:
+// luma through a self-guided filter, colour differences (r - y, b - y) blurred.
+fn apply_noise_reduction_in_place(img: &mut Rgba32FImage, nr: &NoiseReduction) {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+
+    let coeffs = nr.luma_sigma.map(|sigma| {
+        let mut means: Vec<f32> = img
+            .as_raw()
+            .chunks_exact(4)
+            .flat_map(|px| {
+                let y = float_luma(px);
+                [y, y * y]
+            })
+            .collect();
+        gaussian_blur_f32(&mut means, w, h, 2, sigma);
+        let mut ab: Vec<f32> = means
+            .par_chunks(2)
+            .flat_map_iter(|m| {
+                let variance = (m[1] - m[0] * m[0]).max(0.0);
+                let a = variance / (variance + nr.eps);
+                [a, m[0] - a * m[0]]
+            })
+            .collect();
+        gaussian_blur_f32(&mut ab, w, h, 2, sigma);
+        ab
+    });
+    let chroma = nr.chroma_sigma.map(|sigma| {
+        let mut diff: Vec<f32> = img
+            .as_raw()
+            .chunks_exact(4)
+            .flat_map(|px| {
+                let y = float_luma(px);
+                [px[0] - y, px[2] - y]
+            })
+            .collect();
+        gaussian_blur_f32(&mut diff, w, h, 2, sigma);
+        diff
+    });
+
+    img.as_mut()
+        .par_chunks_mut(4)
+        .enumerate()
+        .for_each(|(idx, px)| {
+            let y = float_luma(px);
+            let yo = match &coeffs {
+                Some(ab) => {
+                    let q = ab[idx * 2] * y + ab[idx * 2 + 1];
+                    q + (y - q) * nr.detail
+                }
+                None => y,
+            };
+            let (dr, db) = match &chroma {
+                Some(diff) => (diff[idx * 2], diff[idx * 2 + 1]),
+                None => (px[0] - y, px[2] - y),
+            };
+            let g = yo - (0.2126 * dr + 0.0722 * db) / 0.7152;
+            for (c, v) in px.iter_mut().zip([yo + dr, g, yo + db]) {
+                *c = v.clamp(0.0, 1.0);
+            }
+        });
+}
+
+fn white_balance_is_identity(globals: &GlobalAdjustments) -> bool {
+    let dual = &globals.dual_illuminant;
+    globals.temp.abs() < 1e-4
+        && globals.tint.abs() < 1e-4
+        && !(dual.enabled && (dual.temp.abs() >= 1e-4 || dual.tint.abs() >= 1e-4))
+}
+
+// temp/tint (-100..100) to linear channel gains
+fn white_balance_gains(temp: f32, tint: f32) -> [f32; 3] {
+    let temp = temp / 100.0; // -1..1 approx
+    let tint = tint / 100.0; // -1..1 approx
+    [
+        1.0 + temp * 0.5 + tint * 0.2,
+        1.0 - tint * 0.2,
+        1.0 - temp * 0.5 + tint * 0.2,
+    ]
+}
+
+/// Apply the temp/tint channel gains in linear light, blending towards the second
+/// illuminant's gains where the dual-illuminant block says so. Runs first, on the
+/// uncropped frame before lens corrections, on every path: the result does not
+/// depend on the output size and the mask always covers the same frame.
+pub fn apply_white_balance(img: &mut RgbaImage, globals: &GlobalAdjustments) {
+    if white_balance_is_identity(globals) {
+        return;
+    }
+    let gains = white_balance_gains(globals.temp, globals.tint);
+    let dual = &globals.dual_illuminant;
+    let second = dual
+        .enabled
+        .then(|| white_balance_gains(dual.temp, dual.tint));
+    let sampler = MaskSampler::new(&dual.mask);
+    let (w, h) = img.dimensions();
+    let to_linear = srgb_lut();
+    img.as_mut()
+        .par_chunks_mut(4)
+        .enumerate()
+        .for_each(|(idx, px)| {
+            let linear = [0, 1, 2].map(|i| to_linear[px[i] as usize]);
+            let gains = match second {
+                None => gains,
+                Some(second) => {
+                    let t = match dual.blend {
+                        IlluminantBlend::Mask => sampler.weight_with_luma(
+                            (idx as u32 % w) as f32 / w as f32,
+                            (idx as u32 / w) as f32 / h as f32,
+                            (0.2126 * px[0] as f32 + 0.7152 * px[1] as f32 + 0.0722 * px[2] as f32)
+                                / 255.0,
+                        ),
+                        IlluminantBlend::Luminance => {
+                            let y = 0.2126 * linear[0] + 0.7152 * linear[1] + 0.0722 * linear[2];
+                            let span = (dual.luminance_high - dual.luminance_low).max(1e-4);
+                            let u = ((y - dual.luminance_low) / span).clamp(0.0, 1.0);
+                            u * u * (3.0 - 2.0 * u)
+                        }
+                    };
+                    [0, 1, 2].map(|i| gains[i] + (second[i] - gains[i]) * t)
+                }
+            };
+            for i in 0..3 {
+                let v = (linear[i] * gains[i]).clamp(0.0, 1.0);
+                px[i] = (linear_to_srgb(v) * 255.0).round() as u8;
+            }
+        });
+}
+
+fn local_contrast_is_identity(globals: &GlobalAdjustments) -> bool {
+    globals.clarity.abs() < 1e-4 && globals.texture.abs() < 1e-4
+}
+
+// Exposure a layer is pushed to while its mask is tuned: the top of the slider range.
+const EMPHASIS_EXPOSURE_EV: f32 = 5.0;
+
+/// The "show effect at 100%" view of one layer: enabled, fully opaque and at
+/// maximum exposure so the mask's reach stands out. Works on a copy the caller
+/// renders; nothing is saved.
+pub fn emphasize_layer(recipe: &mut EditRecipe, layer_id: &str) -> Result<(), String> {
+    let layer = recipe
+        .layers
+        .iter_mut()
+        .find(|layer| layer.id == layer_id)
+        .ok_or("Layer not found")?;
+    layer.enabled = true;
+    layer.opacity = 1.0;
+    layer.adjustments.exposure_ev = EMPHASIS_EXPOSURE_EV;
+    Ok(())
+}
+
+fn apply_local_layer_in_place(img: &mut Rgba32FImage, layer: &AdjustmentLayer) {
     if !layer.enabled || layer.opacity <= 0.0 {
         return;
     }
-    let start = layer.mask.start;
-    let end = layer.mask.end;
-    let feather = layer.mask.feather.max(0.001);
-    let invert = layer.mask.invert;
     let opacity = layer.opacity;
     let adj = &layer.adjustments;
 
@@ -686,37 +1725,18 @@ fn apply_local_layer_in_place(data: &mut [u8], w: u32, h: u32, layer: &Adjustmen
     let exposure_mul = 2f32.powf(adj.exposure_ev);
     let saturation = adj.saturation / 100.0;
 
-    let dx = end.0 - start.0;
-    let dy = end.1 - start.1;
-    let len_sq = (dx * dx + dy * dy).max(1e-6);
-
+    let sampler = MaskSampler::new(&layer.mask);
+    let (w, h) = img.dimensions();
+    let data: &mut [f32] = img.as_mut();
     data.par_chunks_mut(4).enumerate().for_each(|(idx, px)| {
         let x = (idx as u32 % w) as f32 / w as f32;
         let y = (idx as u32 / w) as f32 / h as f32;
-
-        let pxv = x - start.0;
-        let pyv = y - start.1;
-        let t = (pxv * dx + pyv * dy) / len_sq;
-        let t_clamped = t.clamp(0.0, 1.0);
-
-        let edge0 = 0.5 - feather * 0.5;
-        let edge1 = 0.5 + feather * 0.5;
-        let mut mask = ((t_clamped - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
-        mask = mask * mask * (3.0 - 2.0 * mask);
-        if invert {
-            mask = 1.0 - mask;
-        }
-        mask *= opacity;
+        let mask = sampler.weight_with_luma(x, y, float_luma(px)) * opacity;
         if mask <= 0.0001 {
             return;
         }
 
-        let mut c = [
-            px[0] as f32 / 255.0,
-            px[1] as f32 / 255.0,
-            px[2] as f32 / 255.0,
-            px[3] as f32 / 255.0,
-        ];
+        let mut c = [px[0], px[1], px[2]];
 
         for i in 0..3 {
             c[i] *= exposure_mul;
@@ -731,25 +1751,180 @@ fn apply_local_layer_in_place(data: &mut [u8], w: u32, h: u32, layer: &Adjustmen
             c[i] = l + (c[i] - l) * sat_factor;
         }
         for i in 0..3 {
-            c[i] = c[i].clamp(0.0, 1.0);
+            px[i] = px[i] * (1.0 - mask) + c[i].clamp(0.0, 1.0) * mask;
         }
-
-        px[0] = ((px[0] as f32 / 255.0 * (1.0 - mask) + c[0] * mask) * 255.0).round() as u8;
-        px[1] = ((px[1] as f32 / 255.0 * (1.0 - mask) + c[1] * mask) * 255.0).round() as u8;
-        px[2] = ((px[2] as f32 / 255.0 * (1.0 - mask) + c[2] * mask) * 255.0).round() as u8;
     });
 }
 
-fn apply_layers_in_place(data: &mut [u8], w: u32, h: u32, layers: &[AdjustmentLayer]) {
-    if layers.is_empty() {
+// A layer's coverage per pixel in row order, opacity included, for the GPU layer
+// pass; sampled where apply_local_layer_in_place samples it. None when the mask
+// reads luma, which only the CPU pass sees as it goes.
+fn layer_mask_weights(layer: &AdjustmentLayer, w: u32, h: u32) -> Option<Vec<f32>> {
+    let sampler = MaskSampler::new(&layer.mask);
+    if sampler.reads_luma() {
+        return None;
+    }
+    let weights = (0..w as usize * h as usize)
+        .into_par_iter()
+        .map(|idx| {
+            let x = (idx as u32 % w) as f32 / w as f32;
+            let y = (idx as u32 / w) as f32 / h as f32;
+            sampler.weight(x, y) * layer.opacity
+        })
+        .collect();
+    Some(weights)
+}
+
+/// Pixels between the stages of `apply_recipe_balanced`: 8-bit as they arrive and
+/// come back from the GPU, f32 (sRGB-encoded, 0..1) once a CPU stage has run, so
+/// CPU stages hand each other unrounded values.
+pub(crate) enum Working {
+    Bytes(RgbaImage),
+    Float(Rgba32FImage),
+}
+
+impl Working {
+    fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Working::Bytes(img) => img.dimensions(),
+            Working::Float(img) => img.dimensions(),
+        }
+    }
+
+    /// The pixels for a GPU stage to upload. Float pixels are rounded into a copy,
+    /// and only when there is a GPU, so a CPU fallback still gets them unrounded.
+    pub(crate) fn upload(&self) -> Option<Cow<'_, RgbaImage>> {
+        match self {
+            Working::Bytes(img) => Some(Cow::Borrowed(img)),
+            Working::Float(img) => gpu::available().then(|| Cow::Owned(quantize(img, false))),
+        }
+    }
+
+    /// The pixels for a CPU stage, converted to f32 on first use.
+    pub(crate) fn float(&mut self) -> &mut Rgba32FImage {
+        if let Working::Bytes(img) = self {
+            *self = Working::Float(to_float(img));
+        }
+        match self {
+            Working::Float(img) => img,
+            Working::Bytes(_) => unreachable!("converted above"),
+        }
+    }
+
+    fn resize(&mut self, w: u32, h: u32) {
+        *self = match self {
+            Working::Bytes(img) => {
+                Working::Bytes(imageops::resize(img, w, h, ResizeFilter::CatmullRom))
+            }
+            Working::Float(img) => {
+                Working::Float(imageops::resize(img, w, h, ResizeFilter::CatmullRom))
+            }
+        };
+    }
+
+    // The 8-bit result, rounded once at the end; `dither` breaks up banding.
+    fn finish(self, dither: bool) -> RgbaImage {
+        match self {
+            Working::Bytes(img) => img,
+            Working::Float(img) => quantize(&img, dither),
+        }
+    }
+}
+
+fn to_float(img: &RgbaImage) -> Rgba32FImage {
+    let (w, h) = img.dimensions();
+    let samples = img.as_raw().par_iter().map(|&v| v as f32 / 255.0).collect();
+    Rgba32FImage::from_raw(w, h, samples).expect("one sample per byte")
+}
+
+/// Round float pixels to 8-bit, with the ordered dither the GPU globals pass uses
+/// when `dither` is set.
+pub(crate) fn quantize(img: &Rgba32FImage, dither: bool) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let mut out = RgbaImage::new(w, h);
+    out.as_mut()
+        .par_chunks_mut(4)
+        .zip(img.as_raw().par_chunks(4))
+        .enumerate()
+        .for_each(|(idx, (px, src))| {
+            let offset = if dither {
+                dither_offset(idx as u32 % w, idx as u32 / w)
+            } else {
+                0.0
+            };
+            for c in 0..3 {
+                px[c] = (src[c] * 255.0 + offset).round().clamp(0.0, 255.0) as u8;
+            }
+            px[3] = (src[3] * 255.0).round().clamp(0.0, 255.0) as u8;
+        });
+    out
+}
+
+// Stages run on a gpu::Chain, redone on the CPU if its readback fails.
+enum Chained {
+    Resize(u32, u32),
+    Globals,
+    Layer(usize),
+}
+
+// A run of GPU stages in apply_recipe_balanced, kept on the GPU until a CPU stage
+// needs the pixels.
+struct GpuRun {
+    chain: gpu::Chain,
+    stages: Vec<Chained>,
+}
+
+impl GpuRun {
+    // Run `op` on the chain, noting `stage` when it went through.
+    fn record(&mut self, stage: Chained, op: impl FnOnce(&mut gpu::Chain) -> bool) -> bool {
+        let done = op(&mut self.chain);
+        if done {
+            self.stages.push(stage);
+        }
+        done
+    }
+}
+
+// The running GPU run, or one started from `working`; None without a GPU.
+fn gpu_run<'a>(
+    working: &Working,
+    run: &'a mut Option<GpuRun>,
+    source: Option<&gpu::SourceKey>,
+) -> Option<&'a mut GpuRun> {
+    if run.is_none() {
+        let upload = working.upload()?;
+        *run = gpu::Chain::start(&upload, source).map(|chain| GpuRun {
+            chain,
+            stages: Vec::new(),
+        });
+    }
+    run.as_mut()
+}
+
+// Bring a GPU run's result back into `working`, which still holds the pixels the
+// run started from, or redo its stages on the CPU when the readback fails.
+fn land(working: &mut Working, run: &mut Option<GpuRun>, recipe: &EditRecipe) {
+    let Some(run) = run.take() else {
+        return;
+    };
+    if run.stages.is_empty() {
         return;
     }
-    layers
-        .iter()
-        .for_each(|layer| apply_local_layer_in_place(data, w, h, layer));
+    if let Some(img) = run.chain.finish() {
+        *working = Working::Bytes(img);
+        return;
+    }
+    for stage in run.stages {
+        match stage {
+            Chained::Resize(w, h) => working.resize(w, h),
+            Chained::Globals => apply_globals_in_place(working.float(), &recipe.globals),
+            Chained::Layer(idx) => apply_local_layer_in_place(working.float(), &recipe.layers[idx]),
+        }
+    }
 }
 
-fn encode_png_fast(img: &RgbaImage) -> Result<Vec<u8>, String> {
+pub(crate) fn encode_png_fast(img: &RgbaImage) -> Result<Vec<u8>, String> {
+    let _timer = perf::Timer::start("encode");
     let mut buffer = Vec::new();
     let cursor = Cursor::new(&mut buffer);
     let encoder = PngEncoder::new_with_quality(cursor, CompressionType::Fast, FilterType::NoFilter);
@@ -764,29 +1939,490 @@ fn encode_png_fast(img: &RgbaImage) -> Result<Vec<u8>, String> {
     Ok(buffer)
 }
 
+/// Apply a whole recipe: white balance, dead pixels, spot removal, lens corrections and crop first,
+/// in the order previews use, then everything else.
+pub fn apply_recipe(mut working: RgbaImage, recipe: &EditRecipe) -> RgbaImage {
+    apply_white_balance(&mut working, &recipe.globals);
+    repair_pixels(&mut working, &recipe.dead_pixels);
+    apply_retouch(&mut working, &recipe.retouch);
+    let working = correct_lens(working, recipe);
+    let frame_long_edge = working.width().max(working.height()) as f32;
+    let working = match &recipe.crop {
+        Some(crop) => apply_crop(working, crop),
+        None => working,
+    };
+    apply_recipe_balanced(working, recipe, None, frame_long_edge, None)
+}
+
+/// Resize to `resize` when given, then apply document mode, noise reduction, dehaze, globals, tone
+/// curves, clarity/texture, local layers, the LUT, the B&W conversion and grain of a recipe whose
+/// crop and white balance were already applied, preferring the GPU for everything but B&W.
+/// Resize, globals and layers stay on the GPU as linear half floats from one to the next while
+/// the stages between them are identity. CPU stages hand each other f32 pixels, which are rounded
+/// to 8-bit only for a GPU stage's upload and once at the end. `source` names the incoming pixels
+/// so the first GPU stage can reuse their resident upload. `frame_long_edge` is the long edge of
+/// the uncropped frame in the resized pixels, which grain is sized against.
+pub fn apply_recipe_balanced(
+    working: RgbaImage,
+    recipe: &EditRecipe,
+    source: Option<&gpu::SourceKey>,
+    frame_long_edge: f32,
+    resize: Option<(u32, u32)>,
+) -> RgbaImage {
+    let mut working = Working::Bytes(working);
+    // the key names `working` only until some stage has changed it
+    let mut source = source;
+    let mut run: Option<GpuRun> = None;
+    if let Some((w, h)) = resize.filter(|&size| size != working.dimensions()) {
+        let resized = gpu_run(&working, &mut run, source.take())
+            .is_some_and(|gpu| gpu.record(Chained::Resize(w, h), |chain| chain.resize(w, h)));
+        if !resized {
+            land(&mut working, &mut run, recipe);
+            working.resize(w, h);
+        }
+    }
+    if recipe.document.enabled {
+        land(&mut working, &mut run, recipe);
+        apply_document_mode(working.float(), &recipe.document);
+        source = None;
+    }
+    // denoise first so later contrast stages do not amplify the noise
+    let (w, h) = working.dimensions();
+    if let Some(nr) = noise_reduction_params(&recipe.globals, w, h) {
+        land(&mut working, &mut run, recipe);
+        let source = source.take();
+        match working.upload().and_then(|bytes| {
+            gpu::noise_reduction_rgba(
+                &bytes,
+                source,
+                nr.luma_sigma,
+                nr.eps,
+                nr.detail,
+                nr.chroma_sigma,
+            )
+        }) {
+            Some(gpu_img) => working = Working::Bytes(gpu_img),
+            None => apply_noise_reduction_in_place(working.float(), &nr),
+        }
+    }
+    // dehaze works on the scene before tone and colour edits
+    if recipe.globals.dehaze.abs() >= 1e-4 {
+        land(&mut working, &mut run, recipe);
+        apply_dehaze(&mut working, recipe.globals.dehaze);
+        source = None;
+    }
+    if !globals_are_identity(&recipe.globals) {
+        let (w, h) = run
+            .as_ref()
+            .map_or(working.dimensions(), |gpu| gpu.chain.dimensions());
+        let tone_sigmas = tone_sigmas(w, h);
+        let applied = gpu_run(&working, &mut run, source.take()).is_some_and(|gpu| {
+            gpu.record(Chained::Globals, |chain| {
+                chain.globals(&recipe.globals, tone_sigmas)
+            })
+        });
+        if !applied {
+            land(&mut working, &mut run, recipe);
+            apply_globals_in_place(working.float(), &recipe.globals);
+        }
+    }
+    if !levels_are_identity(&recipe.globals.levels) {
+        land(&mut working, &mut run, recipe);
+        apply_levels(&mut working, &recipe.globals.levels);
+        source = None;
+    }
+    if !curves_are_identity(&recipe.curves) {
+        land(&mut working, &mut run, recipe);
+        apply_curves(&mut working, &recipe.curves);
+        source = None;
+    }
+    if !local_contrast_is_identity(&recipe.globals) {
+        land(&mut working, &mut run, recipe);
+        let clarity = recipe.globals.clarity / 100.0;
+        let texture = recipe.globals.texture / 100.0;
+        let (w, h) = working.dimensions();
+        let (coarse_sigma, fine_sigma) = local_contrast_sigmas(w, h);
+        let source = source.take();
+        match working.upload().and_then(|bytes| {
+            gpu::local_contrast_rgba(&bytes, source, clarity, texture, coarse_sigma, fine_sigma)
+        }) {
+            Some(gpu_img) => working = Working::Bytes(gpu_img),
+            None => apply_local_contrast_in_place(working.float(), clarity, texture),
+        }
+    }
+    // layers join a run already on the GPU; on their own the upload is not worth it
+    for (idx, layer) in recipe.layers.iter().enumerate() {
+        if !layer.enabled || layer.opacity <= 0.0 {
+            continue;
+        }
+        let chained = run.as_mut().is_some_and(|gpu| {
+            let (w, h) = gpu.chain.dimensions();
+            layer_mask_weights(layer, w, h).is_some_and(|mask| {
+                gpu.record(Chained::Layer(idx), |chain| {
+                    chain.layer(&mask, &layer.adjustments)
+                })
+            })
+        });
+        if !chained {
+            land(&mut working, &mut run, recipe);
+            apply_local_layer_in_place(working.float(), layer);
+        }
+    }
+    land(&mut working, &mut run, recipe);
+    if let Some(lut_ref) = recipe.lut.as_ref().filter(|l| !l.path.trim().is_empty()) {
+        // a missing or unreadable LUT leaves the image as is rather than failing the render
+        if let Ok(lut) = cached_lut(Path::new(&lut_ref.path)) {
+            apply_lut_blended(&mut working, &lut, lut_ref.strength / 100.0);
+        }
+    }
+    if recipe.bw.enabled {
+        apply_black_and_white_in_place(working.float(), &recipe.bw);
+    }
+    if recipe.grain.amount > 0.0 {
+        apply_grain_rgba(working.float(), &recipe.grain, frame_long_edge);
+    }
+    working.finish(recipe.globals.dither)
+}
+
+/// The session-cached preview of an asset at about `max_dimension` on the long
+/// edge, for analyses that do not need the original's resolution.
+pub fn cached_preview(
+    asset_id: &str,
+    path: &Path,
+    max_dimension: u32,
+) -> Result<Arc<RgbaImage>, String> {
+    scaled_preview(asset_id, path, max_dimension).map(|preview| preview.buf)
+}
+
+/// Width / height of the original, from the file header when the `image` crate
+/// knows the format and from the cached thumbnail otherwise (RAWs).
+pub fn source_aspect(path: &Path) -> Result<f32, String> {
+    let (w, h) = match image::image_dimensions(path) {
+        // header dimensions are as stored; orientations 5..=8 turn the frame a quarter
+        Ok((w, h)) if read_orientation(path).is_some_and(|o| o >= 5) => (h, w),
+        Ok(dims) => dims,
+        Err(_) => {
+            let thumb = load_or_create_thumbnail(path)?;
+            let img = image::load_from_memory(&thumb)
+                .map_err(|e| format!("Failed to decode thumbnail: {e}"))?;
+            (img.width(), img.height())
+        }
+    };
+    if w == 0 || h == 0 {
+        return Err("Image has no pixels".into());
+    }
+    Ok(w as f32 / h as f32)
+}
+
+/// Suggest exposure, contrast, highlights, shadows, whites and blacks from the
+/// luminance histogram of the cached master preview. Other fields stay at their
+/// defaults; the frontend takes the tone sliders.
+pub fn auto_tone(asset_id: &str, path: &Path) -> Result<GlobalAdjustments, String> {
+    let master = master_preview(asset_id, path, PREVIEW_MASTER_BASE)?;
+    let mut bins = [0u64; HISTOGRAM_BINS];
+    for px in master.buf.pixels() {
+        let l = 0.2126 * px[0] as f32 + 0.7152 * px[1] as f32 + 0.0722 * px[2] as f32;
+        bins[(l.round() as usize).min(HISTOGRAM_BINS - 1)] += 1;
+    }
+    let total = bins.iter().sum::<u64>().max(1) as f64;
+    let percentile = |p: f64| {
+        let target = (total * p) as u64;
+        let mut seen = 0u64;
+        for (bin, &count) in bins.iter().enumerate() {
+            seen += count;
+            if seen > target {
+                return bin as f32 / (HISTOGRAM_BINS - 1) as f32;
+            }
+        }
+        1.0
+    };
+    let share = |keep: &dyn Fn(f32) -> bool| {
+        let hits: u64 = bins
+            .iter()
+            .enumerate()
+            .filter(|(bin, _)| keep(*bin as f32 / (HISTOGRAM_BINS - 1) as f32))
+            .map(|(_, &count)| count)
+            .sum();
+        hits as f32 / total as f32
+    };
+
+    let exposure_ev = (AUTO_TONE_MEDIAN / percentile(0.5).max(0.02))
+        .log2()
+        .clamp(-3.0, 3.0);
+    let gain = 2f32.powf(exposure_ev);
+
+    // pull back what exposure pushes into the top, lift what stays buried
+    let bright = share(&|v| v * gain > 0.9);
+    let dark = share(&|v| v * gain < 0.1);
+    let highlights = -(bright * 250.0).min(60.0);
+    let shadows = (dark * 250.0).min(60.0);
+
+    // stretch the 0.5%..99.5% range onto the black/white points; contrast scales
+    // around 0.5 after whites/blacks shift, so solve the shift for the clamped gain
+    let lo = percentile(0.005) * gain;
+    let hi = (percentile(0.995) * gain).min(1.0);
+    let stretch = (AUTO_TONE_WHITE - AUTO_TONE_BLACK) / (hi - lo).max(0.05);
+    let contrast = ((stretch - 1.0) * 100.0).clamp(-30.0, 40.0);
+    let stretch = 1.0 + contrast / 100.0;
+    let offset = 0.5 - lo - (0.5 - AUTO_TONE_BLACK) / stretch;
+    let (whites, blacks) = if offset >= 0.0 {
+        ((offset * 1000.0).min(100.0), 0.0)
+    } else {
+        (0.0, (-offset * 1000.0).min(100.0))
+    };
+
+    Ok(GlobalAdjustments {
+        exposure_ev: (exposure_ev * 20.0).round() / 20.0,
+        contrast: contrast.round(),
+        highlights: highlights.round(),
+        shadows: shadows.round(),
+        whites: whites.round(),
+        blacks: blacks.round(),
+        ..GlobalAdjustments::default()
+    })
+}
+
+/// Render a synthetic frame through the globals pass on the GPU, timing upload,
+/// compute and readback, and on the CPU for comparison.
+pub fn profile_render() -> GpuProfile {
+    let (w, h) = PROFILE_SIZE;
+    let frame = RgbaImage::from_fn(w, h, |x, y| {
+        Rgba([
+            (x * 255 / w) as u8,
+            (y * 255 / h) as u8,
+            ((x ^ y) & 0xff) as u8,
+            255,
+        ])
+    });
+    // every stage on, the tone guide's blurs included
+    let globals = GlobalAdjustments {
+        exposure_ev: 0.3,
+        contrast: 10.0,
+        highlights: -30.0,
+        shadows: 30.0,
+        whites: 5.0,
+        vibrance: 15.0,
+        ..GlobalAdjustments::default()
+    };
+    let gpu = gpu::profile_globals(&frame, &globals, tone_sigmas(w, h));
+
+    let start = Instant::now();
+    let mut cpu_frame = to_float(&frame);
+    apply_globals_in_place(&mut cpu_frame, &globals);
+    std::hint::black_box(quantize(&cpu_frame, globals.dither));
+    let cpu_ms = start.elapsed().as_secs_f32() * 1000.0;
+
+    let timings = gpu.as_ref().ok();
+    GpuProfile {
+        path: if gpu.is_ok() { "gpu" } else { "cpu" }.to_string(),
+        fallback_reason: gpu.as_ref().err().cloned(),
+        adapter: gpu::context_adapter().ok().map(|info| info.name),
+        width: w,
+        height: h,
+        upload_ms: timings.map(|t| t.upload_ms),
+        pass_ms: timings.map(|t| t.pass_ms),
+        readback_ms: timings.map(|t| t.readback_ms),
+        cpu_ms,
+    }
+}
+
+/// Decode the original at full resolution (no preview cap, no caching).
+pub fn decode_full_resolution(path: &Path) -> Result<RgbaImage, String> {
+    let mut img = load_dynamic_image(path, true)?.to_rgba8();
+    manage_source_color(&mut img, path);
+    Ok(img)
+}
+
+// Render the original at full resolution with its saved recipe, JPEG-encoded.
+fn render_full_preview(path: &Path, recipe: Option<EditRecipe>) -> Result<Vec<u8>, String> {
+    let mut working = decode_full_resolution(path)?;
+    if let Some(mut recipe) = recipe {
+        resolve_seed(&mut recipe.grain, path);
+        resolve_brush_masks(&mut recipe, path);
+        resolve_profile(&mut recipe.lens, path);
+        working = apply_recipe(working, &recipe);
+    }
+    let _timer = perf::Timer::start("encode");
+    let rgb = DynamicImage::ImageRgba8(working).to_rgb8();
+    let mut buffer = Vec::new();
+    JpegEncoder::new_with_quality(&mut buffer, FULL_PREVIEW_QUALITY)
+        .encode_image(&rgb)
+        .map_err(|e| format!("Failed to encode JPEG: {e}"))?;
+    Ok(buffer)
+}
+
+/// The persisted 1:1 preview for the asset's current recipe, rendered and stored
+/// on a miss. The bool is true when it had to be rendered.
+pub fn load_or_create_full_preview(path: &Path) -> Result<(Vec<u8>, bool), String> {
+    let recipe = load_recipe_for_asset(path)?;
+    let slot = full_preview_path(path, recipe.as_ref())?;
+    if let Ok(bytes) = fs::read(&slot) {
+        return Ok((bytes, false));
+    }
+    let bytes = render_full_preview(path, recipe)?;
+    if let Some(parent) = slot.parent() {
+        fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    write_atomic(&slot, &bytes).map_err(|e| format!("Write 1:1 preview failed: {e}"))?;
+    Ok((bytes, true))
+}
+
+/// Build 1:1 previews one asset at a time (each render is already parallel and a
+/// full-resolution decode is large), reporting progress per asset.
+pub fn pregenerate_full_previews(
+    app: &AppHandle,
+    assets: &[(String, PathBuf)],
+) -> Result<FullPreviewSummary, String> {
+    let _job_guard = begin_job()?;
+    let mut summary = FullPreviewSummary::default();
+    for (idx, (asset_id, path)) in assets.iter().enumerate() {
+        if stopping() {
+            break;
+        }
+        let error = match load_or_create_full_preview(path) {
+            Ok((_, true)) => {
+                summary.generated += 1;
+                None
+            }
+            Ok((_, false)) => {
+                summary.up_to_date += 1;
+                None
+            }
+            Err(err) => {
+                summary.failed += 1;
+                Some(err)
+            }
+        };
+        let _ = app.emit(
+            FULL_PREVIEW_EVENT,
+            FullPreviewProgress {
+                asset_id: asset_id.clone(),
+                done: idx + 1,
+                total: assets.len(),
+                error,
+            },
+        );
+    }
+    Ok(summary)
+}
+
 pub fn render_preview_with_recipe(
     asset_id: &str,
     path: &Path,
     recipe: Option<EditRecipe>,
     max_dimension: Option<u32>,
+    soft_proof: Option<&SoftProof>,
 ) -> Result<Vec<u8>, String> {
+    encode_png_fast(&render_preview_rgba(
+        asset_id,
+        path,
+        recipe,
+        max_dimension,
+        soft_proof,
+    )?)
+}
+
+/// The preview with one layer's mask laid over it in red, strongest where the
+/// layer applies fully. Luminance ranges are judged on the rendered pixels.
+pub fn render_mask_overlay(
+    asset_id: &str,
+    path: &Path,
+    recipe: EditRecipe,
+    layer_id: &str,
+    max_dimension: Option<u32>,
+) -> Result<Vec<u8>, String> {
+    let mut mask = recipe
+        .layers
+        .iter()
+        .find(|layer| layer.id == layer_id)
+        .ok_or("Layer not found")?
+        .mask
+        .clone();
+    resolve_brush_mask(&mut mask, path);
+    let mut working = render_preview_rgba(asset_id, path, Some(recipe), max_dimension, None)?;
+    overlay_mask(&mut working, &mask);
+    encode_png_fast(&working)
+}
+
+/// The mask's coverage for drawing over the preview (see mask::render_mask_preview),
+/// with luminance ranges judged on the preview rendered with `recipe`, else the
+/// saved one. Brush bitmaps must already be resolved.
+pub fn render_mask_coverage(
+    asset_id: &str,
+    path: &Path,
+    mask: &Mask,
+    recipe: Option<EditRecipe>,
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage, String> {
+    if !MaskSampler::new(mask).reads_luma() {
+        return Ok(render_mask_preview(mask, width, height, None));
+    }
+    let recipe = match recipe {
+        Some(recipe) => Some(recipe),
+        None => load_recipe_for_asset(path)?,
+    };
+    let working = render_preview_rgba(asset_id, path, recipe, Some(width.max(height)), None)?;
+    Ok(render_mask_preview(mask, width, height, Some(&working)))
+}
+
+// Names the pixels render_preview_rgba hands to apply_recipe_balanced: the cached
+// preview they start from and every edit made to it before that point.
+fn preview_source_key(asset_id: &str, base: &CachedPreview, recipe: &EditRecipe) -> gpu::SourceKey {
+    let globals = &recipe.globals;
+    let edits = serde_json::to_string(&(
+        (globals.temp, globals.tint, &globals.dual_illuminant),
+        &recipe.dead_pixels,
+        &recipe.retouch,
+        (&recipe.lens, recipe.flags.skip_lens_correction),
+        &recipe.crop,
+    ))
+    .unwrap_or_default();
+    let mut hasher = DefaultHasher::new();
+    (base.generation, base.buf.dimensions(), edits).hash(&mut hasher);
+    gpu::SourceKey {
+        asset_id: asset_id.to_string(),
+        stamp: hasher.finish(),
+    }
+}
+
+fn render_preview_rgba(
+    asset_id: &str,
+    path: &Path,
+    mut recipe: Option<EditRecipe>,
+    max_dimension: Option<u32>,
+    soft_proof: Option<&SoftProof>,
+) -> Result<RgbaImage, String> {
+    let _timer = perf::Timer::start("render");
     let target = max_dimension.unwrap_or(1440);
-    let base = scaled_preview(asset_id, path, target)?;
-    let mut working: RgbaImage = (*base).clone();
+    if let Some(r) = recipe.as_mut() {
+        resolve_seed(&mut r.grain, path);
+        resolve_brush_masks(r, path);
+        resolve_profile(&mut r.lens, path);
+    }
+    let base = match recipe.as_ref() {
+        Some(r) if !white_balance_is_identity(&r.globals) => {
+            balanced_preview(asset_id, path, target, &r.globals)?
+        }
+        _ => scaled_preview(asset_id, path, target)?,
+    };
+    let source = recipe
+        .as_ref()
+        .map(|r| preview_source_key(asset_id, &base, r));
+    let mut working: RgbaImage = (*base.buf).clone();
 
     if let Some(r) = recipe.as_ref() {
-        if !globals_are_identity(&r.globals) {
-            if let Some(gpu_img) = gpu::apply_globals_rgba(&working, &r.globals) {
-                working = gpu_img;
-            } else {
-                apply_globals_in_place(working.as_mut(), &r.globals);
-            }
-        }
-        if layers_have_effect(&r.layers) {
-            let (w, h) = working.dimensions();
-            apply_layers_in_place(working.as_mut(), w, h, &r.layers);
+        repair_pixels(&mut working, &r.dead_pixels);
+        apply_retouch(&mut working, &r.retouch);
+        working = correct_lens(working, r);
+        if let Some(crop) = &r.crop {
+            working = apply_crop(working, crop);
         }
+        let frame_long_edge = base.buf.width().max(base.buf.height()) as f32;
+        working = apply_recipe_balanced(working, r, source.as_ref(), frame_long_edge, None);
     }
-
-    encode_png_fast(&working)
+    if let Some(proof) = soft_proof {
+        apply_soft_proof(&mut working, proof)?;
+    }
+    Ok(working)
 }
@@ -0,0 +1,273 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use rayon::prelude::*;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::catalog::{catalog_key, load_catalog, update_catalog};
+use crate::mask::copy_brush_bitmaps;
+use crate::models::{AssetIntegrity, CatalogEntry, IntegrityStatus, RelinkSummary};
+use crate::recipe_io::{load_recipe_for_asset, save_recipe_for_asset};
+use crate::state::rebind_path;
+
+const READ_CHUNK: usize = 1 << 20;
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Streaming xxh3-128 of the file contents as lowercase hex.
+pub fn file_checksum(path: &Path) -> Result<String, String> {
+    let file = File::open(path).map_err(|e| format!("Open failed: {e}"))?;
+    let mut reader = BufReader::with_capacity(READ_CHUNK, file);
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0u8; READ_CHUNK];
+    loop {
+        let n = reader
+            .read(&mut buf)
+            .map_err(|e| format!("Read failed: {e}"))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:032x}", hasher.digest128()))
+}
+
+struct Scanned {
+    path: String,
+    size: u64,
+    modified: u64,
+    checksum: Result<String, String>,
+}
+
+fn scan(path: &Path) -> Scanned {
+    let meta = path.metadata().ok();
+    Scanned {
+        path: catalog_key(path),
+        size: meta.as_ref().map(|m| m.len()).unwrap_or(0),
+        modified: meta
+            .and_then(|m| m.modified().ok())
+            .map(unix_seconds)
+            .unwrap_or(0),
+        checksum: file_checksum(path),
+    }
+}
+
+fn classify(previous: Option<&CatalogEntry>, scanned: &Scanned, checksum: &str) -> IntegrityStatus {
+    let Some(prev) = previous else {
+        return IntegrityStatus::New;
+    };
+    match prev.checksum.as_deref() {
+        None => IntegrityStatus::New,
+        Some(stored) if stored == checksum => IntegrityStatus::Verified,
+        // a changed size or mtime means the file was rewritten on purpose
+        Some(_) if prev.size != scanned.size || prev.modified != scanned.modified => {
+            IntegrityStatus::Modified
+        }
+        // same size and mtime but different bytes: bit rot or a truncated copy
+        Some(_) => IntegrityStatus::Mismatch,
+    }
+}
+
+/// Hash every file, compare against the catalog and record the new checksums.
+/// Mismatches keep the stored checksum so a later run still flags them.
+/// Catalog entries under `folder` whose file is gone are reported as missing.
+pub fn verify_files(folder: &Path, files: &[PathBuf]) -> Result<Vec<AssetIntegrity>, String> {
+    let scanned: Vec<Scanned> = files.par_iter().map(|path| scan(path)).collect();
+    let now = unix_seconds(SystemTime::now());
+
+    update_catalog(|catalog| {
+        let mut report = Vec::with_capacity(scanned.len());
+        for item in &scanned {
+            let checksum = match &item.checksum {
+                Ok(sum) => sum,
+                Err(err) => {
+                    report.push(AssetIntegrity {
+                        path: item.path.clone(),
+                        status: IntegrityStatus::Unreadable,
+                        checksum: None,
+                        error: Some(err.clone()),
+                    });
+                    continue;
+                }
+            };
+            let status = classify(catalog.assets.get(&item.path), item, checksum);
+            if status != IntegrityStatus::Mismatch {
+                let entry = catalog.assets.entry(item.path.clone()).or_default();
+                entry.checksum = Some(checksum.clone());
+                entry.size = item.size;
+                entry.modified = item.modified;
+                entry.verified_at = now;
+            }
+            report.push(AssetIntegrity {
+                path: item.path.clone(),
+                status,
+                checksum: Some(checksum.clone()),
+                error: None,
+            });
+        }
+
+        let mut missing: Vec<AssetIntegrity> = catalog
+            .assets
+            .iter()
+            .filter(|(path, entry)| {
+                let path = Path::new(path.as_str());
+                entry.checksum.is_some() && path.parent() == Some(folder) && !path.exists()
+            })
+            .map(|(path, entry)| AssetIntegrity {
+                path: path.clone(),
+                status: IntegrityStatus::Missing,
+                checksum: entry.checksum.clone(),
+                error: None,
+            })
+            .collect();
+        missing.sort_by(|a, b| a.path.cmp(&b.path));
+        report.extend(missing);
+        report
+    })
+}
+
+// An original found at a new location, already hashed.
+struct Relink {
+    old_key: String,
+    old: PathBuf,
+    new: Scanned,
+}
+
+// Entries never hashed fall back to the file name and the recorded size.
+fn same_file(
+    old: &Path,
+    previous: Option<&CatalogEntry>,
+    scanned: &Scanned,
+) -> Result<bool, String> {
+    let checksum = scanned.checksum.as_ref().map_err(|e| e.clone())?;
+    if let Some(stored) = previous.and_then(|entry| entry.checksum.as_deref()) {
+        return Ok(stored == checksum);
+    }
+    let size = previous.map_or(0, |entry| entry.size);
+    Ok(size == scanned.size && old.file_name() == Path::new(&scanned.path).file_name())
+}
+
+// Recipes already beside the new file win over the ones left at the old location.
+fn carry_sidecar(old: &Path, new: &Path) -> Result<(), String> {
+    if load_recipe_for_asset(new)?.is_some() {
+        return Ok(());
+    }
+    match load_recipe_for_asset(old) {
+        Ok(Some(recipe)) => {
+            copy_brush_bitmaps(&recipe, old, new)?;
+            save_recipe_for_asset(new, &recipe)
+        }
+        _ => Ok(()),
+    }
+}
+
+// Move catalog entries (and proxy and derived links pointing at them) to the new keys,
+// then point the session's asset ids at the new files.
+fn apply_relinks(relinks: &[Relink]) -> Result<(), String> {
+    for relink in relinks {
+        carry_sidecar(&relink.old, Path::new(&relink.new.path))?;
+    }
+    let now = unix_seconds(SystemTime::now());
+    update_catalog(|catalog| {
+        for relink in relinks {
+            let new_key = relink.new.path.clone();
+            let mut entry = catalog.assets.remove(&relink.old_key).unwrap_or_default();
+            entry.checksum = relink.new.checksum.clone().ok();
+            entry.size = relink.new.size;
+            entry.modified = relink.new.modified;
+            entry.verified_at = now;
+            catalog.assets.insert(new_key.clone(), entry);
+            for other in catalog.assets.values_mut() {
+                if other.proxy.as_deref() == Some(relink.old_key.as_str()) {
+                    other.proxy = Some(new_key.clone());
+                }
+                if other.proxy_of.as_deref() == Some(relink.old_key.as_str()) {
+                    other.proxy_of = Some(new_key.clone());
+                }
+                if let Some(derived) = other.derived.as_mut() {
+                    for source in derived.sources.iter_mut() {
+                        if *source == relink.old_key {
+                            *source = new_key.clone();
+                        }
+                    }
+                }
+            }
+        }
+    })?;
+    for relink in relinks {
+        rebind_path(&relink.old, Path::new(&relink.new.path));
+        rebind_path(Path::new(&relink.old_key), Path::new(&relink.new.path));
+    }
+    Ok(())
+}
+
+/// Rebind one original to `new_path` after checking it is the same file: by
+/// checksum, or by name and size when the original was never hashed.
+pub fn locate_file(old_path: &Path, new_path: &Path) -> Result<(), String> {
+    if !new_path.is_file() {
+        return Err(format!("{} is not a file", new_path.display()));
+    }
+    let old_key = catalog_key(old_path);
+    let new = scan(new_path);
+    if !same_file(old_path, load_catalog()?.assets.get(&old_key), &new)? {
+        return Err(format!(
+            "Mismatch: {} is not the original file",
+            new_path.display()
+        ));
+    }
+    apply_relinks(&[Relink {
+        old_key,
+        old: old_path.to_path_buf(),
+        new,
+    }])
+}
+
+/// Rebind every catalog entry under `old_root` whose file is gone to the same
+/// relative path under `new_root`, keeping only files that pass the same check.
+pub fn relink_assets(old_root: &Path, new_root: &Path) -> Result<RelinkSummary, String> {
+    let catalog = load_catalog()?;
+    let mut moved: Vec<(String, PathBuf)> = catalog
+        .assets
+        .keys()
+        .filter_map(|key| {
+            let old = Path::new(key);
+            let rel = old.strip_prefix(old_root).ok()?;
+            (!old.exists()).then(|| (key.clone(), new_root.join(rel)))
+        })
+        .collect();
+    moved.sort();
+    let found: Vec<(String, Option<Scanned>)> = moved
+        .into_par_iter()
+        .map(|(key, candidate)| {
+            let scanned = candidate.is_file().then(|| scan(&candidate));
+            (key, scanned)
+        })
+        .collect();
+
+    let mut summary = RelinkSummary::default();
+    let mut relinks = Vec::new();
+    for (old_key, scanned) in found {
+        let Some(new) = scanned else {
+            summary.missing.push(old_key);
+            continue;
+        };
+        if !same_file(Path::new(&old_key), catalog.assets.get(&old_key), &new).unwrap_or(false) {
+            summary.mismatched.push(old_key);
+            continue;
+        }
+        relinks.push(Relink {
+            old: PathBuf::from(&old_key),
+            old_key,
+            new,
+        });
+    }
+    apply_relinks(&relinks)?;
+    summary.relinked = relinks.len();
+    Ok(summary)
+}
@@ -0,0 +1,688 @@
+use image::RgbaImage;
+
+// Natural (row-major) index of each coefficient in zigzag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+// Codes up to this long decode with one table lookup.
+const FAST_BITS: u32 = 9;
+// Shrink factors the scaled IDCT offers, largest first.
+const SHRINKS: [u32; 3] = [8, 4, 2];
+
+struct Huffman {
+    fast: Vec<(u8, u8)>, // (code length, symbol) by the next FAST_BITS bits; length 0 misses
+    max_code: [i32; 17],
+    min_code: [i32; 17],
+    offset: [i32; 17],
+    symbols: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8; 16], symbols: Vec<u8>) -> Option<Self> {
+        let mut table = Huffman {
+            fast: vec![(0, 0); 1 << FAST_BITS],
+            max_code: [-1; 17],
+            min_code: [0; 17],
+            offset: [0; 17],
+            symbols,
+        };
+        let (mut code, mut k) = (0i32, 0usize);
+        for len in 1..=16 {
+            let count = counts[len - 1] as usize;
+            table.min_code[len] = code;
+            table.offset[len] = k as i32;
+            for _ in 0..count {
+                // an oversubscribed table runs out of codes of this length
+                if code >= 1 << len {
+                    return None;
+                }
+                let symbol = *table.symbols.get(k)?;
+                if len as u32 <= FAST_BITS {
+                    let shift = FAST_BITS - len as u32;
+                    let first = (code as usize) << shift;
+                    for slot in &mut table.fast[first..first + (1 << shift)] {
+                        *slot = (len as u8, symbol);
+                    }
+                }
+                code += 1;
+                k += 1;
+            }
+            if count > 0 {
+                table.max_code[len] = code - 1;
+            }
+            code <<= 1;
+        }
+        Some(table)
+    }
+}
+
+// MSB-first reader over entropy-coded data. Stuffed 0xFF00 pairs read as 0xFF;
+// a marker ends the data and zeros are read from there on.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u64,
+    count: u32,
+    at_marker: bool,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Bits {
+            data,
+            pos: 0,
+            acc: 0,
+            count: 0,
+            at_marker: false,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.count <= 56 {
+            let mut byte = 0;
+            if !self.at_marker {
+                match self.data.get(self.pos) {
+                    Some(0xFF) if self.data.get(self.pos + 1) == Some(&0) => {
+                        byte = 0xFF;
+                        self.pos += 2;
+                    }
+                    Some(0xFF) | None => self.at_marker = true,
+                    Some(&b) => {
+                        byte = b;
+                        self.pos += 1;
+                    }
+                }
+            }
+            self.acc |= (byte as u64) << (56 - self.count);
+            self.count += 8;
+        }
+    }
+
+    fn peek(&self, n: u32) -> u32 {
+        (self.acc >> (64 - n)) as u32
+    }
+
+    fn consume(&mut self, n: u32) {
+        self.acc <<= n;
+        self.count -= n;
+    }
+
+    fn symbol(&mut self, table: &Huffman) -> Option<u8> {
+        self.fill();
+        let (len, symbol) = table.fast[self.peek(FAST_BITS) as usize];
+        if len > 0 {
+            self.consume(len as u32);
+            return Some(symbol);
+        }
+        for len in FAST_BITS as usize + 1..=16 {
+            let code = self.peek(len as u32) as i32;
+            if code <= table.max_code[len] {
+                self.consume(len as u32);
+                let at = table.offset[len] + code - table.min_code[len];
+                return table.symbols.get(at as usize).copied();
+            }
+        }
+        None
+    }
+
+    // The next `size` bits as a signed coefficient (F.2.2.1 EXTEND).
+    fn receive(&mut self, size: u8) -> i32 {
+        if size == 0 {
+            return 0;
+        }
+        self.fill();
+        let size = size as u32;
+        let value = self.peek(size) as i32;
+        self.consume(size);
+        if value < 1 << (size - 1) {
+            value - (1 << size) + 1
+        } else {
+            value
+        }
+    }
+
+    // Drop leftover bits and step over the RSTn marker that follows.
+    fn restart(&mut self) {
+        self.acc = 0;
+        self.count = 0;
+        self.at_marker = false;
+        if self.data.get(self.pos) == Some(&0xFF)
+            && self
+                .data
+                .get(self.pos + 1)
+                .is_some_and(|m| (0xD0..=0xD7).contains(m))
+        {
+            self.pos += 2;
+        }
+    }
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant: usize,
+    dc: usize,
+    ac: usize,
+}
+
+#[derive(Default)]
+struct Frame {
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+}
+
+struct Tables {
+    quant: [[u16; 64]; 4], // natural order
+    dc: [Option<Huffman>; 4],
+    ac: [Option<Huffman>; 4],
+    restart_interval: usize, // MCUs between RSTn markers, 0 for none
+}
+
+impl Default for Tables {
+    fn default() -> Self {
+        Tables {
+            quant: [[0; 64]; 4],
+            dc: Default::default(),
+            ac: Default::default(),
+            restart_interval: 0,
+        }
+    }
+}
+
+// One decoded component at the reduced size, padded out to whole blocks.
+struct Plane {
+    width: usize,
+    samples: Vec<u8>,
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> Option<usize> {
+    Some(u16::from_be_bytes([*bytes.get(at)?, *bytes.get(at + 1)?]) as usize)
+}
+
+// Row j of an N-point IDCT of the lowest N coefficients: each output sample is the
+// full 8-point basis evaluated at the centre of the pixels it stands for.
+fn idct_basis<const N: usize>() -> [[f32; N]; N] {
+    std::array::from_fn(|j| {
+        std::array::from_fn(|u| {
+            let c = if u == 0 {
+                std::f32::consts::FRAC_1_SQRT_2
+            } else {
+                1.0
+            };
+            let angle = (2 * j + 1) as f32 * u as f32 * std::f32::consts::PI / (2 * N) as f32;
+            c / 2.0 * angle.cos()
+        })
+    })
+}
+
+// One 8x8 block decoded to N x N samples. N is a constant so the IDCT loops unroll.
+#[allow(clippy::too_many_arguments)]
+fn decode_block<const N: usize>(
+    bits: &mut Bits,
+    dc: &Huffman,
+    ac: &Huffman,
+    quant: &[u16; 64],
+    pred: &mut i32,
+    basis: &[[f32; N]; N],
+    out: &mut [[u8; N]; N],
+) -> Option<()> {
+    let mut coef = [[0f32; N]; N];
+    // float to u8 casts saturate, so +0.5 then truncating rounds and clamps at once
+    let size = bits.symbol(dc)?;
+    if size > 11 {
+        return None;
+    }
+    // damaged data can walk the predictor anywhere; wrap rather than overflow
+    *pred = pred.wrapping_add(bits.receive(size));
+    coef[0][0] = pred.wrapping_mul(quant[0] as i32) as f32;
+    let mut detail = false;
+    let mut k = 1;
+    while k < 64 {
+        let rs = bits.symbol(ac)?;
+        let (run, size) = ((rs >> 4) as usize, rs & 15);
+        if size == 0 {
+            if run != 15 {
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run;
+        if k > 63 {
+            return None;
+        }
+        // coefficients beyond the kept corner are read past, never dequantized
+        let value = bits.receive(size);
+        let at = ZIGZAG[k];
+        let (u, v) = (at % 8, at / 8);
+        if u < N && v < N {
+            coef[v][u] = (value * quant[at] as i32) as f32;
+            detail = true;
+        }
+        k += 1;
+    }
+    if !detail {
+        let flat = (coef[0][0] / 8.0 + 128.5) as u8;
+        *out = [[flat; N]; N];
+        return Some(());
+    }
+    let mut rows = [[0f32; N]; N];
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (u, value) in row.iter_mut().enumerate() {
+            *value = (0..N).map(|v| basis[y][v] * coef[v][u]).sum();
+        }
+    }
+    for (row, samples) in rows.iter().zip(out.iter_mut()) {
+        for (x, sample) in samples.iter_mut().enumerate() {
+            let value: f32 = (0..N).map(|u| basis[x][u] * row[u]).sum();
+            *sample = (value + 128.5) as u8;
+        }
+    }
+    Some(())
+}
+
+fn decode_scan<const N: usize>(
+    data: &[u8],
+    frame: &Frame,
+    order: &[usize],
+    tables: &Tables,
+) -> Option<Vec<Plane>> {
+    let single = frame.components.len() == 1;
+    let h_max = frame.components.iter().map(|c| c.h).max()?;
+    let v_max = frame.components.iter().map(|c| c.v).max()?;
+    // a lone component is coded block by block whatever its sampling factors
+    let (mcu_w, mcu_h) = if single {
+        (8, 8)
+    } else {
+        (8 * h_max, 8 * v_max)
+    };
+    let mcus_x = frame.width.div_ceil(mcu_w);
+    let mcus_y = frame.height.div_ceil(mcu_h);
+    let factors = |c: &Component| if single { (1, 1) } else { (c.h, c.v) };
+    let mut planes: Vec<Plane> = frame
+        .components
+        .iter()
+        .map(|c| {
+            let (h, v) = factors(c);
+            let width = mcus_x * h * N;
+            Plane {
+                width,
+                samples: vec![0; width * mcus_y * v * N],
+            }
+        })
+        .collect();
+    let basis = idct_basis::<N>();
+    let mut preds = vec![0i32; frame.components.len()];
+    let mut bits = Bits::new(data);
+    let mut block = [[0u8; N]; N];
+    for mcu in 0..mcus_x * mcus_y {
+        if tables.restart_interval > 0 && mcu > 0 && mcu % tables.restart_interval == 0 {
+            bits.restart();
+            preds.iter_mut().for_each(|p| *p = 0);
+        }
+        let (mx, my) = (mcu % mcus_x, mcu / mcus_x);
+        for &idx in order {
+            let component = &frame.components[idx];
+            let (h, v) = factors(component);
+            let dc = tables.dc[component.dc].as_ref()?;
+            let ac = tables.ac[component.ac].as_ref()?;
+            for by in 0..v {
+                for bx in 0..h {
+                    decode_block::<N>(
+                        &mut bits,
+                        dc,
+                        ac,
+                        &tables.quant[component.quant],
+                        &mut preds[idx],
+                        &basis,
+                        &mut block,
+                    )?;
+                    let plane = &mut planes[idx];
+                    let (x0, y0) = ((mx * h + bx) * N, (my * v + by) * N);
+                    for (y, samples) in block.iter().enumerate() {
+                        let at = (y0 + y) * plane.width + x0;
+                        plane.samples[at..at + N].copy_from_slice(samples);
+                    }
+                }
+            }
+        }
+    }
+    Some(planes)
+}
+
+/// Decode a baseline JPEG at 1/2, 1/4 or 1/8 scale through a reduced IDCT, the
+/// largest shrink whose long edge still reaches `target`. None when no shrink
+/// fits or the file is progressive, arithmetic-coded, CMYK or damaged; callers
+/// then decode in full. Pixels come out as stored, before EXIF rotation.
+pub fn decode_jpeg_scaled(bytes: &[u8], target: u32) -> Option<RgbaImage> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut tables = Tables::default();
+    let mut frame = Frame::default();
+    let mut adobe_rgb = false;
+    let mut pos = 2;
+    loop {
+        while bytes.get(pos) == Some(&0xFF) && bytes.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        if bytes.get(pos) != Some(&0xFF) {
+            return None;
+        }
+        let marker = *bytes.get(pos + 1)?;
+        let length = u16_at(bytes, pos + 2)?;
+        let segment = bytes.get(pos + 4..pos + 2 + length)?;
+        match marker {
+            0xDB => {
+                let mut at = 0;
+                while at < segment.len() {
+                    let (precision, id) = (segment[at] >> 4, (segment[at] & 15) as usize);
+                    at += 1;
+                    let table = tables.quant.get_mut(id)?;
+                    for &natural in &ZIGZAG {
+                        table[natural] = if precision == 0 {
+                            *segment.get(at)? as u16
+                        } else {
+                            u16_at(segment, at)? as u16
+                        };
+                        at += 1 + precision as usize;
+                    }
+                }
+            }
+            0xC4 => {
+                let mut at = 0;
+                while at < segment.len() {
+                    let (class, id) = (segment[at] >> 4, (segment[at] & 15) as usize);
+                    let counts: [u8; 16] = segment.get(at + 1..at + 17)?.try_into().ok()?;
+                    let total: usize = counts.iter().map(|&c| c as usize).sum();
+                    let symbols = segment.get(at + 17..at + 17 + total)?.to_vec();
+                    let table = Some(Huffman::new(&counts, symbols)?);
+                    match class {
+                        0 => *tables.dc.get_mut(id)? = table,
+                        _ => *tables.ac.get_mut(id)? = table,
+                    }
+                    at += 17 + total;
+                }
+            }
+            // baseline and extended sequential Huffman, 8-bit only
+            0xC0 | 0xC1 => {
+                if *segment.first()? != 8 {
+                    return None;
+                }
+                frame.height = u16_at(segment, 1)?;
+                frame.width = u16_at(segment, 3)?;
+                let count = *segment.get(5)? as usize;
+                for i in 0..count {
+                    let spec = segment.get(6 + i * 3..9 + i * 3)?;
+                    let (h, v) = ((spec[1] >> 4) as usize, (spec[1] & 15) as usize);
+                    if !(1..=4).contains(&h) || !(1..=4).contains(&v) || spec[2] > 3 {
+                        return None;
+                    }
+                    frame.components.push(Component {
+                        id: spec[0],
+                        h,
+                        v,
+                        quant: spec[2] as usize,
+                        dc: 0,
+                        ac: 0,
+                    });
+                }
+            }
+            // progressive, lossless, hierarchical and arithmetic frames
+            0xC2 | 0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => return None,
+            0xDD => tables.restart_interval = u16_at(segment, 0)?,
+            // Adobe's transform flag 0 means the three channels are RGB already
+            0xEE => adobe_rgb = segment.starts_with(b"Adobe") && segment.get(11) == Some(&0),
+            0xDA => break,
+            0xD9 => return None,
+            _ => {}
+        }
+        pos += 2 + length;
+    }
+
+    if frame.width == 0 || frame.height == 0 || !matches!(frame.components.len(), 1 | 3) {
+        return None;
+    }
+    // the full decoder this stands in for refuses frames past image's default
+    // allocation limit; a tiny file declaring a huge frame stops here too, before
+    // any plane is allocated or a block decoded
+    let frame_bytes = (frame.width * frame.height * frame.components.len()) as u64;
+    if image::Limits::default()
+        .max_alloc
+        .is_some_and(|max| frame_bytes > max)
+    {
+        return None;
+    }
+    let long_edge = frame.width.max(frame.height) as u32;
+    let shrink = SHRINKS
+        .into_iter()
+        .find(|&s| long_edge.div_ceil(s) >= target)? as usize;
+
+    let scan = bytes.get(pos + 4..)?;
+    let count = *scan.first()? as usize;
+    // several scans (non-interleaved baseline) are rare enough to leave to the full decoder
+    if count != frame.components.len() {
+        return None;
+    }
+    let mut order = Vec::with_capacity(count);
+    for i in 0..count {
+        let spec = scan.get(1 + i * 2..3 + i * 2)?;
+        let idx = frame.components.iter().position(|c| c.id == spec[0])?;
+        let component = &mut frame.components[idx];
+        component.dc = (spec[1] >> 4) as usize;
+        component.ac = (spec[1] & 15) as usize;
+        if component.dc > 3 || component.ac > 3 {
+            return None;
+        }
+        order.push(idx);
+    }
+    let header = u16_at(bytes, pos + 2)?;
+    let data = bytes.get(pos + 2 + header..)?;
+    let planes = match shrink {
+        8 => decode_scan::<1>(data, &frame, &order, &tables),
+        4 => decode_scan::<2>(data, &frame, &order, &tables),
+        _ => decode_scan::<4>(data, &frame, &order, &tables),
+    }?;
+
+    let out_w = frame.width.div_ceil(shrink);
+    let out_h = frame.height.div_ceil(shrink);
+    let h_max = frame.components.iter().map(|c| c.h).max()?;
+    let v_max = frame.components.iter().map(|c| c.v).max()?;
+    let rgb = adobe_rgb || frame.components.iter().map(|c| c.id).eq(*b"RGB");
+    let single = frame.components.len() == 1;
+    // each plane's row stretched to the output width: chroma planes are smaller
+    let mut stretched = vec![vec![0u8; out_w]; frame.components.len()];
+    let mut pixels = vec![255u8; out_w * out_h * 4];
+    for (y, out) in pixels.chunks_exact_mut(out_w * 4).enumerate() {
+        for ((c, plane), row) in frame.components.iter().zip(&planes).zip(&mut stretched) {
+            let (sy, h) = if single {
+                (y, h_max)
+            } else {
+                (y * c.v / v_max, c.h)
+            };
+            let source = &plane.samples[sy * plane.width..(sy + 1) * plane.width];
+            if h == h_max {
+                row.copy_from_slice(&source[..out_w]);
+            } else {
+                for (x, sample) in row.iter_mut().enumerate() {
+                    *sample = source[x * h / h_max];
+                }
+            }
+        }
+        let pixels = out.chunks_exact_mut(4);
+        if single {
+            for (px, &l) in pixels.zip(&stretched[0]) {
+                px[..3].fill(l);
+            }
+        } else if rgb {
+            for (px, ((&r, &g), &b)) in
+                pixels.zip(stretched[0].iter().zip(&stretched[1]).zip(&stretched[2]))
+            {
+                px[..3].copy_from_slice(&[r, g, b]);
+            }
+        } else {
+            for (px, ((&l, &cb), &cr)) in
+                pixels.zip(stretched[0].iter().zip(&stretched[1]).zip(&stretched[2]))
+            {
+                // JFIF YCbCr in 16.16 fixed point
+                let (l, cb, cr) = ((l as i32) << 16, cb as i32 - 128, cr as i32 - 128);
+                let rgb = [
+                    l + 91_881 * cr,
+                    l - 22_554 * cb - 46_802 * cr,
+                    l + 116_130 * cb,
+                ];
+                for (out, value) in px.iter_mut().zip(rgb) {
+                    *out = ((value + 32_768) >> 16).clamp(0, 255) as u8;
+                }
+            }
+        }
+    }
+    RgbaImage::from_raw(out_w as u32, out_h as u32, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::{ExtendedColorType, Rgb, RgbImage};
+
+    fn encode(img: &RgbImage) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        JpegEncoder::new_with_quality(&mut bytes, 90)
+            .encode(
+                img.as_raw(),
+                img.width(),
+                img.height(),
+                ExtendedColorType::Rgb8,
+            )
+            .unwrap();
+        bytes
+    }
+
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            Rgb([(x * 255 / width) as u8, (y * 255 / height) as u8, 96])
+        })
+    }
+
+    // Offset of the first segment with `marker`, at its 0xFF.
+    fn find_marker(bytes: &[u8], marker: u8) -> usize {
+        let mut pos = 2;
+        while bytes[pos + 1] != marker {
+            pos += 2 + u16_at(bytes, pos + 2).unwrap();
+        }
+        pos
+    }
+
+    #[test]
+    fn decodes_at_reduced_scale() {
+        let source = gradient(256, 128);
+        let decoded = decode_jpeg_scaled(&encode(&source), 32).unwrap();
+        assert_eq!(decoded.dimensions(), (32, 16));
+        for (x, y) in [(2, 2), (16, 8), (29, 13)] {
+            let px = decoded.get_pixel(x, y);
+            let expected = source.get_pixel(x * 8 + 4, y * 8 + 4);
+            for c in 0..3 {
+                let diff = (px[c] as i32 - expected[c] as i32).abs();
+                assert!(diff <= 12, "channel {c} at ({x}, {y}) off by {diff}");
+            }
+        }
+    }
+
+    #[test]
+    fn picks_the_largest_shrink_that_reaches_the_target() {
+        let bytes = encode(&gradient(200, 100));
+        assert_eq!(decode_jpeg_scaled(&bytes, 25).unwrap().width(), 25);
+        assert_eq!(decode_jpeg_scaled(&bytes, 30).unwrap().width(), 50);
+        assert_eq!(decode_jpeg_scaled(&bytes, 90).unwrap().width(), 100);
+        assert!(decode_jpeg_scaled(&bytes, 150).is_none());
+    }
+
+    #[test]
+    fn rejects_oversubscribed_huffman_tables() {
+        let mut counts = [0u8; 16];
+        counts[0] = 3;
+        assert!(Huffman::new(&counts, vec![0, 1, 2]).is_none());
+        counts[0] = 2;
+        assert!(Huffman::new(&counts, vec![0, 1]).is_some());
+        // the two 1-bit codes already fill the tree, so a longer code cannot fit
+        counts[9] = 1;
+        assert!(Huffman::new(&counts, vec![0, 1, 2]).is_none());
+    }
+
+    #[test]
+    fn oversubscribed_table_in_a_file_returns_none() {
+        let mut bytes = encode(&gradient(64, 64));
+        let dht = find_marker(&bytes, 0xC4);
+        // claim every code of length 1..3 is used, more than the tree holds
+        bytes[dht + 5..dht + 8].copy_from_slice(&[2, 4, 8]);
+        assert!(decode_jpeg_scaled(&bytes, 8).is_none());
+    }
+
+    #[test]
+    fn truncated_files_do_not_panic() {
+        let bytes = encode(&gradient(96, 64));
+        for len in 0..bytes.len() {
+            let _ = decode_jpeg_scaled(&bytes[..len], 12);
+        }
+    }
+
+    #[test]
+    fn malformed_markers_do_not_panic() {
+        let bytes = encode(&gradient(64, 48));
+        let scan = find_marker(&bytes, 0xDA);
+        for at in 2..scan + 16 {
+            for value in [0x00, 0x01, 0x7F, 0xFF] {
+                let mut damaged = bytes.clone();
+                damaged[at] = value;
+                let _ = decode_jpeg_scaled(&damaged, 8);
+            }
+        }
+        // segment lengths too short to hold their own length field
+        let dqt = find_marker(&bytes, 0xDB);
+        for length in [0u8, 1, 2] {
+            let mut damaged = bytes.clone();
+            damaged[dqt + 2..dqt + 4].copy_from_slice(&[0, length]);
+            assert!(decode_jpeg_scaled(&damaged, 8).is_none());
+        }
+    }
+
+    #[test]
+    fn damaged_entropy_data_does_not_panic() {
+        let bytes = encode(&gradient(128, 96));
+        let scan = find_marker(&bytes, 0xDA);
+        let data = scan + 2 + u16_at(&bytes, scan + 2).unwrap();
+        let mut state = 0x2545_f491u32;
+        for _ in 0..200 {
+            let mut damaged = bytes.clone();
+            for _ in 0..8 {
+                // xorshift, so the damage is the same on every run
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                let at = data + state as usize % (bytes.len() - data - 2);
+                damaged[at] = (state >> 24) as u8;
+            }
+            let _ = decode_jpeg_scaled(&damaged, 16);
+        }
+    }
+
+    #[test]
+    fn rejects_frames_past_the_allocation_limit() {
+        let mut bytes = encode(&gradient(64, 48));
+        let sof = find_marker(&bytes, 0xC0);
+        bytes[sof + 5..sof + 9].copy_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+        assert!(decode_jpeg_scaled(&bytes, 8).is_none());
+    }
+
+    #[test]
+    fn rejects_non_jpeg_input() {
+        assert!(decode_jpeg_scaled(b"", 8).is_none());
+        assert!(decode_jpeg_scaled(b"\x89PNG\r\n", 8).is_none());
+        assert!(decode_jpeg_scaled(&[0xFF, 0xD8, 0xFF, 0xD9], 8).is_none());
+    }
+}
@@ -0,0 +1,251 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use image::RgbaImage;
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+
+use crate::cache::data_root;
+use crate::color::{linear_to_srgb, srgb_to_linear};
+use crate::image_io::sample_bilinear;
+use crate::metadata::read_metadata;
+use crate::models::{EditRecipe, LensCoefficients, LensCorrection, LensProfile};
+
+// Full-strength slider terms, in the units of the ptlens `b` and pa `k1` coefficients.
+const MANUAL_DISTORTION_RANGE: f32 = 0.05;
+const MANUAL_VIGNETTING_RANGE: f32 = 0.6;
+// keeps the vignetting gain bounded for profiles that overshoot in the corners
+const MIN_VIGNETTING_FALLOFF: f32 = 0.1;
+const AUTOSCALE_STEPS: usize = 24;
+
+type ProfileDb = Arc<Vec<LensProfile>>;
+
+// The database file is re-read when its mtime changes.
+static PROFILES: Lazy<Mutex<Option<(SystemTime, ProfileDb)>>> = Lazy::new(|| Mutex::new(None));
+
+fn profiles_path() -> Result<PathBuf, String> {
+    Ok(data_root()?.join("lens_profiles.json"))
+}
+
+/// Lens profiles from `lens_profiles.json` in the app data folder; empty when
+/// the file does not exist.
+pub fn lens_profiles() -> Result<ProfileDb, String> {
+    let path = profiles_path()?;
+    let Ok(modified) = fs::metadata(&path).and_then(|m| m.modified()) else {
+        return Ok(Arc::new(Vec::new()));
+    };
+    let mut cached = PROFILES.lock().map_err(|e| e.to_string())?;
+    if let Some((stamp, profiles)) = cached.as_ref() {
+        if *stamp == modified {
+            return Ok(profiles.clone());
+        }
+    }
+    let data = fs::read_to_string(&path).map_err(|e| format!("Read lens profiles failed: {e}"))?;
+    let profiles: ProfileDb = Arc::new(
+        serde_json::from_str(&data).map_err(|e| format!("Parse lens profiles failed: {e}"))?,
+    );
+    *cached = Some((modified, profiles.clone()));
+    Ok(profiles)
+}
+
+// Lens names differ in spacing and punctuation between EXIF and databases.
+fn normalize_model(model: &str) -> String {
+    model
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// The profile for an EXIF lens model: an exact match, else the shortest
+/// profile name containing it (databases often prefix the maker).
+pub fn find_profile(lens_model: &str) -> Result<Option<LensProfile>, String> {
+    let wanted = normalize_model(lens_model);
+    if wanted.is_empty() {
+        return Ok(None);
+    }
+    let profiles = lens_profiles()?;
+    let candidates = profiles
+        .iter()
+        .filter(|p| !p.calibration.is_empty())
+        .map(|p| (normalize_model(&p.model), p));
+    let best = candidates
+        .filter(|(name, _)| name.contains(&wanted))
+        .min_by_key(|(name, _)| (*name != wanted, name.len()));
+    Ok(best.map(|(_, p)| p.clone()))
+}
+
+/// Coefficients at `focal` mm, linearly interpolated between the two nearest
+/// calibrated focal lengths and clamped to the calibrated range.
+pub fn coefficients_at(profile: &LensProfile, focal: f32) -> LensCoefficients {
+    let mut points = profile.calibration.clone();
+    points.sort_by(|a, b| a.focal.total_cmp(&b.focal));
+    let Some(first) = points.first() else {
+        return LensCoefficients::default();
+    };
+    if focal <= first.focal {
+        return first.coefficients;
+    }
+    for pair in points.windows(2) {
+        let (lo, hi) = (&pair[0], &pair[1]);
+        if focal <= hi.focal {
+            let t = (focal - lo.focal) / (hi.focal - lo.focal).max(1e-3);
+            let (l, h) = (lo.coefficients, hi.coefficients);
+            let mix = |a: f32, b: f32| a + (b - a) * t;
+            return LensCoefficients {
+                a: mix(l.a, h.a),
+                b: mix(l.b, h.b),
+                c: mix(l.c, h.c),
+                k1: mix(l.k1, h.k1),
+                k2: mix(l.k2, h.k2),
+                k3: mix(l.k3, h.k3),
+            };
+        }
+    }
+    points[points.len() - 1].coefficients
+}
+
+// "35 mm" / "35.0 mm" as shown by the EXIF reader
+fn parse_focal(focal: &str) -> Option<f32> {
+    let number: String = focal
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    number.parse().ok().filter(|f: &f32| *f > 0.0)
+}
+
+/// The database coefficients for the lens and focal length in the asset's EXIF.
+/// Assets without a focal length use the shortest calibrated one.
+pub fn profile_for_asset(path: &Path) -> Option<LensCoefficients> {
+    let meta = read_metadata(path).ok()?;
+    let profile = find_profile(meta.lens.as_deref()?).ok()??;
+    let focal = meta.focal.as_deref().and_then(parse_focal).unwrap_or(0.0);
+    Some(coefficients_at(&profile, focal))
+}
+
+/// Fill in the profile once per render when the recipe asks for one, so the
+/// previews and exports of an asset share the same coefficients.
+pub fn resolve_profile(lens: &mut LensCorrection, path: &Path) {
+    if lens.enabled && lens.use_profile && lens.profile.is_none() {
+        lens.profile = profile_for_asset(path);
+    }
+}
+
+// Profile terms (when enabled) plus the manual sliders.
+fn effective_coefficients(lens: &LensCorrection) -> LensCoefficients {
+    let mut k = match lens.profile {
+        Some(profile) if lens.use_profile => profile,
+        _ => LensCoefficients::default(),
+    };
+    k.b -= MANUAL_DISTORTION_RANGE * lens.distortion / 100.0;
+    k.k1 -= MANUAL_VIGNETTING_RANGE * lens.vignetting / 100.0;
+    k
+}
+
+// ptlens: source radius for a corrected radius, both normalized to half the short side
+fn distorted_radius(k: &LensCoefficients, r: f32) -> f32 {
+    let d = 1.0 - k.a - k.b - k.c;
+    r * (((k.a * r + k.b) * r + k.c) * r + d)
+}
+
+// Largest zoom (<= 1) at which the frame edges and corners still sample inside
+// the source, so correcting barrel distortion leaves no empty borders.
+fn autoscale(k: &LensCoefficients, half_w: f32, half_h: f32) -> f32 {
+    let extents = [half_w, half_h, (half_w * half_w + half_h * half_h).sqrt()];
+    let fits = |zoom: f32| {
+        extents
+            .iter()
+            .all(|&r| distorted_radius(k, r * zoom) <= r * 1.001)
+    };
+    if fits(1.0) {
+        return 1.0;
+    }
+    let (mut lo, mut hi) = (0.5f32, 1.0f32);
+    for _ in 0..AUTOSCALE_STEPS {
+        let mid = 0.5 * (lo + hi);
+        if fits(mid) {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    lo
+}
+
+// pa model: brightness falls off as 1 + k1 r^2 + k2 r^4 + k3 r^6, r relative to
+// the half diagonal; divided out in linear light.
+fn correct_vignetting(img: &mut RgbaImage, k: &LensCoefficients) {
+    let (w, h) = img.dimensions();
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let half_diagonal = (cx * cx + cy * cy).sqrt().max(1.0);
+    img.par_chunks_mut(w as usize * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let dy = (y as f32 + 0.5 - cy) / half_diagonal;
+            for (x, px) in row.chunks_exact_mut(4).enumerate() {
+                let dx = (x as f32 + 0.5 - cx) / half_diagonal;
+                let r2 = dx * dx + dy * dy;
+                let falloff = 1.0 + r2 * (k.k1 + r2 * (k.k2 + r2 * k.k3));
+                let gain = 1.0 / falloff.max(MIN_VIGNETTING_FALLOFF);
+                for c in px.iter_mut().take(3) {
+                    let v = srgb_to_linear(*c as f32 / 255.0) * gain;
+                    *c = (linear_to_srgb(v.clamp(0.0, 1.0)) * 255.0).round() as u8;
+                }
+            }
+        });
+}
+
+fn correct_distortion(img: &RgbaImage, k: &LensCoefficients) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    let (cx, cy) = (w as f32 / 2.0, h as f32 / 2.0);
+    let unit = cx.min(cy).max(1.0);
+    let zoom = autoscale(k, cx / unit, cy / unit);
+    let mut out = RgbaImage::new(w, h);
+    out.par_chunks_mut(w as usize * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for x in 0..w as usize {
+                let dx = (x as f32 + 0.5 - cx) / unit * zoom;
+                let dy = (y as f32 + 0.5 - cy) / unit * zoom;
+                let r = (dx * dx + dy * dy).sqrt();
+                let scale = if r > 1e-6 {
+                    distorted_radius(k, r) / r
+                } else {
+                    1.0
+                };
+                let sx = (dx * scale * unit + cx - 0.5).clamp(0.0, (w - 1) as f32);
+                let sy = (dy * scale * unit + cy - 0.5).clamp(0.0, (h - 1) as f32);
+                row[x * 4..x * 4 + 4].copy_from_slice(&sample_bilinear(img, sx, sy));
+            }
+        });
+    out
+}
+
+/// Vignetting then geometric correction of the uncropped frame. Coordinates are
+/// normalized to the frame, so downscaled previews match full-size exports.
+fn apply_lens_correction(img: RgbaImage, lens: &LensCorrection) -> RgbaImage {
+    let (w, h) = img.dimensions();
+    if !lens.enabled || w == 0 || h == 0 {
+        return img;
+    }
+    let k = effective_coefficients(lens);
+    let mut img = img;
+    if k.k1.abs() >= 1e-6 || k.k2.abs() >= 1e-6 || k.k3.abs() >= 1e-6 {
+        correct_vignetting(&mut img, &k);
+    }
+    if k.a.abs() >= 1e-6 || k.b.abs() >= 1e-6 || k.c.abs() >= 1e-6 {
+        img = correct_distortion(&img, &k);
+    }
+    img
+}
+
+/// The recipe's lens correction, unless the asset opts out of it.
+pub fn correct_lens(img: RgbaImage, recipe: &EditRecipe) -> RgbaImage {
+    if recipe.flags.skip_lens_correction {
+        return img;
+    }
+    apply_lens_correction(img, &recipe.lens)
+}
@@ -1,26 +1,147 @@
+mod api;
+mod auto_crop;
+mod backup;
+mod blur;
 mod cache;
+mod catalog;
+mod color;
 mod commands;
+mod crop;
+mod culling;
+mod curves;
+mod decode_worker;
+mod depth;
+mod derived;
+mod document;
+mod export;
 mod gpu;
+mod grain;
+mod hooks;
+mod horizon;
+mod hot_pixels;
 mod image_io;
+mod integrity;
+mod jpeg_scaled;
+mod lens;
+mod libraw_mosaic;
+mod look_match;
+mod lut;
+mod mask;
 mod metadata;
 mod models;
+mod naming;
+mod noise;
+mod optimize;
+mod palette;
+mod perf;
+mod proof;
 mod recipe_io;
+mod retouch;
+mod scan_rules;
+mod settings;
+mod shutdown;
+mod sky;
 mod state;
+mod watermark;
+mod xmp;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
+    // the same binary doubles as the isolated RAW decode worker
+    if let Some(code) = decode_worker::run_from_args() {
+        std::process::exit(code);
+    }
     tauri::Builder::default()
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
+        .setup(|app| {
+            cache::spawn_cache_watchdog(app.handle());
+            gpu::watch_fallbacks(app.handle());
+            // a bad port must not keep the app from starting; saving the
+            // settings again reports it
+            let _ = api::sync_server(&settings::current_settings().local_api);
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             commands::open_folder,
+            commands::pick_folder,
+            commands::get_hooks,
+            commands::set_hooks,
             commands::get_thumbnail,
+            commands::optimize_library,
+            commands::cancel_optimize,
             commands::render_preview,
+            commands::negotiate_preview_size,
+            commands::close_viewport,
+            commands::get_full_preview,
+            commands::generate_full_previews,
+            commands::auto_adjust,
+            commands::detect_horizon,
+            commands::auto_straighten,
+            commands::get_raw_histogram,
             commands::read_metadata,
+            commands::find_lens_profile,
             commands::save_recipe,
             commands::load_recipe,
-            commands::detect_gpus
+            commands::patch_recipe,
+            commands::validate_recipe,
+            commands::diff_recipes,
+            commands::set_asset_flags,
+            commands::set_user_fields,
+            commands::preview_mask,
+            commands::render_mask_overlay,
+            commands::has_depth_map,
+            commands::save_brush_mask,
+            commands::generate_sky_mask,
+            commands::evaluate_curve,
+            commands::export_assets,
+            commands::quick_export,
+            commands::export_slideshow,
+            commands::generate_proxies,
+            commands::push_proxy_edits,
+            commands::add_derived_asset,
+            commands::prune_derived_assets,
+            commands::load_lut,
+            commands::list_export_presets,
+            commands::save_export_preset,
+            commands::delete_export_preset,
+            commands::list_export_history,
+            commands::rerun_export,
+            commands::verify_assets,
+            commands::relink_assets,
+            commands::locate_file,
+            commands::export_catalog_bundle,
+            commands::import_catalog_bundle,
+            commands::backup_app_data,
+            commands::restore_app_data,
+            commands::get_caption,
+            commands::get_embedded_xmp,
+            commands::set_caption,
+            commands::set_captions_batch,
+            commands::apply_crop_batch,
+            commands::apply_default_develop,
+            commands::suggest_crops,
+            commands::start_cull_session,
+            commands::mark_cull,
+            commands::commit_cull_session,
+            commands::discard_cull_session,
+            commands::filter_by_color,
+            commands::filter_by_user_fields,
+            commands::match_look,
+            commands::get_settings,
+            commands::update_settings,
+            commands::detect_gpus,
+            commands::set_preferred_gpu,
+            commands::get_perf_metrics,
+            commands::gpu_profile,
+            commands::gpu_status
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app, event| {
+            // let exports and sidecar writes finish before the process goes away
+            if let tauri::RunEvent::Exit = event {
+                shutdown::drain();
+            }
+        });
 }
@@ -0,0 +1,109 @@
+use std::os::raw::{c_int, c_void};
+
+use libraw_sys::{
+    libraw_close, libraw_data_t, libraw_dcraw_clear_mem, libraw_dcraw_make_mem_image,
+    libraw_dcraw_process, libraw_init, libraw_open_buffer, libraw_unpack,
+};
+
+use crate::hot_pixels::suppress_hot_sensels;
+
+// LibRaw's `filters` value for Fuji X-Trans; values below this are not a 2x2 Bayer
+// pattern either, and neither has same-colour sites two pixels apart.
+const FILTERS_XTRANS: u32 = 9;
+const FILTERS_MIN_BAYER: u32 = 1000;
+
+// Closes the LibRaw handle however processing ends.
+struct Handle(*mut libraw_data_t);
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        // SAFETY: the pointer came from libraw_init and is closed only here
+        unsafe { libraw_close(self.0) }
+    }
+}
+
+fn check(code: c_int, step: &str) -> Result<(), String> {
+    if code == 0 {
+        Ok(())
+    } else {
+        Err(format!("LibRaw {step} failed (code {code})"))
+    }
+}
+
+/// Run LibRaw's 16-bit processing as the libraw crate's `process_16bit` does, but
+/// through the C API so hot sensels can be fixed in the unpacked Bayer mosaic
+/// before LibRaw demosaics it. Returns width, height and the interleaved samples.
+pub fn process_16bit_without_hot_sensels(bytes: &[u8]) -> Result<(u32, u32, Vec<u16>), String> {
+    // SAFETY: every pointer is checked before use, the raw buffer is only touched
+    // between unpack and processing within the bounds LibRaw reports, and the
+    // output image is copied out before it is freed
+    unsafe {
+        let data = libraw_init(0);
+        if data.is_null() {
+            return Err("LibRaw init failed".into());
+        }
+        let handle = Handle(data);
+        (*data).params.output_bps = 16;
+        check(
+            libraw_open_buffer(data, bytes.as_ptr() as *mut c_void, bytes.len()),
+            "open",
+        )?;
+        check(libraw_unpack(data), "unpack")?;
+        suppress_in_mosaic(&mut *data);
+        check(libraw_dcraw_process(data), "processing")?;
+
+        let mut code = 0;
+        let image = libraw_dcraw_make_mem_image(data, &mut code);
+        if image.is_null() {
+            return Err(format!("LibRaw output failed (code {code})"));
+        }
+        let (w, h, bits) = ((*image).width, (*image).height, (*image).bits);
+        let bytes = std::slice::from_raw_parts((*image).data.as_ptr(), (*image).data_size as usize);
+        let samples = (bits == 16).then(|| {
+            bytes
+                .chunks_exact(2)
+                .map(|pair| u16::from_ne_bytes([pair[0], pair[1]]))
+                .collect()
+        });
+        libraw_dcraw_clear_mem(image);
+        drop(handle);
+        samples
+            .map(|samples| (w as u32, h as u32, samples))
+            .ok_or_else(|| format!("LibRaw returned {bits}-bit output"))
+    }
+}
+
+// Fix hot sensels in the visible part of a Bayer mosaic in place. The masked
+// border is left alone: LibRaw reads its black level from there.
+unsafe fn suppress_in_mosaic(data: &mut libraw_data_t) {
+    let raw = data.rawdata.raw_image;
+    let filters = data.idata.filters;
+    if raw.is_null() || filters == FILTERS_XTRANS || filters < FILTERS_MIN_BAYER {
+        return;
+    }
+    let sizes = &data.sizes;
+    let (w, h) = (sizes.width as usize, sizes.height as usize);
+    let (top, left) = (sizes.top_margin as usize, sizes.left_margin as usize);
+    let stride = sizes.raw_pitch as usize / 2;
+    if w < 3 || h < 3 || left + w > stride || top + h > sizes.raw_height as usize {
+        return;
+    }
+    let (black, white) = (data.color.black as f32, data.color.maximum as f32);
+    if white <= black {
+        return;
+    }
+    let mosaic = std::slice::from_raw_parts_mut(raw, stride * sizes.raw_height as usize);
+    let at = |x: usize, y: usize| (top + y) * stride + left + x;
+    let mut plane: Vec<f32> = (0..w * h)
+        .map(|idx| (mosaic[at(idx % w, idx / w)] as f32 - black) / (white - black))
+        .collect();
+    let source = plane.clone();
+    // in a Bayer mosaic every site two pixels away has the same colour
+    suppress_hot_sensels(&mut plane, &vec![true; w * h], w, h);
+    for (idx, (fixed, was)) in plane.iter().zip(&source).enumerate() {
+        if fixed != was {
+            let value = black + fixed.max(0.0) * (white - black);
+            mosaic[at(idx % w, idx / w)] = value.round().min(u16::MAX as f32) as u16;
+        }
+    }
+}
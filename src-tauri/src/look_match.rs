@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+use image::RgbaImage;
+
+use crate::color::srgb_to_linear;
+use crate::image_io::{apply_recipe, load_or_create_thumbnail};
+use crate::mask::resolve_brush_masks;
+use crate::models::{EditRecipe, GlobalAdjustments};
+use crate::recipe_io::{load_recipe_for_asset, save_recipe_for_asset};
+
+// Matching runs on the cached thumbnails: statistics of a 360 px render are
+// stable enough and the whole set stays cheap to iterate.
+const REFINE_PASSES: usize = 4;
+const MIN_STAT: f32 = 1e-4;
+
+// Statistics the matched sliders can move: brightness (exposure), tonal spread
+// (contrast), channel balance (temp/tint) and colourfulness (saturation).
+struct LookStats {
+    mean_luma: f32,  // encoded, like the exposure gain
+    spread: f32,     // 5th..95th percentile of encoded luma
+    red_blue: f32,   // linear mean red / mean blue
+    green_rest: f32, // linear mean green / mean of red and blue
+    chroma: f32,     // mean encoded max - min
+}
+
+fn measure(img: &RgbaImage) -> LookStats {
+    let mut sums = [0f64; 3];
+    let mut luma_sum = 0f64;
+    let mut chroma_sum = 0f64;
+    let mut histogram = [0u32; 256];
+    let mut count = 0u64;
+    for px in img.pixels() {
+        if px[3] == 0 {
+            continue;
+        }
+        let lin = [
+            srgb_to_linear(px[0] as f32 / 255.0),
+            srgb_to_linear(px[1] as f32 / 255.0),
+            srgb_to_linear(px[2] as f32 / 255.0),
+        ];
+        for c in 0..3 {
+            sums[c] += lin[c] as f64;
+        }
+        let encoded = 0.2126 * px[0] as f32 + 0.7152 * px[1] as f32 + 0.0722 * px[2] as f32;
+        luma_sum += encoded as f64 / 255.0;
+        histogram[(encoded.round() as usize).min(255)] += 1;
+        let hi = px[0].max(px[1]).max(px[2]);
+        let lo = px[0].min(px[1]).min(px[2]);
+        chroma_sum += (hi - lo) as f64 / 255.0;
+        count += 1;
+    }
+    let n = count.max(1) as f64;
+    let percentile = |p: f64| {
+        let target = (n * p) as u64;
+        let mut seen = 0u64;
+        for (bin, &c) in histogram.iter().enumerate() {
+            seen += c as u64;
+            if seen > target {
+                return bin as f32 / 255.0;
+            }
+        }
+        1.0
+    };
+    let mean = |v: f64| ((v / n) as f32).max(MIN_STAT);
+    let [r, g, b] = sums.map(mean);
+    LookStats {
+        mean_luma: mean(luma_sum),
+        spread: (percentile(0.95) - percentile(0.05)).max(MIN_STAT),
+        red_blue: r / b,
+        green_rest: g / ((r + b) * 0.5),
+        chroma: mean(chroma_sum),
+    }
+}
+
+// Each slider is inverted from the gain it applies in apply_white_balance and
+// apply_globals_in_place, then scaled by how far the statistic is off.
+fn step_towards(globals: &mut GlobalAdjustments, current: &LookStats, wanted: &LookStats) {
+    globals.exposure_ev =
+        (globals.exposure_ev + (wanted.mean_luma / current.mean_luma).log2()).clamp(-5.0, 5.0);
+
+    let contrast_gain = (1.0 + globals.contrast / 100.0) * wanted.spread / current.spread;
+    globals.contrast = ((contrast_gain - 1.0) * 100.0).clamp(-100.0, 100.0);
+
+    let temp = globals.temp / 100.0;
+    let rb_gain = (1.0 + temp * 0.5) / (1.0 - temp * 0.5) * wanted.red_blue / current.red_blue;
+    globals.temp = (2.0 * (rb_gain - 1.0) / (rb_gain + 1.0) * 100.0).clamp(-100.0, 100.0);
+
+    let tint = globals.tint / 100.0;
+    let g_gain = (1.0 - tint * 0.2) / (1.0 + tint * 0.2) * wanted.green_rest / current.green_rest;
+    globals.tint = (5.0 * (1.0 - g_gain) / (1.0 + g_gain) * 100.0).clamp(-100.0, 100.0);
+
+    let sat_gain = (1.0 + globals.saturation / 100.0) * wanted.chroma / current.chroma;
+    globals.saturation = ((sat_gain - 1.0) * 100.0).clamp(-100.0, 100.0);
+}
+
+fn thumbnail_rgba(path: &Path) -> Result<RgbaImage, String> {
+    let bytes = load_or_create_thumbnail(path)?;
+    Ok(image::load_from_memory(&bytes)
+        .map_err(|e| format!("Failed to decode thumbnail: {e}"))?
+        .to_rgba8())
+}
+
+// Brush masks are resolved on a copy; the recipe saved back keeps bare names.
+fn rendered(thumb: &RgbaImage, recipe: Option<&EditRecipe>, path: &Path) -> RgbaImage {
+    match recipe {
+        Some(recipe) => {
+            let mut resolved = recipe.clone();
+            resolve_brush_masks(&mut resolved, path);
+            apply_recipe(thumb.clone(), &resolved)
+        }
+        None => thumb.clone(),
+    }
+}
+
+/// Adjust exposure, contrast, temp/tint and saturation of each target's recipe so
+/// its render matches the source's render statistically. Other settings are kept.
+/// Returns the recipes in target order; locked targets keep (and return) theirs.
+pub fn match_look(source: &Path, targets: &[PathBuf]) -> Result<Vec<EditRecipe>, String> {
+    let source_recipe = load_recipe_for_asset(source)?;
+    let wanted = measure(&rendered(
+        &thumbnail_rgba(source)?,
+        source_recipe.as_ref(),
+        source,
+    ));
+
+    let mut matched = Vec::with_capacity(targets.len());
+    for target in targets {
+        let mut recipe = load_recipe_for_asset(target)?.unwrap_or_default();
+        if recipe.flags.locked {
+            matched.push(recipe);
+            continue;
+        }
+        let thumb = thumbnail_rgba(target)?;
+        // the sliders interact (exposure shifts the spread, clipping eats chroma),
+        // so re-measure and refine a few times instead of solving once
+        for _ in 0..REFINE_PASSES {
+            let current = measure(&rendered(&thumb, Some(&recipe), target));
+            step_towards(&mut recipe.globals, &current, &wanted);
+        }
+        save_recipe_for_asset(target, &recipe)?;
+        matched.push(recipe);
+    }
+    Ok(matched)
+}
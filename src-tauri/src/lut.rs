@@ -0,0 +1,203 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+use image::RgbaImage;
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+
+use crate::gpu;
+use crate::image_io::Working;
+use crate::models::LutInfo;
+use crate::state::ensure_color_file_allowed;
+
+const MAX_LUT_SIZE: usize = 256;
+
+// parsed LUTs by canonical path, dropped when the file's mtime changes
+static LUT_CACHE: Lazy<DashMap<PathBuf, (SystemTime, Arc<Lut3d>)>> = Lazy::new(DashMap::new);
+
+/// A 3D color lookup table as stored in `.cube` files (red varies fastest).
+#[derive(Debug, Clone)]
+pub struct Lut3d {
+    pub title: Option<String>,
+    pub size: usize,
+    pub domain_min: [f32; 3],
+    pub domain_max: [f32; 3],
+    pub data: Vec<[f32; 3]>,
+}
+
+fn parse_triplet(parts: &[&str], line_no: usize) -> Result<[f32; 3], String> {
+    if parts.len() != 3 {
+        return Err(format!("Line {line_no}: expected 3 values"));
+    }
+    let mut out = [0f32; 3];
+    for (slot, part) in out.iter_mut().zip(parts) {
+        *slot = part
+            .parse::<f32>()
+            .map_err(|_| format!("Line {line_no}: invalid number '{part}'"))?;
+    }
+    Ok(out)
+}
+
+/// Parse the Adobe/Resolve `.cube` text format (3D tables only).
+pub fn parse_cube(text: &str) -> Result<Lut3d, String> {
+    let mut title = None;
+    let mut size = 0usize;
+    let mut domain_min = [0.0f32; 3];
+    let mut domain_max = [1.0f32; 3];
+    let mut data: Vec<[f32; 3]> = Vec::new();
+
+    for (idx, raw_line) in text.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = raw_line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        match parts[0] {
+            "TITLE" => {
+                let rest = line["TITLE".len()..].trim().trim_matches('"');
+                title = (!rest.is_empty()).then(|| rest.to_string());
+            }
+            "LUT_1D_SIZE" => return Err("1D LUTs are not supported".into()),
+            "LUT_3D_SIZE" => {
+                size = parts
+                    .get(1)
+                    .and_then(|v| v.parse().ok())
+                    .ok_or(format!("Line {line_no}: invalid LUT_3D_SIZE"))?;
+                if !(2..=MAX_LUT_SIZE).contains(&size) {
+                    return Err(format!("Unsupported LUT size {size}"));
+                }
+            }
+            "DOMAIN_MIN" => domain_min = parse_triplet(&parts[1..], line_no)?,
+            "DOMAIN_MAX" => domain_max = parse_triplet(&parts[1..], line_no)?,
+            // unknown keywords (e.g. LUT_3D_INPUT_RANGE) are skipped
+            keyword
+                if keyword
+                    .chars()
+                    .next()
+                    .is_some_and(|c| c.is_ascii_alphabetic()) => {}
+            _ => data.push(parse_triplet(&parts, line_no)?),
+        }
+    }
+
+    if size == 0 {
+        return Err("Missing LUT_3D_SIZE".into());
+    }
+    if data.len() != size * size * size {
+        return Err(format!(
+            "Expected {} LUT entries, found {}",
+            size * size * size,
+            data.len()
+        ));
+    }
+    Ok(Lut3d {
+        title,
+        size,
+        domain_min,
+        domain_max,
+        data,
+    })
+}
+
+pub fn load_cube(path: &Path) -> Result<Lut3d, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Read LUT failed: {e}"))?;
+    parse_cube(&text)
+}
+
+/// Load a `.cube` through the cache; the path must be inside an allowed root or
+/// a system colour folder. The same Arc comes back until the file changes.
+pub fn cached_lut(path: &Path) -> Result<Arc<Lut3d>, String> {
+    let path = ensure_color_file_allowed(path)?;
+    let modified = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .map_err(|e| format!("Read LUT failed: {e}"))?;
+    if let Some(hit) = LUT_CACHE.get(&path) {
+        if hit.0 == modified {
+            return Ok(hit.1.clone());
+        }
+    }
+    let lut = Arc::new(load_cube(&path)?);
+    LUT_CACHE.insert(path, (modified, lut.clone()));
+    Ok(lut)
+}
+
+pub fn lut_info(path: &Path) -> Result<LutInfo, String> {
+    let lut = cached_lut(path)?;
+    Ok(LutInfo {
+        path: path.to_string_lossy().to_string(),
+        title: lut.title.clone(),
+        size: lut.size,
+    })
+}
+
+impl Lut3d {
+    fn at(&self, r: usize, g: usize, b: usize) -> [f32; 3] {
+        self.data[r + self.size * (g + self.size * b)]
+    }
+
+    /// Trilinear lookup of a normalized RGB triplet.
+    pub fn sample(&self, rgb: [f32; 3]) -> [f32; 3] {
+        let max = (self.size - 1) as f32;
+        let mut base = [0usize; 3];
+        let mut frac = [0f32; 3];
+        for c in 0..3 {
+            let span = (self.domain_max[c] - self.domain_min[c]).max(f32::EPSILON);
+            let pos = ((rgb[c] - self.domain_min[c]) / span).clamp(0.0, 1.0) * max;
+            let i = (pos.floor() as usize).min(self.size - 2);
+            base[c] = i;
+            frac[c] = pos - i as f32;
+        }
+        let [r, g, b] = base;
+        let [fr, fg, fb] = frac;
+        let lerp = |a: [f32; 3], b: [f32; 3], t: f32| -> [f32; 3] {
+            [
+                a[0] + (b[0] - a[0]) * t,
+                a[1] + (b[1] - a[1]) * t,
+                a[2] + (b[2] - a[2]) * t,
+            ]
+        };
+        let c00 = lerp(self.at(r, g, b), self.at(r + 1, g, b), fr);
+        let c10 = lerp(self.at(r, g + 1, b), self.at(r + 1, g + 1, b), fr);
+        let c01 = lerp(self.at(r, g, b + 1), self.at(r + 1, g, b + 1), fr);
+        let c11 = lerp(self.at(r, g + 1, b + 1), self.at(r + 1, g + 1, b + 1), fr);
+        lerp(lerp(c00, c10, fg), lerp(c01, c11, fg), fb)
+    }
+}
+
+/// Apply the LUT to display-referred (sRGB-encoded) pixels in place.
+pub fn apply_lut_rgba(img: &mut RgbaImage, lut: &Lut3d) {
+    let data: &mut [u8] = img.as_mut();
+    data.par_chunks_mut(4).for_each(|px| {
+        let out = lut.sample([
+            px[0] as f32 / 255.0,
+            px[1] as f32 / 255.0,
+            px[2] as f32 / 255.0,
+        ]);
+        for c in 0..3 {
+            px[c] = (out[c].clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+    });
+}
+
+/// Blend the LUT result over the original by `strength` (0..1), on the GPU
+/// when possible.
+pub fn apply_lut_blended(img: &mut Working, lut: &Arc<Lut3d>, strength: f32) {
+    let strength = strength.clamp(0.0, 1.0);
+    if let Some(out) = img
+        .upload()
+        .and_then(|bytes| gpu::apply_lut_rgba(&bytes, lut, strength))
+    {
+        *img = Working::Bytes(out);
+        return;
+    }
+    img.float().as_mut().par_chunks_mut(4).for_each(|px| {
+        let src = [px[0], px[1], px[2]];
+        let out = lut.sample(src);
+        for c in 0..3 {
+            px[c] = (src[c] + (out[c] - src[c]) * strength).clamp(0.0, 1.0);
+        }
+    });
+}
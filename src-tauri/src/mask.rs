@@ -0,0 +1,400 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use dashmap::DashMap;
+use image::{GrayImage, Rgba, RgbaImage};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use uuid::Uuid;
+
+use crate::depth::framed_depth_map;
+use crate::models::{EditRecipe, FeatherFalloff, Mask, MaskCombine};
+use crate::state::ensure_allowed;
+
+pub const BRUSH_MASK: &str = "brush";
+pub const LUMINANCE_MASK: &str = "luminance_range";
+pub const DEPTH_MASK: &str = "depth";
+// Longest side of the soft-edge preview.
+const MASK_PREVIEW_MAX_DIM: u32 = 1024;
+// Full coverage in the overlay view: half-strength red, so the photo stays readable.
+const OVERLAY_RGB: [u8; 3] = [255, 0, 0];
+const OVERLAY_OPACITY: f32 = 0.5;
+// The gaussian edge spans +-3 sigma across the feather band.
+const GAUSSIAN_SIGMAS: f32 = 3.0;
+// Unreferenced brush bitmaps younger than this survive a save: the editor may
+// hold strokes (or an undo step) that are not in the saved recipe yet.
+const BRUSH_ORPHAN_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Abramowitz & Stegun 7.1.26, |error| < 1.5e-7.
+fn erf(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_6
+            + t * (-0.284_496_74 + t * (1.421_413_7 + t * (-1.453_152 + t * 1.061_405_4))));
+    (1.0 - poly * (-x * x).exp()).copysign(x)
+}
+
+// Position across the feather band (0..1) to coverage (0..1).
+fn falloff(falloff: FeatherFalloff, u: f32) -> f32 {
+    match falloff {
+        FeatherFalloff::Linear => u,
+        FeatherFalloff::Smooth => u * u * (3.0 - 2.0 * u),
+        FeatherFalloff::Gaussian => {
+            let z = (u - 0.5) * GAUSSIAN_SIGMAS * std::f32::consts::SQRT_2;
+            let edge = erf(0.5 * GAUSSIAN_SIGMAS * std::f32::consts::SQRT_2);
+            // rescaled so the band still starts at exactly 0 and ends at 1
+            (0.5 + 0.5 * erf(z) / edge).clamp(0.0, 1.0)
+        }
+    }
+}
+
+fn gradient_weight(mask: &Mask, x: f32, y: f32) -> f32 {
+    let (dx, dy) = (mask.end.0 - mask.start.0, mask.end.1 - mask.start.1);
+    let len_sq = (dx * dx + dy * dy).max(1e-6);
+    let t = (((x - mask.start.0) * dx + (y - mask.start.1) * dy) / len_sq).clamp(0.0, 1.0);
+    let feather = mask.feather.max(0.001);
+    let u = ((t - (0.5 - feather * 0.5)) / feather).clamp(0.0, 1.0);
+    falloff(mask.falloff, u)
+}
+
+// Full inside the range, falling off over `feather` past either end. Serves
+// luminance (display luma) and depth (nearness) ranges alike.
+fn range_weight(mask: &Mask, range: (f32, f32), value: f32) -> f32 {
+    let (low, high) = (range.0.min(range.1), range.0.max(range.1));
+    let outside = (low - value).max(value - high).max(0.0);
+    if outside <= 0.0 {
+        return 1.0;
+    }
+    let feather = mask.feather.max(0.001);
+    falloff(mask.falloff, (1.0 - outside / feather).clamp(0.0, 1.0))
+}
+
+// Painted coverage by path, reloaded when the file changes.
+static BRUSH_CACHE: Lazy<DashMap<PathBuf, (SystemTime, Arc<GrayImage>)>> = Lazy::new(DashMap::new);
+
+fn cached_bitmap(path: &Path) -> Result<Arc<GrayImage>, String> {
+    let path = &ensure_allowed(path)?;
+    let modified = fs::metadata(path)
+        .and_then(|m| m.modified())
+        .map_err(|e| format!("Read brush mask failed: {e}"))?;
+    if let Some(hit) = BRUSH_CACHE.get(path) {
+        if hit.0 == modified {
+            return Ok(hit.1.clone());
+        }
+    }
+    let bitmap = Arc::new(
+        image::open(path)
+            .map_err(|e| format!("Decode brush mask failed: {e}"))?
+            .to_luma8(),
+    );
+    BRUSH_CACHE.insert(path.to_path_buf(), (modified, bitmap.clone()));
+    Ok(bitmap)
+}
+
+// Bilinear lookup, so a bitmap painted at preview size scales to any render.
+fn sample_bitmap(bitmap: &GrayImage, x: f32, y: f32) -> f32 {
+    let (w, h) = bitmap.dimensions();
+    let fx = (x * w as f32 - 0.5).clamp(0.0, (w - 1) as f32);
+    let fy = (y * h as f32 - 0.5).clamp(0.0, (h - 1) as f32);
+    let (x0, y0) = (fx as u32, fy as u32);
+    let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+    let (tx, ty) = (fx - x0 as f32, fy - y0 as f32);
+    let at = |x: u32, y: u32| bitmap.get_pixel(x, y)[0] as f32 / 255.0;
+    let top = at(x0, y0) * (1.0 - tx) + at(x1, y0) * tx;
+    let bottom = at(x0, y1) * (1.0 - tx) + at(x1, y1) * tx;
+    top * (1.0 - ty) + bottom * ty
+}
+
+/// A mask ready to evaluate: brush bitmaps are loaded once here rather than per
+/// pixel. The one place mask geometry is evaluated, so previews and renders agree.
+pub struct MaskSampler<'a> {
+    mask: &'a Mask,
+    bitmap: Option<Arc<GrayImage>>,
+    components: Vec<(MaskCombine, MaskSampler<'a>)>,
+}
+
+impl<'a> MaskSampler<'a> {
+    /// A brush mask whose bitmap is unset or unreadable covers nothing.
+    pub fn new(mask: &'a Mask) -> Self {
+        let bitmap = match (mask.mask_type.as_str(), &mask.bitmap) {
+            (BRUSH_MASK | DEPTH_MASK, Some(path)) => cached_bitmap(Path::new(path)).ok(),
+            _ => None,
+        };
+        let components = mask
+            .components
+            .iter()
+            .map(|component| (component.mode, MaskSampler::new(&component.mask)))
+            .collect();
+        Self {
+            mask,
+            bitmap,
+            components,
+        }
+    }
+
+    /// Whether coverage depends on the pixel's luma, so `weight` alone cannot
+    /// stand in for it.
+    pub fn reads_luma(&self) -> bool {
+        self.mask.mask_type == LUMINANCE_MASK
+            || self
+                .components
+                .iter()
+                .any(|(_, component)| component.reads_luma())
+    }
+
+    /// Coverage at a point in normalized image coordinates, before the layer's
+    /// opacity. Without a pixel to look at, luminance ranges cover everything.
+    pub fn weight(&self, x: f32, y: f32) -> f32 {
+        self.evaluate(x, y, None)
+    }
+
+    /// `weight` for a pixel whose display luma (0..1) is known.
+    pub fn weight_with_luma(&self, x: f32, y: f32, luma: f32) -> f32 {
+        self.evaluate(x, y, Some(luma))
+    }
+
+    fn evaluate(&self, x: f32, y: f32, luma: Option<f32>) -> f32 {
+        let weight = match (self.mask.mask_type.as_str(), &self.bitmap) {
+            (BRUSH_MASK, Some(bitmap)) if bitmap.width() > 0 && bitmap.height() > 0 => {
+                sample_bitmap(bitmap, x, y)
+            }
+            (BRUSH_MASK, _) => 0.0,
+            (DEPTH_MASK, Some(depth)) if depth.width() > 0 && depth.height() > 0 => {
+                range_weight(self.mask, self.mask.depth, sample_bitmap(depth, x, y))
+            }
+            // a file without a depth map has no subject to pick out
+            (DEPTH_MASK, _) => 0.0,
+            (LUMINANCE_MASK, _) => luma.map_or(1.0, |luma| {
+                range_weight(self.mask, self.mask.luminance, luma)
+            }),
+            _ => gradient_weight(self.mask, x, y),
+        };
+        let weight = if self.mask.invert {
+            1.0 - weight
+        } else {
+            weight
+        };
+        self.components
+            .iter()
+            .fold(weight, |acc, (mode, component)| {
+                let other = component.evaluate(x, y, luma);
+                match mode {
+                    MaskCombine::Add => (acc + other).min(1.0),
+                    MaskCombine::Subtract => (acc - other).max(0.0),
+                    MaskCombine::Intersect => acc * other,
+                }
+            })
+    }
+}
+
+/// Whether `name` is a plain file name, the only form recipes store bitmaps as.
+pub fn is_bare_name(name: &str) -> bool {
+    Path::new(name).file_name().is_some_and(|f| f == name)
+}
+
+/// Point a brush mask at its bitmap beside `asset_path`. Recipes store the bare
+/// file name so a folder can move with its sidecars; anything that is not a plain
+/// file name is dropped. Depth masks are pointed at the file's depth map.
+pub fn resolve_brush_mask(mask: &mut Mask, asset_path: &Path) {
+    for component in mask.components.iter_mut() {
+        resolve_brush_mask(&mut component.mask, asset_path);
+    }
+    if mask.mask_type == DEPTH_MASK {
+        mask.bitmap = framed_depth_map(asset_path)
+            .ok()
+            .flatten()
+            .map(|path| path.to_string_lossy().to_string());
+        return;
+    }
+    if mask.mask_type != BRUSH_MASK {
+        return;
+    }
+    let Some(folder) = asset_path.parent() else {
+        return;
+    };
+    mask.bitmap = mask.bitmap.take().and_then(|name| {
+        is_bare_name(&name).then(|| folder.join(name).to_string_lossy().to_string())
+    });
+}
+
+/// `resolve_brush_mask` for every mask a recipe carries.
+pub fn resolve_brush_masks(recipe: &mut EditRecipe, asset_path: &Path) {
+    for layer in recipe.layers.iter_mut() {
+        resolve_brush_mask(&mut layer.mask, asset_path);
+    }
+    resolve_brush_mask(&mut recipe.globals.dual_illuminant.mask, asset_path);
+}
+
+/// Drop every bitmap a mask points at, for masks with no asset to resolve against.
+pub fn clear_brush_bitmaps(mask: &mut Mask) {
+    for component in mask.components.iter_mut() {
+        clear_brush_bitmaps(&mut component.mask);
+    }
+    mask.bitmap = None;
+}
+
+fn collect_brush_names(mask: &Mask, names: &mut Vec<String>) {
+    for component in &mask.components {
+        collect_brush_names(&component.mask, names);
+    }
+    if mask.mask_type != BRUSH_MASK {
+        return;
+    }
+    if let Some(name) = &mask.bitmap {
+        let plain = is_bare_name(name);
+        if plain && !names.contains(name) {
+            names.push(name.clone());
+        }
+    }
+}
+
+/// The bare bitmap names an unresolved recipe's brush masks refer to.
+pub fn brush_bitmap_names(recipe: &EditRecipe) -> Vec<String> {
+    let mut names = Vec::new();
+    for layer in &recipe.layers {
+        collect_brush_names(&layer.mask, &mut names);
+    }
+    collect_brush_names(&recipe.globals.dual_illuminant.mask, &mut names);
+    names
+}
+
+/// Copy the bitmaps `recipe` refers to from beside `from` to beside `to`, so the
+/// recipe keeps working wherever it is written. Bitmaps already there are kept.
+pub fn copy_brush_bitmaps(recipe: &EditRecipe, from: &Path, to: &Path) -> Result<(), String> {
+    let (Some(source), Some(target)) = (from.parent(), to.parent()) else {
+        return Ok(());
+    };
+    if source == target {
+        return Ok(());
+    }
+    for name in brush_bitmap_names(recipe) {
+        let (src, dest) = (source.join(&name), target.join(&name));
+        if src.is_file() && !dest.exists() {
+            fs::copy(&src, &dest).map_err(|e| format!("Copy brush mask failed: {e}"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Remove bitmaps painted for `asset_path` that its saved `recipe` no longer
+/// refers to, once they are old enough not to belong to unsaved strokes.
+pub fn prune_brush_bitmaps(asset_path: &Path, recipe: &EditRecipe) {
+    let (Some(folder), Some(stem)) = (asset_path.parent(), asset_path.file_stem()) else {
+        return;
+    };
+    let prefix = format!("{}.mask-", stem.to_string_lossy());
+    let keep = brush_bitmap_names(recipe);
+    let Ok(entries) = fs::read_dir(folder) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !name.starts_with(&prefix) || !name.ends_with(".png") || keep.contains(&name) {
+            continue;
+        }
+        let stale = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age >= BRUSH_ORPHAN_AGE);
+        if stale {
+            // best effort: a bitmap left behind only costs disk space
+            let _ = fs::remove_file(entry.path());
+        }
+    }
+}
+
+/// Store painted coverage for `asset_path` and return the file name for
+/// `Mask::bitmap`. Takes any PNG: its alpha when it has one, else its luma.
+pub fn save_brush_bitmap(asset_path: &Path, png: &[u8]) -> Result<String, String> {
+    let decoded =
+        image::load_from_memory(png).map_err(|e| format!("Decode brush mask failed: {e}"))?;
+    let coverage: GrayImage = if decoded.color().has_alpha() {
+        let rgba = decoded.to_rgba8();
+        GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+            image::Luma([rgba.get_pixel(x, y)[3]])
+        })
+    } else {
+        decoded.to_luma8()
+    };
+    store_brush_bitmap(asset_path, &coverage)
+}
+
+/// Write a coverage bitmap beside `asset_path` under a fresh name, which is
+/// returned for `Mask::bitmap`.
+pub fn store_brush_bitmap(asset_path: &Path, coverage: &GrayImage) -> Result<String, String> {
+    let stem = asset_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_else(|| "edit".to_string());
+    let id = Uuid::new_v4().simple().to_string();
+    let name = format!("{stem}.mask-{}.png", &id[..8]);
+    let folder = asset_path.parent().ok_or("Asset has no folder")?;
+    coverage
+        .save(folder.join(&name))
+        .map_err(|e| format!("Write brush mask failed: {e}"))?;
+    Ok(name)
+}
+
+// Rec. 709 luma of an 8-bit pixel, 0..1: the luma luminance ranges are judged on.
+pub(crate) fn display_luma(px: &[u8]) -> f32 {
+    (0.2126 * px[0] as f32 + 0.7152 * px[1] as f32 + 0.0722 * px[2] as f32) / 255.0
+}
+
+/// Tint `img` towards red by the mask's coverage, for showing where a layer
+/// applies on top of the rendered preview.
+pub fn overlay_mask(img: &mut RgbaImage, mask: &Mask) {
+    let (w, h) = img.dimensions();
+    let sampler = MaskSampler::new(mask);
+    img.par_chunks_mut(w as usize * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let v = y as f32 / h as f32;
+            for (x, px) in row.chunks_exact_mut(4).enumerate() {
+                let u = x as f32 / w as f32;
+                let a = sampler.weight_with_luma(u, v, display_luma(px)) * OVERLAY_OPACITY;
+                for (c, target) in OVERLAY_RGB.iter().enumerate() {
+                    px[c] = (px[c] as f32 * (1.0 - a) + *target as f32 * a).round() as u8;
+                }
+            }
+        });
+}
+
+/// The mask as white with coverage in alpha, fitted to `width` x `height` (capped
+/// at 1024 px), for drawing soft edges over the preview. Luminance ranges read the
+/// nearest pixel of `luma_from` (the rendered preview); without it they cover
+/// everything.
+pub fn render_mask_preview(
+    mask: &Mask,
+    width: u32,
+    height: u32,
+    luma_from: Option<&RgbaImage>,
+) -> RgbaImage {
+    let (width, height) = (width.max(1), height.max(1));
+    let scale = (MASK_PREVIEW_MAX_DIM as f32 / width.max(height) as f32).min(1.0);
+    let w = ((width as f32 * scale).round() as u32).max(1);
+    let h = ((height as f32 * scale).round() as u32).max(1);
+    let sampler = MaskSampler::new(mask);
+    let mut out = RgbaImage::from_pixel(w, h, Rgba([255, 255, 255, 0]));
+    out.par_chunks_mut(w as usize * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            let v = y as f32 / h as f32;
+            for (x, px) in row.chunks_exact_mut(4).enumerate() {
+                let u = x as f32 / w as f32;
+                let weight = match luma_from {
+                    Some(img) => {
+                        let sx = ((u * img.width() as f32) as u32).min(img.width() - 1);
+                        let sy = ((v * img.height() as f32) as u32).min(img.height() - 1);
+                        sampler.weight_with_luma(u, v, display_luma(&img.get_pixel(sx, sy).0))
+                    }
+                    None => sampler.weight(u, v),
+                };
+                px[3] = (weight * 255.0).round() as u8;
+            }
+        });
+    out
+}
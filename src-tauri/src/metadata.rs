@@ -1,9 +1,172 @@
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::BufReader;
+use std::io::{BufReader, Cursor, Seek, Write};
 use std::path::Path;
 
-use crate::models::Metadata;
+use crate::lens::find_profile;
+use crate::models::{Metadata, MetadataPolicy, PrivacyAction, PrivacyZone};
 use exif;
+use exif::experimental::Writer as ExifWriter;
+use exif::{Context, Field, In, Value};
+use tiff::encoder::{DirectoryEncoder, TiffKindStandard, TiffValue};
+use tiff::tags::{Tag as TiffTag, Type as TiffType};
+
+// ExposureMode value the camera writes for every frame of an auto bracket.
+// Drive modes beyond that live in maker notes, which are not parsed.
+const EXPOSURE_MODE_AUTO_BRACKET: u32 = 2;
+// TIFF/EP ImageNumber, the shot counter most RAW formats carry in IFD0.
+const IMAGE_NUMBER: u16 = 0x9211;
+
+// Diagonal of a 36x24 mm frame.
+const FULL_FRAME_DIAGONAL_MM: f32 = 43.27;
+// Crop factors outside this range come from bogus focal-plane tags.
+const PLAUSIBLE_CROP: std::ops::RangeInclusive<f32> = 0.5..=8.0;
+// Recording this much tighter than the body's sensor counts as a crop mode.
+const CROP_MODE_MARGIN: f32 = 1.15;
+// Sensor crop factors by EXIF Model prefix (lowercased), for bodies whose files
+// lack FocalLengthIn35mmFilm. First match wins, so specific names come before
+// the prefixes they share. A name ending in a letter only matches a whole
+// token: "canon eos r" takes the R5 but not the Rebel or the RP.
+const BODY_CROP_FACTORS: &[(&str, f32)] = &[
+    ("ilce-7", 1.0),
+    ("ilce-9", 1.0),
+    ("ilce-1", 1.0),
+    ("ilce-6", 1.5),
+    ("nex-", 1.5),
+    ("canon eos r7", 1.6),
+    ("canon eos r10", 1.6),
+    ("canon eos r50", 1.6),
+    ("canon eos r100", 1.6),
+    ("canon eos rp", 1.0),
+    ("canon eos r", 1.0),
+    ("canon eos 5d", 1.0),
+    ("canon eos 6d", 1.0),
+    ("canon eos-1d x", 1.0),
+    ("canon eos m", 1.6),
+    ("nikon z 50", 1.5),
+    ("nikon z fc", 1.5),
+    ("nikon z 30", 1.5),
+    ("nikon z", 1.0),
+    ("nikon d850", 1.0),
+    ("nikon d810", 1.0),
+    ("nikon d780", 1.0),
+    ("nikon d750", 1.0),
+    ("nikon d500", 1.5),
+    ("gfx", 0.79),
+    ("x-", 1.5),
+    ("x100", 1.5),
+    ("dc-g", 2.0),
+    ("dc-gh", 2.0),
+    ("dc-gx", 2.0),
+    ("dmc-g", 2.0),
+    ("dmc-gh", 2.0),
+    ("dmc-gx", 2.0),
+    ("dmc-gf", 2.0),
+    ("e-m", 2.0),
+    ("om-", 2.0),
+    ("pentax k-1", 1.0),
+    ("leica q", 1.0),
+    ("leica sl", 1.0),
+];
+
+// EXIF strings come back quoted and sometimes padded.
+fn clean_text(value: &str) -> String {
+    value
+        .trim_matches(|c: char| c == '"' || c.is_whitespace())
+        .to_string()
+}
+
+fn body_crop_factor(camera: &str) -> Option<f32> {
+    let camera = clean_text(camera).to_ascii_lowercase();
+    BODY_CROP_FACTORS
+        .iter()
+        .find(|(model, _)| {
+            camera.strip_prefix(model).is_some_and(|rest| {
+                !(model.ends_with(|c: char| c.is_ascii_alphabetic())
+                    && rest.starts_with(|c: char| c.is_ascii_alphabetic()))
+            })
+        })
+        .map(|(_, factor)| *factor)
+}
+
+// Millimetres per FocalPlaneResolutionUnit (2 inch, 3 cm, 4 mm, 5 um).
+fn focal_plane_unit_mm(unit: u32) -> Option<f32> {
+    match unit {
+        2 => Some(25.4),
+        3 => Some(10.0),
+        4 => Some(1.0),
+        5 => Some(0.001),
+        _ => None,
+    }
+}
+
+// Crop factor of the recorded area from the focal-plane resolution: a crop mode
+// records fewer pixels at the same pixels per millimetre.
+fn focal_plane_crop(exif: &exif::Exif) -> Option<f32> {
+    let number = |tag: exif::Tag| {
+        exif.get_field(tag, In::PRIMARY).and_then(|f| {
+            signed_rational(&f.value).or_else(|| f.value.get_uint(0).map(|v| v as f32))
+        })
+    };
+    let unit = focal_plane_unit_mm(
+        exif.get_field(exif::Tag::FocalPlaneResolutionUnit, In::PRIMARY)
+            .and_then(|f| f.value.get_uint(0))
+            .unwrap_or(2),
+    )?;
+    let width = number(exif::Tag::PixelXDimension)? / number(exif::Tag::FocalPlaneXResolution)?;
+    let height = number(exif::Tag::PixelYDimension)? / number(exif::Tag::FocalPlaneYResolution)?;
+    let crop = FULL_FRAME_DIAGONAL_MM / (width.hypot(height) * unit);
+    (crop.is_finite() && PLAUSIBLE_CROP.contains(&crop)).then_some(crop)
+}
+
+// Equivalent focal length and crop: the camera's own FocalLengthIn35mmFilm
+// first, then the focal-plane resolution, then the body table, then the crop
+// factor the matched lens profile was calibrated at.
+fn fill_equivalent_focal(meta: &mut Metadata, exif: &exif::Exif) {
+    let Some(focal) = meta.focal_mm.filter(|f| *f > 0.0) else {
+        return;
+    };
+    let body = meta.camera.as_deref().and_then(body_crop_factor);
+    let from_tag = exif
+        .get_field(exif::Tag::FocalLengthIn35mmFilm, In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .filter(|v| *v > 0)
+        .map(|v| v as f32 / focal)
+        .filter(|crop| PLAUSIBLE_CROP.contains(crop));
+    let from_profile = || {
+        let lens = clean_text(meta.lens.as_deref()?);
+        find_profile(&lens).ok().flatten()?.crop_factor
+    };
+    let Some(crop) = from_tag
+        .or_else(|| focal_plane_crop(exif))
+        .or(body)
+        .or_else(from_profile)
+    else {
+        return;
+    };
+    meta.crop_factor = Some(crop);
+    meta.focal_35mm = Some((focal * crop).round());
+    meta.crop_mode = body.map(|body| crop > body * CROP_MODE_MARGIN);
+}
+
+fn signed_rational(value: &Value) -> Option<f32> {
+    match value {
+        Value::SRational(v) => v.first().map(|r| r.to_f64() as f32),
+        Value::Rational(v) => v.first().map(|r| r.to_f64() as f32),
+        _ => None,
+    }
+    .filter(|v| v.is_finite())
+}
+
+/// The EXIF Orientation (1..=8) of the primary image, if the file carries one.
+pub fn read_orientation(path: &Path) -> Option<u8> {
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let field = exif.get_field(exif::Tag::Orientation, In::PRIMARY)?;
+    field.value.get_uint(0).and_then(|v| u8::try_from(v).ok())
+}
 
 pub fn read_metadata(path: &Path) -> Result<Metadata, String> {
     let file = File::open(path).map_err(|e| e.to_string())?;
@@ -33,14 +196,386 @@ pub fn read_metadata(path: &Path) -> Result<Metadata, String> {
                 meta.aperture = Some(field.display_value().with_unit(&exif).to_string())
             }
             exif::Tag::FocalLength => {
-                meta.focal = Some(field.display_value().with_unit(&exif).to_string())
+                meta.focal = Some(field.display_value().with_unit(&exif).to_string());
+                meta.focal_mm = signed_rational(&field.value);
             }
             exif::Tag::DateTimeOriginal => {
                 meta.date = Some(field.display_value().with_unit(&exif).to_string())
             }
+            exif::Tag::ExposureBiasValue => meta.exposure_bias = signed_rational(&field.value),
+            exif::Tag::ExposureMode
+                if field.value.get_uint(0) == Some(EXPOSURE_MODE_AUTO_BRACKET) =>
+            {
+                meta.drive_mode = Some("bracket".to_string())
+            }
+            tag if tag.number() == IMAGE_NUMBER => meta.sequence_number = field.value.get_uint(0),
             _ => {}
         }
     }
+    fill_equivalent_focal(&mut meta, &exif);
 
     Ok(meta)
 }
+
+// Primary-IFD tags that describe the photo rather than the stored pixels. Every
+// other primary tag (sizes, strips, tiles, sub-IFDs, DNG structure, orientation)
+// describes data the re-encoded, already upright export does not have.
+const DESCRIPTIVE_TIFF_TAGS: &[exif::Tag] = &[
+    exif::Tag::ImageDescription,
+    exif::Tag::Make,
+    exif::Tag::Model,
+    exif::Tag::Software,
+    exif::Tag::DateTime,
+    exif::Tag::Artist,
+    exif::Tag::Copyright,
+];
+
+// Exif sub-IFD tags about the original encoding rather than the shot.
+const EXIF_LAYOUT_TAGS: &[exif::Tag] = &[
+    exif::Tag::PixelXDimension,
+    exif::Tag::PixelYDimension,
+    exif::Tag::ColorSpace,
+    exif::Tag::ComponentsConfiguration,
+    exif::Tag::CompressedBitsPerPixel,
+    exif::Tag::MakerNote,
+];
+
+fn carried_over(tag: exif::Tag) -> bool {
+    match tag.context() {
+        Context::Tiff => DESCRIPTIVE_TIFF_TAGS.contains(&tag),
+        Context::Exif => !EXIF_LAYOUT_TAGS.contains(&tag),
+        Context::Gps => true,
+        _ => false,
+    }
+}
+
+/// EXIF fields of the original to carry into an export under `policy`.
+/// Sources without readable EXIF simply yield no fields.
+pub fn export_exif_fields(path: &Path, policy: MetadataPolicy) -> Vec<Field> {
+    if policy == MetadataPolicy::StripAll {
+        return Vec::new();
+    }
+    let Ok(file) = File::open(path) else {
+        return Vec::new();
+    };
+    let Ok(exif) = exif::Reader::new().read_from_container(&mut BufReader::new(file)) else {
+        return Vec::new();
+    };
+    exif.fields()
+        .filter(|field| field.ifd_num == In::PRIMARY)
+        .filter(|field| carried_over(field.tag))
+        .filter(|field| policy != MetadataPolicy::StripGps || field.tag.context() != Context::Gps)
+        .filter(|field| !matches!(field.value, Value::Unknown(..)))
+        .cloned()
+        .collect()
+}
+
+const EARTH_RADIUS_M: f64 = 6_371_000.0;
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+// Degrees, minutes, seconds with the hemisphere reference ("S" and "W" negative).
+fn gps_degrees(fields: &[Field], value_tag: exif::Tag, ref_tag: exif::Tag) -> Option<f64> {
+    let Value::Rational(parts) = &fields.iter().find(|f| f.tag == value_tag)?.value else {
+        return None;
+    };
+    let degrees = parts
+        .iter()
+        .zip([1.0, 60.0, 3600.0])
+        .map(|(part, scale)| part.to_f64() / scale)
+        .sum::<f64>();
+    let negative = fields
+        .iter()
+        .find(|f| f.tag == ref_tag)
+        .map(|f| f.display_value().to_string())
+        .is_some_and(|r| r.contains('S') || r.contains('W'));
+    let degrees = if negative { -degrees } else { degrees };
+    degrees.is_finite().then_some(degrees)
+}
+
+fn haversine_m(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (lat1, lat2) = (a.0.to_radians(), b.0.to_radians());
+    let (dlat, dlon) = ((b.0 - a.0).to_radians(), (b.1 - a.1).to_radians());
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_M * h.sqrt().asin()
+}
+
+fn gps_fields(tag: exif::Tag, ref_tag: exif::Tag, degrees: f64, refs: [&str; 2]) -> [Field; 2] {
+    let abs = degrees.abs();
+    let whole = abs.trunc();
+    let minutes = ((abs - whole) * 60.0).trunc();
+    let seconds = ((abs - whole) * 60.0 - minutes) * 60.0;
+    let rational = |num: u32, denom: u32| exif::Rational { num, denom };
+    let value = Value::Rational(vec![
+        rational(whole as u32, 1),
+        rational(minutes as u32, 1),
+        rational((seconds * 100.0).round() as u32, 100),
+    ]);
+    let reference = if degrees < 0.0 { refs[1] } else { refs[0] };
+    [
+        Field {
+            tag: ref_tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![reference.as_bytes().to_vec()]),
+        },
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value,
+        },
+    ]
+}
+
+/// Strip or blur the GPS position in `fields` when it lies inside the privacy
+/// zone. Fuzzing snaps the position to the centre of a `fuzz_m` grid cell and
+/// drops every other GPS tag.
+pub fn apply_privacy_zone(fields: &mut Vec<Field>, zone: &PrivacyZone) {
+    if !zone.enabled {
+        return;
+    }
+    let position = (
+        gps_degrees(fields, exif::Tag::GPSLatitude, exif::Tag::GPSLatitudeRef),
+        gps_degrees(fields, exif::Tag::GPSLongitude, exif::Tag::GPSLongitudeRef),
+    );
+    let (Some(lat), Some(lon)) = position else {
+        return;
+    };
+    if haversine_m((lat, lon), (zone.latitude, zone.longitude)) > zone.radius_m {
+        return;
+    }
+    match zone.action {
+        PrivacyAction::Strip => fields.retain(|f| f.tag.context() != Context::Gps),
+        PrivacyAction::Fuzz => {
+            let lat_step = zone.fuzz_m.max(1.0) / METERS_PER_DEGREE;
+            let lon_step = lat_step / lat.to_radians().cos().max(0.01);
+            let snap = |value: f64, step: f64| ((value / step).floor() + 0.5) * step;
+            let (lat, lon) = (snap(lat, lat_step).clamp(-90.0, 90.0), snap(lon, lon_step));
+            let lon = (lon + 180.0).rem_euclid(360.0) - 180.0;
+            // altitude, direction and the like would still narrow it down
+            fields.retain(|f| f.tag.context() != Context::Gps || f.tag == exif::Tag::GPSVersionID);
+            fields.extend(gps_fields(
+                exif::Tag::GPSLatitude,
+                exif::Tag::GPSLatitudeRef,
+                lat,
+                ["N", "S"],
+            ));
+            fields.extend(gps_fields(
+                exif::Tag::GPSLongitude,
+                exif::Tag::GPSLongitudeRef,
+                lon,
+                ["E", "W"],
+            ));
+        }
+    }
+}
+
+/// Serialize fields as a TIFF-structured EXIF block, the payload JPEG APP1
+/// and PNG eXIf chunks expect.
+pub fn encode_exif(fields: &[Field]) -> Option<Vec<u8>> {
+    if fields.is_empty() {
+        return None;
+    }
+    let mut writer = ExifWriter::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    let mut buf = Cursor::new(Vec::new());
+    writer
+        .write(&mut buf, cfg!(target_endian = "little"))
+        .ok()?;
+    Some(buf.into_inner())
+}
+
+// RATIONAL / SRATIONAL arrays; the tiff crate only encodes single rationals.
+struct RationalList(Vec<u32>);
+struct SRationalList(Vec<i32>);
+
+impl TiffValue for RationalList {
+    const BYTE_LEN: u8 = 8;
+    const FIELD_TYPE: TiffType = TiffType::RATIONAL;
+
+    fn count(&self) -> usize {
+        self.0.len() / 2
+    }
+
+    fn data(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.0.iter().flat_map(|v| v.to_ne_bytes()).collect())
+    }
+}
+
+impl TiffValue for SRationalList {
+    const BYTE_LEN: u8 = 8;
+    const FIELD_TYPE: TiffType = TiffType::SRATIONAL;
+
+    fn count(&self) -> usize {
+        self.0.len() / 2
+    }
+
+    fn data(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(self.0.iter().flat_map(|v| v.to_ne_bytes()).collect())
+    }
+}
+
+fn ascii_value(parts: &[Vec<u8>]) -> String {
+    let joined = parts.first().map(Vec::as_slice).unwrap_or_default();
+    joined
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|&b| if b.is_ascii() { b as char } else { '?' })
+        .collect()
+}
+
+fn write_tiff_field<W: Write + Seek>(
+    dir: &mut DirectoryEncoder<'_, W, TiffKindStandard>,
+    field: &Field,
+) -> tiff::TiffResult<()> {
+    let tag = TiffTag::from_u16_exhaustive(field.tag.number());
+    match &field.value {
+        Value::Byte(v) | Value::Undefined(v, _) => dir.write_tag(tag, v.as_slice()),
+        Value::Ascii(v) => dir.write_tag(tag, ascii_value(v).as_str()),
+        Value::Short(v) => dir.write_tag(tag, v.as_slice()),
+        Value::Long(v) => dir.write_tag(tag, v.as_slice()),
+        Value::Rational(v) => dir.write_tag(
+            tag,
+            RationalList(v.iter().flat_map(|r| [r.num, r.denom]).collect()),
+        ),
+        Value::SByte(v) => dir.write_tag(tag, v.as_slice()),
+        Value::SShort(v) => dir.write_tag(tag, v.as_slice()),
+        Value::SLong(v) => dir.write_tag(tag, v.as_slice()),
+        Value::SRational(v) => dir.write_tag(
+            tag,
+            SRationalList(v.iter().flat_map(|r| [r.num, r.denom]).collect()),
+        ),
+        Value::Float(v) => dir.write_tag(tag, v.as_slice()),
+        Value::Double(v) => dir.write_tag(tag, v.as_slice()),
+        Value::Unknown(..) => Ok(()),
+    }
+}
+
+fn write_sub_ifd<W: Write + Seek>(
+    encoder: &mut tiff::encoder::TiffEncoder<W>,
+    fields: &[Field],
+    context: Context,
+) -> tiff::TiffResult<Option<u32>> {
+    let mut selected = fields
+        .iter()
+        .filter(|f| f.tag.context() == context)
+        .peekable();
+    if selected.peek().is_none() {
+        return Ok(None);
+    }
+    let mut dir = encoder.extra_directory()?;
+    for field in selected {
+        write_tiff_field(&mut dir, field)?;
+    }
+    Ok(Some(dir.finish_with_offsets()?.offset))
+}
+
+/// Offsets of the EXIF/GPS sub-IFDs written ahead of the main TIFF image.
+pub struct TiffExifOffsets {
+    exif: Option<u32>,
+    gps: Option<u32>,
+}
+
+/// Write the EXIF and GPS sub-IFDs; must run before the image directory is created.
+pub fn write_tiff_exif_ifds<W: Write + Seek>(
+    encoder: &mut tiff::encoder::TiffEncoder<W>,
+    fields: &[Field],
+) -> tiff::TiffResult<TiffExifOffsets> {
+    Ok(TiffExifOffsets {
+        exif: write_sub_ifd(encoder, fields, Context::Exif)?,
+        gps: write_sub_ifd(encoder, fields, Context::Gps)?,
+    })
+}
+
+/// Write the primary-IFD tags (make, model, date, ...) and link the sub-IFDs.
+pub fn write_tiff_exif_tags<W: Write + Seek>(
+    dir: &mut DirectoryEncoder<'_, W, TiffKindStandard>,
+    fields: &[Field],
+    offsets: &TiffExifOffsets,
+) -> tiff::TiffResult<()> {
+    for field in fields.iter().filter(|f| f.tag.context() == Context::Tiff) {
+        write_tiff_field(dir, field)?;
+    }
+    if let Some(offset) = offsets.exif {
+        dir.write_tag(TiffTag::ExifDirectory, offset)?;
+    }
+    if let Some(offset) = offsets.gps {
+        dir.write_tag(TiffTag::GpsDirectory, offset)?;
+    }
+    Ok(())
+}
+
+// IPTC Caption-Abstract is limited to 2000 bytes by the IIM spec.
+const IPTC_CAPTION_MAX: usize = 2000;
+
+fn iim_dataset(out: &mut Vec<u8>, record: u8, dataset: u8, data: &[u8]) {
+    out.extend_from_slice(&[0x1C, record, dataset]);
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.extend_from_slice(data);
+}
+
+/// IPTC-IIM block holding a UTF-8 caption (record 2:120).
+pub fn iptc_caption_block(caption: &str) -> Vec<u8> {
+    let mut end = caption.len().min(IPTC_CAPTION_MAX);
+    while !caption.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut out = Vec::new();
+    iim_dataset(&mut out, 1, 90, b"\x1b%G"); // coded character set: UTF-8
+    iim_dataset(&mut out, 2, 0, &4u16.to_be_bytes()); // record version
+    iim_dataset(&mut out, 2, 120, &caption.as_bytes()[..end]);
+    out
+}
+
+/// Insert the IIM block as a Photoshop APP13 segment after the leading APPn segments.
+pub fn insert_jpeg_iptc(jpeg: &mut Vec<u8>, iim: &[u8]) {
+    let mut resource = b"Photoshop 3.0\0".to_vec();
+    resource.extend_from_slice(b"8BIM");
+    resource.extend_from_slice(&0x0404u16.to_be_bytes());
+    resource.extend_from_slice(&[0, 0]); // empty pascal name, padded
+    resource.extend_from_slice(&(iim.len() as u32).to_be_bytes());
+    resource.extend_from_slice(iim);
+    if iim.len() % 2 == 1 {
+        resource.push(0);
+    }
+    if resource.len() + 2 > u16::MAX as usize || jpeg.len() < 4 {
+        return;
+    }
+    let mut segment = vec![0xFF, 0xED];
+    segment.extend_from_slice(&((resource.len() + 2) as u16).to_be_bytes());
+    segment.extend_from_slice(&resource);
+
+    let mut pos = 2; // after SOI
+    while pos + 4 <= jpeg.len() && jpeg[pos] == 0xFF && (0xE0..=0xEF).contains(&jpeg[pos + 1]) {
+        let len = u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]) as usize;
+        pos += 2 + len;
+    }
+    let pos = pos.min(jpeg.len());
+    jpeg.splice(pos..pos, segment);
+}
+
+// UserComment's character code for UCS-2; the text follows in the byte order of
+// the EXIF block, which `encode_exif` and the TIFF encoder both write natively.
+const USER_COMMENT_UNICODE: &[u8; 8] = b"UNICODE\0";
+
+/// Replace the description fields with the caption, for formats without IPTC.
+/// ImageDescription is ASCII only, so other captions go into UserComment as
+/// UTF-16.
+pub fn set_caption_field(fields: &mut Vec<Field>, caption: &str) {
+    fields.retain(|f| f.tag != exif::Tag::ImageDescription && f.tag != exif::Tag::UserComment);
+    if caption.is_ascii() {
+        fields.push(Field {
+            tag: exif::Tag::ImageDescription,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![caption.as_bytes().to_vec()]),
+        });
+        return;
+    }
+    let mut comment = USER_COMMENT_UNICODE.to_vec();
+    comment.extend(caption.encode_utf16().flat_map(u16::to_ne_bytes));
+    fields.push(Field {
+        tag: exif::Tag::UserComment,
+        ifd_num: In::PRIMARY,
+        value: Value::Undefined(comment, 0),
+    });
+}
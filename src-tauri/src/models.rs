@@ -1,4 +1,7 @@
+use std::collections::{BTreeMap, HashMap};
+
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -7,6 +10,7 @@ pub struct AssetSummary {
     pub file_name: String,
     pub extension: String,
     pub path: String,
+    pub derived_from: Option<String>, // set on derived files: the asset id of the source listed above
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -17,8 +21,8 @@ pub struct FolderIndex {
     pub assets: Vec<AssetSummary>,
 }
 
-#[derive(Debug, Clone, Default, Serialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
 pub struct Metadata {
     pub camera: Option<String>,
     pub lens: Option<String>,
@@ -27,6 +31,13 @@ pub struct Metadata {
     pub aperture: Option<String>,
     pub focal: Option<String>,
     pub date: Option<String>,
+    pub exposure_bias: Option<f32>,   // EV, for bracket detection
+    pub drive_mode: Option<String>,   // "bracket" when the camera auto-bracketed
+    pub sequence_number: Option<u32>, // frame number within a burst or bracket
+    pub focal_mm: Option<f32>,        // focal length as a number
+    pub focal_35mm: Option<f32>,      // 35mm-equivalent focal length of the frame as shot
+    pub crop_factor: Option<f32>,     // full-frame diagonal over the recorded area's
+    pub crop_mode: Option<bool>,      // a larger sensor recording a cropped area; None if unknown
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,6 +53,89 @@ pub struct GlobalAdjustments {
     pub tint: f32,
     pub vibrance: f32,
     pub saturation: f32,
+    pub clarity: f32,             // midtone local contrast, -100..100
+    pub texture: f32,             // fine detail, -100..100
+    pub dehaze: f32,              // -100 adds haze, 100 removes it
+    pub nr_luminance: f32,        // 0..100
+    pub nr_luminance_detail: f32, // 0..100, texture kept by luminance NR
+    pub nr_color: f32,            // 0..100
+    pub levels: Levels,
+    pub dither: bool, // ordered dither when the globals pass rounds back to 8 bits
+    pub dual_illuminant: DualIlluminant,
+}
+
+// A second white balance for mixed light (a tungsten room with daylight windows).
+// temp/tint above are the first illuminant; this block's are the second, blended
+// in by a mask or by brightness.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DualIlluminant {
+    pub enabled: bool,
+    pub temp: f32, // same scale as globals.temp
+    pub tint: f32,
+    pub blend: IlluminantBlend,
+    // IlluminantBlend::Mask: coverage of the second illuminant, over the whole
+    // frame as shot (before lens corrections and crop)
+    pub mask: Mask,
+    // IlluminantBlend::Luminance: linear luminance where the second illuminant
+    // starts to take over and where it has fully
+    pub luminance_low: f32,
+    pub luminance_high: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IlluminantBlend {
+    #[default]
+    Mask,
+    Luminance, // bright areas (windows, lamps in frame) take the second illuminant
+}
+
+impl Default for DualIlluminant {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            temp: 0.0,
+            tint: 0.0,
+            blend: IlluminantBlend::Mask,
+            mask: Mask::default(),
+            luminance_low: 0.25,
+            luminance_high: 0.75,
+        }
+    }
+}
+
+// Classic levels; the master channel applies first, then the per-channel ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Levels {
+    pub master: LevelsChannel,
+    pub red: LevelsChannel,
+    pub green: LevelsChannel,
+    pub blue: LevelsChannel,
+}
+
+// Points in 0..1 of the encoded value; gamma > 1 lifts the midtones.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LevelsChannel {
+    pub input_black: f32,
+    pub input_white: f32,
+    pub gamma: f32,
+    pub output_black: f32,
+    pub output_white: f32,
+}
+
+impl Default for LevelsChannel {
+    fn default() -> Self {
+        Self {
+            input_black: 0.0,
+            input_white: 1.0,
+            gamma: 1.0,
+            output_black: 0.0,
+            output_white: 1.0,
+        }
+    }
 }
 
 impl Default for GlobalAdjustments {
@@ -57,6 +151,15 @@ impl Default for GlobalAdjustments {
             tint: 0.0,
             vibrance: 0.0,
             saturation: 0.0,
+            clarity: 0.0,
+            texture: 0.0,
+            dehaze: 0.0,
+            nr_luminance: 0.0,
+            nr_luminance_detail: 50.0,
+            nr_color: 0.0,
+            levels: Levels::default(),
+            dither: false,
+            dual_illuminant: DualIlluminant::default(),
         }
     }
 }
@@ -81,14 +184,53 @@ impl Default for LocalAdjustments {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", default)]
 pub struct Mask {
-    pub mask_type: String, // "linear_gradient"
+    pub mask_type: String, // "linear_gradient", "brush", "luminance_range" or "depth"
     pub start: (f32, f32), // normalized 0..1
     pub end: (f32, f32),
     pub feather: f32, // 0..1
+    pub falloff: FeatherFalloff,
     pub invert: bool,
+    // brush only: file name of the painted coverage PNG beside the sidecar,
+    // stretched over the frame the layer is applied to
+    pub bitmap: Option<String>,
+    // luminance_range only: display luma (0..1) fully covered; coverage falls off
+    // over `feather` beyond either end
+    pub luminance: (f32, f32),
+    // depth only: nearness (0 farthest .. 1 nearest in the file's depth map) fully
+    // covered, falling off like `luminance`
+    pub depth: (f32, f32),
+    // further shapes folded into this one in order, e.g. a gradient minus a
+    // luminance range
+    pub components: Vec<MaskComponent>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct MaskComponent {
+    pub mode: MaskCombine,
+    pub mask: Mask, // its own invert applies before combining
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MaskCombine {
+    #[default]
+    Add, // union, capped at full coverage
+    Subtract,
+    Intersect,
+}
+
+// Shape of the transition across the feather band.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FeatherFalloff {
+    Linear,
+    #[default]
+    Smooth, // smoothstep
+    Gaussian,
 }
 
 impl Default for Mask {
@@ -98,7 +240,12 @@ impl Default for Mask {
             start: (0.3, 0.2),
             end: (0.7, 0.8),
             feather: 0.2,
+            falloff: FeatherFalloff::Smooth,
             invert: false,
+            bitmap: None,
+            luminance: (0.0, 1.0),
+            depth: (0.5, 1.0),
+            components: Vec::new(),
         }
     }
 }
@@ -127,12 +274,286 @@ impl Default for AdjustmentLayer {
     }
 }
 
+// Darkroom toners simulated on top of the monochrome conversion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PaperTone {
+    #[default]
+    Neutral,
+    Selenium,
+    Sepia,
+    Cyanotype,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BlackAndWhite {
+    pub enabled: bool,
+    pub paper: PaperTone,
+    pub tone_strength: f32, // 0..100
+}
+
+impl Default for BlackAndWhite {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            paper: PaperTone::Neutral,
+            tone_strength: 50.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Grain {
+    pub amount: f32,    // 0..100
+    pub size: f32,      // 0..100
+    pub roughness: f32, // 0..100
+    pub seed: u64,      // 0 derives the seed from the asset path
+}
+
+impl Default for Grain {
+    fn default() -> Self {
+        Self {
+            amount: 0.0,
+            size: 25.0,
+            roughness: 50.0,
+            seed: 0,
+        }
+    }
+}
+
+// A `.cube` file applied as part of the recipe
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LutReference {
+    pub path: String,
+    pub strength: f32, // 0..100
+}
+
+impl Default for LutReference {
+    fn default() -> Self {
+        Self {
+            path: String::new(),
+            strength: 100.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LutInfo {
+    pub path: String,
+    pub title: Option<String>,
+    pub size: usize,
+}
+
+// Tone curves over display-referred values; each is a list of (input, output)
+// control points in 0..1, empty meaning the identity. The master curve is applied
+// first, then the per-channel ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ToneCurves {
+    pub master: Vec<(f32, f32)>,
+    pub red: Vec<(f32, f32)>,
+    pub green: Vec<(f32, f32)>,
+    pub blue: Vec<(f32, f32)>,
+}
+
+// Processing for scanned documents and receipts, run before the photo adjustments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DocumentMode {
+    pub enabled: bool,
+    pub deskew: bool,
+    pub normalize_white: bool,
+    pub sharpen: f32, // 0..100
+    pub grayscale: bool,
+}
+
+impl Default for DocumentMode {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            deskew: true,
+            normalize_white: true,
+            sharpen: 60.0,
+            grayscale: false,
+        }
+    }
+}
+
+// Lens corrections, applied to the source frame before the crop. The profile is
+// looked up by the EXIF lens model; the sliders add to it or stand in for it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LensCorrection {
+    pub enabled: bool,
+    pub use_profile: bool,
+    pub distortion: f32, // -100..100, positive straightens barrel distortion
+    pub vignetting: f32, // -100..100, positive brightens the corners
+    pub profile: Option<LensCoefficients>, // filled per asset from the database when empty
+}
+
+impl Default for LensCorrection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            use_profile: true,
+            distortion: 0.0,
+            vignetting: 0.0,
+            profile: None,
+        }
+    }
+}
+
+// Lensfun "ptlens" distortion (a, b, c) and "pa" vignetting (k1, k2, k3) terms
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LensCoefficients {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub k1: f32,
+    pub k2: f32,
+    pub k3: f32,
+}
+
+// One lens of the profile database, calibrated at one or more focal lengths
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LensProfile {
+    pub maker: Option<String>,
+    pub model: String,            // compared with the EXIF LensModel
+    pub crop_factor: Option<f32>, // of the body it was calibrated on (lensfun's cropfactor)
+    pub calibration: Vec<LensCalibration>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LensCalibration {
+    pub focal: f32, // mm
+    #[serde(flatten)]
+    pub coefficients: LensCoefficients,
+}
+
+// Geometry: mirroring, straightening and a normalized (0..1) rectangle in the
+// mirrored, straightened frame
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Crop {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    pub aspect: Option<f32>, // width / height the rect was constrained to
+    pub aspect_locked: bool, // renders keep exactly `aspect`, trimming the rect if needed
+    pub angle: f32,          // straighten, degrees clockwise, -45..45
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub constrain: bool, // straighten without zoom, keeping the rect off the rotation's empty corners
+}
+
+impl Default for Crop {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+            aspect: None,
+            aspect_locked: false,
+            angle: 0.0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            constrain: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CropSuggestion {
+    pub crop: Crop,
+    pub score: f32, // 0..1, higher is better
+}
+
+// Which edge a batch crop keeps when it has to trim
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CropGravity {
+    #[default]
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", default)]
 pub struct EditRecipe {
     pub version: u8,
     pub globals: GlobalAdjustments,
     pub layers: Vec<AdjustmentLayer>,
+    pub bw: BlackAndWhite,
+    pub grain: Grain,
+    pub lut: Option<LutReference>,
+    pub crop: Option<Crop>,
+    pub curves: ToneCurves,
+    pub document: DocumentMode,
+    pub lens: LensCorrection,
+    // sensor defects to patch, normalized (x, y) on the uncropped frame
+    pub dead_pixels: Vec<(f32, f32)>,
+    pub retouch: Vec<RetouchSpot>, // dust and blemish removal, applied in order
+    pub flags: AssetFlags,         // changed only through set_asset_flags
+    // studio bookkeeping such as client or delivery status; changed only through set_user_fields
+    pub user_fields: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RetouchMode {
+    #[default]
+    Heal, // source texture, blended into the colour and tone around the target
+    Clone, // source pixels copied as they are
+}
+
+// One spot removal: the target circle is painted over from the source circle.
+// Positions are normalized (x, y) on the uncropped frame, like dead pixels.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RetouchSpot {
+    pub id: String,
+    pub mode: RetouchMode,
+    pub source: (f32, f32),
+    pub target: (f32, f32),
+    pub radius: f32,  // fraction of the long edge
+    pub feather: f32, // 0..1 of the radius faded out at the edge
+    pub opacity: f32, // 0..1
+}
+
+impl Default for RetouchSpot {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            mode: RetouchMode::default(),
+            source: (0.5, 0.5),
+            target: (0.5, 0.5),
+            radius: 0.01,
+            feather: 0.3,
+            opacity: 1.0,
+        }
+    }
+}
+
+// Per-asset opt-outs, kept in the sidecar with the recipe.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AssetFlags {
+    pub skip_lens_correction: bool, // render without lens correction whatever the recipe says
+    pub skip_default_preset: bool,  // leave alone when default develop settings are applied
+    pub locked: bool,               // read-only: recipe writes are refused, batches skip it
 }
 
 impl Default for EditRecipe {
@@ -141,14 +562,808 @@ impl Default for EditRecipe {
             version: 1,
             globals: GlobalAdjustments::default(),
             layers: Vec::new(),
+            bw: BlackAndWhite::default(),
+            grain: Grain::default(),
+            lut: None,
+            crop: None,
+            curves: ToneCurves::default(),
+            document: DocumentMode::default(),
+            lens: LensCorrection::default(),
+            dead_pixels: Vec::new(),
+            retouch: Vec::new(),
+            flags: AssetFlags::default(),
+            user_fields: BTreeMap::new(),
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelHistogram {
+    pub bins: Vec<u32>,
+    pub clipped: u64,
+    pub samples: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawHistogram {
+    pub source: String, // "sensor" | "libraw16" | "decoded"
+    pub red: ChannelHistogram,
+    pub green: ChannelHistogram,
+    pub blue: ChannelHistogram,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ExportFormat {
+    #[default]
+    Jpeg,
+    Png,
+    Tiff,
+    Heic, // requires the `heic` cargo feature
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResizeMode {
+    #[default]
+    None,
+    LongEdge,
+    ShortEdge,
+    Megapixels,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputColorSpace {
+    #[default]
+    Srgb,
+    AdobeRgb,
+    ProPhoto,
+    DisplayP3,
+}
+
+// Preview simulation of an output profile (printer/paper or another display).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftProof {
+    pub profile_path: String, // .icc/.icm inside an allowed root or a system colour folder
+    #[serde(default)]
+    pub intent: ProofIntent,
+    #[serde(default)]
+    pub gamut_warning: bool, // paint colours the profile cannot reproduce
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ProofIntent {
+    #[default]
+    Perceptual,
+    RelativeColorimetric,
+    Saturation,
+    AbsoluteColorimetric,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CollisionPolicy {
+    #[default]
+    Unique,
+    Overwrite,
+    Skip,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DestinationMode {
+    #[default]
+    Fixed, // `destination`
+    SourceSubfolder, // `exports/` next to each original
+    Ask,             // folder picker on every export
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MetadataPolicy {
+    #[default]
+    Copy, // original EXIF (camera, exposure, lens, GPS)
+    StripGps,
+    StripAll,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportResize {
+    pub mode: ResizeMode,
+    pub value: f32, // pixels for edge modes, MP for megapixels
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ExportSettings {
+    pub format: ExportFormat,
+    pub quality: u8, // JPEG only, 1..100
+    pub resize: ExportResize,
+    pub color_space: OutputColorSpace,
+    pub destination_mode: DestinationMode,
+    pub destination: String,       // used by DestinationMode::Fixed
+    pub filename_template: String, // e.g. "{date}_{original}_{seq}"
+    pub collision: CollisionPolicy,
+    pub metadata: MetadataPolicy,
+    pub dpi: Option<u16>, // written as the resolution tag, None leaves it unset
+    pub dither: bool,     // ordered dither when converting to a non-sRGB output space
+    pub review: bool,     // client review copy: settings' review watermark burned in, no metadata
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Jpeg,
+            quality: 90,
+            resize: ExportResize::default(),
+            color_space: OutputColorSpace::Srgb,
+            destination_mode: DestinationMode::Fixed,
+            destination: String::new(),
+            filename_template: "{original}".into(),
+            collision: CollisionPolicy::Unique,
+            metadata: MetadataPolicy::Copy,
+            dpi: None,
+            dither: false,
+            review: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportPreset {
+    pub id: String,
+    pub name: String,
+    pub settings: ExportSettings, // includes the preset's destination policy
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SlideshowSettings {
+    pub width: u32,
+    pub height: u32,
+    pub background: [u8; 3],      // letterbox fill
+    pub lut_path: Option<String>, // .cube burned in after the recipe
+    pub quality: u8,
+    pub destination: String,
+    pub prefix: String, // files are named "{prefix}_0001.jpg"
+}
+
+impl Default for SlideshowSettings {
+    fn default() -> Self {
+        Self {
+            width: 3840,
+            height: 2160,
+            background: [0, 0, 0],
+            lut_path: None,
+            quality: 92,
+            destination: String::new(),
+            prefix: "slide".into(),
+        }
+    }
+}
+
+// JPEG stand-ins for offline editing, mirrored under `destination`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ProxySettings {
+    pub destination: String,
+    pub long_edge: u32,
+    pub quality: u8,
+}
+
+impl Default for ProxySettings {
+    fn default() -> Self {
+        Self {
+            destination: String::new(),
+            long_edge: 2560,
+            quality: 92,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyResult {
+    pub asset_id: String,
+    pub proxy_path: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxySyncSummary {
+    pub synced: usize,
+    pub missing: Vec<String>, // originals that are not reachable right now
+    pub locked: Vec<String>,  // originals whose recipe is locked
+}
+
+// Outcome of writing one change into many recipes.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchEditSummary {
+    pub applied: usize,
+    pub locked: Vec<String>,    // assets whose recipe is locked
+    pub opted_out: Vec<String>, // assets whose flags exclude them from this change
+}
+
+// Outcome of rebinding a moved folder; paths are the old catalog keys.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RelinkSummary {
+    pub relinked: usize,
+    pub mismatched: Vec<String>, // a file exists at the new location but its checksum differs
+    pub missing: Vec<String>,    // nothing at the new location either
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum QuickExportTarget {
+    Email, // 1600px long edge, q75
+    Web,   // 2048px long edge, q80
+    Full,  // full resolution, q95
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResult {
+    pub asset_id: String,
+    pub output_path: String,
+    pub width: u32,
+    pub height: u32,
+    #[serde(default)]
+    pub skipped: bool, // target existed and the collision policy was skip
+    #[serde(default)]
+    pub hook_error: Option<String>, // the post-export hook failed; the file itself is fine
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportJobAsset {
+    pub asset_id: String,
+    pub path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportJob {
+    pub id: String,
+    pub created_at: u64, // unix seconds
+    pub settings: ExportSettings,
+    pub assets: Vec<ExportJobAsset>,
+    pub results: Vec<ExportResult>,
+    pub error: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GpuAdapter {
+    pub id: String, // stable across launches; what set_preferred_gpu takes
     pub name: String,
     pub backend: String,
     pub device_type: String,
+    pub max_texture_dimension: u32,
+    pub float32_filterable: bool,
+    pub timestamp_query: bool,
+    // largest single buffer the driver allows; wgpu does not report memory, and
+    // this says little about how much there is
+    pub max_buffer_mb: u64,
+    pub context_active: bool, // the processing context runs on this adapter
+    pub preferred: bool,      // chosen in settings; used from the next launch
+    pub context_error: Option<String>, // set on every entry when the context failed
+    // features whose shaders this driver rejected; only on the active adapter
+    pub disabled_features: Vec<GpuFeatureFailure>,
+}
+
+// Timing of one pipeline stage ("decode", "render", "encode", "gpu", "export");
+// the percentiles cover its most recent calls.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PerfMetric {
+    pub stage: String,
+    pub count: u64, // calls this session
+    pub p50_ms: f32,
+    pub p95_ms: f32,
+    pub last_ms: f32,
+}
+
+// One benchmark render timed stage by stage, for diagnosing slow previews.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuProfile {
+    pub path: String,                    // "gpu", or "cpu" when renders fall back
+    pub fallback_reason: Option<String>, // why renders run on the CPU
+    pub adapter: Option<String>,
+    pub width: u32,
+    pub height: u32,
+    // GPU stages; None on the CPU path
+    pub upload_ms: Option<f32>,
+    pub pass_ms: Option<f32>,
+    pub readback_ms: Option<f32>,
+    pub cpu_ms: f32, // the same render on the CPU, for comparison
+}
+
+// Ops of one kind that fell back to the CPU this session ("init": no GPU
+// context, "limits": image over the texture limits, "readback": map failure,
+// "pipeline": a shader the driver rejected).
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuFallback {
+    pub kind: String,
+    pub detail: String, // the latest occurrence
+    pub count: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuStatus {
+    pub active: bool, // the processing context is up
+    pub adapter: Option<String>,
+    pub backend: Option<String>,
+    pub init_error: Option<String>,
+    pub fallbacks: Vec<GpuFallback>,
+    pub disabled_features: Vec<GpuFeatureFailure>,
+}
+
+// A GPU feature switched off for the session because its shader or pipeline
+// failed to build; that work runs on the CPU instead.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuFeatureFailure {
+    pub feature: String, // "globals", "blur", "noise reduction", ...
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IntegrityStatus {
+    New,        // first checksum recorded
+    Verified,   // matches the stored checksum
+    Modified,   // contents and size/mtime changed, checksum updated
+    Mismatch,   // contents changed with identical size/mtime
+    Missing,    // catalogued but no longer on disk
+    Unreadable, // could not be read
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssetIntegrity {
+    pub path: String,
+    pub status: IntegrityStatus,
+    pub checksum: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanRules {
+    pub skip_hidden: bool,
+    pub honor_nomedia: bool,
+    // globs against "<folder name>/<file name>", e.g. "*_rejects/*" or "*.xcf"
+    pub exclude_patterns: Vec<String>,
+    pub min_file_size: u64, // bytes; smaller files are ignored
+}
+
+impl Default for ScanRules {
+    fn default() -> Self {
+        Self {
+            skip_hidden: true,
+            honor_nomedia: true,
+            exclude_patterns: Vec::new(),
+            min_file_size: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppSettings {
+    // folders the backend may read from and export into, granted through the
+    // native folder dialog; update_settings refuses to change them
+    pub allowed_roots: Vec<String>,
+    pub scan_rules: ScanRules,
+    // per-folder overrides keyed by folder path
+    pub folder_scan_rules: HashMap<String, ScanRules>,
+    pub export_presets: Vec<ExportPreset>, // user presets, listed after the built-ins
+    pub cache_cap_mb: Option<u64>,         // None uses the built-in cap
+    pub isolate_raw_decodes: bool,         // run native RAW decoders in a helper process
+    pub keep_hot_pixels: bool, // skip hot-pixel suppression (astro frames, dark-frame work)
+    pub skip_noise_defaults: bool, // new files open with NR at 0 instead of the camera's profile
+    // decodes ignore embedded profiles and take pixels as sRGB
+    #[serde(alias = "unmanagedThumbnails")]
+    pub unmanaged_color: bool,
+    pub full_jpeg_decode: bool, // thumbnails and previews of JPEGs skip the scaled-DCT shortcut
+    // written by apply_default_develop, except to assets flagged skip_default_preset
+    pub default_develop: Option<GlobalAdjustments>,
+    // keyed by "Make Model" as the raw decoder reports it
+    pub camera_calibrations: HashMap<String, CameraCalibration>,
+    pub local_api: LocalApiSettings,
+    pub privacy_zone: PrivacyZone,
+    pub review_watermark: ReviewWatermark,
+    // adapter id from detect_gpus; None takes the high-performance adapter
+    pub preferred_gpu: Option<String>,
+}
+
+// Text burned diagonally into review exports, kept apart from delivery settings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ReviewWatermark {
+    pub text: String,
+    pub opacity: f32, // 0..1
+    pub size: f32,    // share of the image diagonal the text spans, 0..1
+}
+
+impl Default for ReviewWatermark {
+    fn default() -> Self {
+        Self {
+            text: "FOR REVIEW ONLY".into(),
+            opacity: 0.35,
+            size: 0.6,
+        }
+    }
+}
+
+// Exports of photos taken within `radius_m` of this point lose or blur their GPS;
+// the originals and the catalog keep the true position.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PrivacyZone {
+    pub enabled: bool,
+    pub latitude: f64,  // degrees, north positive
+    pub longitude: f64, // degrees, east positive
+    pub radius_m: f64,
+    pub action: PrivacyAction,
+    pub fuzz_m: f64, // Fuzz: positions snap to the centre of a grid cell this wide
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PrivacyAction {
+    #[default]
+    Strip,
+    Fuzz,
+}
+
+impl Default for PrivacyZone {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            latitude: 0.0,
+            longitude: 0.0,
+            radius_m: 500.0,
+            action: PrivacyAction::Strip,
+            fuzz_m: 2000.0,
+        }
+    }
+}
+
+// User scripts run after files enter the library or leave it as exports.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HookSettings {
+    pub post_import: Option<HookScript>, // once per file, the first time a folder shows it
+    pub post_export: Option<HookScript>, // after each written export
+}
+
+// Run as `program args... <file path> <metadata JSON>` with a scrubbed environment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HookScript {
+    pub program: String,
+    pub args: Vec<String>, // placed before the path and metadata
+    pub timeout_secs: u64, // the script is killed after this long
+}
+
+impl Default for HookScript {
+    fn default() -> Self {
+        Self {
+            program: String::new(),
+            args: Vec::new(),
+            timeout_secs: 30,
+        }
+    }
+}
+
+// The optional HTTP API on 127.0.0.1 for scripts and companion tools.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct LocalApiSettings {
+    pub enabled: bool,
+    pub port: u16,
+    pub token: String, // sent as "Authorization: Bearer <token>"; generated when left empty
+}
+
+impl Default for LocalApiSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            port: 47_620,
+            token: String::new(),
+        }
+    }
+}
+
+// One entry of the local API's asset listing.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalApiAsset {
+    pub id: String,
+    pub path: String,
+}
+
+// Body of the local API's POST /v1/export.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalApiExport {
+    pub asset_ids: Vec<String>,
+    pub settings: ExportSettings,
+}
+
+// Measured noise of one camera body at one ISO, learnt from the first original
+// exported. Seeds noise reduction on files that have no recipe yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoiseProfile {
+    pub camera: String, // EXIF model
+    pub iso: u32,
+    pub luma: f32,    // noise sigma, display-referred 0..1
+    pub chroma: f32,  // mean of the r - y and b - y sigmas
+    pub samples: u32, // images measured
+}
+
+// Replaces the decoder's built-in colour matrix for one camera body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraCalibration {
+    pub color_matrix: [[f32; 3]; 3], // XYZ (D65) -> camera, as a DNG ColorMatrix
+}
+
+// Result of the startup cache sweep, emitted as the "cache-sweep" event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheSweep {
+    pub used_bytes: u64, // after eviction
+    pub cap_bytes: u64,
+    pub reclaimed_bytes: u64,
+    pub removed_files: usize,
+}
+
+// Emitted as "full-preview-progress" after each asset of a 1:1 preview run.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FullPreviewProgress {
+    pub asset_id: String,
+    pub done: usize,
+    pub total: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FullPreviewSummary {
+    pub generated: usize,
+    pub up_to_date: usize,
+    pub failed: usize,
+}
+
+// Emitted as "optimize-progress" after each asset of a library optimize.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptimizeProgress {
+    pub job_id: String,
+    pub path: String,
+    pub done: usize,
+    pub total: usize,
+    pub error: Option<String>,
+}
+
+// Emitted as "optimize-finished" when a pass ends, cancelled or not.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OptimizeSummary {
+    pub job_id: String,
+    pub processed: usize,
+    pub failed: usize,
+    pub bad_sidecars: Vec<String>, // originals whose sidecar is unreadable or would be refused
+    pub cancelled: bool,
+    pub error: Option<String>, // set when the pass itself failed, e.g. a catalog write
+}
+
+// Per-original facts that outlive a session, keyed by absolute path.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CatalogEntry {
+    pub checksum: Option<String>, // xxh3-128, hex
+    pub size: u64,
+    pub modified: u64, // unix seconds
+    pub verified_at: u64,
+    pub caption: Option<String>, // written to IPTC Caption-Abstract on export
+    pub proxy: Option<String>,   // offline-editing proxy generated from this original
+    pub proxy_of: Option<String>, // set on proxies: the original they stand in for
+    pub colors: Vec<DominantColor>, // dominant colours of the thumbnail, largest share first
+    pub cull: Option<CullMark>,  // keep/toss from the last committed culling session
+    pub imported_at: Option<u64>, // unix seconds the file was first listed; gates the import hook
+    pub xmp: Option<EmbeddedXmp>, // organization another app embedded, read when first listed
+    pub derived: Option<DerivedAsset>, // set on files the app made from other originals
+    pub exif: Option<Metadata>,  // EXIF as of `exif_modified`, filled by a library optimize
+    pub exif_modified: u64,      // source mtime (unix seconds) `exif` was read at
+    pub sharpness: Option<f32>,  // variance of the thumbnail's luma Laplacian; higher is sharper
+    pub user_fields: Option<BTreeMap<String, String>>, // the sidecar's as of `user_fields_modified`
+    pub user_fields_modified: u64, // sidecar mtime (unix seconds) `user_fields` was read at; 0 for none
+}
+
+// A file produced from one or more originals, kept in the managed derived folder.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DerivedAsset {
+    pub kind: DerivedKind,
+    pub sources: Vec<String>, // catalog keys of the originals it was made from
+    pub created_at: u64,      // unix seconds
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DerivedKind {
+    Hdr,
+    Panorama,
+    Denoised,
+}
+
+// XMP fields another app wrote into a JPEG or HEIC.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct EmbeddedXmp {
+    pub rating: Option<i8>, // 0..5 stars, -1 rejected
+    pub label: Option<String>,
+    pub keywords: Vec<String>,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CullMark {
+    Keep,
+    Toss,
+}
+
+// Returned when a culling session opens: its id and the marks already committed.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CullSession {
+    pub id: String,
+    pub marks: HashMap<String, CullMark>, // by asset id
+}
+
+// Marks written by a session commit; unchanged marks are not counted.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CullSummary {
+    pub kept: usize,
+    pub tossed: usize,
+    pub cleared: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DominantColor {
+    pub rgb: [u8; 3],
+    pub hue: f32,        // degrees, 0..360
+    pub saturation: f32, // HSV saturation, 0..1
+    pub weight: f32,     // share of the frame, 0..1
+}
+
+// Matches assets whose user field `key` is set, and equals `value` (ignoring
+// case) when one is given.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UserFieldFilter {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+// Hue window in degrees; start > end wraps through red
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct HueRange {
+    pub start: f32,
+    pub end: f32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct Catalog {
+    pub assets: HashMap<String, CatalogEntry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleEntry {
+    pub relative_path: String, // '/'-separated, relative to the bundle root
+    pub catalog: Option<CatalogEntry>, // proxy and derived links relative like `relative_path`
+    pub recipe: Option<EditRecipe>, // its LUT path too; links outside the root are dropped
+    pub metadata: Option<Metadata>,
+    #[serde(default)]
+    pub brush_masks: BTreeMap<String, Vec<u8>>, // painted mask PNGs by file name
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CatalogBundle {
+    pub version: u32,
+    pub created_at: u64,
+    pub entries: Vec<BundleEntry>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleImportSummary {
+    pub imported: usize,
+    pub missing: Vec<String>, // relative paths with no file under the target root
+    pub rejected: Vec<String>, // relative paths whose recipe failed validation
+    pub locked: Vec<String>,  // relative paths whose local recipe is locked
+}
+
+// manifest.json of an app data backup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupManifest {
+    pub version: u32,
+    pub created_at: u64,
+    pub data_files: Vec<String>,      // file names under the data folder
+    pub sidecars: Vec<BackupSidecar>, // empty unless sidecars were included
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSidecar {
+    pub entry: String,      // name inside the zip
+    pub asset_path: String, // original the recipe belongs to
+    #[serde(default)]
+    pub brush_masks: Vec<String>, // painted mask PNGs, stored as "<entry>/<name>"
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackupSummary {
+    pub data_files: usize,
+    pub sidecars: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RestoreSummary {
+    pub data_files: usize,
+    pub sidecars: usize,
+    pub missing: Vec<String>,  // asset paths with no file on this machine
+    pub rejected: Vec<String>, // asset paths outside the allowed folders or whose recipe failed to parse
+    pub locked: Vec<String>,   // asset paths whose local recipe is locked
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum IssueSeverity {
+    Warning,
+    Error, // the recipe is refused on save
+}
+
+// One setting that differs between two recipes; null stands for unset.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipeChange {
+    pub field: String, // same paths as RecipeIssue
+    pub old: Value,
+    pub new: Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecipeIssue {
+    pub field: String, // camelCase path, e.g. "layers[0].opacity"
+    pub severity: IssueSeverity,
+    pub message: String,
 }
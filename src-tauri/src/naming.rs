@@ -0,0 +1,138 @@
+use std::path::{Path, PathBuf};
+
+use crate::models::{CollisionPolicy, Metadata};
+
+pub const DEFAULT_TEMPLATE: &str = "{original}";
+const DEFAULT_SEQ_WIDTH: usize = 3;
+// a template's {seq:N} is held to this range; wider would only pad with zeros
+const MAX_SEQ_WIDTH: usize = 10;
+
+pub struct NamingContext<'a> {
+    pub source: &'a Path,
+    pub seq: usize,
+    pub metadata: Option<&'a Metadata>,
+}
+
+/// Whether the template references tokens that require reading EXIF.
+pub fn needs_metadata(template: &str) -> bool {
+    ["{date", "{camera", "{iso"]
+        .iter()
+        .any(|token| template.contains(token))
+}
+
+fn clean_exif_value(value: &str) -> String {
+    // kamadak-exif quotes ASCII values and may append units ("ISO 100")
+    value.trim().trim_matches('"').trim().to_string()
+}
+
+fn exif_date(meta: Option<&Metadata>) -> Option<String> {
+    // display form is "YYYY-MM-DD HH:MM:SS"
+    let raw = clean_exif_value(meta?.date.as_deref()?);
+    let date: String = raw.chars().take(10).collect();
+    if date.len() == 10 {
+        Some(date.replace(':', "-"))
+    } else {
+        None
+    }
+}
+
+fn token_value(name: &str, arg: Option<&str>, ctx: &NamingContext) -> Option<String> {
+    match name {
+        "original" => ctx
+            .source
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string()),
+        "seq" => {
+            let width = arg
+                .and_then(|w| w.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_SEQ_WIDTH)
+                .clamp(1, MAX_SEQ_WIDTH);
+            Some(format!("{:0width$}", ctx.seq, width = width))
+        }
+        "date" => Some(exif_date(ctx.metadata).unwrap_or_else(|| "undated".into())),
+        "camera" => Some(
+            ctx.metadata
+                .and_then(|m| m.camera.as_deref())
+                .map(clean_exif_value)
+                .unwrap_or_else(|| "unknown".into()),
+        ),
+        "iso" => Some(
+            ctx.metadata
+                .and_then(|m| m.iso.as_deref())
+                .map(clean_exif_value)
+                .unwrap_or_else(|| "0".into()),
+        ),
+        _ => None,
+    }
+}
+
+fn sanitize(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let trimmed = cleaned.trim().trim_matches('.').to_string();
+    if trimmed.is_empty() {
+        "export".into()
+    } else {
+        trimmed
+    }
+}
+
+/// Expand `{original}`, `{date}`, `{seq}` / `{seq:N}`, `{camera}` and `{iso}`.
+/// Unknown tokens are kept verbatim so typos are visible in the output name.
+pub fn render_template(template: &str, ctx: &NamingContext) -> String {
+    let template = if template.trim().is_empty() {
+        DEFAULT_TEMPLATE
+    } else {
+        template
+    };
+    let mut out = String::with_capacity(template.len() + 16);
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let Some(close) = after.find('}') else {
+            out.push_str(&rest[open..]);
+            rest = "";
+            break;
+        };
+        let token = &after[..close];
+        let (name, arg) = match token.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (token, None),
+        };
+        match token_value(name.trim(), arg, ctx) {
+            Some(value) => out.push_str(&value),
+            None => out.push_str(&rest[open..open + close + 2]),
+        }
+        rest = &after[close + 1..];
+    }
+    out.push_str(rest);
+    sanitize(&out)
+}
+
+/// Resolve the final output path for `stem.ext` under the collision policy.
+/// Returns None when the file exists and the policy is to skip it.
+pub fn resolve_collision(
+    dir: &Path,
+    stem: &str,
+    ext: &str,
+    policy: CollisionPolicy,
+) -> Option<PathBuf> {
+    let candidate = dir.join(format!("{stem}.{ext}"));
+    if !candidate.exists() {
+        return Some(candidate);
+    }
+    match policy {
+        CollisionPolicy::Overwrite => Some(candidate),
+        CollisionPolicy::Skip => None,
+        CollisionPolicy::Unique => (1..)
+            .map(|n| dir.join(format!("{stem}-{n}.{ext}")))
+            .find(|path| !path.exists()),
+    }
+}
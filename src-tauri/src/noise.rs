@@ -0,0 +1,217 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+
+use image::imageops;
+use image::RgbaImage;
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+
+use crate::cache::data_root;
+use crate::metadata::read_metadata;
+use crate::models::{EditRecipe, GlobalAdjustments, NoiseProfile};
+use crate::settings::current_settings;
+use crate::shutdown::write_atomic;
+
+// Measured noise (display-referred 0..1 units) that maps to a slider at 100.
+// Luma matches the regulariser in noise_reduction_params (strength * 0.08).
+const LUMA_NOISE_AT_FULL: f32 = 0.08;
+const CHROMA_NOISE_AT_FULL: f32 = 0.04;
+// Seeded values below this are left at 0; clean files stay untouched.
+const MIN_SEEDED_STRENGTH: f32 = 5.0;
+// Noise is measured on a centred tile this many pixels across at full
+// resolution: scaling down would average the noise away, and a tile keeps the
+// cost flat whatever the sensor.
+const NOISE_TILE: u32 = 1024;
+// A new measurement counts at least 1 / this much towards a profile, so the
+// average keeps following the camera (firmware, sensor ageing) however many
+// images it has seen.
+const MAX_AVERAGED_SAMPLES: u32 = 20;
+// An ISO this many stops past the measured range is not guessed at.
+const MAX_EXTRAPOLATED_STOPS: f32 = 1.0;
+
+// Serializes read-modify-write of the database file.
+static PROFILES_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn profiles_path() -> Result<PathBuf, String> {
+    Ok(data_root()?.join("noise_profiles.json"))
+}
+
+fn load_profiles() -> Result<Vec<NoiseProfile>, String> {
+    let path = profiles_path()?;
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let data = fs::read_to_string(&path).map_err(|e| format!("Read noise profiles failed: {e}"))?;
+    serde_json::from_str(&data).map_err(|e| format!("Parse noise profiles failed: {e}"))
+}
+
+// EXIF model strings come back quoted and sometimes padded.
+fn camera_and_iso(asset_path: &Path) -> Option<(String, u32)> {
+    let meta = read_metadata(asset_path).ok()?;
+    let camera = meta
+        .camera?
+        .trim_matches(|c: char| c == '"' || c.is_whitespace())
+        .to_string();
+    let iso: String = meta
+        .iso?
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    let iso = iso.parse().ok().filter(|iso: &u32| *iso > 0)?;
+    (!camera.is_empty()).then_some((camera, iso))
+}
+
+// Immerkaer's estimator: a kernel that cancels edges and gradients to first order,
+// so what is left in flat and textured areas alike is the noise.
+fn estimate_sigma(values: &[f32], w: usize, h: usize) -> f32 {
+    if w < 3 || h < 3 {
+        return 0.0;
+    }
+    let sum: f32 = (1..h - 1)
+        .into_par_iter()
+        .map(|y| {
+            let at = |x: usize, y: usize| values[y * w + x];
+            (1..w - 1)
+                .map(|x| {
+                    let corners =
+                        at(x - 1, y - 1) + at(x + 1, y - 1) + at(x - 1, y + 1) + at(x + 1, y + 1);
+                    let edges = at(x, y - 1) + at(x - 1, y) + at(x + 1, y) + at(x, y + 1);
+                    (corners - 2.0 * edges + 4.0 * at(x, y)).abs()
+                })
+                .sum::<f32>()
+        })
+        .sum();
+    sum * (std::f32::consts::FRAC_PI_2).sqrt() / (6.0 * (w - 2) as f32 * (h - 2) as f32)
+}
+
+// Luma and chroma noise of a decoded, unedited image.
+fn measure_noise(img: &RgbaImage) -> (f32, f32) {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let mut luma = Vec::with_capacity(w * h);
+    let mut red = Vec::with_capacity(w * h);
+    let mut blue = Vec::with_capacity(w * h);
+    for px in img.as_raw().chunks_exact(4) {
+        let [r, g, b] = [0, 1, 2].map(|i| px[i] as f32 / 255.0);
+        let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+        luma.push(y);
+        red.push(r - y);
+        blue.push(b - y);
+    }
+    let chroma = 0.5 * (estimate_sigma(&red, w, h) + estimate_sigma(&blue, w, h));
+    (estimate_sigma(&luma, w, h), chroma)
+}
+
+fn learn_noise(camera: String, iso: u32, tile: &RgbaImage) -> Result<(), String> {
+    let (luma, chroma) = measure_noise(tile);
+    let _guard = PROFILES_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut profiles = load_profiles()?;
+    match profiles
+        .iter_mut()
+        .find(|p| p.camera == camera && p.iso == iso)
+    {
+        Some(profile) => {
+            profile.samples = profile.samples.saturating_add(1);
+            let weight = 1.0 / profile.samples.min(MAX_AVERAGED_SAMPLES) as f32;
+            profile.luma += (luma - profile.luma) * weight;
+            profile.chroma += (chroma - profile.chroma) * weight;
+        }
+        None => profiles.push(NoiseProfile {
+            camera,
+            iso,
+            luma,
+            chroma,
+            samples: 1,
+        }),
+    }
+    profiles.sort_by(|a, b| a.camera.cmp(&b.camera).then(a.iso.cmp(&b.iso)));
+    let serialized = serde_json::to_vec_pretty(&profiles)
+        .map_err(|e| format!("Serialize noise profiles failed: {e}"))?;
+    write_atomic(&profiles_path()?, serialized)
+        .map_err(|e| format!("Write noise profiles failed: {e}"))
+}
+
+/// Fold the noise of a freshly decoded original into the running average for its
+/// camera at its ISO. Measured on a full-resolution tile and stored on a thread
+/// of its own; files without a camera model or ISO are ignored.
+pub fn record_noise(asset_path: &Path, img: &RgbaImage) {
+    let Some((camera, iso)) = camera_and_iso(asset_path) else {
+        return;
+    };
+    let (w, h) = img.dimensions();
+    let (tw, th) = (w.min(NOISE_TILE), h.min(NOISE_TILE));
+    let tile = imageops::crop_imm(img, (w - tw) / 2, (h - th) / 2, tw, th).to_image();
+    thread::spawn(move || {
+        // a profile is a convenience for later files; failing to learn one costs nothing now
+        let _ = learn_noise(camera, iso, &tile);
+    });
+}
+
+// Noise at `iso`, interpolated in stops between the nearest measured ISOs.
+fn noise_at(profiles: &[NoiseProfile], camera: &str, iso: u32) -> Option<(f32, f32)> {
+    let mut points: Vec<&NoiseProfile> = profiles.iter().filter(|p| p.camera == camera).collect();
+    points.sort_by_key(|p| p.iso);
+    let stops = |iso: u32| (iso as f32).log2();
+    let (first, last) = (points.first()?, points.last()?);
+    if iso <= first.iso {
+        return (stops(first.iso) - stops(iso) <= MAX_EXTRAPOLATED_STOPS)
+            .then_some((first.luma, first.chroma));
+    }
+    if iso >= last.iso {
+        return (stops(iso) - stops(last.iso) <= MAX_EXTRAPOLATED_STOPS)
+            .then_some((last.luma, last.chroma));
+    }
+    let pair = points.windows(2).find(|pair| iso <= pair[1].iso)?;
+    let (lo, hi) = (pair[0], pair[1]);
+    let t = (stops(iso) - stops(lo.iso)) / (stops(hi.iso) - stops(lo.iso)).max(1e-3);
+    Some((
+        lo.luma + (hi.luma - lo.luma) * t,
+        lo.chroma + (hi.chroma - lo.chroma) * t,
+    ))
+}
+
+/// Set the noise reduction sliders from what earlier images of the same camera
+/// at this ISO measured. Returns false (leaving `globals` alone) when there is no
+/// profile close enough or the file is clean.
+pub fn seed_noise_reduction(globals: &mut GlobalAdjustments, asset_path: &Path) -> bool {
+    let Some((camera, iso)) = camera_and_iso(asset_path) else {
+        return false;
+    };
+    let Ok(profiles) = load_profiles() else {
+        return false;
+    };
+    let Some((luma, chroma)) = noise_at(&profiles, &camera, iso) else {
+        return false;
+    };
+    let strength = |noise: f32, at_full: f32| {
+        let value = (noise / at_full * 100.0).clamp(0.0, 100.0).round();
+        if value < MIN_SEEDED_STRENGTH {
+            0.0
+        } else {
+            value
+        }
+    };
+    let (nr_luminance, nr_color) = (
+        strength(luma, LUMA_NOISE_AT_FULL),
+        strength(chroma, CHROMA_NOISE_AT_FULL),
+    );
+    if nr_luminance == 0.0 && nr_color == 0.0 {
+        return false;
+    }
+    globals.nr_luminance = nr_luminance;
+    globals.nr_color = nr_color;
+    true
+}
+
+/// The recipe an asset without a sidecar opens (and exports) with: noise
+/// reduction from its camera's profile. None when there is nothing to seed or
+/// seeding is switched off.
+pub fn seeded_recipe(asset_path: &Path) -> Option<EditRecipe> {
+    if current_settings().skip_noise_defaults {
+        return None;
+    }
+    let mut seeded = EditRecipe::default();
+    seed_noise_reduction(&mut seeded.globals, asset_path).then_some(seeded)
+}
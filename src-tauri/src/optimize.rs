@@ -0,0 +1,194 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::UNIX_EPOCH;
+
+use dashmap::DashMap;
+use image::RgbaImage;
+use once_cell::sync::Lazy;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+use crate::catalog::{catalog_key, update_catalog};
+use crate::image_io::load_or_create_thumbnail;
+use crate::metadata::read_metadata;
+use crate::models::{DominantColor, IssueSeverity, Metadata, OptimizeProgress, OptimizeSummary};
+use crate::palette::dominant_colors;
+use crate::recipe_io::{load_recipe_for_asset, validate_recipe};
+use crate::shutdown::{begin_job, stopping};
+
+const OPTIMIZE_PROGRESS_EVENT: &str = "optimize-progress";
+const OPTIMIZE_FINISHED_EVENT: &str = "optimize-finished";
+// Facts are written to the catalog in batches rather than one rewrite per file.
+const CATALOG_BATCH: usize = 25;
+
+// Passes run one at a time; later requests wait on this in a thread of their own.
+static RUN_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+// Cancel flags of queued and running passes, by job id.
+static JOBS: Lazy<DashMap<String, Arc<AtomicBool>>> = Lazy::new(DashMap::new);
+
+struct Facts {
+    key: String,
+    colors: Vec<DominantColor>,
+    exif: Option<Metadata>,
+    modified: u64,
+    sharpness: f32,
+}
+
+// Variance of the 4-neighbour Laplacian of luma (0..1): blur and missed focus
+// flatten it.
+fn sharpness(img: &RgbaImage) -> f32 {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    if w < 3 || h < 3 {
+        return 0.0;
+    }
+    let luma: Vec<f32> = img
+        .pixels()
+        .map(|px| (0.2126 * px[0] as f32 + 0.7152 * px[1] as f32 + 0.0722 * px[2] as f32) / 255.0)
+        .collect();
+    let mut sum = 0.0f64;
+    let mut sum_sq = 0.0f64;
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let at = |x: usize, y: usize| luma[y * w + x];
+            let lap =
+                (at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y)) as f64;
+            sum += lap;
+            sum_sq += lap * lap;
+        }
+    }
+    let n = ((w - 2) * (h - 2)) as f64;
+    let mean = sum / n;
+    (sum_sq / n - mean * mean).max(0.0) as f32
+}
+
+// A sidecar that fails to parse, or that saving would refuse.
+fn sidecar_ok(path: &Path) -> bool {
+    match load_recipe_for_asset(path) {
+        Ok(Some(recipe)) => validate_recipe(&recipe)
+            .iter()
+            .all(|issue| issue.severity != IssueSeverity::Error),
+        Ok(None) => true,
+        Err(_) => false,
+    }
+}
+
+// Fills the thumbnail cache as a side effect.
+fn analyse(path: &Path) -> Result<Facts, String> {
+    let thumbnail = image::load_from_memory(&load_or_create_thumbnail(path)?)
+        .map_err(|e| format!("Failed to decode thumbnail: {e}"))?
+        .to_rgba8();
+    let modified = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    Ok(Facts {
+        key: catalog_key(path),
+        colors: dominant_colors(&thumbnail),
+        exif: read_metadata(path).ok(),
+        modified,
+        sharpness: sharpness(&thumbnail),
+    })
+}
+
+fn flush(pending: &mut Vec<Facts>) -> Result<(), String> {
+    if pending.is_empty() {
+        return Ok(());
+    }
+    let batch = std::mem::take(pending);
+    update_catalog(|catalog| {
+        for facts in batch {
+            let entry = catalog.assets.entry(facts.key).or_default();
+            entry.colors = facts.colors;
+            entry.exif = facts.exif;
+            entry.exif_modified = facts.modified;
+            entry.sharpness = Some(facts.sharpness);
+        }
+    })
+}
+
+fn run(
+    app: &AppHandle,
+    job_id: &str,
+    paths: &[PathBuf],
+    cancel: &AtomicBool,
+) -> Result<OptimizeSummary, String> {
+    let _turn = RUN_LOCK.lock().map_err(|e| e.to_string())?;
+    let _job_guard = begin_job()?;
+    let mut summary = OptimizeSummary {
+        job_id: job_id.to_string(),
+        ..OptimizeSummary::default()
+    };
+    let mut pending = Vec::new();
+    for (idx, path) in paths.iter().enumerate() {
+        if stopping() || cancel.load(Ordering::Relaxed) {
+            summary.cancelled = true;
+            break;
+        }
+        let error = match analyse(path) {
+            Ok(facts) => {
+                pending.push(facts);
+                summary.processed += 1;
+                None
+            }
+            Err(err) => {
+                summary.failed += 1;
+                Some(err)
+            }
+        };
+        if !sidecar_ok(path) {
+            summary
+                .bad_sidecars
+                .push(path.to_string_lossy().to_string());
+        }
+        if pending.len() >= CATALOG_BATCH {
+            flush(&mut pending)?;
+        }
+        let _ = app.emit(
+            OPTIMIZE_PROGRESS_EVENT,
+            OptimizeProgress {
+                job_id: job_id.to_string(),
+                path: path.to_string_lossy().to_string(),
+                done: idx + 1,
+                total: paths.len(),
+                error,
+            },
+        );
+    }
+    // what was gathered before a cancel is kept
+    flush(&mut pending)?;
+    Ok(summary)
+}
+
+/// Queue one background pass over `paths` that fills missing thumbnails and
+/// stores EXIF, sharpness scores and dominant colours in the catalog, and
+/// checks every sidecar. Returns the job id at once; progress and the summary
+/// arrive as events.
+pub fn queue_optimize(app: AppHandle, paths: Vec<PathBuf>) -> String {
+    let job_id = Uuid::new_v4().to_string();
+    let cancel = Arc::new(AtomicBool::new(false));
+    JOBS.insert(job_id.clone(), cancel.clone());
+    let id = job_id.clone();
+    thread::spawn(move || {
+        let result = run(&app, &id, &paths, &cancel);
+        JOBS.remove(&id);
+        let summary = result.unwrap_or_else(|err| OptimizeSummary {
+            job_id: id,
+            error: Some(err),
+            ..OptimizeSummary::default()
+        });
+        let _ = app.emit(OPTIMIZE_FINISHED_EVENT, summary);
+    });
+    job_id
+}
+
+/// Stop a queued or running pass after the asset in hand. Unknown ids (passes
+/// that already finished) are ignored.
+pub fn cancel_optimize(job_id: &str) {
+    if let Some(cancel) = JOBS.get(job_id) {
+        cancel.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+use image::RgbaImage;
+use rayon::prelude::*;
+
+use crate::catalog::{catalog_key, load_catalog, update_catalog};
+use crate::image_io::load_or_create_thumbnail;
+use crate::models::{DominantColor, HueRange};
+
+const PALETTE_SIZE: usize = 5;
+const KMEANS_ITERATIONS: usize = 8;
+// at most this many pixels feed the clustering; thumbnails are small already
+const MAX_SAMPLES: usize = 16_384;
+// clusters covering less of the frame than this are noise, not a colour of the shot
+const MIN_WEIGHT: f32 = 0.03;
+// greys carry no hue worth filtering on
+const MIN_FILTER_SATURATION: f32 = 0.15;
+const MIN_FILTER_WEIGHT: f32 = 0.1;
+
+fn hue_saturation(rgb: [f32; 3]) -> (f32, f32) {
+    let max = rgb[0].max(rgb[1]).max(rgb[2]);
+    let min = rgb[0].min(rgb[1]).min(rgb[2]);
+    let delta = max - min;
+    if delta <= 1e-6 {
+        return (0.0, 0.0);
+    }
+    let hue = if max == rgb[0] {
+        60.0 * ((rgb[1] - rgb[2]) / delta).rem_euclid(6.0)
+    } else if max == rgb[1] {
+        60.0 * ((rgb[2] - rgb[0]) / delta + 2.0)
+    } else {
+        60.0 * ((rgb[0] - rgb[1]) / delta + 4.0)
+    };
+    (hue, delta / max)
+}
+
+fn distance2(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (0..3).map(|c| (a[c] - b[c]) * (a[c] - b[c])).sum()
+}
+
+/// Up to five dominant colours of `img`, largest share first. K-means in sRGB
+/// seeded from luma-sorted samples, so the same image always gives the same palette.
+pub fn dominant_colors(img: &RgbaImage) -> Vec<DominantColor> {
+    let pixels = img.as_raw().len() / 4;
+    let stride = (pixels / MAX_SAMPLES).max(1);
+    let mut samples: Vec<[f32; 3]> = img
+        .as_raw()
+        .chunks_exact(4)
+        .step_by(stride)
+        .filter(|px| px[3] > 0)
+        .map(|px| [px[0] as f32, px[1] as f32, px[2] as f32].map(|v| v / 255.0))
+        .collect();
+    if samples.len() < PALETTE_SIZE {
+        return Vec::new();
+    }
+    samples.sort_by(|a, b| {
+        let luma = |p: &[f32; 3]| 0.2126 * p[0] + 0.7152 * p[1] + 0.0722 * p[2];
+        luma(a).total_cmp(&luma(b))
+    });
+    let mut centers: Vec<[f32; 3]> = (0..PALETTE_SIZE)
+        .map(|i| samples[(2 * i + 1) * samples.len() / (2 * PALETTE_SIZE)])
+        .collect();
+
+    let mut counts = [0usize; PALETTE_SIZE];
+    for _ in 0..KMEANS_ITERATIONS {
+        let assignment: Vec<usize> = samples
+            .par_iter()
+            .map(|p| {
+                (0..PALETTE_SIZE)
+                    .min_by(|&a, &b| {
+                        distance2(p, &centers[a]).total_cmp(&distance2(p, &centers[b]))
+                    })
+                    .unwrap_or(0)
+            })
+            .collect();
+        let mut sums = [[0.0f32; 3]; PALETTE_SIZE];
+        counts = [0usize; PALETTE_SIZE];
+        for (p, &k) in samples.iter().zip(&assignment) {
+            for c in 0..3 {
+                sums[k][c] += p[c];
+            }
+            counts[k] += 1;
+        }
+        for k in 0..PALETTE_SIZE {
+            if counts[k] > 0 {
+                centers[k] = sums[k].map(|v| v / counts[k] as f32);
+            }
+        }
+    }
+
+    let total = samples.len() as f32;
+    let mut palette: Vec<DominantColor> = centers
+        .iter()
+        .zip(&counts)
+        .map(|(center, &count)| {
+            let (hue, saturation) = hue_saturation(*center);
+            DominantColor {
+                rgb: center.map(|v| (v * 255.0).round() as u8),
+                hue,
+                saturation,
+                weight: count as f32 / total,
+            }
+        })
+        .filter(|color| color.weight >= MIN_WEIGHT)
+        .collect();
+    palette.sort_by(|a, b| b.weight.total_cmp(&a.weight));
+    palette
+}
+
+// Palette of the asset's cached thumbnail, making the thumbnail first if needed.
+fn thumbnail_palette(path: &Path) -> Result<Vec<DominantColor>, String> {
+    let thumb = image::load_from_memory(&load_or_create_thumbnail(path)?)
+        .map_err(|e| format!("Failed to decode thumbnail: {e}"))?
+        .to_rgba8();
+    Ok(dominant_colors(&thumb))
+}
+
+// Start and end in degrees; a start past the end wraps through red (e.g. 330..30).
+fn hue_in_range(hue: f32, range: &HueRange) -> bool {
+    let (start, end) = (range.start.rem_euclid(360.0), range.end.rem_euclid(360.0));
+    if start <= end {
+        (start..=end).contains(&hue)
+    } else {
+        hue >= start || hue <= end
+    }
+}
+
+/// Ids of the assets with a saturated dominant colour inside `range`. Assets
+/// without a palette in the catalog are analysed on the way and recorded in one
+/// catalog write; one whose thumbnail cannot be made is left out rather than
+/// failing the filter.
+pub fn filter_by_color(
+    assets: &[(String, PathBuf)],
+    range: &HueRange,
+) -> Result<Vec<String>, String> {
+    let catalog = load_catalog()?;
+    let matches = |colors: &[DominantColor]| {
+        colors.iter().any(|color| {
+            color.saturation >= MIN_FILTER_SATURATION
+                && color.weight >= MIN_FILTER_WEIGHT
+                && hue_in_range(color.hue, range)
+        })
+    };
+    let mut found = Vec::new();
+    let mut analysed = Vec::new();
+    for (id, path) in assets {
+        let key = catalog_key(path);
+        let stored = catalog
+            .assets
+            .get(&key)
+            .map(|entry| entry.colors.clone())
+            .filter(|colors| !colors.is_empty());
+        let colors = match stored {
+            Some(colors) => colors,
+            None => {
+                let Ok(colors) = thumbnail_palette(path) else {
+                    continue;
+                };
+                analysed.push((key, colors.clone()));
+                colors
+            }
+        };
+        if matches(&colors) {
+            found.push(id.clone());
+        }
+    }
+    if !analysed.is_empty() {
+        update_catalog(|catalog| {
+            for (key, colors) in analysed {
+                catalog.assets.entry(key).or_default().colors = colors;
+            }
+        })?;
+    }
+    Ok(found)
+}
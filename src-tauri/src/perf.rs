@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+
+use crate::models::PerfMetric;
+
+// Percentiles cover each stage's most recent calls so they follow the current
+// workload; the count covers the session.
+const RECENT_CALLS: usize = 256;
+
+#[derive(Default)]
+struct StageTimes {
+    count: u64,
+    recent_ms: VecDeque<f32>,
+}
+
+static STAGES: Lazy<DashMap<&'static str, StageTimes>> = Lazy::new(DashMap::new);
+
+fn record(stage: &'static str, elapsed: Duration) {
+    let mut times = STAGES.entry(stage).or_default();
+    times.count += 1;
+    if times.recent_ms.len() == RECENT_CALLS {
+        times.recent_ms.pop_front();
+    }
+    times.recent_ms.push_back(elapsed.as_secs_f32() * 1000.0);
+}
+
+/// Records the time from `start` until it is dropped under `stage`, so a
+/// function can time itself across every return: `let _timer = Timer::start(..)`.
+pub struct Timer {
+    stage: &'static str,
+    start: Instant,
+}
+
+impl Timer {
+    pub fn start(stage: &'static str) -> Self {
+        Self {
+            stage,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        record(self.stage, self.start.elapsed());
+    }
+}
+
+// Nearest-rank percentile of sorted, non-empty samples.
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    let rank = (p * sorted.len() as f32).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Counts and recent p50/p95 durations of every stage timed so far, by name.
+pub fn metrics() -> Vec<PerfMetric> {
+    let mut metrics: Vec<PerfMetric> = STAGES
+        .iter()
+        .filter(|entry| !entry.recent_ms.is_empty())
+        .map(|entry| {
+            let mut sorted: Vec<f32> = entry.recent_ms.iter().copied().collect();
+            sorted.sort_by(f32::total_cmp);
+            PerfMetric {
+                stage: entry.key().to_string(),
+                count: entry.count,
+                p50_ms: percentile(&sorted, 0.5),
+                p95_ms: percentile(&sorted, 0.95),
+                last_ms: entry.recent_ms.back().copied().unwrap_or_default(),
+            }
+        })
+        .collect();
+    metrics.sort_by(|a, b| a.stage.cmp(&b.stage));
+    metrics
+}
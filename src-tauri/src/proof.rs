@@ -0,0 +1,175 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use dashmap::DashMap;
+use image::RgbaImage;
+use moxcms::{
+    ColorProfile, DataColorSpace, Layout, RenderingIntent, Transform8BitExecutor, TransformOptions,
+};
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+
+use crate::models::{ProofIntent, SoftProof};
+use crate::state::ensure_color_file_allowed;
+
+// Photoshop's default gamut warning colour.
+const GAMUT_WARNING_RGB: [u8; 3] = [128, 128, 128];
+// A colorimetric round trip that moves any channel further than this is out of gamut.
+const GAMUT_TOLERANCE: u8 = 6;
+const PROOF_ROWS_PER_TASK: usize = 32;
+
+struct ProofTransforms {
+    // sRGB -> proof space with the chosen intent, and back for display
+    forward: Box<Transform8BitExecutor>,
+    back: Box<Transform8BitExecutor>,
+    // relative colorimetric forward leg for the gamut check; None when the
+    // chosen intent already is relative colorimetric
+    check: Option<Box<Transform8BitExecutor>>,
+    channels: usize,
+}
+
+type ProofKey = (PathBuf, ProofIntent);
+
+static PROOF_CACHE: Lazy<DashMap<ProofKey, (SystemTime, Arc<ProofTransforms>)>> =
+    Lazy::new(DashMap::new);
+
+fn rendering_intent(intent: ProofIntent) -> RenderingIntent {
+    match intent {
+        ProofIntent::Perceptual => RenderingIntent::Perceptual,
+        ProofIntent::RelativeColorimetric => RenderingIntent::RelativeColorimetric,
+        ProofIntent::Saturation => RenderingIntent::Saturation,
+        ProofIntent::AbsoluteColorimetric => RenderingIntent::AbsoluteColorimetric,
+    }
+}
+
+fn build_transforms(path: &Path, intent: ProofIntent) -> Result<ProofTransforms, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Read proof profile failed: {e}"))?;
+    let profile = ColorProfile::new_from_slice(&bytes)
+        .map_err(|e| format!("Parse proof profile failed: {e}"))?;
+    // CMYK data travels in the four-channel layout
+    let (layout, channels) = match profile.color_space {
+        DataColorSpace::Cmyk => (Layout::Rgba, 4),
+        DataColorSpace::Rgb => (Layout::Rgb, 3),
+        DataColorSpace::Gray => (Layout::Gray, 1),
+        other => return Err(format!("Unsupported proof profile colour space: {other:?}")),
+    };
+    let srgb = ColorProfile::new_srgb();
+    let options = |intent: RenderingIntent| TransformOptions {
+        rendering_intent: intent,
+        ..TransformOptions::default()
+    };
+    let transform_err = |e: moxcms::CmsError| format!("Create proof transform failed: {e}");
+    let forward = srgb
+        .create_transform_8bit(
+            Layout::Rgb,
+            &profile,
+            layout,
+            options(rendering_intent(intent)),
+        )
+        .map_err(transform_err)?;
+    let back = profile
+        .create_transform_8bit(
+            layout,
+            &srgb,
+            Layout::Rgb,
+            options(RenderingIntent::RelativeColorimetric),
+        )
+        .map_err(transform_err)?;
+    let check = match intent {
+        ProofIntent::RelativeColorimetric => None,
+        _ => Some(
+            srgb.create_transform_8bit(
+                Layout::Rgb,
+                &profile,
+                layout,
+                options(RenderingIntent::RelativeColorimetric),
+            )
+            .map_err(transform_err)?,
+        ),
+    };
+    Ok(ProofTransforms {
+        forward,
+        back,
+        check,
+        channels,
+    })
+}
+
+fn cached_transforms(path: &Path, intent: ProofIntent) -> Result<Arc<ProofTransforms>, String> {
+    let path = ensure_color_file_allowed(path)?;
+    let modified = fs::metadata(&path)
+        .and_then(|m| m.modified())
+        .map_err(|e| format!("Read proof profile failed: {e}"))?;
+    let key = (path, intent);
+    if let Some(hit) = PROOF_CACHE.get(&key) {
+        if hit.0 == modified {
+            return Ok(hit.1.clone());
+        }
+    }
+    let transforms = Arc::new(build_transforms(&key.0, intent)?);
+    PROOF_CACHE.insert(key, (modified, transforms.clone()));
+    Ok(transforms)
+}
+
+fn round_trip(
+    forward: &Transform8BitExecutor,
+    back: &Transform8BitExecutor,
+    rgb: &[u8],
+    channels: usize,
+) -> Result<Vec<u8>, String> {
+    let mut proofed = vec![0u8; rgb.len() / 3 * channels];
+    let mut out = vec![0u8; rgb.len()];
+    forward
+        .transform(rgb, &mut proofed)
+        .and_then(|_| back.transform(&proofed, &mut out))
+        .map_err(|e| format!("Soft proof failed: {e}"))?;
+    Ok(out)
+}
+
+/// Replace the display-referred sRGB preview by how it reproduces through the
+/// proof profile: out with the chosen intent, back relative colorimetric. With
+/// the gamut warning on, pixels a colorimetric round trip cannot hold are
+/// painted grey.
+pub fn apply_soft_proof(img: &mut RgbaImage, proof: &SoftProof) -> Result<(), String> {
+    let transforms = cached_transforms(Path::new(&proof.profile_path), proof.intent)?;
+    let row_bytes = img.width() as usize * 4;
+    if row_bytes == 0 {
+        return Ok(());
+    }
+    img.par_chunks_mut(row_bytes * PROOF_ROWS_PER_TASK)
+        .try_for_each(|rows| {
+            let rgb: Vec<u8> = rows
+                .chunks_exact(4)
+                .flat_map(|px| [px[0], px[1], px[2]])
+                .collect();
+            let t = &transforms;
+            let shown = round_trip(t.forward.as_ref(), t.back.as_ref(), &rgb, t.channels)?;
+            let out_of_gamut = if !proof.gamut_warning {
+                None
+            } else {
+                match &t.check {
+                    Some(check) => Some(round_trip(
+                        check.as_ref(),
+                        t.back.as_ref(),
+                        &rgb,
+                        t.channels,
+                    )?),
+                    None => Some(shown.clone()),
+                }
+            };
+            for (i, px) in rows.chunks_exact_mut(4).enumerate() {
+                let warn = out_of_gamut.as_ref().is_some_and(|held| {
+                    (0..3).any(|c| rgb[i * 3 + c].abs_diff(held[i * 3 + c]) > GAMUT_TOLERANCE)
+                });
+                let color = if warn {
+                    GAMUT_WARNING_RGB
+                } else {
+                    [shown[i * 3], shown[i * 3 + 1], shown[i * 3 + 2]]
+                };
+                px[..3].copy_from_slice(&color);
+            }
+            Ok(())
+        })
+}
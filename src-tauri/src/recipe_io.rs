@@ -1,9 +1,292 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
-use crate::models::EditRecipe;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde_json::Value;
 
-fn sidecar_path(asset_path: &Path) -> PathBuf {
+use crate::catalog::store_user_fields;
+use crate::mask::{prune_brush_bitmaps, BRUSH_MASK, DEPTH_MASK, LUMINANCE_MASK};
+use crate::models::{
+    AssetFlags, BatchEditSummary, EditRecipe, GlobalAdjustments, IlluminantBlend, IssueSeverity,
+    Mask, RecipeChange, RecipeIssue,
+};
+use crate::shutdown::write_atomic;
+
+// newest recipe layout this build understands
+const RECIPE_VERSION: u8 = 1;
+const MASK_TYPES: &[&str] = &["linear_gradient", BRUSH_MASK, LUMINANCE_MASK, DEPTH_MASK];
+static SIDECAR_LOCKS: Lazy<DashMap<PathBuf, Arc<Mutex<()>>>> = Lazy::new(DashMap::new);
+
+struct Lint {
+    issues: Vec<RecipeIssue>,
+}
+
+impl Lint {
+    fn push(&mut self, field: &str, severity: IssueSeverity, message: String) {
+        self.issues.push(RecipeIssue {
+            field: field.to_string(),
+            severity,
+            message,
+        });
+    }
+
+    // non-finite values are errors; values outside the slider range still render
+    // (they are clamped or extrapolated) so they only warn
+    fn range(&mut self, field: &str, value: f32, min: f32, max: f32) {
+        if !value.is_finite() {
+            self.push(field, IssueSeverity::Error, "Not a finite number".into());
+        } else if value < min || value > max {
+            self.push(
+                field,
+                IssueSeverity::Warning,
+                format!("{value} is outside {min}..{max}"),
+            );
+        }
+    }
+}
+
+// `field` is the mask's path, e.g. "layers[0].mask"; components are checked the same way.
+fn lint_mask(lint: &mut Lint, field: &str, mask: &Mask) {
+    let at = |name: &str| format!("{field}.{name}");
+    if !MASK_TYPES.contains(&mask.mask_type.as_str()) {
+        lint.push(
+            &at("maskType"),
+            IssueSeverity::Error,
+            format!("Unknown mask type \"{}\"", mask.mask_type),
+        );
+    }
+    if mask.mask_type == BRUSH_MASK && mask.bitmap.is_none() {
+        lint.push(
+            &at("bitmap"),
+            IssueSeverity::Warning,
+            "Nothing painted yet".into(),
+        );
+    }
+    lint.range(&at("feather"), mask.feather, 0.0, 1.0);
+    // gradient handles may sit outside the frame, but not at infinity
+    for (name, value) in [
+        ("start.0", mask.start.0),
+        ("start.1", mask.start.1),
+        ("end.0", mask.end.0),
+        ("end.1", mask.end.1),
+    ] {
+        lint.range(&at(name), value, -1.0, 2.0);
+    }
+    lint.range(&at("luminance.0"), mask.luminance.0, 0.0, 1.0);
+    lint.range(&at("luminance.1"), mask.luminance.1, 0.0, 1.0);
+    lint.range(&at("depth.0"), mask.depth.0, 0.0, 1.0);
+    lint.range(&at("depth.1"), mask.depth.1, 0.0, 1.0);
+    for (idx, component) in mask.components.iter().enumerate() {
+        lint_mask(
+            lint,
+            &at(&format!("components[{idx}].mask")),
+            &component.mask,
+        );
+    }
+}
+
+/// Check ranges, non-finite values, mask types and the recipe version. Field
+/// names use the serialized (camelCase) paths.
+pub fn validate_recipe(recipe: &EditRecipe) -> Vec<RecipeIssue> {
+    let mut lint = Lint { issues: Vec::new() };
+    if recipe.version > RECIPE_VERSION {
+        lint.push(
+            "version",
+            IssueSeverity::Error,
+            format!(
+                "Recipe version {} is newer than supported version {RECIPE_VERSION}",
+                recipe.version
+            ),
+        );
+    } else if recipe.version == 0 {
+        lint.push(
+            "version",
+            IssueSeverity::Warning,
+            "Missing recipe version".into(),
+        );
+    }
+
+    let g = &recipe.globals;
+    lint.range("globals.exposureEv", g.exposure_ev, -5.0, 5.0);
+    for (field, value) in [
+        ("globals.contrast", g.contrast),
+        ("globals.highlights", g.highlights),
+        ("globals.shadows", g.shadows),
+        ("globals.whites", g.whites),
+        ("globals.blacks", g.blacks),
+        ("globals.temp", g.temp),
+        ("globals.tint", g.tint),
+        ("globals.vibrance", g.vibrance),
+        ("globals.saturation", g.saturation),
+        ("globals.clarity", g.clarity),
+        ("globals.texture", g.texture),
+        ("globals.dehaze", g.dehaze),
+    ] {
+        lint.range(field, value, -100.0, 100.0);
+    }
+    for (field, value) in [
+        ("globals.nrLuminance", g.nr_luminance),
+        ("globals.nrLuminanceDetail", g.nr_luminance_detail),
+        ("globals.nrColor", g.nr_color),
+    ] {
+        lint.range(field, value, 0.0, 100.0);
+    }
+
+    for (idx, layer) in recipe.layers.iter().enumerate() {
+        let at = |name: &str| format!("layers[{idx}].{name}");
+        lint_mask(&mut lint, &at("mask"), &layer.mask);
+        lint.range(&at("opacity"), layer.opacity, 0.0, 1.0);
+        let adj = &layer.adjustments;
+        lint.range(&at("adjustments.exposureEv"), adj.exposure_ev, -5.0, 5.0);
+        lint.range(&at("adjustments.temp"), adj.temp, -100.0, 100.0);
+        lint.range(&at("adjustments.tint"), adj.tint, -100.0, 100.0);
+        lint.range(&at("adjustments.saturation"), adj.saturation, -100.0, 100.0);
+    }
+
+    for (name, channel) in [
+        ("globals.levels.master", &g.levels.master),
+        ("globals.levels.red", &g.levels.red),
+        ("globals.levels.green", &g.levels.green),
+        ("globals.levels.blue", &g.levels.blue),
+    ] {
+        lint.range(&format!("{name}.inputBlack"), channel.input_black, 0.0, 1.0);
+        lint.range(&format!("{name}.inputWhite"), channel.input_white, 0.0, 1.0);
+        lint.range(&format!("{name}.gamma"), channel.gamma, 0.1, 10.0);
+        lint.range(
+            &format!("{name}.outputBlack"),
+            channel.output_black,
+            0.0,
+            1.0,
+        );
+        lint.range(
+            &format!("{name}.outputWhite"),
+            channel.output_white,
+            0.0,
+            1.0,
+        );
+        if channel.input_black >= channel.input_white {
+            lint.push(
+                &format!("{name}.inputBlack"),
+                IssueSeverity::Warning,
+                "Input black point is not below the white point".into(),
+            );
+        }
+    }
+
+    for (name, points) in [
+        ("curves.master", &recipe.curves.master),
+        ("curves.red", &recipe.curves.red),
+        ("curves.green", &recipe.curves.green),
+        ("curves.blue", &recipe.curves.blue),
+    ] {
+        for (idx, &(x, y)) in points.iter().enumerate() {
+            lint.range(&format!("{name}[{idx}].0"), x, 0.0, 1.0);
+            lint.range(&format!("{name}[{idx}].1"), y, 0.0, 1.0);
+        }
+    }
+
+    lint.range("document.sharpen", recipe.document.sharpen, 0.0, 100.0);
+    lint.range("lens.distortion", recipe.lens.distortion, -100.0, 100.0);
+    lint.range("lens.vignetting", recipe.lens.vignetting, -100.0, 100.0);
+    lint.range("bw.toneStrength", recipe.bw.tone_strength, 0.0, 100.0);
+    lint.range("grain.amount", recipe.grain.amount, 0.0, 100.0);
+    lint.range("grain.size", recipe.grain.size, 0.0, 100.0);
+    lint.range("grain.roughness", recipe.grain.roughness, 0.0, 100.0);
+
+    if let Some(lut) = &recipe.lut {
+        if lut.path.trim().is_empty() {
+            lint.push("lut.path", IssueSeverity::Warning, "No LUT file set".into());
+        }
+        lint.range("lut.strength", lut.strength, 0.0, 100.0);
+    }
+
+    if let Some(crop) = &recipe.crop {
+        lint.range("crop.x", crop.x, 0.0, 1.0);
+        lint.range("crop.y", crop.y, 0.0, 1.0);
+        lint.range("crop.width", crop.width, 0.0, 1.0);
+        lint.range("crop.height", crop.height, 0.0, 1.0);
+        lint.range("crop.angle", crop.angle, -45.0, 45.0);
+        if crop.width <= 0.0 || crop.height <= 0.0 {
+            lint.push("crop", IssueSeverity::Error, "Crop has no area".into());
+        }
+        if let Some(aspect) = crop.aspect {
+            if !aspect.is_finite() || aspect <= 0.0 {
+                lint.push(
+                    "crop.aspect",
+                    IssueSeverity::Error,
+                    "Aspect must be a positive ratio".into(),
+                );
+            }
+        }
+    }
+    let dual = &g.dual_illuminant;
+    lint.range("globals.dualIlluminant.temp", dual.temp, -100.0, 100.0);
+    lint.range("globals.dualIlluminant.tint", dual.tint, -100.0, 100.0);
+    lint.range(
+        "globals.dualIlluminant.luminanceLow",
+        dual.luminance_low,
+        0.0,
+        1.0,
+    );
+    lint.range(
+        "globals.dualIlluminant.luminanceHigh",
+        dual.luminance_high,
+        0.0,
+        1.0,
+    );
+    if dual.blend == IlluminantBlend::Luminance && dual.luminance_low >= dual.luminance_high {
+        lint.push(
+            "globals.dualIlluminant.luminanceHigh",
+            IssueSeverity::Warning,
+            "High luminance must be above low".into(),
+        );
+    }
+    lint_mask(&mut lint, "globals.dualIlluminant.mask", &dual.mask);
+    for (idx, &(x, y)) in recipe.dead_pixels.iter().enumerate() {
+        lint.range(&format!("deadPixels[{idx}].0"), x, 0.0, 1.0);
+        lint.range(&format!("deadPixels[{idx}].1"), y, 0.0, 1.0);
+    }
+    for key in recipe.user_fields.keys() {
+        if key.trim().is_empty() {
+            lint.push(
+                &format!("userFields[{key:?}]"),
+                IssueSeverity::Error,
+                "Field name is empty".into(),
+            );
+        }
+    }
+    for (idx, spot) in recipe.retouch.iter().enumerate() {
+        let at = |name: &str| format!("retouch[{idx}].{name}");
+        lint.range(&at("source.0"), spot.source.0, 0.0, 1.0);
+        lint.range(&at("source.1"), spot.source.1, 0.0, 1.0);
+        lint.range(&at("target.0"), spot.target.0, 0.0, 1.0);
+        lint.range(&at("target.1"), spot.target.1, 0.0, 1.0);
+        lint.range(&at("radius"), spot.radius, 0.0, 0.5);
+        lint.range(&at("feather"), spot.feather, 0.0, 1.0);
+        lint.range(&at("opacity"), spot.opacity, 0.0, 1.0);
+    }
+    lint.issues
+}
+
+/// Reject recipes with validation errors; warnings are let through.
+pub fn ensure_valid_recipe(recipe: &EditRecipe) -> Result<(), String> {
+    let errors: Vec<String> = validate_recipe(recipe)
+        .into_iter()
+        .filter(|issue| issue.severity == IssueSeverity::Error)
+        .map(|issue| format!("{}: {}", issue.field, issue.message))
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Invalid recipe: {}", errors.join("; ")))
+    }
+}
+
+/// Where the recipe of `asset_path` lives: `<stem>.lumen.json` beside it.
+pub fn sidecar_path(asset_path: &Path) -> PathBuf {
     let mut file_name = asset_path
         .file_stem()
         .map(|s| s.to_string_lossy().to_string())
@@ -15,11 +298,152 @@ fn sidecar_path(asset_path: &Path) -> PathBuf {
         .unwrap_or_else(|| PathBuf::from(file_name))
 }
 
-pub fn save_recipe_for_asset(asset_path: &Path, recipe: &EditRecipe) -> Result<(), String> {
+// Run `f` holding the asset's sidecar lock. Every read-modify-write of a sidecar
+// goes through here, so two writers to one asset cannot drop each other's change.
+// Not reentrant: `f` must use the unlocked helpers.
+fn with_sidecar_lock<T>(asset_path: &Path, f: impl FnOnce() -> T) -> T {
+    let lock = SIDECAR_LOCKS
+        .entry(sidecar_path(asset_path))
+        .or_default()
+        .clone();
+    let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+    f()
+}
+
+fn write_sidecar(asset_path: &Path, recipe: &EditRecipe) -> Result<(), String> {
+    ensure_valid_recipe(recipe)?;
     let path = sidecar_path(asset_path);
     let serialized = serde_json::to_string_pretty(recipe)
         .map_err(|e| format!("Serialize recipe failed: {e}"))?;
-    fs::write(&path, serialized).map_err(|e| format!("Write sidecar failed: {e}"))
+    write_atomic(&path, serialized).map_err(|e| format!("Write sidecar failed: {e}"))
+}
+
+// The flags and user fields of a stored sidecar, read field by field so a recipe
+// this build cannot parse does not hide them. None when there is no sidecar or it
+// is not JSON at all, in which case nothing marks the asset locked.
+fn stored_bookkeeping(asset_path: &Path) -> Option<(AssetFlags, BTreeMap<String, String>)> {
+    let data = fs::read_to_string(sidecar_path(asset_path)).ok()?;
+    let stored: Value = serde_json::from_str(&data).ok()?;
+    let field = |key: &str| stored.get(key).cloned().unwrap_or(Value::Null);
+    Some((
+        serde_json::from_value(field("flags")).unwrap_or_default(),
+        serde_json::from_value(field("userFields")).unwrap_or_default(),
+    ))
+}
+
+/// Save a recipe, keeping the flags and user fields already stored for the asset
+/// (whatever the recipe says); a first sidecar takes the recipe's own, so moved
+/// and imported recipes keep theirs. Refused while the asset is locked; a
+/// damaged sidecar is overwritten.
+pub fn save_recipe_for_asset(asset_path: &Path, recipe: &EditRecipe) -> Result<(), String> {
+    with_sidecar_lock(asset_path, || store_recipe(asset_path, recipe))
+}
+
+// save_recipe_for_asset for a caller already holding the sidecar lock.
+fn store_recipe(asset_path: &Path, recipe: &EditRecipe) -> Result<(), String> {
+    let (flags, user_fields) = stored_bookkeeping(asset_path)
+        .unwrap_or_else(|| (recipe.flags, recipe.user_fields.clone()));
+    if flags.locked {
+        return Err(format!("{} is locked", asset_path.display()));
+    }
+    write_sidecar(
+        asset_path,
+        &EditRecipe {
+            flags,
+            user_fields,
+            ..recipe.clone()
+        },
+    )?;
+    prune_brush_bitmaps(asset_path, recipe);
+    Ok(())
+}
+
+/// Write `recipe` as given, flags and user fields included, over whatever the
+/// sidecar holds. For copies the app keeps in step itself, such as proxies.
+pub fn replace_recipe_for_asset(asset_path: &Path, recipe: &EditRecipe) -> Result<(), String> {
+    with_sidecar_lock(asset_path, || write_sidecar(asset_path, recipe))?;
+    prune_brush_bitmaps(asset_path, recipe);
+    Ok(())
+}
+
+/// Whether the asset's sidecar marks it read-only. Batches skip such assets.
+pub fn is_locked(asset_path: &Path) -> bool {
+    stored_bookkeeping(asset_path).is_some_and(|(flags, _)| flags.locked)
+}
+
+/// Write the default develop settings into each asset's recipe, leaving out
+/// assets that opted out of them or are locked.
+pub fn apply_default_develop(
+    paths: &[PathBuf],
+    defaults: &GlobalAdjustments,
+) -> Result<BatchEditSummary, String> {
+    let mut summary = BatchEditSummary::default();
+    for path in paths {
+        with_sidecar_lock(path, || {
+            let flags = stored_bookkeeping(path)
+                .map(|(flags, _)| flags)
+                .unwrap_or_default();
+            if flags.locked {
+                summary.locked.push(path.to_string_lossy().to_string());
+                return Ok(());
+            }
+            if flags.skip_default_preset {
+                summary.opted_out.push(path.to_string_lossy().to_string());
+                return Ok(());
+            }
+            let mut recipe = load_recipe_for_asset(path)
+                .ok()
+                .flatten()
+                .unwrap_or_default();
+            recipe.globals = defaults.clone();
+            store_recipe(path, &recipe)?;
+            summary.applied += 1;
+            Ok::<_, String>(())
+        })?;
+    }
+    Ok(summary)
+}
+
+/// Replace the asset's flags, creating a default recipe if it has none. Unlike a
+/// save, this goes through while the asset is locked, as do user fields.
+pub fn set_flags_for_asset(asset_path: &Path, flags: AssetFlags) -> Result<EditRecipe, String> {
+    with_sidecar_lock(asset_path, || {
+        let mut recipe = load_recipe_for_asset(asset_path)?.unwrap_or_default();
+        recipe.flags = flags;
+        write_sidecar(asset_path, &recipe)?;
+        Ok(recipe)
+    })
+}
+
+/// Set (Some) or remove (None) user fields on every asset, creating a default
+/// recipe where there is none. Bookkeeping rather than an edit, so it goes
+/// through while an asset is locked. The catalog's copy of the fields is refreshed for every
+/// sidecar written, so filters see the change even within the same second.
+pub fn set_user_fields_for_assets(
+    asset_paths: &[PathBuf],
+    changes: &BTreeMap<String, Option<String>>,
+) -> Result<(), String> {
+    if changes.keys().any(|key| key.trim().is_empty()) {
+        return Err("Field name is empty".into());
+    }
+    let mut written = Vec::new();
+    let result = asset_paths.iter().try_for_each(|asset_path| {
+        let recipe = with_sidecar_lock(asset_path, || {
+            let mut recipe = load_recipe_for_asset(asset_path)?.unwrap_or_default();
+            for (key, value) in changes {
+                match value {
+                    Some(value) => recipe.user_fields.insert(key.clone(), value.clone()),
+                    None => recipe.user_fields.remove(key),
+                };
+            }
+            write_sidecar(asset_path, &recipe)?;
+            Ok::<_, String>(recipe)
+        })?;
+        written.push((asset_path.clone(), recipe.user_fields));
+        Ok::<_, String>(())
+    });
+    store_user_fields(written)?;
+    result
 }
 
 pub fn load_recipe_for_asset(asset_path: &Path) -> Result<Option<EditRecipe>, String> {
@@ -32,3 +456,97 @@ pub fn load_recipe_for_asset(asset_path: &Path) -> Result<Option<EditRecipe>, St
         serde_json::from_str(&data).map_err(|e| format!("Parse sidecar failed: {e}"))?;
     Ok(Some(recipe))
 }
+
+// RFC 7396: objects merge recursively, null deletes a key, anything else replaces.
+fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(entries) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = Value::Object(Default::default());
+    }
+    if let Value::Object(map) = target {
+        for (key, value) in entries {
+            if value.is_null() {
+                map.remove(key);
+            } else {
+                merge_patch(map.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+// Objects recurse by key and arrays of objects (layers) by index when the
+// lengths match; anything else that differs is reported whole.
+fn collect_changes(field: &str, old: &Value, new: &Value, out: &mut Vec<RecipeChange>) {
+    if old == new {
+        return;
+    }
+    let child = |key: &str| {
+        if field.is_empty() {
+            key.to_string()
+        } else {
+            format!("{field}.{key}")
+        }
+    };
+    match (old, new) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (key, value) in a {
+                collect_changes(&child(key), value, b.get(key).unwrap_or(&Value::Null), out);
+            }
+            for (key, value) in b.iter().filter(|(key, _)| !a.contains_key(*key)) {
+                collect_changes(&child(key), &Value::Null, value, out);
+            }
+        }
+        (Value::Array(a), Value::Array(b))
+            if a.len() == b.len() && a.iter().chain(b).all(Value::is_object) =>
+        {
+            for (i, (x, y)) in a.iter().zip(b).enumerate() {
+                collect_changes(&format!("{field}[{i}]"), x, y, out);
+            }
+        }
+        _ => out.push(RecipeChange {
+            field: field.to_string(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
+
+/// Settings that differ between two recipes, sorted by field path.
+pub fn diff_recipes(old: &EditRecipe, new: &EditRecipe) -> Result<Vec<RecipeChange>, String> {
+    let to_value = |r: &EditRecipe| {
+        serde_json::to_value(r).map_err(|e| format!("Serialize recipe failed: {e}"))
+    };
+    let mut changes = Vec::new();
+    collect_changes("", &to_value(old)?, &to_value(new)?, &mut changes);
+    Ok(changes)
+}
+
+/// Load the stored recipe (or a default one), let `edit` change it and save it
+/// as save_recipe_for_asset would, all under the sidecar lock. Returns the saved
+/// recipe.
+pub fn update_recipe_for_asset(
+    asset_path: &Path,
+    edit: impl FnOnce(&mut EditRecipe) -> Result<(), String>,
+) -> Result<EditRecipe, String> {
+    with_sidecar_lock(asset_path, || {
+        let mut recipe = load_recipe_for_asset(asset_path)?.unwrap_or_default();
+        edit(&mut recipe)?;
+        store_recipe(asset_path, &recipe)?;
+        Ok(recipe)
+    })
+}
+
+/// Apply a JSON merge patch to the stored recipe (or a default one), save and return it.
+pub fn patch_recipe_for_asset(asset_path: &Path, patch: &Value) -> Result<EditRecipe, String> {
+    update_recipe_for_asset(asset_path, |recipe| {
+        let mut merged =
+            serde_json::to_value(&*recipe).map_err(|e| format!("Serialize recipe failed: {e}"))?;
+        merge_patch(&mut merged, patch);
+        *recipe =
+            serde_json::from_value(merged).map_err(|e| format!("Invalid recipe patch: {e}"))?;
+        Ok(())
+    })
+}
@@ -0,0 +1,206 @@
+use image::RgbaImage;
+
+use crate::models::{RetouchMode, RetouchSpot};
+
+// Successive over-relaxation factor for the heal fill. The fill is solved coarse
+// to fine: grids up to HEAL_COARSEST across get a sweep per cell, each finer level
+// starts from the one below and only needs a few sweeps to settle. The work per
+// pixel is then the same at every size, so previews and exports heal alike.
+const HEAL_RELAXATION: f32 = 1.8;
+const HEAL_COARSEST: usize = 32;
+const HEAL_LEVEL_SWEEPS: usize = 24;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Cell {
+    Outside, // off the image or beyond the ring
+    Ring,    // just outside the circle: fixes the heal fill
+    Inside,
+}
+
+// Opacity of the patch `dist` pixels from the target centre.
+fn edge_weight(dist: f32, radius: f32, feather: f32) -> f32 {
+    let hard = radius * (1.0 - feather.clamp(0.0, 1.0));
+    if dist <= hard {
+        1.0
+    } else if dist >= radius {
+        0.0
+    } else {
+        let t = (radius - dist) / (radius - hard);
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+// Gauss-Seidel sweeps with over-relaxation over the inside cells of `values`.
+fn relax(cells: &[Cell], values: &mut [f32], side: usize, sweeps: usize) {
+    for _ in 0..sweeps {
+        for idx in 0..cells.len() {
+            if cells[idx] != Cell::Inside {
+                continue;
+            }
+            let (x, y) = (idx % side, idx / side);
+            let mut sum = 0.0;
+            let mut n = 0;
+            let neighbours = [
+                x.checked_sub(1).map(|nx| (nx, y)),
+                (x + 1 < side).then_some((x + 1, y)),
+                y.checked_sub(1).map(|ny| (x, ny)),
+                (y + 1 < side).then_some((x, y + 1)),
+            ];
+            for (nx, ny) in neighbours.into_iter().flatten() {
+                let at = ny * side + nx;
+                if cells[at] != Cell::Outside {
+                    sum += values[at];
+                    n += 1;
+                }
+            }
+            if n > 0 {
+                values[idx] += HEAL_RELAXATION * (sum / n as f32 - values[idx]);
+            }
+        }
+    }
+}
+
+// Smooth fill of the inside cells from the ring: the membrane that heal adds to
+// the source texture so its edge meets the target's surroundings.
+fn membrane(cells: &[Cell], ring: &[f32], side: usize) -> Vec<f32> {
+    if side <= HEAL_COARSEST {
+        let (sum, count) = cells
+            .iter()
+            .zip(ring)
+            .filter(|(cell, _)| **cell == Cell::Ring)
+            .fold((0.0, 0usize), |(sum, count), (_, v)| (sum + v, count + 1));
+        let mean = if count > 0 { sum / count as f32 } else { 0.0 };
+        let mut values: Vec<f32> = cells
+            .iter()
+            .zip(ring)
+            .map(|(cell, v)| if *cell == Cell::Ring { *v } else { mean })
+            .collect();
+        relax(cells, &mut values, side, side);
+        return values;
+    }
+
+    // the same problem at half the resolution: a 2x2 block is ring when any of
+    // it is (at the mean of its ring values), else inside when any of it is
+    let half = side.div_ceil(2);
+    let coarse_at = |idx: usize| (idx / side / 2) * half + (idx % side) / 2;
+    let mut coarse_cells = vec![Cell::Outside; half * half];
+    let mut coarse_ring = vec![0.0f32; half * half];
+    let mut ring_counts = vec![0u32; half * half];
+    for (idx, cell) in cells.iter().enumerate() {
+        let at = coarse_at(idx);
+        match cell {
+            Cell::Ring => {
+                coarse_cells[at] = Cell::Ring;
+                coarse_ring[at] += ring[idx];
+                ring_counts[at] += 1;
+            }
+            Cell::Inside if coarse_cells[at] == Cell::Outside => coarse_cells[at] = Cell::Inside,
+            _ => {}
+        }
+    }
+    for (value, count) in coarse_ring.iter_mut().zip(&ring_counts) {
+        if *count > 0 {
+            *value /= *count as f32;
+        }
+    }
+    let coarse = membrane(&coarse_cells, &coarse_ring, half);
+    let mut values: Vec<f32> = cells
+        .iter()
+        .enumerate()
+        .map(|(idx, cell)| {
+            if *cell == Cell::Ring {
+                ring[idx]
+            } else {
+                coarse[coarse_at(idx)]
+            }
+        })
+        .collect();
+    relax(cells, &mut values, side, HEAL_LEVEL_SWEEPS);
+    values
+}
+
+fn apply_spot(img: &mut RgbaImage, spot: &RetouchSpot) {
+    let (w, h) = (img.width() as i64, img.height() as i64);
+    let radius = spot.radius * w.max(h) as f32;
+    if radius < 0.5 || spot.opacity <= 0.0 {
+        return;
+    }
+    let tx = spot.target.0 * w as f32;
+    let ty = spot.target.1 * h as f32;
+    let dx = (spot.source.0 * w as f32 - tx).round() as i64;
+    let dy = (spot.source.1 * h as f32 - ty).round() as i64;
+    if dx == 0 && dy == 0 {
+        return;
+    }
+    // one cell of margin past the ring, so neighbours of ring cells always exist
+    let reach = radius.ceil() as i64 + 2;
+    let side = (2 * reach + 1) as usize;
+    let (x0, y0) = (tx.floor() as i64 - reach, ty.floor() as i64 - reach);
+    let mut cells = vec![Cell::Outside; side * side];
+    let mut weights = vec![0.0f32; side * side];
+    let mut target = vec![[0.0f32; 3]; side * side];
+    let mut source = vec![[0.0f32; 3]; side * side];
+    for j in 1..side - 1 {
+        for i in 1..side - 1 {
+            let (x, y) = (x0 + i as i64, y0 + j as i64);
+            if x < 0 || y < 0 || x >= w || y >= h {
+                continue;
+            }
+            let dist = ((x as f32 + 0.5 - tx).powi(2) + (y as f32 + 0.5 - ty).powi(2)).sqrt();
+            let idx = j * side + i;
+            cells[idx] = if dist <= radius {
+                Cell::Inside
+            } else if dist <= radius + 1.5 {
+                Cell::Ring
+            } else {
+                continue;
+            };
+            weights[idx] = edge_weight(dist, radius, spot.feather) * spot.opacity.min(1.0);
+            let px = img.get_pixel(x as u32, y as u32);
+            target[idx] = [px[0] as f32, px[1] as f32, px[2] as f32];
+            // a source circle hanging off the frame repeats the edge pixels
+            let (sx, sy) = ((x + dx).clamp(0, w - 1), (y + dy).clamp(0, h - 1));
+            let px = img.get_pixel(sx as u32, sy as u32);
+            source[idx] = [px[0] as f32, px[1] as f32, px[2] as f32];
+        }
+    }
+    let patch: Vec<[f32; 3]> = match spot.mode {
+        RetouchMode::Clone => source,
+        RetouchMode::Heal => {
+            let mut healed = source.clone();
+            for c in 0..3 {
+                let ring: Vec<f32> = target
+                    .iter()
+                    .zip(&source)
+                    .map(|(t, s)| t[c] - s[c])
+                    .collect();
+                let offset = membrane(&cells, &ring, side);
+                for (value, delta) in healed.iter_mut().zip(offset) {
+                    value[c] += delta;
+                }
+            }
+            healed
+        }
+    };
+    for (idx, cell) in cells.iter().enumerate() {
+        if *cell != Cell::Inside || weights[idx] <= 0.0 {
+            continue;
+        }
+        let (x, y) = (x0 + (idx % side) as i64, y0 + (idx / side) as i64);
+        let weight = weights[idx];
+        let px = img.get_pixel_mut(x as u32, y as u32);
+        for c in 0..3 {
+            let mixed = target[idx][c] + (patch[idx][c] - target[idx][c]) * weight;
+            px[c] = mixed.round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Paint each spot's target circle from its source circle, in order, so a
+/// later spot may sample an earlier one's result. Runs on the uncropped frame
+/// right after dead pixels are patched, so dust sits where the sensor put it.
+pub fn apply_retouch(img: &mut RgbaImage, spots: &[RetouchSpot]) {
+    for spot in spots {
+        apply_spot(img, spot);
+    }
+}
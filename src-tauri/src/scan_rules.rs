@@ -0,0 +1,113 @@
+use std::path::Path;
+
+use walkdir::DirEntry;
+
+use crate::models::ScanRules;
+use crate::settings::current_settings;
+use crate::state::resolve_path;
+
+const NOMEDIA_MARKER: &str = ".nomedia";
+
+/// Rules for `folder`: a per-folder override when configured, else the global rules.
+pub fn rules_for(folder: &Path) -> ScanRules {
+    let settings = current_settings();
+    let resolved = resolve_path(folder).ok();
+    settings
+        .folder_scan_rules
+        .iter()
+        .find(|(key, _)| {
+            let key = Path::new(key.as_str());
+            key == folder || resolved.as_deref() == resolve_path(key).ok().as_deref()
+        })
+        .map(|(_, rules)| rules.clone())
+        .unwrap_or(settings.scan_rules)
+}
+
+// `*` and `?` stay within one path segment, `**` crosses segments.
+fn glob_match(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+            let rest = rest.strip_prefix(b"/").unwrap_or(rest);
+            (0..=text.len()).any(|i| glob_match(rest, &text[i..]))
+        }
+        Some(b'*') => {
+            let rest = &pattern[1..];
+            for i in 0..=text.len() {
+                if glob_match(rest, &text[i..]) {
+                    return true;
+                }
+                if text.get(i) == Some(&b'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some(b'?') => {
+            matches!(text.first(), Some(c) if *c != b'/') && glob_match(&pattern[1..], &text[1..])
+        }
+        Some(c) => {
+            text.first().map(u8::to_ascii_lowercase) == Some(c.to_ascii_lowercase())
+                && glob_match(&pattern[1..], &text[1..])
+        }
+    }
+}
+
+fn matches_pattern(pattern: &str, relative: &str) -> bool {
+    let pattern = pattern.trim().replace('\\', "/");
+    if pattern.is_empty() {
+        return false;
+    }
+    // patterns without a separator apply to the file name at any depth
+    if !pattern.contains('/') {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        return glob_match(pattern.as_bytes(), name.as_bytes());
+    }
+    glob_match(pattern.as_bytes(), relative.as_bytes())
+}
+
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+/// Whether the opened folder itself holds nothing to scan: it carries a
+/// `.nomedia` marker the rules honour.
+pub fn folder_excluded(root: &Path, rules: &ScanRules) -> bool {
+    rules.honor_nomedia && root.join(NOMEDIA_MARKER).exists()
+}
+
+/// Whether a walked entry under `root` should be left out of the scan. Scans
+/// list one folder, so patterns see the file under the folder's own name
+/// ("shoot_rejects/IMG_1.jpg"), and a folder pattern like `*_rejects/*` leaves
+/// out every file of a folder it names.
+pub fn is_excluded(root: &Path, entry: &DirEntry, rules: &ScanRules) -> bool {
+    if entry.depth() == 0 {
+        return false;
+    }
+    if rules.skip_hidden && is_hidden(entry) {
+        return true;
+    }
+    if entry.file_type().is_dir() {
+        if rules.honor_nomedia && entry.path().join(NOMEDIA_MARKER).exists() {
+            return true;
+        }
+    } else if rules.min_file_size > 0
+        && entry
+            .metadata()
+            .map(|m| m.len() < rules.min_file_size)
+            .unwrap_or(false)
+    {
+        return true;
+    }
+    let relative = entry
+        .path()
+        .strip_prefix(root.parent().unwrap_or(root))
+        .unwrap_or(entry.path())
+        .to_string_lossy()
+        .replace('\\', "/");
+    rules
+        .exclude_patterns
+        .iter()
+        .any(|pattern| matches_pattern(pattern, &relative))
+}
@@ -0,0 +1,66 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+
+use once_cell::sync::Lazy;
+
+use crate::cache::data_root;
+use crate::models::AppSettings;
+use crate::shutdown::write_atomic;
+
+// loaded on first use; writes go through `modify_settings` so the cache stays in sync
+static SETTINGS: Lazy<RwLock<AppSettings>> =
+    Lazy::new(|| RwLock::new(read_settings_file().unwrap_or_default()));
+// serializes read-modify-write of the settings file
+static SETTINGS_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+fn settings_path() -> Result<PathBuf, String> {
+    Ok(data_root()?.join("settings.json"))
+}
+
+fn read_settings_file() -> Result<AppSettings, String> {
+    let path = settings_path()?;
+    if !path.exists() {
+        return Ok(AppSettings::default());
+    }
+    let data = fs::read_to_string(&path).map_err(|e| format!("Read settings failed: {e}"))?;
+    serde_json::from_str(&data).map_err(|e| format!("Parse settings failed: {e}"))
+}
+
+pub fn current_settings() -> AppSettings {
+    SETTINGS
+        .read()
+        .map(|settings| settings.clone())
+        .unwrap_or_default()
+}
+
+fn save_settings(settings: &AppSettings) -> Result<(), String> {
+    let serialized = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Serialize settings failed: {e}"))?;
+    write_atomic(&settings_path()?, serialized)
+        .map_err(|e| format!("Write settings failed: {e}"))?;
+    let mut cached = SETTINGS.write().map_err(|e| e.to_string())?;
+    *cached = settings.clone();
+    Ok(())
+}
+
+/// Mutate and write back the settings under the lock. Nothing is written when
+/// `f` fails.
+pub fn modify_settings<T>(
+    f: impl FnOnce(&mut AppSettings) -> Result<T, String>,
+) -> Result<T, String> {
+    let _guard = SETTINGS_LOCK.lock().map_err(|e| e.to_string())?;
+    let mut settings = current_settings();
+    let out = f(&mut settings)?;
+    save_settings(&settings)?;
+    Ok(out)
+}
+
+/// Re-read settings.json after something other than `modify_settings` replaced it.
+pub fn reload_settings() -> Result<AppSettings, String> {
+    let _guard = SETTINGS_LOCK.lock().map_err(|e| e.to_string())?;
+    let settings = read_settings_file()?;
+    let mut cached = SETTINGS.write().map_err(|e| e.to_string())?;
+    *cached = settings.clone();
+    Ok(settings)
+}
@@ -0,0 +1,92 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+
+// Background jobs (exports, 1:1 preview runs, the cache sweep) hold a JobGuard;
+// on exit the app stops handing out new ones, asks running loops to stop at
+// their next item and waits for the guards to drop.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static ACTIVE_JOBS: Lazy<(Mutex<usize>, Condvar)> = Lazy::new(|| (Mutex::new(0), Condvar::new()));
+// long enough for an in-flight export frame, short enough not to hang the exit
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(15);
+// Makes write_atomic's temp names unique within the process.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+pub struct JobGuard(());
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        let (count, idle) = &*ACTIVE_JOBS;
+        if let Ok(mut count) = count.lock() {
+            *count = count.saturating_sub(1);
+            idle.notify_all();
+        }
+    }
+}
+
+/// Register a background job; refused once shutdown has started.
+pub fn begin_job() -> Result<JobGuard, String> {
+    if stopping() {
+        return Err("Openroom is shutting down".into());
+    }
+    let (count, _) = &*ACTIVE_JOBS;
+    *count.lock().map_err(|e| e.to_string())? += 1;
+    Ok(JobGuard(()))
+}
+
+/// Long loops check this between items and stop early.
+pub fn stopping() -> bool {
+    STOPPING.load(Ordering::SeqCst)
+}
+
+/// Stop accepting jobs and wait (bounded) for the running ones to finish.
+/// Returns false if some were still running at the deadline.
+pub fn drain() -> bool {
+    STOPPING.store(true, Ordering::SeqCst);
+    let deadline = Instant::now() + DRAIN_TIMEOUT;
+    let (count, idle) = &*ACTIVE_JOBS;
+    let Ok(mut active) = count.lock() else {
+        return false;
+    };
+    while *active > 0 {
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return false;
+        }
+        active = match idle.wait_timeout(active, left) {
+            Ok((guard, _)) => guard,
+            Err(_) => return false,
+        };
+    }
+    true
+}
+
+/// Write through a temp file and rename over the target, so a process killed
+/// mid-write leaves the old file rather than a truncated one. Each call gets its
+/// own temp file, so concurrent saves of one path never publish each other's
+/// half-written bytes; the last rename wins.
+pub fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(format!(
+        ".{}-{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp = path.with_file_name(tmp_name);
+    let written = File::create(&tmp).and_then(|mut file| {
+        file.write_all(contents.as_ref())?;
+        // on disk before the rename makes it visible, or a crash can leave the
+        // new name pointing at empty blocks
+        file.sync_all()
+    });
+    written
+        .and_then(|_| fs::rename(&tmp, path))
+        .inspect_err(|_| {
+            let _ = fs::remove_file(&tmp);
+        })
+}
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use image::{GrayImage, Luma};
+use rayon::prelude::*;
+
+use crate::blur::gaussian_blur_f32;
+use crate::crop::apply_crop;
+use crate::image_io::cached_preview;
+use crate::lens::{correct_lens, resolve_profile};
+use crate::mask::{store_brush_bitmap, BRUSH_MASK};
+use crate::models::Mask;
+use crate::recipe_io::load_recipe_for_asset;
+
+// Analysed on a small cached variant; the bitmap is sampled bilinearly at any size.
+const SKY_ANALYSIS_DIM: u32 = 960;
+// Sobel magnitude (0..1 luma) above which a pixel is texture, not sky.
+const MAX_SKY_GRADIENT: f32 = 0.12;
+// blue sky: blue leads red by this much and is not far behind green
+const MIN_BLUE_LEAD: f32 = 0.04;
+const MIN_BLUE_BRIGHTNESS: f32 = 0.3;
+// overcast or hazy sky: bright and nearly grey
+const MIN_OVERCAST_BRIGHTNESS: f32 = 0.65;
+const MAX_OVERCAST_SATURATION: f32 = 0.18;
+// soft edge of the finished mask, relative to the long edge
+const EDGE_SIGMA_FRACTION: f32 = 0.004;
+// less sky than this is treated as none found
+const MIN_SKY_COVERAGE: f32 = 0.01;
+
+fn looks_like_sky(px: &[u8]) -> bool {
+    let [r, g, b] = [0, 1, 2].map(|i| px[i] as f32 / 255.0);
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let saturation = if max > 0.0 { (max - min) / max } else { 0.0 };
+    let blue = b - r >= MIN_BLUE_LEAD && b >= g * 0.9 && max >= MIN_BLUE_BRIGHTNESS;
+    let overcast = max >= MIN_OVERCAST_BRIGHTNESS && saturation <= MAX_OVERCAST_SATURATION;
+    blue || overcast
+}
+
+/// Find the sky in the asset (as framed by its saved lens correction and crop,
+/// so the bitmap lines up with layer masks) and store it as a brush bitmap.
+/// Sky is smooth, blue or bright grey, and reaches the top edge: candidates are
+/// flood-filled from the top row so blue walls and white shirts stay out.
+/// None when no sky is found.
+pub fn generate_sky_mask(asset_id: &str, path: &Path) -> Result<Option<Mask>, String> {
+    let mut frame = (*cached_preview(asset_id, path, SKY_ANALYSIS_DIM)?).clone();
+    if let Some(mut recipe) = load_recipe_for_asset(path)? {
+        resolve_profile(&mut recipe.lens, path);
+        frame = correct_lens(frame, &recipe);
+        if let Some(crop) = &recipe.crop {
+            frame = apply_crop(frame, crop);
+        }
+    }
+    let (w, h) = (frame.width() as usize, frame.height() as usize);
+    if w < 3 || h < 3 {
+        return Ok(None);
+    }
+    let luma: Vec<f32> = frame
+        .pixels()
+        .map(|px| (0.2126 * px[0] as f32 + 0.7152 * px[1] as f32 + 0.0722 * px[2] as f32) / 255.0)
+        .collect();
+    let candidate: Vec<bool> = (0..w * h)
+        .into_par_iter()
+        .map(|idx| {
+            let (x, y) = (idx % w, idx / w);
+            let at = |dx: isize, dy: isize| {
+                let nx = (x as isize + dx).clamp(0, w as isize - 1) as usize;
+                let ny = (y as isize + dy).clamp(0, h as isize - 1) as usize;
+                luma[ny * w + nx]
+            };
+            let gx = (at(1, -1) + 2.0 * at(1, 0) + at(1, 1))
+                - (at(-1, -1) + 2.0 * at(-1, 0) + at(-1, 1));
+            let gy = (at(-1, 1) + 2.0 * at(0, 1) + at(1, 1))
+                - (at(-1, -1) + 2.0 * at(0, -1) + at(1, -1));
+            let smooth = (gx * gx + gy * gy).sqrt() <= MAX_SKY_GRADIENT;
+            smooth && looks_like_sky(&frame.as_raw()[idx * 4..idx * 4 + 4])
+        })
+        .collect();
+
+    let mut sky = vec![false; w * h];
+    let mut queue: VecDeque<usize> = (0..w).filter(|&x| candidate[x]).collect();
+    for &idx in &queue {
+        sky[idx] = true;
+    }
+    while let Some(idx) = queue.pop_front() {
+        let (x, y) = (idx % w, idx / w);
+        let neighbors = [
+            (x > 0).then(|| idx - 1),
+            (x + 1 < w).then(|| idx + 1),
+            (y > 0).then(|| idx - w),
+            (y + 1 < h).then(|| idx + w),
+        ];
+        for n in neighbors.into_iter().flatten() {
+            if candidate[n] && !sky[n] {
+                sky[n] = true;
+                queue.push_back(n);
+            }
+        }
+    }
+    let covered = sky.iter().filter(|&&s| s).count();
+    if (covered as f32) < (w * h) as f32 * MIN_SKY_COVERAGE {
+        return Ok(None);
+    }
+
+    let mut coverage: Vec<f32> = sky.iter().map(|&s| if s { 1.0 } else { 0.0 }).collect();
+    let sigma = (w.max(h) as f32 * EDGE_SIGMA_FRACTION).max(1.0);
+    gaussian_blur_f32(&mut coverage, w, h, 1, sigma);
+    let bitmap = GrayImage::from_fn(w as u32, h as u32, |x, y| {
+        let value = coverage[y as usize * w + x as usize];
+        Luma([(value.clamp(0.0, 1.0) * 255.0).round() as u8])
+    });
+    let name = store_brush_bitmap(path, &bitmap)?;
+    Ok(Some(Mask {
+        mask_type: BRUSH_MASK.to_string(),
+        bitmap: Some(name),
+        ..Mask::default()
+    }))
+}
@@ -1,10 +1,17 @@
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use once_cell::sync::Lazy;
 
+use crate::cache::{cache_root, data_root};
+use crate::settings::current_settings;
+
 pub static ASSET_REGISTRY: Lazy<DashMap<String, PathBuf>> = Lazy::new(DashMap::new);
 
+// Canonical folders opened this session. Together with the configured roots
+// these bound every path the backend reads from or writes to.
+static SESSION_ROOTS: Lazy<DashSet<PathBuf>> = Lazy::new(DashSet::new);
+
 pub fn register_assets<I>(assets: I)
 where
     I: IntoIterator<Item = (String, PathBuf)>,
@@ -15,6 +22,134 @@ where
     }
 }
 
+/// Add one asset to the open folder's registry, leaving the rest in place.
+pub fn register_asset(id: String, path: PathBuf) {
+    ASSET_REGISTRY.insert(id, path);
+}
+
 pub fn path_for(id: &str) -> Option<PathBuf> {
-    ASSET_REGISTRY.get(id).map(|entry| entry.value().clone())
+    let path = ASSET_REGISTRY.get(id).map(|entry| entry.value().clone())?;
+    // registered paths come from opened folders, but re-check in case a root was removed
+    ensure_allowed(&path).ok().map(|_| path)
+}
+
+pub fn id_for_path(path: &Path) -> Option<String> {
+    ASSET_REGISTRY
+        .iter()
+        .find(|entry| entry.value().as_path() == path)
+        .map(|entry| entry.key().clone())
+}
+
+/// Point every asset id registered for `old` at `new`, after the original moved.
+pub fn rebind_path(old: &Path, new: &Path) {
+    for mut entry in ASSET_REGISTRY.iter_mut() {
+        if entry.value().as_path() == old {
+            *entry.value_mut() = new.to_path_buf();
+        }
+    }
+}
+
+/// Canonicalize `path`, also for paths that do not exist yet: the nearest
+/// existing ancestor is resolved and the remainder appended. `..` and other
+/// non-plain components in the remainder are rejected.
+pub fn resolve_path(path: &Path) -> Result<PathBuf, String> {
+    if !path.is_absolute() {
+        return Err("Path must be absolute".into());
+    }
+    let mut existing = path;
+    let mut rest: Vec<&std::ffi::OsStr> = Vec::new();
+    while !existing.exists() {
+        let name = match existing.components().next_back() {
+            Some(Component::Normal(name)) => name,
+            _ => return Err("Invalid path".into()),
+        };
+        rest.push(name);
+        existing = existing.parent().ok_or("Invalid path")?;
+    }
+    let mut resolved = existing
+        .canonicalize()
+        .map_err(|e| format!("Resolve path failed: {e}"))?;
+    resolved.extend(rest.iter().rev());
+    Ok(resolved)
+}
+
+/// Grant access to a folder the user explicitly opened.
+pub fn allow_root(folder: &Path) -> Result<PathBuf, String> {
+    let root = resolve_path(folder)?;
+    if !root.is_dir() {
+        return Err("Provided path is not a directory".into());
+    }
+    SESSION_ROOTS.insert(root.clone());
+    Ok(root)
+}
+
+fn is_within_roots(resolved: &Path) -> bool {
+    if SESSION_ROOTS
+        .iter()
+        .any(|root| resolved.starts_with(root.key()))
+    {
+        return true;
+    }
+    let configured = current_settings().allowed_roots;
+    let app_dirs = [cache_root(), data_root()];
+    configured
+        .iter()
+        .filter_map(|root| resolve_path(Path::new(root)).ok())
+        .chain(app_dirs.into_iter().filter_map(Result::ok))
+        .any(|root| resolved.starts_with(root))
+}
+
+/// Resolve `path` and make sure it lies under an opened or configured root.
+pub fn ensure_allowed(path: &Path) -> Result<PathBuf, String> {
+    let resolved = resolve_path(path)?;
+    if is_within_roots(&resolved) {
+        Ok(resolved)
+    } else {
+        Err(format!(
+            "Access denied: {} is outside the opened folders",
+            path.display()
+        ))
+    }
+}
+
+// Where the OS and colour-management tools install profiles; nobody opens these
+// as photo folders, but printer and display profiles live nowhere else.
+fn system_color_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    let home = dirs::home_dir();
+    if cfg!(target_os = "macos") {
+        dirs.push(PathBuf::from("/Library/ColorSync/Profiles"));
+        dirs.push(PathBuf::from("/System/Library/ColorSync/Profiles"));
+        dirs.extend(home.map(|home| home.join("Library/ColorSync/Profiles")));
+    } else if cfg!(windows) {
+        let system_root = std::env::var_os("SystemRoot").map(PathBuf::from);
+        dirs.extend(system_root.map(|root| root.join(r"System32\spool\drivers\color")));
+    } else {
+        dirs.push(PathBuf::from("/usr/share/color"));
+        dirs.push(PathBuf::from("/usr/local/share/color"));
+        dirs.push(PathBuf::from("/var/lib/colord/icc"));
+        if let Some(home) = home {
+            dirs.push(home.join(".local/share/icc"));
+            dirs.push(home.join(".color/icc"));
+        }
+    }
+    dirs
+}
+
+/// `ensure_allowed` for colour files that are only ever read, such as output
+/// profiles: the OS colour folders are allowed as well as the opened roots.
+pub fn ensure_color_file_allowed(path: &Path) -> Result<PathBuf, String> {
+    let resolved = resolve_path(path)?;
+    let in_system_dir = system_color_dirs()
+        .iter()
+        .filter_map(|dir| dir.canonicalize().ok())
+        .any(|dir| resolved.starts_with(dir));
+    if in_system_dir || is_within_roots(&resolved) {
+        Ok(resolved)
+    } else {
+        Err(format!(
+            "Access denied: {} is outside the opened folders and the system colour folders",
+            path.display()
+        ))
+    }
 }
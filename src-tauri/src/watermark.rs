@@ -0,0 +1,180 @@
+use image::RgbaImage;
+use rayon::prelude::*;
+
+use crate::models::ReviewWatermark;
+
+// 5x7 capitals, digits and common punctuation; each row's low five bits, left
+// pixel highest. Lowercase is drawn as capitals and anything else as '?'.
+const FONT: &[(char, [u8; 7])] = &[
+    (' ', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+    ('A', [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('B', [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E]),
+    ('C', [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E]),
+    ('D', [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C]),
+    ('E', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F]),
+    ('F', [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10]),
+    ('G', [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F]),
+    ('H', [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11]),
+    ('I', [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('J', [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C]),
+    ('K', [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11]),
+    ('L', [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F]),
+    ('M', [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11]),
+    ('N', [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11]),
+    ('O', [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('P', [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10]),
+    ('Q', [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D]),
+    ('R', [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11]),
+    ('S', [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E]),
+    ('T', [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04]),
+    ('U', [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E]),
+    ('V', [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04]),
+    ('W', [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A]),
+    ('X', [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11]),
+    ('Y', [0x11, 0x11, 0x0A, 0x04, 0x04, 0x04, 0x04]),
+    ('Z', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F]),
+    ('0', [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E]),
+    ('1', [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E]),
+    ('2', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F]),
+    ('3', [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E]),
+    ('4', [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02]),
+    ('5', [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E]),
+    ('6', [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E]),
+    ('7', [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08]),
+    ('8', [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E]),
+    ('9', [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C]),
+    ('-', [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00]),
+    ('_', [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F]),
+    ('+', [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00]),
+    ('.', [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C]),
+    (',', [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08]),
+    (':', [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00]),
+    ('!', [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04]),
+    ('?', [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04]),
+    ('/', [0x01, 0x01, 0x02, 0x04, 0x08, 0x10, 0x10]),
+    ('\'', [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00]),
+    ('(', [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02]),
+    (')', [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08]),
+    ('&', [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D]),
+    ('#', [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A]),
+    ('@', [0x0E, 0x11, 0x17, 0x15, 0x17, 0x10, 0x0F]),
+];
+const GLYPH_W: usize = 5;
+const GLYPH_H: usize = 7;
+const ADVANCE: usize = GLYPH_W + 1;
+// The dark halo that keeps white text readable on bright skies, relative to the
+// text's own opacity.
+const HALO_OPACITY: f32 = 0.5;
+// Subsamples per axis for antialiased edges.
+const SUPERSAMPLE: usize = 3;
+
+fn glyph(c: char) -> [u8; 7] {
+    let c = c.to_ascii_uppercase();
+    FONT.iter()
+        .find(|(known, _)| *known == c)
+        .or_else(|| FONT.iter().find(|(known, _)| *known == '?'))
+        .map(|(_, rows)| *rows)
+        .unwrap_or_default()
+}
+
+// Ink and halo of the text in font units, with a cell of margin all round for
+// the halo.
+struct TextBitmap {
+    width: usize,
+    height: usize,
+    ink: Vec<bool>,
+    halo: Vec<bool>,
+}
+
+fn text_bitmap(text: &str) -> TextBitmap {
+    let chars: Vec<char> = text.chars().collect();
+    let width = chars.len() * ADVANCE - 1 + 2;
+    let height = GLYPH_H + 2;
+    let mut ink = vec![false; width * height];
+    for (idx, c) in chars.into_iter().enumerate() {
+        for (row, bits) in glyph(c).into_iter().enumerate() {
+            for col in 0..GLYPH_W {
+                if bits & (1 << (GLYPH_W - 1 - col)) != 0 {
+                    ink[(row + 1) * width + idx * ADVANCE + col + 1] = true;
+                }
+            }
+        }
+    }
+    let halo = (0..width * height)
+        .map(|at| {
+            let (x, y) = ((at % width) as i64, (at / width) as i64);
+            (-1..=1).any(|dy| {
+                (-1..=1).any(|dx| {
+                    let (nx, ny) = (x + dx, y + dy);
+                    nx >= 0
+                        && ny >= 0
+                        && (nx as usize) < width
+                        && (ny as usize) < height
+                        && ink[ny as usize * width + nx as usize]
+                })
+            })
+        })
+        .collect();
+    TextBitmap {
+        width,
+        height,
+        ink,
+        halo,
+    }
+}
+
+/// Burn `mark` across the image along its rising diagonal: white text over a
+/// soft dark halo, centred, spanning `mark.size` of the diagonal.
+pub fn burn_review_watermark(img: &mut RgbaImage, mark: &ReviewWatermark) {
+    let text = mark.text.trim();
+    let opacity = mark.opacity.clamp(0.0, 1.0);
+    let (w, h) = (img.width() as f32, img.height() as f32);
+    if text.is_empty() || opacity <= 0.0 || mark.size <= 0.0 || w < 1.0 || h < 1.0 {
+        return;
+    }
+    let bitmap = text_bitmap(text);
+    let diagonal = (w * w + h * h).sqrt();
+    // pixels per font unit
+    let scale = mark.size.min(1.0) * diagonal / bitmap.width as f32;
+    // reading direction runs up to the right; "down" is its clockwise normal
+    let (along, down) = ((w / diagonal, -h / diagonal), (h / diagonal, w / diagonal));
+    let (cx, cy) = (w / 2.0, h / 2.0);
+    let cell = |x: f32, y: f32| {
+        let (dx, dy) = (x - cx, y - cy);
+        let u = (dx * along.0 + dy * along.1) / scale + bitmap.width as f32 / 2.0;
+        let v = (dx * down.0 + dy * down.1) / scale + bitmap.height as f32 / 2.0;
+        (u >= 0.0 && v >= 0.0 && (u as usize) < bitmap.width && (v as usize) < bitmap.height)
+            .then(|| v as usize * bitmap.width + u as usize)
+    };
+    let width = img.width() as usize;
+    img.as_mut()
+        .par_chunks_mut(width * 4)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, px) in row.chunks_exact_mut(4).enumerate() {
+                let (mut ink, mut halo) = (0, 0);
+                for sy in 0..SUPERSAMPLE {
+                    for sx in 0..SUPERSAMPLE {
+                        let at = cell(
+                            x as f32 + (sx as f32 + 0.5) / SUPERSAMPLE as f32,
+                            y as f32 + (sy as f32 + 0.5) / SUPERSAMPLE as f32,
+                        );
+                        if let Some(at) = at {
+                            ink += bitmap.ink[at] as usize;
+                            halo += bitmap.halo[at] as usize;
+                        }
+                    }
+                }
+                if halo == 0 {
+                    continue;
+                }
+                let samples = (SUPERSAMPLE * SUPERSAMPLE) as f32;
+                let shade = opacity * HALO_OPACITY * halo as f32 / samples;
+                let light = opacity * ink as f32 / samples;
+                for c in px.iter_mut().take(3) {
+                    let value = *c as f32 * (1.0 - shade);
+                    *c = (value + (255.0 - value) * light).round() as u8;
+                }
+            }
+        });
+}
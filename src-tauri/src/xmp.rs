@@ -0,0 +1,305 @@
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::models::EmbeddedXmp;
+
+const JPEG_XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+// HEIF lists its XMP as a metadata item in the `meta` box, which also says where
+// the item's bytes are. Neither is ever near these sizes in a real file.
+const MAX_META_BYTES: u64 = 4 * 1024 * 1024;
+const MAX_PACKET_BYTES: u64 = 4 * 1024 * 1024;
+const XMP_CONTENT_TYPE: &[u8] = b"application/rdf+xml";
+const PACKET_START: &str = "<x:xmpmeta";
+const PACKET_END: &str = "</x:xmpmeta>";
+
+// The APP1 XMP segment, walking markers up to the start of the scan data.
+fn jpeg_xmp(path: &Path) -> Option<String> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut marker = [0u8; 2];
+    reader.read_exact(&mut marker).ok()?;
+    if marker != [0xFF, 0xD8] {
+        return None;
+    }
+    loop {
+        reader.read_exact(&mut marker).ok()?;
+        if marker[0] != 0xFF {
+            return None;
+        }
+        // start of scan or end of image: no more metadata segments
+        if marker[1] == 0xDA || marker[1] == 0xD9 {
+            return None;
+        }
+        let mut length = [0u8; 2];
+        reader.read_exact(&mut length).ok()?;
+        let length = u16::from_be_bytes(length).checked_sub(2)? as usize;
+        let mut segment = vec![0u8; length];
+        reader.read_exact(&mut segment).ok()?;
+        if marker[1] == 0xE1 {
+            if let Some(packet) = segment.strip_prefix(JPEG_XMP_HEADER) {
+                return Some(String::from_utf8_lossy(packet).to_string());
+            }
+        }
+    }
+}
+
+// Big-endian unsigned integer of `size` bytes (0, 4 or 8 in iloc fields) at the cursor.
+fn read_uint(data: &[u8], pos: &mut usize, size: usize) -> Option<u64> {
+    let bytes = data.get(*pos..pos.checked_add(size)?)?;
+    *pos += size;
+    Some(bytes.iter().fold(0u64, |acc, b| (acc << 8) | *b as u64))
+}
+
+// Child boxes of an ISO BMFF container body: (type, body) pairs.
+fn boxes(data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while pos + 8 <= data.len() {
+        let Some(size) = read_uint(data, &mut pos, 4) else {
+            break;
+        };
+        let kind = &data[pos..pos + 4];
+        pos += 4;
+        let (header, size) = match size {
+            0 => (8, (data.len() - pos + 8) as u64),
+            1 => match read_uint(data, &mut pos, 8) {
+                Some(large) => (16, large),
+                None => break,
+            },
+            size => (8, size),
+        };
+        let Some(end) = (pos - header)
+            .checked_add(size as usize)
+            .filter(|&end| size >= header as u64 && end <= data.len())
+        else {
+            break;
+        };
+        found.push((kind, &data[pos..end]));
+        pos = end;
+    }
+    found
+}
+
+// The top-level `meta` box's body, read without touching the media data.
+fn heif_meta(path: &Path) -> Option<Vec<u8>> {
+    let mut file = File::open(path).ok()?;
+    let len = file.metadata().ok()?.len();
+    let mut offset = 0u64;
+    while offset + 8 <= len {
+        let mut header = [0u8; 16];
+        file.seek(SeekFrom::Start(offset)).ok()?;
+        file.read_exact(&mut header[..8]).ok()?;
+        let mut size = u32::from_be_bytes(header[..4].try_into().ok()?) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            file.read_exact(&mut header[8..]).ok()?;
+            size = u64::from_be_bytes(header[8..].try_into().ok()?);
+            header_len = 16;
+        } else if size == 0 {
+            size = len - offset;
+        }
+        if size < header_len {
+            return None;
+        }
+        if &header[4..8] == b"meta" {
+            let body = size - header_len;
+            if body > MAX_META_BYTES {
+                return None;
+            }
+            let mut meta = vec![0u8; body as usize];
+            file.read_exact(&mut meta).ok()?;
+            return Some(meta);
+        }
+        offset = offset.checked_add(size)?;
+    }
+    None
+}
+
+// The id of the item `iinf` declares as XMP: a `mime` item of type rdf+xml.
+fn xmp_item_id(iinf: &[u8]) -> Option<u32> {
+    let version = *iinf.first()?;
+    let mut pos = 4;
+    read_uint(iinf, &mut pos, if version == 0 { 2 } else { 4 })?;
+    boxes(iinf.get(pos..)?)
+        .into_iter()
+        .filter(|(kind, _)| *kind == b"infe")
+        .find_map(|(_, infe)| {
+            // versions 0 and 1 predate item types
+            let version = *infe.first()?;
+            if version < 2 {
+                return None;
+            }
+            let mut pos = 4;
+            let id = read_uint(infe, &mut pos, if version == 2 { 2 } else { 4 })? as u32;
+            pos += 2; // protection index
+            let item_type = infe.get(pos..pos + 4)?;
+            pos += 4;
+            if item_type != b"mime" {
+                return None;
+            }
+            let mut strings = infe.get(pos..)?.split(|b| *b == 0);
+            strings.next()?; // item name
+            (strings.next()? == XMP_CONTENT_TYPE).then_some(id)
+        })
+}
+
+// Where `iloc` puts item `id`: (offset, length) extents, offsets into the file
+// or, for construction method 1, into the `idat` box.
+fn item_extents(iloc: &[u8], id: u32) -> Option<(u64, Vec<(u64, u64)>)> {
+    let version = *iloc.first()?;
+    let mut pos = 4;
+    let sizes = read_uint(iloc, &mut pos, 1)?;
+    let (offset_size, length_size) = ((sizes >> 4) as usize, (sizes & 0xF) as usize);
+    let sizes = read_uint(iloc, &mut pos, 1)?;
+    let base_offset_size = (sizes >> 4) as usize;
+    let index_size = if version == 1 || version == 2 {
+        (sizes & 0xF) as usize
+    } else {
+        0
+    };
+    let id_size = if version < 2 { 2 } else { 4 };
+    let count = read_uint(iloc, &mut pos, id_size)?;
+    for _ in 0..count {
+        let item = read_uint(iloc, &mut pos, id_size)? as u32;
+        let method = if version == 1 || version == 2 {
+            read_uint(iloc, &mut pos, 2)? & 0xF
+        } else {
+            0
+        };
+        pos += 2; // data reference index
+        let base = read_uint(iloc, &mut pos, base_offset_size)?;
+        let extent_count = read_uint(iloc, &mut pos, 2)?;
+        let mut extents = Vec::new();
+        for _ in 0..extent_count {
+            read_uint(iloc, &mut pos, index_size)?;
+            let offset = read_uint(iloc, &mut pos, offset_size)?;
+            let length = read_uint(iloc, &mut pos, length_size)?;
+            extents.push((base.checked_add(offset)?, length));
+        }
+        if item == id {
+            return Some((method, extents));
+        }
+    }
+    None
+}
+
+// The XMP item of a HEIF file, found through its `meta` box.
+fn heif_xmp(path: &Path) -> Option<String> {
+    let meta = heif_meta(path)?;
+    let children = boxes(meta.get(4..)?);
+    let child = |name: &[u8]| {
+        children
+            .iter()
+            .find(|(kind, _)| *kind == name)
+            .map(|(_, body)| *body)
+    };
+    let id = xmp_item_id(child(b"iinf")?)?;
+    let (method, extents) = item_extents(child(b"iloc")?, id)?;
+    let total: u64 = extents.iter().map(|(_, length)| length).sum();
+    if total == 0 || total > MAX_PACKET_BYTES {
+        return None;
+    }
+    let mut packet = Vec::with_capacity(total as usize);
+    match method {
+        0 => {
+            let mut file = File::open(path).ok()?;
+            for (offset, length) in extents {
+                file.seek(SeekFrom::Start(offset)).ok()?;
+                (&mut file).take(length).read_to_end(&mut packet).ok()?;
+            }
+        }
+        1 => {
+            let idat = child(b"idat")?;
+            for (offset, length) in extents {
+                let start = usize::try_from(offset).ok()?;
+                packet.extend_from_slice(idat.get(start..start.checked_add(length as usize)?)?);
+            }
+        }
+        _ => return None,
+    }
+    let text = String::from_utf8_lossy(&packet);
+    let start = text.find(PACKET_START)?;
+    let end = text[start..].find(PACKET_END)? + start + PACKET_END.len();
+    Some(text[start..end].to_string())
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+// A simple property in either serialization RDF allows: `xmp:Rating="3"` on the
+// description, or `<xmp:Rating>3</xmp:Rating>`.
+fn property(xmp: &str, name: &str) -> Option<String> {
+    let attribute = format!("{name}=\"");
+    if let Some(start) = xmp.find(&attribute).map(|i| i + attribute.len()) {
+        let end = xmp[start..].find('"')? + start;
+        return Some(unescape(&xmp[start..end]));
+    }
+    let (open, close) = (format!("<{name}>"), format!("</{name}>"));
+    let start = xmp.find(&open)? + open.len();
+    let end = xmp[start..].find(&close)? + start;
+    Some(unescape(xmp[start..end].trim()))
+}
+
+// `<rdf:li>` items of an array property (dc:subject is a bag, dc:description an
+// alternative keyed by language).
+fn list_items(xmp: &str, name: &str) -> Vec<String> {
+    let (open, close) = (format!("<{name}>"), format!("</{name}>"));
+    let Some(start) = xmp.find(&open).map(|i| i + open.len()) else {
+        return Vec::new();
+    };
+    let Some(end) = xmp[start..].find(&close).map(|i| i + start) else {
+        return Vec::new();
+    };
+    let mut items = Vec::new();
+    let mut rest = &xmp[start..end];
+    while let Some(li) = rest.find("<rdf:li") {
+        let Some(body) = rest[li..].find('>').map(|i| li + i + 1) else {
+            break;
+        };
+        let Some(stop) = rest[body..].find("</rdf:li>").map(|i| body + i) else {
+            break;
+        };
+        let item = unescape(rest[body..stop].trim());
+        if !item.is_empty() {
+            items.push(item);
+        }
+        rest = &rest[stop..];
+    }
+    items
+}
+
+/// Rating, colour label, keywords and description another app (a phone, a
+/// different editor) embedded as XMP in a JPEG or HEIC. None when the file has
+/// no XMP or none of these fields.
+pub fn read_embedded_xmp(path: &Path) -> Option<EmbeddedXmp> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let xmp = match extension.as_str() {
+        "jpg" | "jpeg" => jpeg_xmp(path)?,
+        "heic" | "heif" => heif_xmp(path)?,
+        _ => return None,
+    };
+    let found = EmbeddedXmp {
+        // -1 marks a rejected file; anything else outside 0..5 is noise
+        rating: property(&xmp, "xmp:Rating")
+            .and_then(|r| r.trim().parse::<f32>().ok())
+            .map(|r| r.round() as i8)
+            .filter(|r| (-1..=5).contains(r)),
+        label: property(&xmp, "xmp:Label").filter(|l| !l.trim().is_empty()),
+        keywords: list_items(&xmp, "dc:subject"),
+        description: list_items(&xmp, "dc:description").into_iter().next(),
+    };
+    let empty = found.rating.is_none()
+        && found.label.is_none()
+        && found.keywords.is_empty()
+        && found.description.is_none();
+    (!empty).then_some(found)
+}
@@ -1,5 +1,4 @@
 import { invoke } from "@tauri-apps/api/core";
-import { open } from "@tauri-apps/plugin-dialog";
 import { useLibraryStore } from "./store";
 import type { FolderIndex } from "./types";
 
@@ -39,14 +38,8 @@ export async function pickFolderAndLoad(): Promise<FolderIndex | null> {
   const setLoading = useLibraryStore.getState().setLoading;
   try {
     setLoading(true);
-    const selection = await open({
-      directory: true,
-      multiple: false,
-      recursive: false,
-      title: "Select a photo folder",
-    });
-
-    const folderPath = Array.isArray(selection) ? selection[0] : selection;
+    // the backend shows the dialog itself: only folders picked there are granted
+    const folderPath = await invoke<string | null>("pick_folder");
     if (!folderPath) {
       setLoading(false);
       return null;